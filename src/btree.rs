@@ -0,0 +1,818 @@
+use std::cmp::Ordering;
+
+use anyhow::{bail, Result};
+
+use crate::cell::{cell_size, local_payload_size, IndexLeafCell};
+use crate::page::collect_cell_pointers;
+use crate::page_header::{BTreePage, PageHeader};
+use crate::pager::Pager;
+use crate::record::Value;
+use crate::varint::{encode_varint, parse_varint};
+
+/// A B-tree page decoded into its cells. Pages are written whole from their cells whenever they
+/// change, which leaves no free blocks nor fragmented bytes, as mentioned here:
+/// [b-tree pages](https://www.sqlite.org/fileformat2.html#b_tree_pages)
+pub struct Node {
+    pub page_type: BTreePage,
+    /// The cells of the page, without the left child pointer of interior cells
+    pub cells: Vec<Vec<u8>>,
+    /// The children of an interior page: the left child of each cell, then the right most pointer
+    pub children: Vec<u32>,
+}
+
+/// A page split off the page of a node, holding the cells after `divider`
+pub struct Split {
+    /// The cell of the parent separating the pages, without its left child pointer
+    pub divider: Vec<u8>,
+    pub page: u32,
+}
+
+/// The database header precedes the B-tree page of page 1
+fn header_offset(page_number: usize) -> usize {
+    if page_number == 1 {
+        100
+    } else {
+        0
+    }
+}
+
+impl Node {
+    pub fn read(pager: &Pager, page_number: usize) -> Result<Self> {
+        let page = pager.read_page(page_number)?;
+        let offset = header_offset(page_number);
+        let page_header = PageHeader::parse(&page[offset..])?;
+        let is_interior = page_header.right_most_pointer.is_some();
+        let mut cells = vec![];
+        let mut children = vec![];
+        for pointer in collect_cell_pointers(
+            &page[offset + page_header.size()..],
+            page_header.number_of_cells.into(),
+        ) {
+            let cell = &page[pointer as usize..];
+            let size = cell_size(cell, &page_header.page_type, pager.usable_size());
+            match is_interior {
+                true => {
+                    children.push(u32::from_be_bytes([cell[0], cell[1], cell[2], cell[3]]));
+                    cells.push(cell[4..size].to_vec());
+                }
+                false => cells.push(cell[..size].to_vec()),
+            }
+        }
+        children.extend(page_header.right_most_pointer);
+        Ok(Self {
+            page_type: page_header.page_type,
+            cells,
+            children,
+        })
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        matches!(self.page_type, BTreePage::LeafTable | BTreePage::LeafIndex)
+    }
+
+    fn header_size(&self) -> usize {
+        match self.is_leaf() {
+            true => 8,
+            false => 12,
+        }
+    }
+
+    /// The number of bytes a cell takes in the page, with its pointer
+    fn cell_cost(&self, cell: &[u8]) -> usize {
+        2 + cell.len() + if self.is_leaf() { 0 } else { 4 }
+    }
+
+    /// The number of bytes available to the cells of a page
+    fn capacity(&self, pager: &Pager, page_number: usize) -> usize {
+        pager.usable_size() - header_offset(page_number) - self.header_size()
+    }
+
+    fn fits(&self, pager: &Pager, page_number: usize) -> bool {
+        let size = self.cells.iter().map(|c| self.cell_cost(c)).sum::<usize>();
+        size <= self.capacity(pager, page_number)
+    }
+
+    /// Writes the node into a page, with its cells packed at the end of the usable space
+    pub fn write(&self, pager: &mut Pager, page_number: usize) -> Result<()> {
+        if !self.fits(pager, page_number) {
+            bail!("The cells do not fit in page {}", page_number);
+        }
+        let offset = header_offset(page_number);
+        let usable_size = pager.usable_size();
+        let mut page = match page_number {
+            1 => pager.read_page(1)?,
+            _ => vec![0; pager.page_size()],
+        };
+        page[offset..usable_size]
+            .iter_mut()
+            .for_each(|byte| *byte = 0);
+
+        let mut content_start = usable_size;
+        let mut pointer = offset + self.header_size();
+        for (i, cell) in self.cells.iter().enumerate() {
+            let size = self.cell_cost(cell) - 2;
+            content_start -= size;
+            let content = &mut page[content_start..content_start + size];
+            match self.is_leaf() {
+                true => content.copy_from_slice(cell),
+                false => {
+                    content[..4].copy_from_slice(&self.children[i].to_be_bytes());
+                    content[4..].copy_from_slice(cell);
+                }
+            }
+            page[pointer..pointer + 2].copy_from_slice(&(content_start as u16).to_be_bytes());
+            pointer += 2;
+        }
+
+        page[offset] = self.page_type as u8;
+        page[offset + 3..offset + 5].copy_from_slice(&(self.cells.len() as u16).to_be_bytes());
+        // A content area starting at 65536 is stored as 0
+        page[offset + 5..offset + 7].copy_from_slice(&(content_start as u16).to_be_bytes());
+        if !self.is_leaf() {
+            let right_most_pointer = self.children[self.cells.len()];
+            page[offset + 8..offset + 12].copy_from_slice(&right_most_pointer.to_be_bytes());
+        }
+        pager.write_page(page_number, &page)
+    }
+
+    /// Writes the node into its page, splitting it into new pages to its right when it does not
+    /// fit. `appended` tells the last cell was just added at the end, in which case it moves
+    /// alone to the new page so that rows inserted in order fill their pages
+    fn store(
+        mut self,
+        pager: &mut Pager,
+        page_number: usize,
+        appended: bool,
+    ) -> Result<Vec<Split>> {
+        if self.fits(pager, page_number) {
+            self.write(pager, page_number)?;
+            return Ok(vec![]);
+        }
+        let capacity = self.capacity(pager, page_number);
+        let costs = self
+            .cells
+            .iter()
+            .map(|c| self.cell_cost(c))
+            .collect::<Vec<_>>();
+
+        // Each split moves the cells after `end` to a new page, the cell at `end` going to the
+        // parent unless the leaf pages of tables keep every cell and copy rowids to the parent
+        let keeps_cells = self.page_type == BTreePage::LeafTable;
+        let fits = |cells: &[usize]| cells.iter().sum::<usize>() <= capacity;
+        let balanced = (1..costs.len())
+            .filter(|&end| {
+                let right = if keeps_cells { end } else { end + 1 };
+                fits(&costs[..end]) && right <= costs.len() && fits(&costs[right..])
+            })
+            .min_by_key(|&end| {
+                let left = costs[..end].iter().sum::<usize>();
+                let right = costs[end..].iter().sum::<usize>();
+                left.max(right)
+            });
+        let ends = match (appended && keeps_cells, balanced) {
+            (true, _) if fits(&costs[..costs.len() - 1]) => vec![costs.len() - 1],
+            (_, Some(end)) => vec![end],
+            (_, None) if keeps_cells => {
+                // Cells too large to be split in two are spread over as many pages as needed
+                let mut ends = vec![];
+                let mut size = 0;
+                for (i, cost) in costs.iter().enumerate() {
+                    if size + cost > capacity {
+                        ends.push(i);
+                        size = 0;
+                    }
+                    size += cost;
+                }
+                ends
+            }
+            (_, None) => bail!("Cannot split page {}", page_number),
+        };
+
+        let mut splits = vec![];
+        for &end in ends.iter().rev() {
+            let (divider, cells, children) = match keeps_cells {
+                true => {
+                    let rowid = leaf_rowid(&self.cells[end - 1]);
+                    (
+                        encode_varint(rowid as u64),
+                        self.cells.split_off(end),
+                        vec![],
+                    )
+                }
+                false => {
+                    let cells = self.cells.split_off(end + 1);
+                    let divider = self.cells.pop().unwrap_or_default();
+                    let children = match self.is_leaf() {
+                        true => vec![],
+                        false => self.children.split_off(end + 1),
+                    };
+                    (divider, cells, children)
+                }
+            };
+            let page = pager.allocate_page()?;
+            Node {
+                page_type: self.page_type,
+                cells,
+                children,
+            }
+            .write(pager, page)?;
+            splits.push(Split {
+                divider,
+                page: page as u32,
+            });
+        }
+        splits.reverse();
+        self.write(pager, page_number)?;
+        Ok(splits)
+    }
+
+    /// Adds the pages split off the child at `position` to an interior node
+    fn insert_splits(&mut self, position: usize, splits: Vec<Split>) {
+        for (i, split) in splits.into_iter().enumerate() {
+            self.cells.insert(position + i, split.divider);
+            self.children.insert(position + i + 1, split.page);
+        }
+    }
+
+    /// Whether the cells of the node fill less than a third of a page, in which case it gets
+    /// merged with a sibling or takes some of its cells
+    fn is_underfull(&self, pager: &Pager, page_number: usize) -> bool {
+        let size = self.cells.iter().map(|c| self.cell_cost(c)).sum::<usize>();
+        size < self.capacity(pager, page_number) / 3
+    }
+
+    /// Appends the cells of the node to the right of this one, the divider separating them in
+    /// their parent coming down between them. Table leaves do not need it, as their cells hold
+    /// their rowids
+    fn join(mut self, divider: Vec<u8>, mut right: Node) -> Self {
+        if self.page_type != BTreePage::LeafTable {
+            self.cells.push(divider);
+        }
+        self.cells.append(&mut right.cells);
+        self.children.append(&mut right.children);
+        self
+    }
+
+    /// Rebalances the child at `position` of an interior node with a sibling: both are merged
+    /// into one page, which splits again when their cells do not fit in a single page
+    fn rebalance(&mut self, pager: &mut Pager, position: usize) -> Result<()> {
+        if self.cells.is_empty() {
+            return Ok(());
+        }
+        let left = position.min(self.cells.len() - 1);
+        let left_page = self.children[left] as usize;
+        let right_page = self.children.remove(left + 1) as usize;
+        let divider = self.cells.remove(left);
+        let merged = Node::read(pager, left_page)?.join(divider, Node::read(pager, right_page)?);
+        pager.free_page(right_page)?;
+        let splits = merged.store(pager, left_page, false)?;
+        self.insert_splits(left, splits);
+        Ok(())
+    }
+}
+
+/// Removes the cell at `index` from a page in place, as mentioned here:
+/// [b-tree pages](https://www.sqlite.org/fileformat2.html#b_tree_pages)
+/// Its bytes join the free blocks of the page, which are merged when adjacent or separated by
+/// fragmented bytes, and given back to the unallocated space when at the start of the content
+fn drop_cell(page: &mut [u8], offset: usize, index: usize, usable_size: usize) -> Result<()> {
+    let page_header = PageHeader::parse(&page[offset..])?;
+    let pointers_start = offset + page_header.size();
+    let pointers_end = pointers_start + 2 * page_header.number_of_cells as usize;
+    let pointer = pointers_start + 2 * index;
+    let start = u16::from_be_bytes([page[pointer], page[pointer + 1]]) as usize;
+    if start < pointers_end || start >= usable_size {
+        bail!("database disk image is malformed");
+    }
+    let size = cell_size(&page[start..], &page_header.page_type, usable_size);
+    if start + size > usable_size {
+        bail!("database disk image is malformed");
+    }
+    page.copy_within(pointer + 2..pointers_end, pointer);
+    page[pointers_end - 2..pointers_end].copy_from_slice(&[0, 0]);
+
+    // Free blocks are chained in increasing order and take at least four bytes each, so a chain
+    // going back or longer than a quarter of the page is corrupt rather than followed forever
+    let mut blocks = vec![(start, size)];
+    let mut previous = 0;
+    let mut next = page_header.first_free_block_start as usize;
+    while next != 0 {
+        if next <= previous || next < pointers_end || next + 4 > usable_size {
+            bail!("database disk image is malformed");
+        }
+        let block_size = u16::from_be_bytes([page[next + 2], page[next + 3]]) as usize;
+        if block_size < 4 || next + block_size > usable_size || blocks.len() > usable_size / 4 {
+            bail!("database disk image is malformed");
+        }
+        blocks.push((next, block_size));
+        previous = next;
+        next = u16::from_be_bytes([page[next], page[next + 1]]) as usize;
+    }
+    blocks.sort_unstable();
+    let mut fragmented = page_header.fragmented_free_bytes as usize;
+    let mut merged: Vec<(usize, usize)> = vec![];
+    for (start, size) in blocks {
+        match merged.last_mut() {
+            Some((last, last_size)) if start < *last + *last_size => {
+                bail!("database disk image is malformed")
+            }
+            // No cell fits in less than four bytes, so smaller gaps are fragmented bytes
+            Some((last, last_size)) if *last + *last_size + 3 >= start => {
+                fragmented = fragmented.saturating_sub(start - (*last + *last_size));
+                *last_size = start + size - *last;
+            }
+            _ => merged.push((start, size)),
+        }
+    }
+    // A content area starting at 65536 is stored as 0
+    let mut content_start = match page_header.start_of_content_area {
+        0 => 65536,
+        start => start as usize,
+    };
+    if merged.first().map(|&(start, _)| start) == Some(content_start) {
+        content_start += merged.remove(0).1;
+    }
+
+    let mut previous = offset + 1;
+    for &(start, size) in &merged {
+        page[previous..previous + 2].copy_from_slice(&(start as u16).to_be_bytes());
+        page[start + 2..start + 4].copy_from_slice(&(size as u16).to_be_bytes());
+        previous = start;
+    }
+    page[previous..previous + 2].copy_from_slice(&[0, 0]);
+    let cell_count = page_header.number_of_cells - 1;
+    page[offset + 3..offset + 5].copy_from_slice(&cell_count.to_be_bytes());
+    page[offset + 5..offset + 7].copy_from_slice(&(content_start as u16).to_be_bytes());
+    page[offset + 7] = fragmented as u8;
+    Ok(())
+}
+
+/// Returns the first overflow page of a cell, without its left child pointer if any
+fn overflow_page(cell: &[u8], page_type: BTreePage, usable_size: usize) -> Option<u32> {
+    let (payload_size, mut offset) = parse_varint(cell);
+    match page_type {
+        BTreePage::LeafTable => offset += parse_varint(&cell[offset..]).1,
+        BTreePage::InteriorTable => return None,
+        _ => {}
+    }
+    let local_size = local_payload_size(payload_size, usable_size, &page_type);
+    let pointer = &cell[offset + local_size..];
+    (local_size < payload_size)
+        .then(|| u32::from_be_bytes([pointer[0], pointer[1], pointer[2], pointer[3]]))
+}
+
+/// Returns the rowid of a cell of a table leaf page
+fn leaf_rowid(cell: &[u8]) -> i64 {
+    let (_, payload_size_len) = parse_varint(cell);
+    parse_varint(&cell[payload_size_len..]).0 as i64
+}
+
+/// Returns the rowid of a cell of a table interior page, without its left child pointer
+fn interior_key(cell: &[u8]) -> i64 {
+    parse_varint(cell).0 as i64
+}
+
+/// Inserts a cell into the table B-tree rooted at `root`, which must not hold its rowid yet
+pub fn insert_into_table(pager: &mut Pager, root: usize, rowid: i64, cell: Vec<u8>) -> Result<()> {
+    let splits = insert_into_table_node(pager, root, rowid, cell, true)?;
+    grow_root(pager, root, splits)
+}
+
+/// Adds the pages split off the root of a B-tree under it. The root keeps its page number, by
+/// moving its cells to a new child
+fn grow_root(pager: &mut Pager, root: usize, mut splits: Vec<Split>) -> Result<()> {
+    while !splits.is_empty() {
+        let node = Node::read(pager, root)?;
+        let page_type = match node.page_type {
+            BTreePage::LeafTable | BTreePage::InteriorTable => BTreePage::InteriorTable,
+            BTreePage::LeafIndex | BTreePage::InteriorIndex => BTreePage::InteriorIndex,
+        };
+        let left = pager.allocate_page()?;
+        node.write(pager, left)?;
+        let mut root_node = Node {
+            page_type,
+            cells: vec![],
+            children: vec![left as u32],
+        };
+        root_node.insert_splits(0, splits);
+        splits = root_node.store(pager, root, false)?;
+    }
+    Ok(())
+}
+
+/// Moves the cells of the only child of a root left without cells into the root, so that the
+/// B-tree shrinks back as its rows are deleted
+fn shrink_root(pager: &mut Pager, root: usize) -> Result<()> {
+    loop {
+        let node = Node::read(pager, root)?;
+        if !node.cells.is_empty() || node.is_leaf() {
+            return Ok(());
+        }
+        let child = node.children[0] as usize;
+        let child_node = Node::read(pager, child)?;
+        if !child_node.fits(pager, root) {
+            return Ok(());
+        }
+        child_node.write(pager, root)?;
+        pager.free_page(child)?;
+    }
+}
+
+/// Writes back an interior node after a deletion under its child at `position`: the pages split
+/// off the child are added, and the child is rebalanced when left underfull
+fn settle_child(
+    mut node: Node,
+    pager: &mut Pager,
+    page_number: usize,
+    position: usize,
+    splits: Vec<Split>,
+    changed: bool,
+) -> Result<Vec<Split>> {
+    let changed = changed || !splits.is_empty();
+    let child = node.children[position] as usize;
+    node.insert_splits(position, splits);
+    if Node::read(pager, child)?.is_underfull(pager, child) {
+        node.rebalance(pager, position)?;
+    } else if !changed {
+        return Ok(vec![]);
+    }
+    node.store(pager, page_number, false)
+}
+
+/// Removes a cell of a leaf page in place, freeing its overflow pages unless it moves elsewhere
+fn remove_leaf_cell(
+    pager: &mut Pager,
+    page_number: usize,
+    node: &Node,
+    index: usize,
+    free_overflow: bool,
+) -> Result<()> {
+    let overflow = overflow_page(&node.cells[index], node.page_type, pager.usable_size());
+    let mut page = pager.read_page(page_number)?;
+    drop_cell(
+        &mut page,
+        header_offset(page_number),
+        index,
+        pager.usable_size(),
+    )?;
+    pager.write_page(page_number, &page)?;
+    match overflow {
+        Some(overflow) if free_overflow => pager.free_overflow(overflow),
+        _ => Ok(()),
+    }
+}
+
+fn insert_into_table_node(
+    pager: &mut Pager,
+    page_number: usize,
+    rowid: i64,
+    cell: Vec<u8>,
+    is_right_most: bool,
+) -> Result<Vec<Split>> {
+    let mut node = Node::read(pager, page_number)?;
+    if node.is_leaf() {
+        let position = node.cells.partition_point(|c| leaf_rowid(c) < rowid);
+        let appended = is_right_most && position == node.cells.len();
+        node.cells.insert(position, cell);
+        return node.store(pager, page_number, appended);
+    }
+
+    let position = node.cells.partition_point(|c| interior_key(c) < rowid);
+    let child = node.children[position] as usize;
+    let is_right_most = is_right_most && position == node.cells.len();
+    let splits = insert_into_table_node(pager, child, rowid, cell, is_right_most)?;
+    if splits.is_empty() {
+        return Ok(vec![]);
+    }
+    node.insert_splits(position, splits);
+    node.store(pager, page_number, false)
+}
+
+/// Returns the largest rowid of the table B-tree rooted at `root`
+pub fn max_rowid(pager: &Pager, root: usize) -> Result<Option<i64>> {
+    let node = Node::read(pager, root)?;
+    match node.is_leaf() {
+        true => Ok(node.cells.last().map(|cell| leaf_rowid(cell))),
+        false => max_rowid(pager, node.children[node.cells.len()] as usize),
+    }
+}
+
+/// Returns the number of entries of the B-tree rooted at `root`. Those of a table are in its
+/// leaves, while the interior cells of an index are entries too
+pub fn count_entries(pager: &Pager, root: usize) -> Result<usize> {
+    let node = Node::read(pager, root)?;
+    let mut count = match node.page_type {
+        BTreePage::InteriorTable => 0,
+        _ => node.cells.len(),
+    };
+    for &child in &node.children {
+        count += count_entries(pager, child as usize)?;
+    }
+    Ok(count)
+}
+
+/// Whether the table B-tree rooted at `root` holds a row with the given rowid
+pub fn contains_rowid(pager: &Pager, root: usize, rowid: i64) -> Result<bool> {
+    let node = Node::read(pager, root)?;
+    match node.is_leaf() {
+        true => Ok(node.cells.iter().any(|cell| leaf_rowid(cell) == rowid)),
+        false => {
+            let position = node.cells.partition_point(|c| interior_key(c) < rowid);
+            contains_rowid(pager, node.children[position] as usize, rowid)
+        }
+    }
+}
+
+/// Builds the cell of a table leaf page holding a row, storing the end of its record in overflow
+/// pages when the record is too large for the page
+pub fn table_leaf_cell(pager: &mut Pager, rowid: i64, record: &[u8]) -> Result<Vec<u8>> {
+    let local_size = local_payload_size(record.len(), pager.usable_size(), &BTreePage::LeafTable);
+    let mut cell = encode_varint(record.len() as u64);
+    cell.extend(encode_varint(rowid as u64));
+    cell.extend_from_slice(&record[..local_size]);
+    if local_size < record.len() {
+        let overflow = pager.write_overflow(&record[local_size..])?;
+        cell.extend_from_slice(&overflow.to_be_bytes());
+    }
+    Ok(cell)
+}
+
+/// Deletes the row with the given rowid from the table B-tree rooted at `root`, returning whether
+/// it was found. Pages left underfull are rebalanced with a sibling, and the tree shrinks back
+/// when its root is left with a single child
+pub fn delete_from_table(pager: &mut Pager, root: usize, rowid: i64) -> Result<bool> {
+    if !contains_rowid(pager, root, rowid)? {
+        return Ok(false);
+    }
+    let splits = delete_from_table_node(pager, root, rowid)?;
+    grow_root(pager, root, splits)?;
+    shrink_root(pager, root)?;
+    Ok(true)
+}
+
+fn delete_from_table_node(pager: &mut Pager, page_number: usize, rowid: i64) -> Result<Vec<Split>> {
+    let node = Node::read(pager, page_number)?;
+    if node.is_leaf() {
+        if let Some(index) = node.cells.iter().position(|c| leaf_rowid(c) == rowid) {
+            remove_leaf_cell(pager, page_number, &node, index, true)?;
+        }
+        return Ok(vec![]);
+    }
+
+    let position = node.cells.partition_point(|c| interior_key(c) < rowid);
+    let splits = delete_from_table_node(pager, node.children[position] as usize, rowid)?;
+    settle_child(node, pager, page_number, position, splits, false)
+}
+
+/// Compares an entry of an index with the entry looked for
+pub type EntryOrder<'a> = dyn Fn(&[Value]) -> Ordering + 'a;
+
+/// Reads the entry of a cell of an index page, without its left child pointer if any
+fn read_entry(pager: &Pager, cell: &[u8]) -> Result<Vec<Value>> {
+    IndexLeafCell::parse(cell, pager.usable_size()).get_record(pager, usize::MAX)
+}
+
+/// Returns the position of the first cell of an index page whose entry is not less than the
+/// entry looked for
+fn entry_position(pager: &Pager, node: &Node, order: &EntryOrder) -> Result<usize> {
+    let (mut low, mut high) = (0, node.cells.len());
+    while low < high {
+        let middle = (low + high) / 2;
+        match order(&read_entry(pager, &node.cells[middle])?) {
+            Ordering::Less => low = middle + 1,
+            _ => high = middle,
+        }
+    }
+    Ok(low)
+}
+
+/// Builds the cell of an index leaf page holding an entry, storing the end of its record in
+/// overflow pages when the record is too large for the page
+pub fn index_leaf_cell(pager: &mut Pager, record: &[u8]) -> Result<Vec<u8>> {
+    let local_size = local_payload_size(record.len(), pager.usable_size(), &BTreePage::LeafIndex);
+    let mut cell = encode_varint(record.len() as u64);
+    cell.extend_from_slice(&record[..local_size]);
+    if local_size < record.len() {
+        let overflow = pager.write_overflow(&record[local_size..])?;
+        cell.extend_from_slice(&overflow.to_be_bytes());
+    }
+    Ok(cell)
+}
+
+/// Returns the entries of the index B-tree rooted at `page_number` that `order` finds equal to
+/// the entry looked for, in the order of the index
+pub fn search_index(
+    pager: &Pager,
+    page_number: usize,
+    order: &EntryOrder,
+) -> Result<Vec<Vec<Value>>> {
+    let mut entries = vec![];
+    search_index_node(pager, page_number, order, &mut entries)?;
+    Ok(entries)
+}
+
+fn search_index_node(
+    pager: &Pager,
+    page_number: usize,
+    order: &EntryOrder,
+    entries: &mut Vec<Vec<Value>>,
+) -> Result<()> {
+    let node = Node::read(pager, page_number)?;
+    // Equal entries may span several children, up to the first greater entry
+    for (i, cell) in node.cells.iter().enumerate() {
+        let entry = read_entry(pager, cell)?;
+        let ordering = order(&entry);
+        if !node.is_leaf() && ordering != Ordering::Less {
+            search_index_node(pager, node.children[i] as usize, order, entries)?;
+        }
+        match ordering {
+            Ordering::Greater => return Ok(()),
+            Ordering::Equal => entries.push(entry),
+            Ordering::Less => {}
+        }
+    }
+    match node.children.last() {
+        Some(&right) => search_index_node(pager, right as usize, order, entries),
+        None => Ok(()),
+    }
+}
+
+/// Inserts a cell into the index B-tree rooted at `root`, before the first entry `order` finds
+/// greater than its own
+pub fn insert_into_index(
+    pager: &mut Pager,
+    root: usize,
+    cell: Vec<u8>,
+    order: &EntryOrder,
+) -> Result<()> {
+    let splits = insert_into_index_node(pager, root, cell, order)?;
+    grow_root(pager, root, splits)
+}
+
+fn insert_into_index_node(
+    pager: &mut Pager,
+    page_number: usize,
+    cell: Vec<u8>,
+    order: &EntryOrder,
+) -> Result<Vec<Split>> {
+    let mut node = Node::read(pager, page_number)?;
+    let position = entry_position(pager, &node, order)?;
+    if node.is_leaf() {
+        node.cells.insert(position, cell);
+        return node.store(pager, page_number, false);
+    }
+    let splits = insert_into_index_node(pager, node.children[position] as usize, cell, order)?;
+    if splits.is_empty() {
+        return Ok(vec![]);
+    }
+    node.insert_splits(position, splits);
+    node.store(pager, page_number, false)
+}
+
+/// Deletes the entry `order` finds equal from the index B-tree rooted at `root`, returning
+/// whether it was found. An entry of an interior page is replaced by the largest entry of the
+/// child before it
+pub fn delete_from_index(pager: &mut Pager, root: usize, order: &EntryOrder) -> Result<bool> {
+    let (found, splits) = delete_from_index_node(pager, root, order)?;
+    grow_root(pager, root, splits)?;
+    shrink_root(pager, root)?;
+    Ok(found)
+}
+
+fn delete_from_index_node(
+    pager: &mut Pager,
+    page_number: usize,
+    order: &EntryOrder,
+) -> Result<(bool, Vec<Split>)> {
+    let mut node = Node::read(pager, page_number)?;
+    let position = entry_position(pager, &node, order)?;
+    let is_match = match node.cells.get(position) {
+        Some(cell) => order(&read_entry(pager, cell)?) == Ordering::Equal,
+        None => false,
+    };
+    if node.is_leaf() {
+        if is_match {
+            remove_leaf_cell(pager, page_number, &node, position, true)?;
+        }
+        return Ok((is_match, vec![]));
+    }
+
+    let child = node.children[position] as usize;
+    let (found, splits) = match is_match {
+        true => {
+            let (cell, splits) = remove_last_entry(pager, child)?;
+            let removed = std::mem::replace(&mut node.cells[position], cell);
+            if let Some(overflow) = overflow_page(&removed, node.page_type, pager.usable_size()) {
+                pager.free_overflow(overflow)?;
+            }
+            (true, splits)
+        }
+        false => delete_from_index_node(pager, child, order)?,
+    };
+    let splits = settle_child(node, pager, page_number, position, splits, is_match)?;
+    Ok((found, splits))
+}
+
+/// Removes the largest entry of an index B-tree, returning its cell
+fn remove_last_entry(pager: &mut Pager, page_number: usize) -> Result<(Vec<u8>, Vec<Split>)> {
+    let node = Node::read(pager, page_number)?;
+    let position = node.cells.len();
+    if node.is_leaf() {
+        if position == 0 {
+            bail!("Index page {} has no entry", page_number);
+        }
+        let cell = node.cells[position - 1].clone();
+        remove_leaf_cell(pager, page_number, &node, position - 1, false)?;
+        return Ok((cell, vec![]));
+    }
+    let (cell, splits) = remove_last_entry(pager, node.children[position] as usize)?;
+    let splits = settle_child(node, pager, page_number, position, splits, false)?;
+    Ok((cell, splits))
+}
+
+/// Allocates the root page of a new empty B-tree, whose leaf pages are of the given type
+pub fn create_tree(pager: &mut Pager, page_type: BTreePage) -> Result<usize> {
+    let root = pager.allocate_page()?;
+    let node = Node {
+        page_type,
+        cells: vec![],
+        children: vec![],
+    };
+    node.write(pager, root)?;
+    Ok(root)
+}
+
+/// Adds all the pages of the B-tree rooted at `page_number` to the freelist, with the overflow
+/// pages of its cells
+pub fn free_tree(pager: &mut Pager, page_number: usize) -> Result<()> {
+    let node = Node::read(pager, page_number)?;
+    for cell in &node.cells {
+        if let Some(overflow) = overflow_page(cell, node.page_type, pager.usable_size()) {
+            pager.free_overflow(overflow)?;
+        }
+    }
+    for &child in &node.children {
+        free_tree(pager, child as usize)?;
+    }
+    pager.free_page(page_number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 512-byte table leaf page holding three 8-byte cells at its end
+    fn leaf_page() -> Vec<u8> {
+        let mut page = vec![0; 512];
+        page[0] = 0x0d;
+        page[3..5].copy_from_slice(&3u16.to_be_bytes());
+        page[5..7].copy_from_slice(&488u16.to_be_bytes());
+        for (i, start) in [488u16, 496, 504].iter().enumerate() {
+            page[8 + 2 * i..10 + 2 * i].copy_from_slice(&start.to_be_bytes());
+            let start = *start as usize;
+            page[start..start + 2].copy_from_slice(&[6, i as u8 + 1]);
+        }
+        page
+    }
+
+    #[test]
+    fn dropped_cells_become_free_blocks_or_unallocated_space() {
+        let mut page = leaf_page();
+        drop_cell(&mut page, 0, 1, 512).unwrap();
+        let header = PageHeader::parse(&page).unwrap();
+        assert_eq!(header.number_of_cells, 2);
+        assert_eq!(header.first_free_block_start, 496);
+        assert_eq!(page[496..500], [0, 0, 0, 8]);
+        assert_eq!(page[8..14], [1, 232, 1, 248, 0, 0]);
+
+        // The free block joins the cell before it, and both go back to the unallocated space
+        drop_cell(&mut page, 0, 0, 512).unwrap();
+        let header = PageHeader::parse(&page).unwrap();
+        assert_eq!(header.number_of_cells, 1);
+        assert_eq!(header.first_free_block_start, 0);
+        assert_eq!(header.start_of_content_area, 504);
+    }
+
+    #[test]
+    fn corrupt_free_block_chains_are_errors() {
+        // A block pointing back at itself, or past the end of the page
+        for (block, next, size) in [(496u16, 496u16, 8u16), (496, 0, 100), (508, 0, 8)] {
+            let mut page = leaf_page();
+            page[1..3].copy_from_slice(&block.to_be_bytes());
+            let block = block as usize;
+            page[block..block + 2].copy_from_slice(&next.to_be_bytes());
+            page[block + 2..block + 4].copy_from_slice(&size.to_be_bytes());
+            let err = drop_cell(&mut page, 0, 0, 512).unwrap_err();
+            assert_eq!(err.to_string(), "database disk image is malformed");
+        }
+
+        // Fragmented bytes the header does not count are not subtracted below zero
+        let mut page = leaf_page();
+        page[1..3].copy_from_slice(&498u16.to_be_bytes());
+        page[498..502].copy_from_slice(&[0, 0, 0, 6]);
+        page[3..5].copy_from_slice(&2u16.to_be_bytes());
+        page[10..12].copy_from_slice(&504u16.to_be_bytes());
+        drop_cell(&mut page, 0, 0, 512).unwrap();
+        assert_eq!(PageHeader::parse(&page).unwrap().fragmented_free_bytes, 0);
+    }
+}
@@ -1,34 +1,113 @@
+use std::convert::TryInto;
+
 use anyhow::Result;
 
+use crate::page_header::BTreePage;
+use crate::pager::Pager;
 use crate::record::{parse_record, Value};
 use crate::varint::parse_varint;
 
+/// The part of a cell holding a payload: the payload may be too large for the page, in which
+/// case the cell only holds its beginning and the number of the overflow page holding the rest
+pub struct Payload<'a> {
+    size: usize,
+    local: &'a [u8],
+    overflow: Option<u32>,
+}
+
+impl<'a> Payload<'a> {
+    /// Parses a payload of `size` bytes, for a B-tree page of the given type
+    fn parse(stream: &'a [u8], size: usize, page_type: &BTreePage, usable_size: usize) -> Self {
+        let local_size = local_payload_size(size, usable_size, page_type);
+        let overflow = (local_size < size)
+            .then(|| u32::from_be_bytes(stream[local_size..local_size + 4].try_into().unwrap()));
+        Self {
+            size,
+            local: &stream[..local_size],
+            overflow,
+        }
+    }
+
+    /// The number of bytes the payload takes in its cell
+    fn len(&self) -> usize {
+        self.local.len() + self.overflow.map_or(0, |_| 4)
+    }
+
+    fn get_record(&self, pager: &Pager, column_count: usize) -> Result<Vec<Value>> {
+        match self.overflow {
+            None => parse_record(self.local, column_count),
+            overflow => parse_record(
+                &pager.read_payload(self.local, self.size, overflow)?,
+                column_count,
+            ),
+        }
+    }
+}
+
+/// Returns how many bytes of a payload are stored in its cell, the rest going to overflow pages
+pub fn local_payload_size(payload_size: usize, usable_size: usize, page_type: &BTreePage) -> usize {
+    let max_local = match page_type {
+        BTreePage::LeafTable => usable_size - 35,
+        _ => (usable_size - 12) * 64 / 255 - 23,
+    };
+    if payload_size <= max_local {
+        return payload_size;
+    }
+    let min_local = (usable_size - 12) * 32 / 255 - 23;
+    let local = min_local + (payload_size - min_local) % (usable_size - 4);
+    if local <= max_local {
+        local
+    } else {
+        min_local
+    }
+}
+
+/// Returns the number of bytes of the cell starting `stream`, in a page of the given type
+pub fn cell_size(stream: &[u8], page_type: &BTreePage, usable_size: usize) -> usize {
+    match page_type {
+        BTreePage::InteriorTable => 4 + parse_varint(&stream[4..]).1,
+        BTreePage::LeafTable => {
+            let (payload_size, payload_size_len) = parse_varint(stream);
+            let (_, rowid_len) = parse_varint(&stream[payload_size_len..]);
+            let offset = payload_size_len + rowid_len;
+            let payload = Payload::parse(&stream[offset..], payload_size, page_type, usable_size);
+            offset + payload.len()
+        }
+        BTreePage::LeafIndex => IndexLeafCell::parse(stream, usable_size).size(),
+        BTreePage::InteriorIndex => 4 + IndexLeafCell::parse(&stream[4..], usable_size).size(),
+    }
+}
+
 pub struct TableLeafCell<'a> {
-    rowid: usize,
-    payload: &'a [u8],
+    pub rowid: i64,
+    payload: Payload<'a>,
 }
 impl<'a> TableLeafCell<'a> {
-    pub fn parse(stream: &'a [u8]) -> Self {
+    pub fn parse(stream: &'a [u8], usable_size: usize) -> Self {
         let (payload_size, payload_size_len) = parse_varint(stream);
         let (rowid, rowid_len) = parse_varint(&stream[payload_size_len..]);
         let offset = payload_size_len + rowid_len;
-        let payload = &stream[offset..offset + payload_size];
-        Self { rowid, payload }
+        let payload = Payload::parse(
+            &stream[offset..],
+            payload_size,
+            &BTreePage::LeafTable,
+            usable_size,
+        );
+        // Varints hold rowids as two's complement, negative ones taking all nine bytes
+        Self {
+            rowid: rowid as i64,
+            payload,
+        }
     }
 
-    pub fn get_record(&self, column_count: usize) -> Result<Vec<Value>> {
-        parse_record(self.payload, column_count).map(|mut v| {
-            if v[0] == Value::Null {
-                v[0] = Value::I64(self.rowid as i64);
-            }
-            v
-        })
+    pub fn get_record(&self, pager: &Pager, column_count: usize) -> Result<Vec<Value>> {
+        self.payload.get_record(pager, column_count)
     }
 }
 
 pub struct TableInteriorCell {
     pub left_child_page: usize,
-    pub key: usize,
+    pub key: i64,
 }
 
 impl TableInteriorCell {
@@ -38,42 +117,55 @@ impl TableInteriorCell {
         let (key, _read_bytes) = parse_varint(&stream[4..]);
         Self {
             left_child_page,
-            key,
+            key: key as i64,
         }
     }
 }
 
 pub struct IndexLeafCell<'a> {
-    payload: &'a [u8],
+    payload_size_len: usize,
+    payload: Payload<'a>,
 }
 impl<'a> IndexLeafCell<'a> {
-    pub fn parse(stream: &'a [u8]) -> Self {
-        let (payload_size, payload_size_bytes) = parse_varint(stream);
-        let payload = &stream[payload_size_bytes..payload_size_bytes + payload_size];
-        Self { payload }
+    pub fn parse(stream: &'a [u8], usable_size: usize) -> Self {
+        let (payload_size, payload_size_len) = parse_varint(stream);
+        let payload = Payload::parse(
+            &stream[payload_size_len..],
+            payload_size,
+            &BTreePage::LeafIndex,
+            usable_size,
+        );
+        Self {
+            payload_size_len,
+            payload,
+        }
     }
 
-    pub fn get_record(&self, column_count: usize) -> Result<Vec<Value>> {
-        parse_record(self.payload, column_count)
+    fn size(&self) -> usize {
+        self.payload_size_len + self.payload.len()
+    }
+
+    pub fn get_record(&self, pager: &Pager, column_count: usize) -> Result<Vec<Value>> {
+        self.payload.get_record(pager, column_count)
     }
 }
 
 pub struct IndexInteriorCell<'a> {
     pub left_child_page: usize,
-    payload: &'a [u8],
+    entry: IndexLeafCell<'a>,
 }
 
 impl<'a> IndexInteriorCell<'a> {
-    pub fn parse(stream: &'a [u8]) -> Self {
+    pub fn parse(stream: &'a [u8], usable_size: usize) -> Self {
         let left_child_page =
             u32::from_be_bytes([stream[0], stream[1], stream[2], stream[3]]) as usize;
-        let (payload_size, key_bytes) = parse_varint(&stream[4..]);
         Self {
             left_child_page,
-            payload: &stream[4 + key_bytes..4 + key_bytes + payload_size],
+            entry: IndexLeafCell::parse(&stream[4..], usable_size),
         }
     }
-    pub fn get_record(&self, column_count: usize) -> Result<Vec<Value>> {
-        parse_record(self.payload, column_count)
+
+    pub fn get_record(&self, pager: &Pager, column_count: usize) -> Result<Vec<Value>> {
+        self.entry.get_record(pager, column_count)
     }
 }
@@ -0,0 +1,44 @@
+use std::cmp::Ordering;
+
+use crate::record::Value;
+
+/// Compares two texts for a collating sequence registered by the application
+pub type CollationFn = dyn Fn(&str, &str) -> Ordering;
+
+/// A collating sequence, which orders text values, as mentioned here:
+/// [collation](https://www.sqlite.org/datatype3.html#collation)
+#[derive(Clone, Copy)]
+pub enum Collation<'a> {
+    Binary,
+    NoCase,
+    RTrim,
+    Custom(&'a CollationFn),
+}
+
+impl<'a> Collation<'a> {
+    /// Finds one of the built-in collating sequences
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "binary" => Some(Collation::Binary),
+            "nocase" => Some(Collation::NoCase),
+            "rtrim" => Some(Collation::RTrim),
+            _ => None,
+        }
+    }
+
+    /// Orders values like `Value::compare`, except that text is ordered by the collating sequence
+    pub fn compare(&self, a: &Value, b: &Value) -> Ordering {
+        match (self, a, b) {
+            (Collation::Binary, _, _) => a.compare(b),
+            (Collation::NoCase, Value::Text(a), Value::Text(b)) => a
+                .bytes()
+                .map(|c| c.to_ascii_lowercase())
+                .cmp(b.bytes().map(|c| c.to_ascii_lowercase())),
+            (Collation::RTrim, Value::Text(a), Value::Text(b)) => {
+                a.trim_end_matches(' ').cmp(b.trim_end_matches(' '))
+            }
+            (Collation::Custom(compare), Value::Text(a), Value::Text(b)) => compare(a, b),
+            _ => a.compare(b),
+        }
+    }
+}
@@ -0,0 +1,692 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+
+use crate::record::{Affinity, Value};
+
+const MS_PER_DAY: i64 = 86_400_000;
+/// Julian day of the Unix epoch, in milliseconds
+const UNIX_EPOCH_JD_MS: i64 = 210_866_760_000_000;
+/// Julian day of 9999-12-31 23:59:59.999, in milliseconds
+const MAX_JD_MS: i64 = 464_269_060_799_999;
+/// Unix times, in seconds, of the first and last valid Julian days
+const UNIX_SECONDS: std::ops::RangeInclusive<f64> = -210_866_760_000.0..=253_402_300_799.0;
+
+/// A point in time, kept like SQLite does as milliseconds since the Julian day epoch
+#[derive(Debug, Clone, Copy)]
+struct DateTime {
+    jd_ms: i64,
+    /// The numeric argument the time value was given as, until a modifier interprets it
+    raw_number: Option<f64>,
+    subsec: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Ymd {
+    year: i64,
+    month: i64,
+    day: i64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Hms {
+    hour: i64,
+    minute: i64,
+    /// Seconds including the milliseconds
+    second: f64,
+}
+
+/// Calls one of SQLite's date and time functions, as mentioned here:
+/// [lang_datefunc](https://www.sqlite.org/lang_datefunc.html)
+pub fn call(name: &str, args: &[Value]) -> Result<Value> {
+    let (format, args) = match (name, args) {
+        ("strftime", [format, args @ ..]) => (Some(format), args),
+        ("strftime", []) => return Ok(Value::Null),
+        (_, args) => (None, args),
+    };
+    let dt = match evaluate(args) {
+        Some(dt) => dt,
+        None => return Ok(Value::Null),
+    };
+
+    let value = match name {
+        "date" => Value::Text(dt.format("%Y-%m-%d")),
+        "time" if dt.subsec => Value::Text(dt.format("%H:%M:%f")),
+        "time" => Value::Text(dt.format("%H:%M:%S")),
+        "datetime" if dt.subsec => Value::Text(dt.format("%Y-%m-%d %H:%M:%f")),
+        "datetime" => Value::Text(dt.format("%Y-%m-%d %H:%M:%S")),
+        "julianday" => Value::F(dt.julian_day()),
+        "unixepoch" if dt.subsec => Value::F((dt.jd_ms - UNIX_EPOCH_JD_MS) as f64 / 1000.0),
+        "unixepoch" => Value::Integer((dt.jd_ms - UNIX_EPOCH_JD_MS).div_euclid(1000)),
+        _ => match format {
+            Some(Value::Null) | None => Value::Null,
+            Some(format) => Value::Text(dt.format(&format.to_text())),
+        },
+    };
+    Ok(value)
+}
+
+pub fn is_date_function(name: &str) -> bool {
+    matches!(
+        name,
+        "date" | "time" | "datetime" | "julianday" | "unixepoch" | "strftime"
+    )
+}
+
+/// Reads a time value and applies the modifiers following it, `None` if any of them is invalid
+fn evaluate(args: &[Value]) -> Option<DateTime> {
+    let mut dt = match args.first() {
+        None => DateTime::now(),
+        Some(value) => DateTime::parse(value)?,
+    };
+    for modifier in args.iter().skip(1) {
+        match modifier {
+            Value::Null => return None,
+            modifier => dt.modify(&modifier.to_text())?,
+        }
+    }
+    dt.raw_number = None;
+    if (0..=MAX_JD_MS).contains(&dt.jd_ms) {
+        Some(dt)
+    } else {
+        None
+    }
+}
+
+impl DateTime {
+    fn now() -> Self {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default();
+        DateTime {
+            jd_ms: UNIX_EPOCH_JD_MS + since_epoch,
+            raw_number: None,
+            subsec: false,
+        }
+    }
+
+    fn from_julian_day(day: f64) -> Self {
+        DateTime {
+            jd_ms: (day * MS_PER_DAY as f64).round() as i64,
+            raw_number: Some(day),
+            subsec: false,
+        }
+    }
+
+    fn from_parts(ymd: Ymd, hms: Hms) -> Self {
+        let (mut year, mut month) = (ymd.year, ymd.month);
+        if month <= 2 {
+            year -= 1;
+            month += 12;
+        }
+        let a = year / 100;
+        let b = 2 - a + a / 4;
+        let x1 = 36525 * (year + 4716) / 100;
+        let x2 = 306001 * (month + 1) / 10000;
+        let days = (x1 + x2 + ymd.day + b) as f64 - 1524.5;
+        let jd_ms = (days * MS_PER_DAY as f64) as i64
+            + hms.hour * 3_600_000
+            + hms.minute * 60_000
+            + (hms.second * 1000.0).round() as i64;
+        DateTime {
+            jd_ms,
+            raw_number: None,
+            subsec: false,
+        }
+    }
+
+    fn parse(value: &Value) -> Option<Self> {
+        match value {
+            Value::Null => None,
+            Value::Text(text) => {
+                let text = text.trim();
+                if text.eq_ignore_ascii_case("now") {
+                    Some(Self::now())
+                } else if let Ok(number) = text.parse::<f64>() {
+                    Some(Self::from_julian_day(number))
+                } else {
+                    parse_date_time(text)
+                }
+            }
+            Value::Blob(_) => parse_date_time(&value.to_text()),
+            number => Some(Self::from_julian_day(
+                number.cast(Affinity::Real).get_numeric_value().ok()?,
+            )),
+        }
+    }
+
+    fn julian_day(&self) -> f64 {
+        self.jd_ms as f64 / MS_PER_DAY as f64
+    }
+
+    fn ymd(&self) -> Ymd {
+        let z = (self.jd_ms + MS_PER_DAY / 2) / MS_PER_DAY;
+        let a = ((z as f64 - 1867216.25) / 36524.25) as i64;
+        let a = z + 1 + a - a / 4;
+        let b = a + 1524;
+        let c = ((b as f64 - 122.1) / 365.25) as i64;
+        let d = (36525 * (c & 32767)) / 100;
+        let e = ((b - d) as f64 / 30.6001) as i64;
+        let x1 = (30.6001 * e as f64) as i64;
+        let month = if e < 14 { e - 1 } else { e - 13 };
+        Ymd {
+            year: if month > 2 { c - 4716 } else { c - 4715 },
+            month,
+            day: b - d - x1,
+        }
+    }
+
+    fn hms(&self) -> Hms {
+        let ms = (self.jd_ms + MS_PER_DAY / 2).rem_euclid(MS_PER_DAY);
+        Hms {
+            hour: ms / 3_600_000,
+            minute: ms / 60_000 % 60,
+            second: (ms % 60_000) as f64 / 1000.0,
+        }
+    }
+
+    /// Day of the week, 0 being Sunday
+    fn weekday(&self) -> i64 {
+        ((self.jd_ms + MS_PER_DAY * 3 / 2) / MS_PER_DAY) % 7
+    }
+
+    fn day_of_year(&self) -> i64 {
+        let ymd = self.ymd();
+        let start = DateTime::from_parts(
+            Ymd {
+                month: 1,
+                day: 1,
+                ..ymd
+            },
+            MIDNIGHT,
+        );
+        let day = DateTime::from_parts(ymd, MIDNIGHT);
+        (day.jd_ms - start.jd_ms) / MS_PER_DAY
+    }
+
+    /// Applies a modifier such as `+1 day` or `start of month`, `None` if it is invalid
+    fn modify(&mut self, modifier: &str) -> Option<()> {
+        let modifier = modifier.trim().to_lowercase();
+        let raw_number = self.raw_number.take();
+        match modifier.as_str() {
+            "unixepoch" => {
+                let seconds = raw_number.filter(|n| UNIX_SECONDS.contains(n))?;
+                self.jd_ms = UNIX_EPOCH_JD_MS + (seconds * 1000.0).round() as i64;
+            }
+            "julianday" => {
+                raw_number?;
+            }
+            "auto" => {
+                let number = raw_number?;
+                if !(0.0..5373484.5).contains(&number) {
+                    if !UNIX_SECONDS.contains(&number) {
+                        return None;
+                    }
+                    self.jd_ms = UNIX_EPOCH_JD_MS + (number * 1000.0).round() as i64;
+                }
+            }
+            // Without time zone data local time is the same as UTC
+            "localtime" | "utc" => {}
+            "subsec" | "subsecond" => self.subsec = true,
+            "start of day" => self.set_parts(self.valid_ymd()?, MIDNIGHT)?,
+            "start of month" => self.set_parts(
+                Ymd {
+                    day: 1,
+                    ..self.valid_ymd()?
+                },
+                MIDNIGHT,
+            )?,
+            "start of year" => self.set_parts(
+                Ymd {
+                    month: 1,
+                    day: 1,
+                    ..self.valid_ymd()?
+                },
+                MIDNIGHT,
+            )?,
+            weekday if weekday.starts_with("weekday ") => {
+                let target = weekday["weekday ".len()..].trim().parse::<f64>().ok()?;
+                if target.fract() != 0.0 || !(0.0..7.0).contains(&target) {
+                    return None;
+                }
+                self.valid_ymd()?;
+                let days = (target as i64 - self.weekday()).rem_euclid(7);
+                self.add_ms(days * MS_PER_DAY)?;
+            }
+            modifier => self.shift(modifier)?,
+        }
+        Some(())
+    }
+
+    /// The date, `None` if a previous modifier left the range of valid dates
+    fn valid_ymd(&self) -> Option<Ymd> {
+        if (0..=MAX_JD_MS).contains(&self.jd_ms) {
+            Some(self.ymd())
+        } else {
+            None
+        }
+    }
+
+    /// Moves to a date and time, `None` if its year is outside of the range SQLite accepts
+    fn set_parts(&mut self, ymd: Ymd, hms: Hms) -> Option<()> {
+        if !(-4713..=9999).contains(&ymd.year) {
+            return None;
+        }
+        self.jd_ms = DateTime::from_parts(ymd, hms).jd_ms;
+        Some(())
+    }
+
+    fn add_ms(&mut self, ms: i64) -> Option<()> {
+        self.jd_ms = self.jd_ms.checked_add(ms)?;
+        Some(())
+    }
+
+    /// Applies `±NNN unit`, `±HH:MM[:SS[.SSS]]` or `±YYYY-MM-DD[ HH:MM[:SS[.SSS]]]`
+    fn shift(&mut self, modifier: &str) -> Option<()> {
+        let (negative, unsigned) = match modifier.as_bytes().first()? {
+            b'-' => (true, &modifier[1..]),
+            b'+' => (false, &modifier[1..]),
+            _ => (false, modifier),
+        };
+        let sign = if negative { -1 } else { 1 };
+
+        if let Some((number, unit)) = unsigned.split_once(|c: char| c.is_whitespace()) {
+            if let Ok(amount) = number.parse::<f64>() {
+                let amount = amount * sign as f64;
+                return self.shift_by_unit(amount, unit.trim().trim_end_matches('s'));
+            }
+        }
+
+        if modifier.starts_with(['+', '-'])
+            && unsigned.len() >= 10
+            && unsigned.is_char_boundary(10)
+            && unsigned.as_bytes()[4] == b'-'
+        {
+            let (date, time) = match unsigned.split_once([' ', 'T']) {
+                Some((date, time)) => (date, Some(time)),
+                None => (unsigned, None),
+            };
+            let ymd = parse_ymd(&date[..10])?;
+            self.add_months(sign * (ymd.year * 12 + ymd.month))?;
+            self.add_ms(sign * ymd.day * MS_PER_DAY)?;
+            if let Some(time) = time {
+                self.add_ms(sign * hms_to_ms(parse_hms(time)?))?;
+            }
+            return Some(());
+        }
+
+        let hms = parse_hms(unsigned)?;
+        self.add_ms(sign * hms_to_ms(hms))
+    }
+
+    /// Shifts by an amount of a unit, which like in SQLite must stay below the unit's limit
+    fn shift_by_unit(&mut self, amount: f64, unit: &str) -> Option<()> {
+        let (ms_per_unit, limit) = match unit {
+            "day" => (MS_PER_DAY, 5_373_485.0),
+            "hour" => (3_600_000, 1.2897e11),
+            "minute" => (60_000, 7.7379e12),
+            "second" => (1000, 4.6427e14),
+            "month" => (30 * MS_PER_DAY, 176_546.0),
+            "year" => (365 * MS_PER_DAY, 14_713.0),
+            _ => return None,
+        };
+        if amount.is_nan() || amount.abs() >= limit {
+            return None;
+        }
+        match unit {
+            "month" => self.add_months(amount.trunc() as i64)?,
+            "year" => self.add_months(amount.trunc() as i64 * 12)?,
+            _ => return self.add_ms((amount * ms_per_unit as f64).round() as i64),
+        }
+        self.add_ms((amount.fract() * ms_per_unit as f64).round() as i64)
+    }
+
+    /// Adds whole months, letting days past the end of the month overflow into the next one
+    fn add_months(&mut self, months: i64) -> Option<()> {
+        let ymd = self.valid_ymd()?;
+        let hms = self.hms();
+        let month_index = ymd.year * 12 + ymd.month - 1 + months;
+        self.set_parts(
+            Ymd {
+                year: month_index.div_euclid(12),
+                month: month_index.rem_euclid(12) + 1,
+                day: ymd.day,
+            },
+            hms,
+        )
+    }
+
+    /// Formats the date following `strftime`'s substitutions
+    fn format(&self, format: &str) -> String {
+        let ymd = self.ymd();
+        let hms = self.hms();
+        let mut result = String::new();
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                result.push(c);
+                continue;
+            }
+            let hour12 = match hms.hour % 12 {
+                0 => 12,
+                h => h,
+            };
+            let text = match chars.next() {
+                Some('d') => format!("{:02}", ymd.day),
+                Some('e') => format!("{:2}", ymd.day),
+                Some('f') => format!("{:06.3}", (hms.second * 1000.0).floor() / 1000.0),
+                Some('F') => format!("{:04}-{:02}-{:02}", ymd.year, ymd.month, ymd.day),
+                Some('H') => format!("{:02}", hms.hour),
+                Some('k') => format!("{:2}", hms.hour),
+                Some('I') => format!("{:02}", hour12),
+                Some('l') => format!("{:2}", hour12),
+                Some('j') => format!("{:03}", self.day_of_year() + 1),
+                Some('J') => format_significant(self.julian_day(), 16),
+                Some('m') => format!("{:02}", ymd.month),
+                Some('M') => format!("{:02}", hms.minute),
+                Some('p') => (if hms.hour < 12 { "AM" } else { "PM" }).to_string(),
+                Some('P') => (if hms.hour < 12 { "am" } else { "pm" }).to_string(),
+                Some('R') => format!("{:02}:{:02}", hms.hour, hms.minute),
+                Some('s') => (self.jd_ms / 1000 - UNIX_EPOCH_JD_MS / 1000).to_string(),
+                Some('S') => format!("{:02}", hms.second as i64),
+                Some('T') => format!("{:02}:{:02}:{:02}", hms.hour, hms.minute, hms.second as i64),
+                Some('u') => match self.weekday() {
+                    0 => 7,
+                    day => day,
+                }
+                .to_string(),
+                Some('w') => self.weekday().to_string(),
+                Some('U') => format!("{:02}", (self.day_of_year() + 7 - self.weekday()) / 7),
+                Some('W') => format!(
+                    "{:02}",
+                    (self.day_of_year() + 7 - (self.weekday() + 6) % 7) / 7
+                ),
+                Some('V') => format!("{:02}", self.iso_week().1),
+                Some('G') => format!("{:04}", self.iso_week().0),
+                Some('g') => format!("{:02}", self.iso_week().0 % 100),
+                Some('Y') => format!("{:04}", ymd.year),
+                Some('%') => "%".to_string(),
+                Some(other) => format!("%{}", other),
+                None => "%".to_string(),
+            };
+            result.push_str(&text);
+        }
+        result
+    }
+
+    /// ISO 8601 year and week number, weeks starting on Monday
+    fn iso_week(&self) -> (i64, i64) {
+        // The Thursday of the same week decides which year the week belongs to
+        let monday_based = (self.weekday() + 6) % 7;
+        let thursday = DateTime {
+            jd_ms: self.jd_ms + (3 - monday_based) * MS_PER_DAY,
+            ..*self
+        };
+        (thursday.ymd().year, thursday.day_of_year() / 7 + 1)
+    }
+}
+
+const MIDNIGHT: Hms = Hms {
+    hour: 0,
+    minute: 0,
+    second: 0.0,
+};
+
+/// Formats a real with `digits` significant digits and no trailing zeros, like `%.16g`
+fn format_significant(v: f64, digits: usize) -> String {
+    let integer_digits = (v.abs().log10().floor() as i64 + 1).max(1) as usize;
+    let text = format!("{:.*}", digits.saturating_sub(integer_digits), v);
+    if text.contains('.') {
+        text.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        text
+    }
+}
+
+fn hms_to_ms(hms: Hms) -> i64 {
+    hms.hour * 3_600_000 + hms.minute * 60_000 + (hms.second * 1000.0).round() as i64
+}
+
+/// Parses `YYYY-MM-DD[( |T)HH:MM[:SS[.SSS]]][Z|±HH:MM]` or `HH:MM[:SS[.SSS]]`
+fn parse_date_time(text: &str) -> Option<DateTime> {
+    if text.len() >= 10 && text.is_char_boundary(10) && text.as_bytes()[4] == b'-' {
+        let ymd = parse_ymd(&text[..10])?;
+        if !(1..=12).contains(&ymd.month) || !(1..=31).contains(&ymd.day) {
+            return None;
+        }
+        let time = text[10..].trim_start_matches([' ', 'T']);
+        if time.is_empty() {
+            return Some(DateTime::from_parts(ymd, MIDNIGHT));
+        }
+        let (hms, offset) = parse_time_with_zone(time)?;
+        let mut dt = DateTime::from_parts(ymd, hms);
+        dt.jd_ms -= offset;
+        Some(dt)
+    } else {
+        let (hms, offset) = parse_time_with_zone(text)?;
+        let ymd = Ymd {
+            year: 2000,
+            month: 1,
+            day: 1,
+        };
+        let mut dt = DateTime::from_parts(ymd, hms);
+        dt.jd_ms -= offset;
+        Some(dt)
+    }
+}
+
+fn parse_ymd(text: &str) -> Option<Ymd> {
+    let bytes = text.as_bytes();
+    if bytes.len() != 10 || bytes[4] != b'-' || bytes[7] != b'-' {
+        return None;
+    }
+    Some(Ymd {
+        year: parse_digits(&text[0..4])?,
+        month: parse_digits(&text[5..7])?,
+        day: parse_digits(&text[8..10])?,
+    })
+}
+
+/// Parses a time of day followed by an optional time zone, returning the zone offset in ms
+fn parse_time_with_zone(text: &str) -> Option<(Hms, i64)> {
+    let text = text.trim_end();
+    let (time, offset) = if let Some(time) = text.strip_suffix(['Z', 'z']) {
+        (time.trim_end(), 0)
+    } else {
+        match text.rfind(['+', '-']) {
+            Some(i) => {
+                let zone = parse_hms(&text[i + 1..])?;
+                let offset = hms_to_ms(zone);
+                let offset = if text.as_bytes()[i] == b'-' {
+                    -offset
+                } else {
+                    offset
+                };
+                (text[..i].trim_end(), offset)
+            }
+            None => (text, 0),
+        }
+    };
+    let hms = parse_hms(time)?;
+    if hms.hour > 24 || hms.minute > 59 || hms.second >= 60.0 {
+        return None;
+    }
+    Some((hms, offset))
+}
+
+/// Parses `HH:MM[:SS[.SSS]]`
+fn parse_hms(text: &str) -> Option<Hms> {
+    let mut parts = text.splitn(3, ':');
+    let hour = parse_digits(parts.next()?)?;
+    let minute = parse_digits(parts.next()?)?;
+    let second = match parts.next() {
+        Some(second) => {
+            let (whole, fraction) = second.split_once('.').unwrap_or((second, ""));
+            if whole.len() != 2 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            second.parse::<f64>().ok()?
+        }
+        None => 0.0,
+    };
+    Some(Hms {
+        hour,
+        minute,
+        second,
+    })
+}
+
+fn parse_digits(text: &str) -> Option<i64> {
+    if text.is_empty() || !text.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> Value {
+        Value::Text(text.to_string())
+    }
+
+    /// Calls a date function on a time value and modifiers, rendering NULL as `NULL`
+    fn date(name: &str, args: &[Value]) -> String {
+        match call(name, args).unwrap() {
+            Value::Null => "NULL".to_string(),
+            value => value.to_text(),
+        }
+    }
+
+    /// Calls a date function on text arguments only
+    fn date_text(name: &str, args: &[&str]) -> String {
+        date(name, &args.iter().map(|arg| text(arg)).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn parses_time_values() {
+        let cases = [
+            ("date", "2024-02-29", "2024-02-29"),
+            ("datetime", "2024-02-29T13:45:30.5", "2024-02-29 13:45:30"),
+            ("time", "12:30", "12:30:00"),
+            (
+                "datetime",
+                "2024-01-15 10:00:00+02:00",
+                "2024-01-15 08:00:00",
+            ),
+            ("datetime", "2024-01-15 10:00Z", "2024-01-15 10:00:00"),
+            ("julianday", "2000-01-01 12:00:00", "2451545.0"),
+            ("unixepoch", "2024-01-01", "1704067200"),
+            ("date", "2024-13-01", "NULL"),
+        ];
+        for (name, arg, expected) in cases {
+            assert_eq!(date_text(name, &[arg]), expected, "{}({})", name, arg);
+        }
+        assert_eq!(
+            date("datetime", &[Value::F(2460000.5)]),
+            "2023-02-25 00:00:00"
+        );
+        assert_eq!(date("date", &[Value::Null]), "NULL");
+    }
+
+    #[test]
+    fn applies_modifiers() {
+        let cases = [
+            ("2024-01-31", "+1 month", "2024-03-02 00:00:00"),
+            ("2024-03-31", "-1 month", "2024-03-02 00:00:00"),
+            ("2024-02-29", "+1 year", "2025-03-01 00:00:00"),
+            ("2024-01-01", "+1.5 days", "2024-01-02 12:00:00"),
+            ("2024-01-01", "-90 minutes", "2023-12-31 22:30:00"),
+            ("2024-01-01 00:00", "+01:30", "2024-01-01 01:30:00"),
+            ("2024-01-01", "+0001-02-03 04:05:06", "2025-03-04 04:05:06"),
+            ("2024-05-17", "start of month", "2024-05-01 00:00:00"),
+            ("2024-05-17", "start of year", "2024-01-01 00:00:00"),
+            ("2024-05-17 13:14:15", "start of day", "2024-05-17 00:00:00"),
+            ("2024-05-17", "weekday 0", "2024-05-19 00:00:00"),
+            ("2024-05-19", "weekday 0", "2024-05-19 00:00:00"),
+            ("2024-05-17", "weekday 3", "2024-05-22 00:00:00"),
+            ("2024-05-17", "weekday 7", "NULL"),
+            ("2024-05-17", "bogus", "NULL"),
+        ];
+        for (time, modifier, expected) in cases {
+            assert_eq!(
+                date_text("datetime", &[time, modifier]),
+                expected,
+                "{}",
+                modifier
+            );
+        }
+
+        let epoch = Value::Integer(1700000000);
+        assert_eq!(
+            date("datetime", &[epoch.clone(), text("unixepoch")]),
+            "2023-11-14 22:13:20"
+        );
+        assert_eq!(
+            date("datetime", &[epoch, text("auto")]),
+            "2023-11-14 22:13:20"
+        );
+        assert_eq!(
+            date(
+                "datetime",
+                &[Value::F(1700000000.123), text("unixepoch"), text("subsec")]
+            ),
+            "2023-11-14 22:13:20.123"
+        );
+        assert_eq!(date("date", &[text("2024-01-01"), Value::Null]), "NULL");
+    }
+
+    #[test]
+    fn dates_out_of_range_are_null() {
+        let cases: [&[&str]; 8] = [
+            &["now", "+1e300 days"],
+            &["now", "+9000000000000000000 months"],
+            &["2024-01-01", "+9000000000000000000 years"],
+            &["2024-01-01", "-9000000000000000000 seconds"],
+            &["2024-01-01", "+9223372036854775807 minutes"],
+            &["2024-01-01", "+10000 years", "-10000 years"],
+            &["2024-01-01", "+1000000000 days", "-1000000000 days"],
+            &["2024-01-01", "+5000000 days", "start of month"],
+        ];
+        for args in cases {
+            assert_eq!(date_text("date", args), "NULL", "{:?}", args);
+        }
+        assert_eq!(
+            date_text("date", &["2024-01-01", "+5000000 days", "-5000000 days"]),
+            "2024-01-01"
+        );
+
+        let huge = Value::F(1e300);
+        assert_eq!(date("datetime", std::slice::from_ref(&huge)), "NULL");
+        assert_eq!(date("datetime", &[huge.clone(), text("unixepoch")]), "NULL");
+        assert_eq!(date("datetime", &[huge.clone(), text("+1 day")]), "NULL");
+        assert_eq!(date("datetime", &[Value::F(1e18), text("auto")]), "NULL");
+        assert_eq!(date("strftime", &[text("%s"), huge]), "NULL");
+    }
+
+    #[test]
+    fn formats_with_strftime() {
+        let weeks = "%j %U %W %V %G %g %u %w";
+        let cases = [
+            (weeks, "2024-01-01", "001 00 01 01 2024 24 1 1"),
+            (weeks, "2021-01-03", "003 01 00 53 2020 20 7 0"),
+            (weeks, "2020-12-31", "366 52 52 53 2020 20 4 4"),
+            (weeks, "2026-12-31", "365 52 52 53 2026 26 4 4"),
+            (
+                "%H:%M:%f %I %p %k %l %s %J",
+                "2024-03-04 15:06:07.891",
+                "15:06:07.891 03 PM 15  3 1709564767 2460374.129257997",
+            ),
+            (
+                "%d/%m/%Y %e %F %T %R %%",
+                "2024-03-04 05:06:07",
+                "04/03/2024  4 2024-03-04 05:06:07 05:06 %",
+            ),
+        ];
+        for (format, time, expected) in cases {
+            assert_eq!(date_text("strftime", &[format, time]), expected, "{}", time);
+        }
+    }
+}
@@ -1,121 +1,1382 @@
-use crate::db_header::DBHeader;
-use std::fs::File;
-use std::os::unix::fs::FileExt;
+use crate::btree;
+use crate::collation::Collation;
+use crate::expr::{
+    apply_comparison_affinity, comparison_collation, BinaryOperator, Columns, Expr, Row,
+    SourceColumn,
+};
+use crate::functions::{call_table, random, table_columns, Functions};
 
 use crate::page::{parse_index_interior, parse_index_leaf, parse_table_interior, parse_table_leaf};
 use crate::page_header::{BTreePage, PageHeader};
-use crate::record::Value;
+use crate::pager::Pager;
+use crate::query;
+use crate::record::{encode_record, Affinity, Value};
 use crate::schema::Schema;
-use crate::sql::Select;
+use crate::sql::{
+    Column, ColumnConstraint, CreateStatement, Delete, DropStatement, IndexedColumn, Insert,
+    InsertSource, ResultColumn, Select, TableConstraint, TableSource, Transaction, Update,
+    WalCheckpoint,
+};
 use anyhow::{bail, Error, Result};
-use std::collections::HashMap;
+use std::cmp::Ordering;
+
+/// Rows of a table, each with its rowid
+type TableRows<K> = Vec<(K, Vec<Value>)>;
 
 pub struct DB {
-    file: File,
-    header: DBHeader,
+    pager: Pager,
+    functions: Functions,
+    /// Whether a transaction was started with `BEGIN`
+    begun: bool,
+    /// The names of the savepoints open in the transaction, the innermost last. They match the
+    /// savepoints of the pager
+    savepoints: Vec<String>,
 }
 
 impl DB {
     pub fn new(file_name: &str) -> Result<Self> {
-        let file = File::open(file_name)?;
-        let db_header_stream = &mut [0u8; 100];
-        file.read_exact_at(db_header_stream, 0)?;
-        let header = DBHeader::parse(db_header_stream)?;
-        Ok(Self { file, header })
+        Ok(Self {
+            pager: Pager::open(file_name)?,
+            functions: Functions::default(),
+            begun: false,
+            savepoints: vec![],
+        })
+    }
+
+    /// Makes a scalar function callable from SQL. It takes `arity` arguments, or any number of
+    /// them when `None`, and is `deterministic` when it always gives the same result for the same
+    /// arguments
+    pub fn register_function(
+        &mut self,
+        name: &str,
+        arity: Option<usize>,
+        deterministic: bool,
+        function: impl Fn(&[Value]) -> Value + 'static,
+    ) {
+        self.functions
+            .register_function(name, arity, deterministic, function)
+    }
+
+    /// Makes an aggregate function callable from SQL. Each group starts from the state created
+    /// by `init`, which `step` updates for every row and `finalize` turns into the result
+    pub fn register_aggregate<S: 'static>(
+        &mut self,
+        name: &str,
+        arity: Option<usize>,
+        deterministic: bool,
+        init: impl Fn() -> S + 'static,
+        step: impl Fn(&mut S, &[Value]) + 'static,
+        finalize: impl Fn(S) -> Value + 'static,
+    ) {
+        self.functions
+            .register_aggregate(name, arity, deterministic, init, step, finalize)
+    }
+
+    /// Makes a collating sequence usable in `COLLATE` clauses and column definitions
+    pub fn register_collation(
+        &mut self,
+        name: &str,
+        compare: impl Fn(&str, &str) -> Ordering + 'static,
+    ) {
+        self.functions.register_collation(name, compare)
     }
 
     pub fn tables(&self) -> Result<Vec<String>> {
-        Ok(self
-            .get_schemas()?
-            .into_iter()
-            .filter(|s| s.kind == "table" && s.table_name != "sqlite_sequence")
-            .map(|s| s.table_name)
-            .collect())
+        self.read(|db| {
+            Ok(db
+                .get_schemas()?
+                .into_iter()
+                .filter(|s| s.kind == "table" && s.table_name != "sqlite_sequence")
+                .map(|s| s.table_name)
+                .collect())
+        })
     }
 
+    /// Returns the number of rows of a table, counting the entries of every page of its B-tree
     pub fn count(&self, table: &str) -> Result<usize> {
-        let page_number = match self.get_schemas()?.iter().find(|s| s.name == table) {
-            None => bail!("Table {} not found", table),
-            Some(schema) => schema.root_page,
-        };
-        let (_, page_header, _) = self.read_page(page_number)?;
-        Ok(page_header.number_of_cells as usize)
+        self.read(|db| {
+            let page_number = match db.get_schemas()?.iter().find(|s| s.name == table) {
+                None => bail!("Table {} not found", table),
+                Some(schema) => schema.root_page,
+            };
+            btree::count_entries(&db.pager, page_number)
+        })
     }
 
     pub fn select(&self, select: Select) -> Result<Vec<Vec<Value>>> {
+        self.read(|db| {
+            let schemas = db.get_schemas()?;
+            let (_, rows) = db.run_select(&select, &schemas)?;
+            Ok(rows)
+        })
+    }
+
+    /// Inserts rows into a table, returning how many were inserted
+    pub fn insert(&mut self, insert: Insert) -> Result<usize> {
+        self.write(|db| db.insert_rows(insert))
+    }
+
+    /// Updates the rows of a table matching the filter, returning how many were updated. The
+    /// new values are computed from the values the row had before the update
+    pub fn update(&mut self, update: Update) -> Result<usize> {
+        self.write(|db| db.update_rows(update))
+    }
+
+    /// Deletes the rows of a table matching the filter, returning how many were deleted
+    pub fn delete(&mut self, delete: Delete) -> Result<usize> {
+        self.write(|db| db.delete_rows(delete))
+    }
+
+    /// Runs a `CREATE` statement, adding a table, an index, a view or a trigger to the schema. A
+    /// new index is filled with the rows its table already holds
+    pub fn create(&mut self, query: &str) -> Result<()> {
+        self.write(|db| db.create_object(query))
+    }
+
+    /// Runs a `DROP` statement, removing an object from the schema and adding its pages to the
+    /// freelist. Dropping a table also drops its indexes and triggers
+    pub fn drop_object(&mut self, drop: DropStatement) -> Result<()> {
+        self.write(|db| db.remove_object(drop))
+    }
+
+    /// Runs `BEGIN`, `COMMIT`, `ROLLBACK` or a savepoint statement, as mentioned here:
+    /// [transactions](https://www.sqlite.org/lang_transaction.html). A savepoint started outside
+    /// of a transaction starts one, which releasing the savepoint commits
+    pub fn transaction(&mut self, statement: Transaction) -> Result<()> {
+        match statement {
+            Transaction::Begin if self.in_transaction() => {
+                bail!("cannot start a transaction within a transaction")
+            }
+            Transaction::Begin => self.begun = true,
+            Transaction::Commit if !self.in_transaction() => {
+                bail!("cannot commit - no transaction is active")
+            }
+            Transaction::Commit => self.commit_transaction()?,
+            Transaction::Rollback if !self.in_transaction() => {
+                bail!("cannot rollback - no transaction is active")
+            }
+            Transaction::Rollback => {
+                self.begun = false;
+                self.savepoints.clear();
+                self.pager.rollback()?;
+            }
+            Transaction::Savepoint(name) => {
+                self.pager.begin_savepoint();
+                self.savepoints.push(name);
+            }
+            Transaction::Release(name) => {
+                let depth = self.find_savepoint(&name)?;
+                while self.savepoints.len() > depth {
+                    self.savepoints.pop();
+                    self.pager.release_savepoint();
+                }
+                if !self.in_transaction() {
+                    self.commit_transaction()?;
+                }
+            }
+            Transaction::RollbackTo(name) => {
+                // The savepoints started after the one rolled back to end with it
+                let depth = self.find_savepoint(&name)?;
+                while self.savepoints.len() > depth + 1 {
+                    self.savepoints.pop();
+                    self.pager.roll_back_savepoint();
+                    self.pager.release_savepoint();
+                }
+                self.pager.roll_back_savepoint();
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a transaction was started with `BEGIN` or by a savepoint, so that statements
+    /// do not commit
+    fn in_transaction(&self) -> bool {
+        self.begun || !self.savepoints.is_empty()
+    }
+
+    /// Returns the position of the innermost savepoint with the given name
+    fn find_savepoint(&self, name: &str) -> Result<usize> {
+        self.savepoints
+            .iter()
+            .rposition(|savepoint| savepoint.eq_ignore_ascii_case(name))
+            .ok_or_else(|| Error::msg(format!("no such savepoint: {}", name)))
+    }
+
+    /// Commits the transaction, which is rolled back when its changes cannot be written
+    fn commit_transaction(&mut self) -> Result<()> {
+        self.begun = false;
+        self.savepoints.clear();
+        if let Err(err) = self.pager.commit() {
+            self.pager.rollback()?;
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Returns the number of every page of the freelist
+    pub fn free_pages(&self) -> Result<Vec<u32>> {
+        self.pager.free_pages()
+    }
+
+    /// Copies the pages of the write-ahead log back into the database file, returning whether
+    /// the checkpoint was blocked, the number of frames in the log and the number of frames
+    /// copied as `PRAGMA wal_checkpoint` does, unless the database is not in WAL mode
+    pub fn checkpoint(
+        &mut self,
+        checkpoint: WalCheckpoint,
+    ) -> Result<Option<(bool, usize, usize)>> {
+        if self.in_transaction() {
+            bail!("cannot checkpoint within a transaction");
+        }
+        self.pager.checkpoint(checkpoint.mode)
+    }
+
+    /// Runs a statement reading the database under a shared lock, which is kept until the end of
+    /// a transaction started with `BEGIN`
+    fn read<T>(&self, statement: impl FnOnce(&Self) -> Result<T>) -> Result<T> {
+        self.pager.begin_read()?;
+        let result = statement(self);
+        if !self.in_transaction() {
+            self.pager.end_read()?;
+        }
+        result
+    }
+
+    /// Runs a statement changing the database. A statement that fails leaves no change behind,
+    /// and outside of a transaction its changes are committed at once
+    fn write<T>(&mut self, statement: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        self.pager.begin_write()?;
+        self.pager.begin_savepoint();
+        let result = statement(self);
+        if result.is_err() {
+            self.pager.roll_back_savepoint();
+        }
+        self.pager.release_savepoint();
+        if !self.in_transaction() {
+            self.commit_transaction()?;
+        }
+        result
+    }
+
+    fn insert_rows(&mut self, insert: Insert) -> Result<usize> {
         let schemas = self.get_schemas()?;
-        let index = find_applicable_index(&select, &schemas)?;
+        let schema = writable_table(&schemas, &insert.table)?;
+        let indexes = table_indexes(schema, &schemas)?;
+        let table_columns = schema.table_columns()?;
+
+        // Values assigned to a rowid name that no column takes set the rowid, and generated
+        // columns take no value
+        let targets = match (&insert.source, insert.columns.is_empty()) {
+            (InsertSource::DefaultValues, _) => vec![],
+            (_, true) => (0..table_columns.len())
+                .filter(|&i| table_columns[i].generated().is_none())
+                .map(Some)
+                .collect::<Vec<_>>(),
+            (_, false) => insert
+                .columns
+                .iter()
+                .map(|name| find_target(schema, name, "INSERT into"))
+                .collect::<Result<Vec<_>>>()?,
+        };
+        let rows = match insert.source {
+            InsertSource::Values(rows) => rows
+                .iter()
+                .map(|row| row.iter().map(|expr| self.eval_constant(expr)).collect())
+                .collect::<Result<Vec<Vec<_>>>>()?,
+            InsertSource::Select(select) => self.run_select(&select, &schemas)?.1,
+            InsertSource::DefaultValues => vec![vec![]],
+        };
+        let defaults = table_columns
+            .iter()
+            .map(|column| match column.default_value() {
+                Some(default) => self.eval_constant(default),
+                None => Ok(Value::Null),
+            })
+            .collect::<Result<Vec<_>>>()?;
 
+        for row in &rows {
+            if row.len() != targets.len() {
+                match insert.columns.is_empty() {
+                    true => bail!(
+                        "table {} has {} columns but {} values were supplied",
+                        schema.name,
+                        targets.len(),
+                        row.len()
+                    ),
+                    false => bail!("{} values for {} columns", row.len(), targets.len()),
+                }
+            }
+            let mut values = defaults.clone();
+            let mut rowid = None;
+            for (target, value) in targets.iter().zip(row) {
+                match target {
+                    Some(i) => values[*i] = value.clone(),
+                    None => rowid = Some(value.clone()),
+                }
+            }
+            let (rowid, values) =
+                self.prepare_row(schema, &indexes, values, rowid, None, &schemas)?;
+            self.write_row(schema, &indexes, rowid, &values)?;
+            if schema.is_autoincrement() {
+                self.update_sequence(&schema.name, rowid, &schemas)?;
+            }
+        }
+        Ok(rows.len())
+    }
+
+    fn update_rows(&mut self, update: Update) -> Result<usize> {
+        let schemas = self.get_schemas()?;
+        let schema = writable_table(&schemas, &update.table)?;
+        let indexes = table_indexes(schema, &schemas)?;
+        let column_count = schema.table_columns()?.len();
+        let rowid_alias = schema.rowid_alias();
+        let targets = update
+            .assignments
+            .iter()
+            .map(|(name, _)| find_target(schema, name, "UPDATE"))
+            .collect::<Result<Vec<_>>>()?;
+
+        let (columns, rows) = self.find_rows(schema, update.filter, &schemas)?;
+        for (old_rowid, old_values) in &rows {
+            let row = Row {
+                columns: &columns,
+                values: old_values,
+                functions: &self.functions,
+            };
+            let mut values = old_values[..column_count].to_vec();
+            let mut rowid = Some(Value::Integer(*old_rowid));
+            for (target, (_, expr)) in targets.iter().zip(&update.assignments) {
+                let value = expr.eval(&row)?;
+                match target {
+                    Some(i) => values[*i] = value,
+                    None => {
+                        // The alias holds the rowid, which it would otherwise restore
+                        if let Some(i) = rowid_alias {
+                            values[i] = value.clone();
+                        }
+                        rowid = Some(value);
+                    }
+                }
+            }
+            let replaced = Some(*old_rowid);
+            let (rowid, values) =
+                self.prepare_row(schema, &indexes, values, rowid, replaced, &schemas)?;
+            self.remove_row(schema, &indexes, *old_rowid, &old_values[..column_count])?;
+            self.write_row(schema, &indexes, rowid, &values)?;
+        }
+        Ok(rows.len())
+    }
+
+    fn delete_rows(&mut self, delete: Delete) -> Result<usize> {
+        let schemas = self.get_schemas()?;
+        let schema = writable_table(&schemas, &delete.table)?;
+        let indexes = table_indexes(schema, &schemas)?;
+        let column_count = schema.table_columns()?.len();
+        let (_, rows) = self.find_rows(schema, delete.filter, &schemas)?;
+        for (rowid, values) in &rows {
+            self.remove_row(schema, &indexes, *rowid, &values[..column_count])?;
+        }
+        Ok(rows.len())
+    }
+
+    fn create_object(&mut self, query: &str) -> Result<()> {
+        let statement = CreateStatement::parse(query.to_string())?;
+        let sql = statement.schema_text(query)?;
+        let (kind, database, name, table_name, if_not_exists) = match &statement {
+            CreateStatement::CreateTable {
+                schema,
+                name,
+                if_not_exists,
+                ..
+            }
+            | CreateStatement::CreateView {
+                schema,
+                name,
+                if_not_exists,
+                ..
+            } => {
+                let kind = match statement {
+                    CreateStatement::CreateTable { .. } => "table",
+                    _ => "view",
+                };
+                (kind, schema, name, name, *if_not_exists)
+            }
+            CreateStatement::CreateIndex {
+                schema,
+                name,
+                table,
+                if_not_exists,
+                ..
+            } => ("index", schema, name, table, *if_not_exists),
+            CreateStatement::CreateTrigger {
+                schema,
+                name,
+                table,
+                if_not_exists,
+                ..
+            } => ("trigger", schema, name, table, *if_not_exists),
+            CreateStatement::Other(_) | CreateStatement::Unparsed { .. } => {
+                bail!("Unsupported statement: {}", query)
+            }
+        };
+        if let Some(database) = database
+            .as_ref()
+            .filter(|d| !d.eq_ignore_ascii_case("main"))
+        {
+            bail!("unknown database {}", database);
+        }
+        if name.to_lowercase().starts_with("sqlite_") {
+            bail!("object name reserved for internal use: {}", name);
+        }
+
+        // Triggers have names of their own, while tables, indexes and views share theirs
+        let schemas = self.get_schemas()?;
+        let existing = schemas.iter().find(|s| {
+            s.name.eq_ignore_ascii_case(name) && (s.kind == "trigger") == (kind == "trigger")
+        });
+        match existing {
+            Some(existing) if existing.kind == kind && if_not_exists => return Ok(()),
+            Some(existing) if existing.kind == kind => bail!("{} {} already exists", kind, name),
+            Some(existing) => {
+                let article = if existing.kind == "index" { "an" } else { "a" };
+                bail!(
+                    "there is already {} {} named {}",
+                    article,
+                    existing.kind,
+                    name
+                )
+            }
+            None => {}
+        }
+        let table = match kind {
+            "index" | "trigger" => {
+                match schemas
+                    .iter()
+                    .find(|s| s.name.eq_ignore_ascii_case(table_name))
+                {
+                    Some(table) if kind == "index" && table.kind != "table" => {
+                        bail!("views may not be indexed")
+                    }
+                    Some(table) if kind == "index" && table.is_without_rowid() => {
+                        bail!("Cannot index WITHOUT ROWID table {}", table.name)
+                    }
+                    Some(table) => Some(table),
+                    None => bail!("no such table: main.{}", table_name),
+                }
+            }
+            _ => None,
+        };
+        let mut object = Schema {
+            kind: kind.to_string(),
+            name: name.clone(),
+            table_name: table.map_or_else(|| table_name.clone(), |table| table.name.clone()),
+            root_page: 0,
+            sql: Some(statement),
+        };
+
+        match (kind, table) {
+            ("table", _) => {
+                check_table(&object)?;
+                let page_type = match object.is_without_rowid() {
+                    true => BTreePage::LeafIndex,
+                    false => BTreePage::LeafTable,
+                };
+                object.root_page = btree::create_tree(&mut self.pager, page_type)?;
+                self.insert_schema_row(&object, Some(sql))?;
+                // The primary key of a WITHOUT ROWID table takes the first number, the table
+                // being its index
+                let first = if object.is_without_rowid() { 2 } else { 1 };
+                for n in 0..object.constraint_indexes()?.len() {
+                    let index = Schema {
+                        kind: "index".to_string(),
+                        name: format!("sqlite_autoindex_{}_{}", object.name, n + first),
+                        table_name: object.name.clone(),
+                        root_page: btree::create_tree(&mut self.pager, BTreePage::LeafIndex)?,
+                        sql: None,
+                    };
+                    self.insert_schema_row(&index, None)?;
+                }
+                // The first AUTOINCREMENT table creates the table keeping their largest rowids
+                if object.is_autoincrement() && !schemas.iter().any(|s| s.name == "sqlite_sequence")
+                {
+                    let sql = "CREATE TABLE sqlite_sequence(name,seq)".to_string();
+                    let sequence = Schema {
+                        kind: "table".to_string(),
+                        name: "sqlite_sequence".to_string(),
+                        table_name: "sqlite_sequence".to_string(),
+                        root_page: btree::create_tree(&mut self.pager, BTreePage::LeafTable)?,
+                        sql: Some(CreateStatement::parse(sql.clone())?),
+                    };
+                    self.insert_schema_row(&sequence, Some(sql))?;
+                }
+            }
+            ("index", Some(table)) => {
+                object.root_page = btree::create_tree(&mut self.pager, BTreePage::LeafIndex)?;
+                self.insert_schema_row(&object, Some(sql))?;
+                let index = table_index(table, &object)?;
+                self.fill_index(table, &index, &schemas)?;
+            }
+            _ => self.insert_schema_row(&object, Some(sql))?,
+        }
+        let header = self.pager.header_mut();
+        header.schema_cookie = header.schema_cookie.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Adds an object to `sqlite_schema`, after the ones it already holds
+    fn insert_schema_row(&mut self, object: &Schema, sql: Option<String>) -> Result<()> {
+        let rowid = btree::max_rowid(&self.pager, 1)?.map_or(1, |max| max + 1);
+        let values = [
+            Value::Text(object.kind.clone()),
+            Value::Text(object.name.clone()),
+            Value::Text(object.table_name.clone()),
+            Value::Integer(object.root_page as i64),
+            sql.map_or(Value::Null, Value::Text),
+        ];
+        let record = encode_record(&values, self.pager.header().schema_format);
+        let cell = btree::table_leaf_cell(&mut self.pager, rowid, &record)?;
+        btree::insert_into_table(&mut self.pager, 1, rowid, cell)
+    }
+
+    /// Adds the entries of the rows a table already holds to a new index
+    fn fill_index(&mut self, table: &Schema, index: &TableIndex, schemas: &[Schema]) -> Result<()> {
+        let columns = table_source_columns(table)?;
+        let column_count = table.table_columns()?.len();
+        let (_, rows) = self.find_rows(table, None, schemas)?;
+        for (rowid, values) in &rows {
+            let entry = self.index_entry(index, &columns, &values[..column_count], *rowid)?;
+            if let Some(entry) = entry {
+                if index.unique {
+                    self.check_unique(table, index, &columns, &entry, None)?;
+                }
+                self.insert_entry(index, &columns, &entry)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_object(&mut self, drop: DropStatement) -> Result<()> {
+        let rows = self.get_schema_rows()?;
+        let kind = drop.kind.name();
+        let found = rows.iter().find(|(_, s)| {
+            s.name.eq_ignore_ascii_case(&drop.name) && (s.kind == "trigger") == (kind == "trigger")
+        });
+        let object = match found {
+            Some((_, object)) if object.kind != kind => bail!(
+                "use DROP {} to delete {} {}",
+                object.kind.to_uppercase(),
+                object.kind,
+                object.name
+            ),
+            Some((_, object)) => object,
+            None if drop.if_exists => return Ok(()),
+            None => bail!("no such {}: {}", kind, drop.name),
+        };
+        if object.is_autoindex() {
+            bail!("index associated with UNIQUE or PRIMARY KEY constraint cannot be dropped");
+        }
+
+        let dropped = rows.iter().filter(|(_, s)| match object.kind.as_str() {
+            "table" => s.table_name == object.name,
+            _ => s.kind == object.kind && s.name == object.name,
+        });
+        for (rowid, schema) in dropped {
+            if schema.root_page != 0 {
+                btree::free_tree(&mut self.pager, schema.root_page)?;
+            }
+            btree::delete_from_table(&mut self.pager, 1, *rowid)?;
+        }
+        if object.is_autoincrement() {
+            let schemas = self.get_schemas()?;
+            if let Some(sequence) = schemas.iter().find(|s| s.name == "sqlite_sequence") {
+                if let Some((rowid, _)) = self.sequence(&object.name, &schemas)? {
+                    btree::delete_from_table(&mut self.pager, sequence.root_page, rowid)?;
+                }
+            }
+        }
+        let header = self.pager.header_mut();
+        header.schema_cookie = header.schema_cookie.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Finds the rows of a rowid table matching a filter, returning them with their rowid
+    fn find_rows(
+        &self,
+        schema: &Schema,
+        filter: Option<Expr>,
+        schemas: &[Schema],
+    ) -> Result<(Columns, TableRows<i64>)> {
+        let source = TableSource::Table {
+            name: schema.name.clone(),
+            alias: None,
+        };
+        let select = Select {
+            distinct: false,
+            columns: vec![ResultColumn::All],
+            from: vec![source.clone()],
+            left_joins: vec![],
+            filter,
+            group_by: vec![],
+            having: None,
+            order_by: vec![],
+            limit: None,
+            offset: None,
+        };
+        let (columns, rows) = self.scan_table(&select, &source, &schema.name, schemas)?;
+        let columns = Columns::new(columns);
+        let mut matching = vec![];
+        for (rowid, values) in rows {
+            if let Some(filter) = &select.filter {
+                let row = Row {
+                    columns: &columns,
+                    values: &values,
+                    functions: &self.functions,
+                };
+                if filter.eval(&row)?.is_truthy() != Some(true) {
+                    continue;
+                }
+            }
+            matching.extend(rowid.map(|rowid| (rowid, values)));
+        }
+        Ok((columns, matching))
+    }
+
+    /// Turns the values of a row about to be written into the values of its columns, generated
+    /// ones included, and finds its rowid: the given one, that of its alias or a new one. It
+    /// must not be used by another row than the one being `replaced`, and neither must the keys
+    /// of its unique indexes
+    fn prepare_row(
+        &self,
+        schema: &Schema,
+        indexes: &[TableIndex],
+        mut values: Vec<Value>,
+        mut rowid: Option<Value>,
+        replaced: Option<i64>,
+        schemas: &[Schema],
+    ) -> Result<(i64, Vec<Value>)> {
+        let table_columns = schema.table_columns()?;
+        let rowid_alias = schema.rowid_alias();
+        for (value, column) in values.iter_mut().zip(table_columns) {
+            *value = value.apply_affinity(column.affinity());
+        }
+        // The rowid alias is stored as NULL, its value being the rowid of the row
+        if let Some(i) = rowid_alias {
+            let alias = std::mem::replace(&mut values[i], Value::Null);
+            if !alias.is_null() {
+                rowid = Some(alias);
+            }
+        }
+        let rowid = match rowid.map(|v| v.apply_affinity(Affinity::Integer)) {
+            None | Some(Value::Null) => self.new_rowid(schema, schemas)?,
+            Some(Value::Integer(rowid)) => {
+                if Some(rowid) != replaced
+                    && btree::contains_rowid(&self.pager, schema.root_page, rowid)?
+                {
+                    let name = rowid_alias.map_or("rowid", |i| &table_columns[i].name);
+                    bail!("UNIQUE constraint failed: {}.{}", schema.name, name);
+                }
+                rowid
+            }
+            Some(_) => bail!("datatype mismatch"),
+        };
+        // Generated columns and checks may read the rowid alias, which holds the rowid until the
+        // row is stored
+        let columns = table_source_columns(schema)?;
+        if let Some(i) = rowid_alias {
+            values[i] = Value::Integer(rowid);
+        }
+        self.generate_columns(table_columns, &columns, &mut values, true)?;
+        for (value, column) in values.iter().zip(table_columns) {
+            let not_null = column
+                .constraints
+                .iter()
+                .any(|c| matches!(c, ColumnConstraint::NotNull(_)));
+            if not_null && value.is_null() {
+                bail!(
+                    "NOT NULL constraint failed: {}.{}",
+                    schema.name,
+                    column.name
+                );
+            }
+        }
+        // A check fails when false, but not when NULL
+        for check in schema.checks()? {
+            let row = Row {
+                columns: &columns,
+                values: &values,
+                functions: &self.functions,
+            };
+            if check.expr.eval(&row)?.is_truthy() == Some(false) {
+                bail!("CHECK constraint failed: {}", check.name);
+            }
+        }
+        if let Some(i) = rowid_alias {
+            values[i] = Value::Null;
+        }
+
+        // Keys holding NULL never conflict, as NULL is distinct from every value
+        for index in indexes.iter().filter(|index| index.unique) {
+            let entry = match self.index_entry(index, &columns, &values, rowid)? {
+                Some(entry) => entry,
+                None => continue,
+            };
+            self.check_unique(schema, index, &columns, &entry, replaced)?;
+        }
+        Ok((rowid, values))
+    }
+
+    /// Makes sure no entry of a unique index other than that of the row being `replaced` has the
+    /// same key as `entry`
+    fn check_unique(
+        &self,
+        table: &Schema,
+        index: &TableIndex,
+        columns: &Columns,
+        entry: &[Value],
+        replaced: Option<i64>,
+    ) -> Result<()> {
+        let key = &entry[..index.columns.len()];
+        if key.iter().any(Value::is_null) {
+            return Ok(());
+        }
+        let order = index_order(&self.functions, index, columns)?;
+        let conflicts = btree::search_index(&self.pager, index.schema.root_page, &|entry| {
+            compare_keys(&order, entry, key)
+        })?;
+        if conflicts
+            .iter()
+            .any(|entry| entry.last().and_then(Value::as_integer) != replaced)
+        {
+            match index
+                .columns
+                .iter()
+                .map(|c| c.name())
+                .collect::<Option<Vec<_>>>()
+            {
+                Some(names) => bail!(
+                    "UNIQUE constraint failed: {}",
+                    names
+                        .iter()
+                        .map(|name| format!("{}.{}", table.name, name))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                None => bail!("UNIQUE constraint failed: index '{}'", index.schema.name),
+            }
+        }
+        Ok(())
+    }
+
+    /// Stores a row prepared by `prepare_row` into its table and its indexes
+    fn write_row(
+        &mut self,
+        schema: &Schema,
+        indexes: &[TableIndex],
+        rowid: i64,
+        values: &[Value],
+    ) -> Result<()> {
+        let schema_format = self.pager.header().schema_format;
+        let columns = table_source_columns(schema)?;
+        for index in indexes {
+            if let Some(entry) = self.index_entry(index, &columns, values, rowid)? {
+                self.insert_entry(index, &columns, &entry)?;
+            }
+        }
+        let stored = schema
+            .stored_columns()?
+            .into_iter()
+            .map(|i| values[i].clone())
+            .collect::<Vec<_>>();
+        let record = encode_record(&stored, schema_format);
+        let cell = btree::table_leaf_cell(&mut self.pager, rowid, &record)?;
+        btree::insert_into_table(&mut self.pager, schema.root_page, rowid, cell)
+    }
+
+    /// Inserts an entry computed by `index_entry` into its index
+    fn insert_entry(
+        &mut self,
+        index: &TableIndex,
+        columns: &Columns,
+        entry: &[Value],
+    ) -> Result<()> {
+        let order = index_order(&self.functions, index, columns)?;
+        let record = encode_record(entry, self.pager.header().schema_format);
+        let cell = btree::index_leaf_cell(&mut self.pager, &record)?;
+        btree::insert_into_index(&mut self.pager, index.schema.root_page, cell, &|e| {
+            compare_entries(&order, e, entry)
+        })
+    }
+
+    /// Removes a row from its table and its indexes
+    fn remove_row(
+        &mut self,
+        schema: &Schema,
+        indexes: &[TableIndex],
+        rowid: i64,
+        values: &[Value],
+    ) -> Result<()> {
+        let columns = table_source_columns(schema)?;
+        for index in indexes {
+            if let Some(entry) = self.index_entry(index, &columns, values, rowid)? {
+                let order = index_order(&self.functions, index, &columns)?;
+                btree::delete_from_index(&mut self.pager, index.schema.root_page, &|e| {
+                    compare_entries(&order, e, &entry)
+                })?;
+            }
+        }
+        btree::delete_from_table(&mut self.pager, schema.root_page, rowid)?;
+        Ok(())
+    }
+
+    /// Computes the entry of an index for a row: the values of its indexed columns followed by
+    /// its rowid. Rows a partial index leaves out have none
+    fn index_entry(
+        &self,
+        index: &TableIndex,
+        columns: &Columns,
+        values: &[Value],
+        rowid: i64,
+    ) -> Result<Option<Vec<Value>>> {
+        // The rowid alias is indexed with the value of the rowid, not the NULL stored for it
+        let mut values = values.to_vec();
+        if let Some(i) = index.table_rowid_alias {
+            values[i] = Value::Integer(rowid);
+        }
+        let row = Row {
+            columns,
+            values: &values,
+            functions: &self.functions,
+        };
+        if let Some(filter) = index.filter {
+            if filter.eval(&row)?.is_truthy() != Some(true) {
+                return Ok(None);
+            }
+        }
+        let mut entry = index
+            .columns
+            .iter()
+            .map(|column| column.expr.eval(&row))
+            .collect::<Result<Vec<_>>>()?;
+        entry.push(Value::Integer(rowid));
+        Ok(Some(entry))
+    }
+
+    /// Chooses the rowid of a new row: the one after the largest rowid of its table, or after
+    /// the largest one an AUTOINCREMENT table ever used. Once the largest possible rowid is
+    /// taken, unused ones are picked at random, except for AUTOINCREMENT tables, as mentioned
+    /// here: [rowid selection](https://www.sqlite.org/autoinc.html)
+    fn new_rowid(&self, schema: &Schema, schemas: &[Schema]) -> Result<i64> {
+        let max = btree::max_rowid(&self.pager, schema.root_page)?;
+        if schema.is_autoincrement() {
+            let seq = self
+                .sequence(&schema.name, schemas)?
+                .map_or(0, |(_, seq)| seq);
+            return match max.unwrap_or(0).max(seq).checked_add(1) {
+                Some(rowid) => Ok(rowid),
+                None => bail!("database or disk is full"),
+            };
+        }
+        match max {
+            Some(i64::MAX) => {}
+            Some(max) => return Ok(max + 1),
+            None => return Ok(1),
+        }
+        for _ in 0..100 {
+            let rowid = (random() & (i64::MAX >> 1)) + 1;
+            if !btree::contains_rowid(&self.pager, schema.root_page, rowid)? {
+                return Ok(rowid);
+            }
+        }
+        bail!("database or disk is full")
+    }
+
+    /// Returns the largest rowid an AUTOINCREMENT table has used, as kept in `sqlite_sequence`,
+    /// with the rowid of its row there
+    fn sequence(&self, table: &str, schemas: &[Schema]) -> Result<Option<(i64, i64)>> {
+        let sequence = match schemas.iter().find(|s| s.name == "sqlite_sequence") {
+            Some(sequence) => sequence,
+            None => return Ok(None),
+        };
+        let (_, rows) = self.find_rows(sequence, None, schemas)?;
+        let found = rows
+            .into_iter()
+            .find(|(_, values)| matches!(&values[0], Value::Text(name) if name == table));
+        Ok(found.map(|(rowid, values)| {
+            let seq = values[1].apply_affinity(Affinity::Integer);
+            (rowid, seq.as_integer().unwrap_or(0))
+        }))
+    }
+
+    /// Records a rowid inserted into an AUTOINCREMENT table in `sqlite_sequence`, when larger
+    /// than the ones it used before
+    fn update_sequence(&mut self, table: &str, rowid: i64, schemas: &[Schema]) -> Result<()> {
+        let sequence = match schemas.iter().find(|s| s.name == "sqlite_sequence") {
+            Some(sequence) => sequence,
+            None => bail!("no such table: main.sqlite_sequence"),
+        };
+        let (row_rowid, seq) = match self.sequence(table, schemas)? {
+            Some((_, seq)) if seq >= rowid => return Ok(()),
+            Some((row_rowid, _)) => {
+                btree::delete_from_table(&mut self.pager, sequence.root_page, row_rowid)?;
+                (row_rowid, rowid)
+            }
+            None => {
+                let max = btree::max_rowid(&self.pager, sequence.root_page)?;
+                (max.map_or(1, |max| max + 1), rowid.max(0))
+            }
+        };
+        let values = [Value::Text(table.to_string()), Value::Integer(seq)];
+        let record = encode_record(&values, self.pager.header().schema_format);
+        let cell = btree::table_leaf_cell(&mut self.pager, row_rowid, &record)?;
+        btree::insert_into_table(&mut self.pager, sequence.root_page, row_rowid, cell)
+    }
+
+    /// Runs a query, returning the columns of its result together with its rows
+    fn run_select(
+        &self,
+        select: &Select,
+        schemas: &[Schema],
+    ) -> Result<(Vec<SourceColumn>, Vec<Vec<Value>>)> {
+        // Sources are joined left to right, so that table-valued functions can take the columns
+        // of the sources before them as arguments
+        let mut columns: Vec<SourceColumn> = vec![];
+        let mut rows = vec![vec![]];
+        for (i, source) in select.from.iter().enumerate() {
+            // Each row so far joins with all the rows of the source
+            let (source_columns, joined): (Vec<SourceColumn>, Vec<Vec<Vec<Value>>>) = match source {
+                TableSource::Table { name, .. } => {
+                    let (source_columns, source_rows) =
+                        match schemas.iter().find(|s| s.kind == "view" && s.name == *name) {
+                            Some(view) => self.scan_view(select, source, view, schemas)?,
+                            None => {
+                                let (columns, rows) =
+                                    self.scan_table(select, source, name, schemas)?;
+                                (columns, rows.into_iter().map(|(_, row)| row).collect())
+                            }
+                        };
+                    let joined = rows
+                        .iter()
+                        .map(|left| {
+                            source_rows
+                                .iter()
+                                .map(|right| [&left[..], right].concat())
+                                .collect()
+                        })
+                        .collect();
+                    (source_columns, joined)
+                }
+                TableSource::Function { name, args, .. } => {
+                    let left_columns = Columns::new(columns.clone());
+                    let mut joined = vec![];
+                    for left in &rows {
+                        let row = Row {
+                            columns: &left_columns,
+                            values: left,
+                            functions: &self.functions,
+                        };
+                        let args = args
+                            .iter()
+                            .map(|arg| arg.eval(&row))
+                            .collect::<Result<Vec<_>>>()?;
+                        let right_rows = call_table(name, &args)?;
+                        joined.push(
+                            right_rows
+                                .iter()
+                                .map(|right| [&left[..], right].concat())
+                                .collect(),
+                        );
+                    }
+                    let source_columns = table_columns(name)?
+                        .into_iter()
+                        .map(|column| SourceColumn {
+                            name: format!("{}.{}", source.alias(), column),
+                            affinity: Affinity::Blob,
+                            collation: None,
+                            hidden: false,
+                        })
+                        .collect();
+                    (source_columns, joined)
+                }
+            };
+            let width = source_columns.len();
+            columns.extend(source_columns);
+            let left_join = match select.left_joins.iter().find(|join| join.source == i) {
+                Some(left_join) => left_join,
+                None => {
+                    rows = joined.into_iter().flatten().collect();
+                    continue;
+                }
+            };
+            // A row that no row of a left join matches is kept, with NULL for its columns
+            let joined_columns = Columns::new(columns.clone());
+            let mut left_joined = vec![];
+            for (left, candidates) in rows.iter().zip(joined) {
+                let matched = left_joined.len();
+                for candidate in candidates {
+                    let row = Row {
+                        columns: &joined_columns,
+                        values: &candidate,
+                        functions: &self.functions,
+                    };
+                    let matches = match &left_join.constraint {
+                        Some(constraint) => constraint.eval(&row)?.is_truthy() == Some(true),
+                        None => true,
+                    };
+                    if matches {
+                        left_joined.push(candidate);
+                    }
+                }
+                if left_joined.len() == matched {
+                    left_joined.push([&left[..], &vec![Value::Null; width]].concat());
+                }
+            }
+            rows = left_joined;
+        }
+
+        let columns = Columns::new(columns);
+        let rows = query::run(select, &columns, rows, &self.functions)?;
+        Ok((query::output_columns(select, &columns), rows))
+    }
+
+    /// Reads the rows of a view by running its query. When the view is the only source, the
+    /// terms of the filter that only read the view are pushed down into its query, where they
+    /// can use the indexes of the tables it reads
+    fn scan_view(
+        &self,
+        select: &Select,
+        source: &TableSource,
+        view: &Schema,
+        schemas: &[Schema],
+    ) -> Result<(Vec<SourceColumn>, Vec<Vec<Value>>)> {
+        let (names, view_query) = match &view.sql {
+            Some(CreateStatement::CreateView {
+                columns,
+                select: Some(select),
+                ..
+            }) => (columns, select),
+            Some(CreateStatement::CreateView { select: None, .. }) => {
+                bail!(
+                    "cannot query view {}: its query is not supported",
+                    view.name
+                )
+            }
+            _ => return Err(view.wrong_kind("a view")),
+        };
+
+        let mut query = (**view_query).clone();
+        if let (Some(filter), [_]) = (&select.filter, &select.from[..]) {
+            let pushed = filter
+                .conjuncts()
+                .into_iter()
+                .filter_map(|term| push_down(term, source, names, view_query, &self.functions))
+                .chain(query.filter.take())
+                .reduce(|left, right| {
+                    Expr::Binary(Box::new(left), BinaryOperator::And, Box::new(right))
+                });
+            query.filter = pushed;
+        }
+
+        let (columns, rows) = self.run_select(&query, schemas)?;
+        if !names.is_empty() && names.len() != columns.len() {
+            bail!(
+                "expected {} columns for '{}' but got {}",
+                names.len(),
+                view.name,
+                columns.len()
+            );
+        }
+        let columns = columns
+            .into_iter()
+            .enumerate()
+            .map(|(i, column)| SourceColumn {
+                name: format!(
+                    "{}.{}",
+                    source.alias(),
+                    names.get(i).unwrap_or(&column.name)
+                ),
+                ..column
+            })
+            .collect();
+        Ok((columns, rows))
+    }
+
+    /// Reads the rows of a table, through an index when the query is on that table alone and
+    /// its filter compares an indexed column with a constant. Values are returned with the
+    /// affinity of their column applied, which turns the integers stored in REAL columns back
+    /// into reals, together with their rowid unless the table is WITHOUT ROWID
+    fn scan_table(
+        &self,
+        select: &Select,
+        source: &TableSource,
+        table: &str,
+        schemas: &[Schema],
+    ) -> Result<(Vec<SourceColumn>, TableRows<Option<i64>>)> {
         let schema = schemas
             .iter()
-            .find(|s| s.name == select.table)
-            .ok_or_else(|| Error::msg(format!("Table {} not found", select.table)))?;
-
-        let columns = schema.columns()?;
-        let indices: HashMap<&String, usize> =
-            columns.iter().enumerate().map(|(i, &v)| (v, i)).collect();
-        let selected_indices = get_selected_column_indices(&select, &indices)?;
-
-        let rows = match (select.filter, index) {
-            (None, _) => self.get_payload(columns.len(), schema.root_page, None)?,
-            (Some((_, val)), Some(ind)) => {
-                let cols_in_index = ind.columns()?.len();
-                let keys = self.search_in_index(cols_in_index, ind.root_page, &val, vec![])?;
-                self.get_payload(columns.len(), schema.root_page, Some(keys))?
-            }
-            (Some((col, val)), None) => {
-                let filter_index = indices
-                    .get(&col.to_owned())
-                    .copied()
-                    .ok_or_else(|| Error::msg(format!("Column {} not found", col)))?;
-
-                self.get_payload(columns.len(), schema.root_page, None)?
-                    .into_iter()
-                    .filter(|row| row[filter_index] == val)
-                    .collect()
+            .find(|s| s.name == table)
+            .ok_or_else(|| Error::msg(format!("Table {} not found", table)))?;
+
+        let table_columns = schema.table_columns()?;
+        let mut columns = table_columns
+            .iter()
+            .map(|column| SourceColumn {
+                name: format!("{}.{}", source.alias(), column.name),
+                affinity: column.affinity(),
+                collation: column.collation().map(String::from),
+                hidden: false,
+            })
+            .collect::<Vec<_>>();
+        let column_count = columns.len();
+        // Rows written before a column was added to the table read its default value instead
+        let defaults = table_columns
+            .iter()
+            .zip(&columns)
+            .map(|(column, source_column)| match column.default_value() {
+                Some(default) => Ok(self
+                    .eval_constant(default)?
+                    .apply_affinity(source_column.affinity)),
+                None => Ok(Value::Null),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // A WITHOUT ROWID table is an index whose entries start with the primary key
+        let without_rowid = schema.is_without_rowid();
+        let stored = schema.stored_columns()?;
+
+        // Rowid tables have their rowid as hidden columns, under the names no column takes
+        let rowid_alias = schema.rowid_alias();
+        if !without_rowid {
+            for name in ROWID_NAMES {
+                if !table_columns
+                    .iter()
+                    .any(|c| c.name.eq_ignore_ascii_case(name))
+                {
+                    columns.push(SourceColumn {
+                        name: format!("{}.{}", source.alias(), name),
+                        affinity: Affinity::Integer,
+                        collation: None,
+                        hidden: true,
+                    });
+                }
+            }
+        }
+
+        let query_columns = Columns::new(columns.clone());
+        let (rowid_seek, seek) = match (&select.from[..], without_rowid) {
+            ([_], false) => {
+                let is_rowid = |i| i >= column_count || Some(i) == rowid_alias;
+                match find_rowid_seek(select, &query_columns, is_rowid, &self.functions) {
+                    Some(value) => (Some(value), None),
+                    None => (
+                        None,
+                        find_index_seek(select, schema, schemas, &query_columns, &self.functions),
+                    ),
+                }
             }
+            ([_], true) => (
+                None,
+                find_index_seek(select, schema, schemas, &query_columns, &self.functions),
+            ),
+            _ => (None, None),
+        };
+        let eval_constant = |expr: &Expr, affinity| -> Result<Value> {
+            let (value, _) = apply_comparison_affinity(
+                (self.eval_constant(expr)?, Affinity::Blob),
+                (Value::Null, affinity),
+            );
+            Ok(value)
+        };
+        let with_rowids = |rows: Vec<(i64, Vec<Value>)>| {
+            rows.into_iter()
+                .map(|(rowid, values)| (Some(rowid), values))
+                .collect::<Vec<_>>()
+        };
+        let without_rowids = |rows: Vec<Vec<Value>>| {
+            rows.into_iter()
+                .map(|values| (None, values))
+                .collect::<Vec<_>>()
         };
 
-        Ok(rows
+        let rows = match (rowid_seek, seek, without_rowid) {
+            (Some(value), _, _) => {
+                // No row matches a value that is not an integer
+                let keys = match eval_constant(value, Affinity::Integer)? {
+                    Value::Integer(key) => vec![key],
+                    _ => vec![],
+                };
+                with_rowids(self.get_payload(stored.len(), schema.root_page, Some(keys))?)
+            }
+            (None, Some(seek), _) => {
+                let value = eval_constant(seek.value, seek.affinity)?;
+                let collation = self.functions.collation(seek.collation.as_deref())?;
+                let order = (collation, seek.descending);
+                match without_rowid {
+                    true => without_rowids(self.search_index(
+                        stored.len(),
+                        schema.root_page,
+                        &value,
+                        order,
+                        vec![],
+                    )?),
+                    false => {
+                        // Entries of an index end with the rowid of their row
+                        let record_len = seek.column_count + 1;
+                        let keys = self
+                            .search_index(record_len, seek.index.root_page, &value, order, vec![])?
+                            .iter()
+                            .filter_map(|entry| entry.last()?.as_integer())
+                            .collect();
+                        with_rowids(self.get_payload(stored.len(), schema.root_page, Some(keys))?)
+                    }
+                }
+            }
+            (None, None, true) => {
+                without_rowids(self.scan_index(stored.len(), schema.root_page, vec![])?)
+            }
+            (None, None, false) => {
+                with_rowids(self.get_payload(stored.len(), schema.root_page, None)?)
+            }
+        };
+        let rows = rows
             .into_iter()
-            .map(|row| {
-                selected_indices
+            .map(|(rowid, values)| {
+                let mut row = defaults.clone();
+                for (&i, value) in stored.iter().zip(values) {
+                    row[i] = value;
+                }
+                if let (Some(rowid), Some(i)) = (rowid, rowid_alias) {
+                    row[i] = Value::Integer(rowid);
+                }
+                let mut row = row
                     .iter()
-                    .map(|&i| row[i].clone())
-                    .collect::<Vec<_>>()
+                    .zip(&columns)
+                    .map(|(value, column)| value.apply_affinity(column.affinity))
+                    .collect::<Vec<_>>();
+                if let Some(rowid) = rowid {
+                    row.resize(columns.len(), Value::Integer(rowid));
+                }
+                self.generate_columns(table_columns, &query_columns, &mut row, false)?;
+                Ok((rowid, row))
             })
-            .collect())
+            .collect::<Result<_>>()?;
+        Ok((columns, rows))
+    }
+
+    /// Evaluates an expression that reads no column
+    fn eval_constant(&self, expr: &Expr) -> Result<Value> {
+        expr.eval(&Row {
+            columns: &Columns::new(vec![]),
+            values: &[],
+            functions: &self.functions,
+        })
     }
 
-    fn search_in_index(
+    /// Computes the generated columns of a row from its other columns. Only the VIRTUAL ones are
+    /// computed unless `with_stored` is set, the STORED ones being read from the records. Columns
+    /// may be generated from each other, so they are computed as many times as there are
+    fn generate_columns(
         &self,
-        column_count: usize,
+        table_columns: &[Column],
+        columns: &Columns,
+        values: &mut [Value],
+        with_stored: bool,
+    ) -> Result<()> {
+        let generated = table_columns
+            .iter()
+            .enumerate()
+            .filter_map(|(i, column)| match column.generated() {
+                Some((expr, stored)) if with_stored || !stored => {
+                    Some((i, expr, column.affinity()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        for _ in 0..generated.len() {
+            for &(i, expr, affinity) in &generated {
+                let row = Row {
+                    columns,
+                    values,
+                    functions: &self.functions,
+                };
+                values[i] = expr.eval(&row)?.apply_affinity(affinity);
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds the entries of an index whose first column equals `value`, each having
+    /// `record_len` values
+    fn search_index(
+        &self,
+        record_len: usize,
         page_number: usize,
         value: &Value,
-        mut buffer: Vec<usize>,
-    ) -> Result<Vec<usize>> {
+        order: (Collation, bool),
+        mut buffer: Vec<Vec<Value>>,
+    ) -> Result<Vec<Vec<Value>>> {
         let (_, page_header, page) = self.read_page(page_number)?;
+        // Compares a value with a key in the order of the index, which may be descending
+        let (collation, descending) = &order;
+        let compare = |value: &Value, key: &Value| match descending {
+            false => collation.compare(value, key),
+            true => collation.compare(key, value),
+        };
 
         match page_header.page_type {
             BTreePage::InteriorIndex => {
-                let index_btree = parse_index_interior(&page, page_header, column_count)?;
-                let branch = index_btree.left.into_iter().find(|(_, vs)| value <= &vs[0]);
-                match &branch {
-                    Some((_, vs)) if &vs[0] == value => {
-                        buffer.push(vs[vs.len() - 1].get_numeric_value().unwrap() as usize)
+                let index_btree =
+                    parse_index_interior(&self.pager, &page, page_header, record_len)?;
+                // Keys equal to the value may span several children, up to the first greater key
+                for (page, vs) in index_btree.left {
+                    let ordering = compare(value, &vs[0]);
+                    if ordering != Ordering::Greater {
+                        buffer = self.search_index(record_len, page, value, order, buffer)?;
+                    }
+                    match ordering {
+                        Ordering::Less => return Ok(buffer),
+                        Ordering::Equal => buffer.push(vs),
+                        Ordering::Greater => {}
                     }
-                    _ => {}
                 }
-                let page = branch.map(|(page, _)| page).unwrap_or(index_btree.right);
-                self.search_in_index(column_count, page, value, buffer)
+                self.search_index(record_len, index_btree.right, value, order, buffer)
             }
             BTreePage::LeafIndex => {
-                let res: Vec<usize> = parse_index_leaf(&page, page_header, column_count + 1)?
-                    .into_iter()
-                    .filter(|row| &row[0] == value)
-                    .map(|row| row[column_count].get_numeric_value().unwrap() as usize)
-                    .collect();
-                buffer.extend(res);
+                buffer.extend(
+                    parse_index_leaf(&self.pager, &page, page_header, record_len)?
+                        .into_iter()
+                        .filter(|row| compare(value, &row[0]) == Ordering::Equal),
+                );
+                Ok(buffer)
+            }
+            _ => bail!("This is a table, not an index"),
+        }
+    }
+
+    /// Reads all the entries of an index in order, each having `record_len` values
+    fn scan_index(
+        &self,
+        record_len: usize,
+        page_number: usize,
+        mut buffer: Vec<Vec<Value>>,
+    ) -> Result<Vec<Vec<Value>>> {
+        let (_, page_header, page) = self.read_page(page_number)?;
+        match page_header.page_type {
+            BTreePage::InteriorIndex => {
+                let index_btree =
+                    parse_index_interior(&self.pager, &page, page_header, record_len)?;
+                for (page, vs) in index_btree.left {
+                    buffer = self.scan_index(record_len, page, buffer)?;
+                    buffer.push(vs);
+                }
+                self.scan_index(record_len, index_btree.right, buffer)
+            }
+            BTreePage::LeafIndex => {
+                buffer.extend(parse_index_leaf(
+                    &self.pager,
+                    &page,
+                    page_header,
+                    record_len,
+                )?);
                 Ok(buffer)
             }
             _ => bail!("This is a table, not an index"),
@@ -123,24 +1384,29 @@ impl DB {
     }
 
     fn get_schemas(&self) -> Result<Vec<Schema>> {
-        Ok(self
-            .get_payload(5, 1, None)?
+        self.get_schema_rows()
+            .map(|rows| rows.into_iter().map(|(_, schema)| schema).collect())
+    }
+
+    /// Returns the rows of `sqlite_schema`, with their rowid
+    fn get_schema_rows(&self) -> Result<Vec<(i64, Schema)>> {
+        self.get_payload(5, 1, None)?
             .into_iter()
-            .map(|record| Schema::parse(record).expect("Invalid record"))
-            .collect::<Vec<_>>())
+            .map(|(rowid, record)| Ok((rowid, Schema::parse(record)?)))
+            .collect()
     }
 
     fn get_payload(
         &self,
         column_count: usize,
         page_number: usize,
-        keys: Option<Vec<usize>>,
-    ) -> Result<Vec<Vec<Value>>> {
+        keys: Option<Vec<i64>>,
+    ) -> Result<Vec<(i64, Vec<Value>)>> {
         let (offset, page_header, page) = self.read_page(page_number)?;
 
         match (&page_header.page_type, keys) {
             (BTreePage::LeafTable, None) => {
-                parse_table_leaf(&page, offset, page_header, column_count)
+                parse_table_leaf(&self.pager, &page, offset, page_header, column_count)
             }
             (BTreePage::InteriorTable, None) => parse_table_interior(&page, offset, page_header)?
                 .pages()
@@ -149,15 +1415,17 @@ impl DB {
                 .collect::<Result<Vec<_>>>()
                 .map(|contents| contents.into_iter().flatten().collect::<Vec<_>>()),
             (BTreePage::LeafTable, Some(pks)) => {
-                let res = parse_table_leaf(&page, offset, page_header, column_count)?
+                let res = parse_table_leaf(&self.pager, &page, offset, page_header, column_count)?
                     .into_iter()
-                    .filter(|vs| pks.contains(&(vs[0].get_numeric_value().unwrap() as usize)))
+                    .filter(|(rowid, _)| pks.contains(rowid))
                     .collect();
                 Ok(res)
             }
-            (BTreePage::InteriorTable, Some(pks)) => {
+            (BTreePage::InteriorTable, Some(mut pks)) => {
                 let tree = parse_table_interior(&page, offset, page_header)?;
-                let mut pages_and_keys = HashMap::new();
+                // Sorted keys fall into the children in order, so rows come out in rowid order
+                pks.sort_unstable();
+                let mut pages_and_keys: Vec<(usize, Vec<i64>)> = vec![];
 
                 for key in pks {
                     let page = tree
@@ -166,10 +1434,10 @@ impl DB {
                         .find(|cell| key <= cell.key)
                         .map(|cell| cell.left_child_page)
                         .unwrap_or(tree.right);
-                    pages_and_keys
-                        .entry(page)
-                        .or_insert_with(Vec::new)
-                        .push(key)
+                    match pages_and_keys.last_mut() {
+                        Some((last, keys)) if *last == page => keys.push(key),
+                        _ => pages_and_keys.push((page, vec![key])),
+                    }
                 }
 
                 pages_and_keys
@@ -184,56 +1452,477 @@ impl DB {
 
     fn read_page(&self, page_number: usize) -> Result<(usize, PageHeader, Vec<u8>)> {
         let db_header_offset = if page_number == 1 { 100 } else { 0 };
-        let page_address = ((page_number - 1) * self.header.page_size) as u64;
-
-        let mut page = vec![0; self.header.page_size];
-        self.file.read_exact_at(&mut page, page_address)?;
+        let page = self.pager.read_page(page_number)?;
         let page_header = PageHeader::parse(&page[db_header_offset..])?;
         Ok((db_header_offset, page_header, page))
     }
 }
 
-fn find_applicable_index<'a, 'b>(
-    select: &'a Select,
-    schemas: &'b [Schema],
-) -> Result<Option<&'b Schema>> {
-    match select.filter {
-        Some((k, _)) => {
-            let mut res = None;
-            for s in schemas.iter() {
-                let is_index_applicable = match s.columns() {
-                    Ok(cols) => cols.contains(&&k.to_string()),
-                    _ => false,
-                };
-                if s.kind == "index" && s.table_name == select.table && is_index_applicable {
-                    res = Some(s);
-                    break;
+/// Finds a table that rows can be written to
+fn writable_table<'a>(schemas: &'a [Schema], table: &str) -> Result<&'a Schema> {
+    match schemas.iter().find(|s| s.name == table) {
+        Some(schema) if schema.kind != "table" => bail!(
+            "cannot modify {} because it is a {}",
+            schema.name,
+            schema.kind
+        ),
+        Some(schema) if schema.is_without_rowid() => {
+            bail!("Cannot write to WITHOUT ROWID table {}", schema.name)
+        }
+        Some(schema) => Ok(schema),
+        None => bail!("no such table: {}", table),
+    }
+}
+
+/// Makes sure the definition of a new table is one SQLite accepts
+fn check_table(table: &Schema) -> Result<()> {
+    let (columns, constraints) = match &table.sql {
+        Some(CreateStatement::CreateTable {
+            columns,
+            constraints,
+            ..
+        }) => (columns, constraints),
+        _ => bail!("{} is not a table", table.name),
+    };
+    for (i, column) in columns.iter().enumerate() {
+        if columns[..i]
+            .iter()
+            .any(|c| c.name.eq_ignore_ascii_case(&column.name))
+        {
+            bail!("duplicate column name: {}", column.name);
+        }
+    }
+    let key_count = columns.iter().filter(|c| c.is_primary_key()).count()
+        + constraints
+            .iter()
+            .filter(|c| matches!(c, TableConstraint::PrimaryKey { .. }))
+            .count();
+    let autoincrement = columns.iter().any(|column| {
+        column.constraints.iter().any(|c| {
+            matches!(
+                c,
+                ColumnConstraint::PrimaryKey {
+                    autoincrement: true,
+                    ..
                 }
+            )
+        })
+    });
+    if autoincrement && table.is_without_rowid() {
+        bail!("AUTOINCREMENT not allowed on WITHOUT ROWID tables");
+    }
+    if autoincrement && !table.is_autoincrement() {
+        bail!("AUTOINCREMENT is only allowed on an INTEGER PRIMARY KEY");
+    }
+    match key_count {
+        0 if table.is_without_rowid() => bail!("PRIMARY KEY missing on table {}", table.name),
+        0 | 1 => Ok(()),
+        _ => bail!("table \"{}\" has more than one primary key", table.name),
+    }
+}
+
+/// An index of a table, with the columns its entries start with
+struct TableIndex<'a> {
+    schema: &'a Schema,
+    columns: Vec<IndexedColumn>,
+    unique: bool,
+    filter: Option<&'a Expr>,
+    table_rowid_alias: Option<usize>,
+}
+
+/// Returns the indexes of a table, including those created for its `UNIQUE` and `PRIMARY KEY`
+/// constraints
+fn table_indexes<'a>(table: &Schema, schemas: &'a [Schema]) -> Result<Vec<TableIndex<'a>>> {
+    schemas
+        .iter()
+        .filter(|s| s.kind == "index" && s.table_name == table.name)
+        .map(|index| table_index(table, index))
+        .collect()
+}
+
+/// Returns an index of a table, whose columns come from the constraints of the table when
+/// SQLite created it for one
+fn table_index<'a>(table: &Schema, index: &'a Schema) -> Result<TableIndex<'a>> {
+    let unique = match &index.sql {
+        Some(CreateStatement::CreateIndex { unique, .. }) => *unique,
+        _ => index.is_autoindex(),
+    };
+    Ok(TableIndex {
+        schema: index,
+        columns: index_columns(table, index)?,
+        unique,
+        filter: index.index_filter(),
+        table_rowid_alias: table.rowid_alias(),
+    })
+}
+
+/// Returns the collating sequence and the direction of each indexed column. Columns use their
+/// own collation unless the index gives one
+fn index_order<'a>(
+    functions: &'a Functions,
+    index: &TableIndex,
+    columns: &Columns,
+) -> Result<Vec<(Collation<'a>, bool)>> {
+    index
+        .columns
+        .iter()
+        .map(|column| {
+            let collation = match &column.collation {
+                Some(collation) => Some(collation.as_str()),
+                None => column.expr.collation(columns).map(|(name, _)| name),
+            };
+            Ok((functions.collation(collation)?, column.descending))
+        })
+        .collect()
+}
+
+/// Compares the key of an index entry, its values before the rowid, with a key in the order of
+/// the index
+fn compare_keys(order: &[(Collation, bool)], entry: &[Value], key: &[Value]) -> Ordering {
+    order
+        .iter()
+        .zip(entry.iter().zip(key))
+        .map(|((collation, descending), (a, b))| match descending {
+            false => collation.compare(a, b),
+            true => collation.compare(b, a),
+        })
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Compares two entries of an index, entries with the same key being ordered by rowid
+fn compare_entries(order: &[(Collation, bool)], entry: &[Value], other: &[Value]) -> Ordering {
+    let rowid = |entry: &[Value]| entry.last().and_then(Value::as_integer);
+    compare_keys(order, entry, other).then_with(|| rowid(entry).cmp(&rowid(other)))
+}
+
+/// Rewrites a term of the filter of a query on a view into a term of the query of the view, by
+/// replacing the columns of the view with the expressions they stand for. Terms cannot be pushed
+/// into a query that groups, deduplicates or limits its rows, nor can terms that are not
+/// deterministic
+fn push_down(
+    term: &Expr,
+    source: &TableSource,
+    names: &[String],
+    query: &Select,
+    functions: &Functions,
+) -> Option<Expr> {
+    let aggregates = !query.group_by.is_empty()
+        || query.having.is_some()
+        || query.columns.iter().any(|column| match column {
+            ResultColumn::Expr(expr, _) => expr.has_aggregate(functions),
+            ResultColumn::All => false,
+        });
+    if aggregates || query.distinct || query.limit.is_some() || query.offset.is_some() {
+        return None;
+    }
+    if !term.is_deterministic(functions) {
+        return None;
+    }
+    // Without `*`, every column of the view is one of the result columns of its query
+    let outputs = query
+        .columns
+        .iter()
+        .map(|column| match column {
+            ResultColumn::Expr(expr, alias) => Some((expr, alias)),
+            ResultColumn::All => None,
+        })
+        .collect::<Option<Vec<_>>>();
+    if outputs.is_none() && !names.is_empty() {
+        return None;
+    }
+
+    fn replace_columns(expr: &Expr, resolve: &dyn Fn(&str) -> Option<Expr>) -> Result<Expr> {
+        match expr {
+            Expr::Column(name) => {
+                resolve(name).ok_or_else(|| Error::msg(format!("Column {} not found", name)))
             }
-            Ok(res)
+            expr => expr.map_children(|child| replace_columns(child, resolve)),
         }
-        None => Ok(None),
     }
+    let resolve = |name: &str| {
+        let name = match name.split_once('.') {
+            Some((table, name)) if table.eq_ignore_ascii_case(source.alias()) => name,
+            Some(_) => return None,
+            None => name,
+        };
+        let outputs = match &outputs {
+            Some(outputs) => outputs,
+            // A view reading `*` has the same column names as its sources
+            None => return Some(Expr::Column(name.to_owned())),
+        };
+        outputs
+            .iter()
+            .enumerate()
+            .find(|(i, (expr, alias))| {
+                let output_name = match (names.get(*i), alias, expr) {
+                    (Some(name), _, _) | (None, Some(name), _) => name.as_str(),
+                    (None, None, Expr::Column(column)) => {
+                        column.rsplit('.').next().unwrap_or(column)
+                    }
+                    (None, None, _) => return false,
+                };
+                output_name.eq_ignore_ascii_case(name)
+            })
+            .map(|(_, (expr, _))| (*expr).clone())
+    };
+    replace_columns(term, &resolve).ok()
 }
 
-fn get_selected_column_indices(
-    select: &Select,
-    indices: &HashMap<&String, usize>,
-) -> Result<Vec<usize>> {
-    let selected_indices = select
-        .columns
+/// The names the rowid of a row can be read by, as mentioned here:
+/// [rowid](https://www.sqlite.org/lang_createtable.html#rowid)
+const ROWID_NAMES: [&str; 3] = ["rowid", "_rowid_", "oid"];
+
+/// Finds the column a value is assigned to by a `statement`, `None` standing for the rowid when
+/// assigned to a rowid name that no column takes. Generated columns cannot be assigned
+fn find_target(schema: &Schema, name: &str, statement: &str) -> Result<Option<usize>> {
+    let columns = schema.table_columns()?;
+    let position = columns
+        .iter()
+        .position(|c| c.name.eq_ignore_ascii_case(name));
+    match position {
+        Some(i) if columns[i].generated().is_some() => bail!(
+            "cannot {} generated column \"{}\"",
+            statement,
+            columns[i].name
+        ),
+        Some(i) => Ok(Some(i)),
+        None if ROWID_NAMES.contains(&name.to_lowercase().as_str()) => Ok(None),
+        None => bail!("table {} has no column named {}", schema.name, name),
+    }
+}
+
+/// Returns the columns of a table, to evaluate expressions on its rows
+fn table_source_columns(table: &Schema) -> Result<Columns> {
+    let columns = table
+        .table_columns()?
         .iter()
-        .map(|&c| {
-            indices
-                .get(&c.to_owned())
-                .copied()
-                .ok_or_else(|| Error::msg(format!("Column {} not found", c)))
+        .map(|column| SourceColumn {
+            name: format!("{}.{}", table.name, column.name),
+            affinity: column.affinity(),
+            collation: column.collation().map(String::from),
+            hidden: false,
         })
-        .collect::<Result<Vec<usize>>>()?;
+        .collect();
+    Ok(Columns::new(columns))
+}
+
+/// Finds a constant the rowid is compared for equality with in the filter, where `is_rowid` tells
+/// which columns hold the rowid
+fn find_rowid_seek<'a>(
+    select: &'a Select,
+    columns: &Columns,
+    is_rowid: impl Fn(usize) -> bool,
+    functions: &Functions,
+) -> Option<&'a Expr> {
+    let filter = select.filter.as_ref()?;
+    filter.conjuncts().into_iter().find_map(|term| match term {
+        Expr::Binary(left, BinaryOperator::Eq, right) => match (&**left, &**right) {
+            (Expr::Column(c), v) | (v, Expr::Column(c))
+                if v.is_constant(functions) && columns.position(c).is_some_and(&is_rowid) =>
+            {
+                Some(v)
+            }
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+/// A lookup in an index for the rows whose first indexed column equals a constant
+struct IndexSeek<'a> {
+    index: &'a Schema,
+    column_count: usize,
+    affinity: Affinity,
+    collation: Option<String>,
+    descending: bool,
+    value: &'a Expr,
+}
 
-    if selected_indices.is_empty() {
-        bail!("Columns {} not found", select.columns.join(","))
+/// Finds an index whose first column is compared for equality with a constant in the filter, using
+/// the collation the index is ordered by. A partial index is only used when every term of its
+/// condition is also a term of the filter. A WITHOUT ROWID table is searched as the index of its
+/// primary key
+fn find_index_seek<'a>(
+    select: &'a Select,
+    table: &'a Schema,
+    schemas: &'a [Schema],
+    columns: &Columns,
+    functions: &Functions,
+) -> Option<IndexSeek<'a>> {
+    let conjuncts = select.filter.as_ref()?.conjuncts();
+    let applicable = |index: &Schema| match index.index_filter() {
+        Some(condition) => condition
+            .conjuncts()
+            .iter()
+            .all(|term| conjuncts.iter().any(|c| c.is_equivalent(term))),
+        None => true,
+    };
+    let candidates = match table.is_without_rowid() {
+        // Its other indexes lead to primary keys rather than rowids, so only the table is searched
+        true => table
+            .primary_key()
+            .ok()?
+            .into_iter()
+            .take(1)
+            .map(|(_, column)| (table, vec![column]))
+            .collect::<Vec<_>>(),
+        false => schemas
+            .iter()
+            .filter(|s| s.kind == "index" && s.table_name == table.name && applicable(s))
+            .filter_map(|index| Some((index, index_columns(table, index).ok()?)))
+            .collect(),
+    };
+    conjuncts.iter().find_map(|term| {
+        let (left, right) = match term {
+            Expr::Binary(left, BinaryOperator::Eq, right) => (&**left, &**right),
+            _ => return None,
+        };
+        let (indexed_expr, value) = match (left, right) {
+            (e, v) | (v, e) if v.is_constant(functions) && !e.is_constant(functions) => (e, v),
+            _ => return None,
+        };
+        let comparison = comparison_collation(left, right, columns).unwrap_or("binary");
+        candidates.iter().find_map(|(index, index_columns)| {
+            let indexed = index_columns.first()?;
+            let collation = indexed.collation.clone().or_else(|| {
+                let name = indexed.name()?;
+                table
+                    .table_columns()
+                    .ok()?
+                    .iter()
+                    .find(|c| c.name.eq_ignore_ascii_case(name))?
+                    .collation()
+                    .map(String::from)
+            });
+            let matches = indexed.expr.is_equivalent(indexed_expr)
+                && collation
+                    .as_deref()
+                    .unwrap_or("binary")
+                    .eq_ignore_ascii_case(comparison);
+            matches.then(|| IndexSeek {
+                index,
+                column_count: index_columns.len(),
+                affinity: indexed_expr.affinity(columns),
+                collation,
+                descending: indexed.descending,
+                value,
+            })
+        })
+    })
+}
+
+/// Returns the columns of an index, which for the indexes SQLite creates for `UNIQUE` and
+/// `PRIMARY KEY` constraints come from the constraints of their table
+fn index_columns(table: &Schema, index: &Schema) -> Result<Vec<IndexedColumn>> {
+    if !index.is_autoindex() {
+        return Ok(index.index_columns()?.to_vec());
     }
+    // Indexes of constraints are numbered from 1, in the order of the constraints
+    index
+        .name
+        .rsplit('_')
+        .next()
+        .and_then(|n| n.parse::<usize>().ok()?.checked_sub(1))
+        .and_then(|n| table.constraint_indexes().ok()?.into_iter().nth(n))
+        .ok_or_else(|| Error::msg(format!("No constraint found for index {}", index.name)))
+}
 
-    Ok(selected_indices)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(kind: &str, name: &str, table: &str, sql: Option<&str>) -> Schema {
+        Schema::parse(vec![
+            Value::Text(kind.to_string()),
+            Value::Text(name.to_string()),
+            Value::Text(table.to_string()),
+            Value::Integer(2),
+            sql.map_or(Value::Null, |sql| Value::Text(sql.to_string())),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn seeks_the_indexes_of_constraints() {
+        let schemas = [
+            schema(
+                "table",
+                "t",
+                "t",
+                Some("CREATE TABLE t (a TEXT PRIMARY KEY, b COLLATE NOCASE UNIQUE, c, UNIQUE (c COLLATE NOCASE))"),
+            ),
+            schema("index", "sqlite_autoindex_t_1", "t", None),
+            schema("index", "sqlite_autoindex_t_2", "t", None),
+            schema("index", "sqlite_autoindex_t_3", "t", None),
+        ];
+        let columns = Columns::new(
+            schemas[0]
+                .table_columns()
+                .unwrap()
+                .iter()
+                .map(|column| SourceColumn {
+                    name: format!("t.{}", column.name),
+                    affinity: column.affinity(),
+                    collation: column.collation().map(String::from),
+                    hidden: false,
+                })
+                .collect(),
+        );
+        let functions = Functions::default();
+        let seek = |filter: &str| {
+            let select =
+                Select::parse_select(&format!("SELECT * FROM t WHERE {}", filter)).unwrap();
+            find_index_seek(&select, &schemas[0], &schemas, &columns, &functions)
+                .map(|seek| (seek.index.name.clone(), seek.collation))
+        };
+        let autoindex = |n: usize, collation: Option<&str>| {
+            Some((
+                format!("sqlite_autoindex_t_{}", n),
+                collation.map(String::from),
+            ))
+        };
+        assert_eq!(seek("a = 'x'"), autoindex(1, None));
+        assert_eq!(seek("'x' = b"), autoindex(2, Some("NOCASE")));
+        assert_eq!(seek("c = 'x' COLLATE nocase"), autoindex(3, Some("NOCASE")));
+        // The comparisons use other collations than the indexes
+        assert_eq!(seek("b = 'x' COLLATE binary"), None);
+        assert_eq!(seek("c = 'x'"), None);
+    }
+
+    #[test]
+    fn pushes_filters_down_into_views() {
+        let functions = Functions::default();
+        let push = |view: &str, names: &[&str], filter: &str| {
+            let view = Select::parse_select(view).unwrap();
+            let select =
+                Select::parse_select(&format!("SELECT * FROM v WHERE {}", filter)).unwrap();
+            let names = names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+            let term = select.filter.unwrap();
+            push_down(&term, &select.from[0], &names, &view, &functions)
+                .map(|expr| format!("{:?}", expr))
+        };
+        let pushed = |view: &str, names: &[&str], filter: &str, expected: &str| {
+            let expected = Select::parse_select(&format!("SELECT * FROM t WHERE {}", expected))
+                .unwrap()
+                .filter;
+            assert_eq!(
+                push(view, names, filter),
+                Some(format!("{:?}", expected.unwrap())),
+                "{}",
+                filter
+            );
+        };
+        pushed("SELECT a + 1 AS x, b FROM t", &[], "x = 2", "a + 1 = 2");
+        pushed("SELECT a, b FROM t", &["x", "y"], "v.y > 1", "b > 1");
+        pushed("SELECT * FROM t", &[], "a = 1", "a = 1");
+        assert_eq!(push("SELECT a FROM t", &[], "random() > a"), None);
+        assert_eq!(push("SELECT a FROM t LIMIT 1", &[], "a = 1"), None);
+        assert_eq!(push("SELECT DISTINCT a FROM t", &[], "a = 1"), None);
+        assert_eq!(
+            push("SELECT a, count(*) FROM t GROUP BY a", &[], "a = 1"),
+            None
+        );
+        assert_eq!(push("SELECT a FROM t", &[], "u.a = 1"), None);
+    }
 }
@@ -1,16 +1,60 @@
 use anyhow::Result;
 use std::convert::TryInto;
 
-#[derive(Debug)]
+/// The fields of the database header we read or maintain, as mentioned here:
+/// [database header](https://www.sqlite.org/fileformat2.html#the_database_header)
+#[derive(Debug, Clone)]
 pub struct DBHeader {
     pub page_size: usize,
+    /// Bytes at the end of every page that are reserved for extensions
+    pub reserved_space: usize,
+    /// Whether changes go through a write-ahead log rather than a rollback journal
+    pub wal_mode: bool,
+    pub file_change_counter: u32,
+    /// The size of the database in pages, when it can be trusted
+    pub database_size: Option<u32>,
+    /// The first trunk page of the freelist, 0 when no page is free
+    pub freelist_trunk_page: u32,
+    pub freelist_page_count: u32,
+    pub schema_cookie: u32,
+    pub schema_format: u32,
 }
 
 impl DBHeader {
     /// Parses a database header stream into a database header
     pub fn parse(stream: &[u8]) -> Result<Self> {
-        let page_size = u16::from_be_bytes(stream[16..18].try_into()?) as usize;
-        let header = DBHeader { page_size };
+        // A page size of 65536 does not fit in two bytes and is stored as 1
+        let page_size = match u16::from_be_bytes(stream[16..18].try_into()?) {
+            1 => 65536,
+            page_size => page_size as usize,
+        };
+        let file_change_counter = u32::from_be_bytes(stream[24..28].try_into()?);
+        let database_size = u32::from_be_bytes(stream[28..32].try_into()?);
+        let version_valid_for = u32::from_be_bytes(stream[92..96].try_into()?);
+        // The size is only valid when written by a version of SQLite that maintains it
+        let database_size = (database_size != 0 && version_valid_for == file_change_counter)
+            .then_some(database_size);
+        let header = DBHeader {
+            page_size,
+            reserved_space: stream[20] as usize,
+            wal_mode: stream[18] == 2,
+            file_change_counter,
+            database_size,
+            freelist_trunk_page: u32::from_be_bytes(stream[32..36].try_into()?),
+            freelist_page_count: u32::from_be_bytes(stream[36..40].try_into()?),
+            schema_cookie: u32::from_be_bytes(stream[40..44].try_into()?),
+            schema_format: u32::from_be_bytes(stream[44..48].try_into()?),
+        };
         Ok(header)
     }
+
+    /// Writes the fields that change with the content of the database into a header stream
+    pub fn write(&self, stream: &mut [u8]) {
+        stream[24..28].copy_from_slice(&self.file_change_counter.to_be_bytes());
+        stream[28..32].copy_from_slice(&self.database_size.unwrap_or_default().to_be_bytes());
+        stream[32..36].copy_from_slice(&self.freelist_trunk_page.to_be_bytes());
+        stream[36..40].copy_from_slice(&self.freelist_page_count.to_be_bytes());
+        stream[40..44].copy_from_slice(&self.schema_cookie.to_be_bytes());
+        stream[92..96].copy_from_slice(&self.file_change_counter.to_be_bytes());
+    }
 }
@@ -0,0 +1,928 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use anyhow::{bail, Error, Result};
+
+use crate::collation::Collation;
+use crate::functions::Functions;
+use crate::json::{self, JsonArg};
+use crate::record::{Affinity, Value};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum UnaryOperator {
+    Negate,
+    Plus,
+    Not,
+    BitNot,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BinaryOperator {
+    Or,
+    And,
+    Eq,
+    NotEq,
+    Is,
+    IsNot,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    BitAnd,
+    BitOr,
+    ShiftLeft,
+    ShiftRight,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Concat,
+    /// `->`, which extracts a subcomponent of JSON as JSON
+    Extract,
+    /// `->>`, which extracts a subcomponent of JSON as an SQL value
+    ExtractValue,
+}
+
+impl BinaryOperator {
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            BinaryOperator::Eq
+                | BinaryOperator::NotEq
+                | BinaryOperator::Is
+                | BinaryOperator::IsNot
+                | BinaryOperator::Lt
+                | BinaryOperator::LtEq
+                | BinaryOperator::Gt
+                | BinaryOperator::GtEq
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Literal(Value),
+    Column(String),
+    Unary(UnaryOperator, Box<Expr>),
+    Binary(Box<Expr>, BinaryOperator, Box<Expr>),
+    Case {
+        operand: Option<Box<Expr>>,
+        branches: Vec<(Expr, Expr)>,
+        otherwise: Option<Box<Expr>>,
+    },
+    Cast(Box<Expr>, Affinity),
+    Collate(Box<Expr>, String),
+    Function {
+        name: String,
+        args: Vec<Expr>,
+        /// Whether an aggregate function only sees each distinct argument once
+        distinct: bool,
+    },
+}
+
+/// A column of the rows of a query, named `table.column`
+#[derive(Debug, Clone)]
+pub struct SourceColumn {
+    pub name: String,
+    pub affinity: Affinity,
+    pub collation: Option<String>,
+    /// Hidden columns, such as the rowid, can be read by name but are left out of `*`
+    pub hidden: bool,
+}
+
+/// The columns of the rows of a query, which expressions refer to by name
+pub struct Columns {
+    columns: Vec<SourceColumn>,
+    indices: HashMap<String, usize>,
+}
+
+impl Columns {
+    /// Maps the qualified names of the columns to their position. Unqualified names are mapped
+    /// too, to the first column with that name
+    pub fn new(columns: Vec<SourceColumn>) -> Self {
+        let mut indices = HashMap::new();
+        for (i, column) in columns.iter().enumerate() {
+            indices.insert(column.name.to_owned(), i);
+        }
+        for (i, column) in columns.iter().enumerate() {
+            if let Some((_, name)) = column.name.split_once('.') {
+                indices.entry(name.to_owned()).or_insert(i);
+            }
+        }
+        Self { columns, indices }
+    }
+
+    /// Returns the names of the columns `*` stands for
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.columns
+            .iter()
+            .filter(|column| !column.hidden)
+            .map(|column| column.name.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    pub fn contains(&self, column: &str) -> bool {
+        self.position(column).is_some()
+    }
+
+    pub fn position(&self, column: &str) -> Option<usize> {
+        match self.indices.get(column) {
+            Some(&index) => Some(index),
+            None => self
+                .indices
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(column))
+                .map(|(_, &index)| index),
+        }
+    }
+
+    fn get(&self, column: &str) -> Option<&SourceColumn> {
+        self.position(column).map(|i| &self.columns[i])
+    }
+}
+
+/// A row of values together with its columns, and the functions it can be passed to
+pub struct Row<'a> {
+    pub columns: &'a Columns,
+    pub values: &'a [Value],
+    pub functions: &'a Functions,
+}
+
+impl<'a> Row<'a> {
+    pub fn get(&self, column: &str) -> Result<&'a Value> {
+        self.columns
+            .position(column)
+            .and_then(|i| self.values.get(i))
+            .ok_or_else(|| Error::msg(format!("Column {} not found", column)))
+    }
+}
+
+impl Expr {
+    pub fn eval(&self, row: &Row) -> Result<Value> {
+        match self {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Column(name) => row.get(name).cloned(),
+            Expr::Unary(op, expr) => Ok(eval_unary(*op, expr.eval(row)?)),
+            Expr::Binary(left, BinaryOperator::And, right) => match left.eval(row)?.is_truthy() {
+                Some(false) => Ok(Value::Integer(0)),
+                l => Ok(match (l, right.eval(row)?.is_truthy()) {
+                    (_, Some(false)) => Value::Integer(0),
+                    (Some(true), Some(true)) => Value::Integer(1),
+                    _ => Value::Null,
+                }),
+            },
+            Expr::Binary(left, BinaryOperator::Or, right) => match left.eval(row)?.is_truthy() {
+                Some(true) => Ok(Value::Integer(1)),
+                l => Ok(match (l, right.eval(row)?.is_truthy()) {
+                    (_, Some(true)) => Value::Integer(1),
+                    (Some(false), Some(false)) => Value::Integer(0),
+                    _ => Value::Null,
+                }),
+            },
+            Expr::Binary(left, op, right) if op.is_comparison() => {
+                let collation = comparison_collation(left, right, row.columns);
+                let collation = row.functions.collation(collation)?;
+                let (left, right) = apply_comparison_affinity(
+                    (left.eval(row)?, left.affinity(row.columns)),
+                    (right.eval(row)?, right.affinity(row.columns)),
+                );
+                eval_binary(left, *op, right, collation)
+            }
+            Expr::Binary(left, op, right) => {
+                eval_binary(left.eval(row)?, *op, right.eval(row)?, Collation::Binary)
+            }
+            Expr::Case {
+                operand,
+                branches,
+                otherwise,
+            } => {
+                let operand = operand
+                    .as_ref()
+                    .map(|e| e.eval(row).map(|value| (e, value)))
+                    .transpose()?;
+                for (when, then) in branches {
+                    let matches = match &operand {
+                        Some((operand, value)) => {
+                            let collation = comparison_collation(operand, when, row.columns);
+                            let collation = row.functions.collation(collation)?;
+                            let (value, when) = apply_comparison_affinity(
+                                (value.clone(), operand.affinity(row.columns)),
+                                (when.eval(row)?, when.affinity(row.columns)),
+                            );
+                            compare(&value, &when, collation) == Some(Ordering::Equal)
+                        }
+                        None => when.eval(row)?.is_truthy() == Some(true),
+                    };
+                    if matches {
+                        return then.eval(row);
+                    }
+                }
+                otherwise
+                    .as_ref()
+                    .map_or(Ok(Value::Null), |otherwise| otherwise.eval(row))
+            }
+            Expr::Cast(expr, affinity) => Ok(expr.eval(row)?.cast(*affinity)),
+            Expr::Collate(expr, _) => expr.eval(row),
+            Expr::Function { name, args, .. } => eval_function(name, args, row),
+        }
+    }
+
+    /// Evaluates the expression once for a whole group of rows, feeding each row to the aggregate
+    /// functions it calls. Columns outside of aggregate functions are read from the last row
+    pub fn eval_group(
+        &self,
+        columns: &Columns,
+        group: &[Vec<Value>],
+        functions: &Functions,
+    ) -> Result<Value> {
+        let empty_row = vec![Value::Null; columns.len()];
+        let values = group.last().unwrap_or(&empty_row);
+        self.resolve_aggregates(columns, group, functions)?
+            .eval(&Row {
+                columns,
+                values,
+                functions,
+            })
+    }
+
+    /// Whether the expression calls an aggregate function
+    pub fn has_aggregate(&self, functions: &Functions) -> bool {
+        match self {
+            Expr::Function { name, args, .. } if functions.is_aggregate(name, args.len()) => true,
+            expr => expr
+                .children()
+                .into_iter()
+                .any(|child| child.has_aggregate(functions)),
+        }
+    }
+
+    /// Whether the expression gives the same value for every row, as it reads no column and only
+    /// calls deterministic functions
+    pub fn is_constant(&self, functions: &Functions) -> bool {
+        match self {
+            Expr::Column(_) => false,
+            Expr::Function { name, args, .. }
+                if functions.is_aggregate(name, args.len())
+                    || !functions.is_deterministic(name, args.len()) =>
+            {
+                false
+            }
+            expr => expr
+                .children()
+                .into_iter()
+                .all(|child| child.is_constant(functions)),
+        }
+    }
+
+    /// Whether the expression only calls deterministic functions
+    pub fn is_deterministic(&self, functions: &Functions) -> bool {
+        match self {
+            Expr::Function { name, args, .. } if !functions.is_deterministic(name, args.len()) => {
+                false
+            }
+            expr => expr
+                .children()
+                .into_iter()
+                .all(|child| child.is_deterministic(functions)),
+        }
+    }
+
+    /// Replaces every aggregate function call by its result over `group`
+    fn resolve_aggregates(
+        &self,
+        columns: &Columns,
+        group: &[Vec<Value>],
+        functions: &Functions,
+    ) -> Result<Expr> {
+        match self {
+            Expr::Function {
+                name,
+                args,
+                distinct,
+            } if functions.is_aggregate(name, args.len()) => {
+                if *distinct && args.len() != 1 {
+                    bail!("DISTINCT aggregates must have exactly one argument");
+                }
+                let mut aggregate = functions.new_aggregate(name, args.len())?;
+                // Distinct arguments are told apart by the collating sequence of the argument
+                let collation = match args.first() {
+                    Some(arg) if *distinct => arg.collation(columns).map(|(name, _)| name),
+                    _ => None,
+                };
+                let collation = functions.collation(collation)?;
+                let mut seen: Vec<Value> = vec![];
+                for values in group {
+                    let row = Row {
+                        columns,
+                        values,
+                        functions,
+                    };
+                    let args = args
+                        .iter()
+                        .map(|arg| arg.eval(&row))
+                        .collect::<Result<Vec<_>>>()?;
+                    if *distinct {
+                        if seen
+                            .iter()
+                            .any(|seen| compare(seen, &args[0], collation) == Some(Ordering::Equal))
+                        {
+                            continue;
+                        }
+                        seen.push(args[0].clone());
+                    }
+                    aggregate.step(&args)?;
+                }
+                Ok(Expr::Literal(aggregate.finalize()?))
+            }
+            expr => expr.map_children(|child| child.resolve_aggregates(columns, group, functions)),
+        }
+    }
+
+    /// Returns the direct subexpressions of an expression
+    pub fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Literal(_) | Expr::Column(_) => vec![],
+            Expr::Unary(_, expr) | Expr::Cast(expr, _) | Expr::Collate(expr, _) => vec![expr],
+            Expr::Binary(left, _, right) => vec![left, right],
+            Expr::Case {
+                operand,
+                branches,
+                otherwise,
+            } => operand
+                .iter()
+                .map(|e| &**e)
+                .chain(branches.iter().flat_map(|(when, then)| vec![when, then]))
+                .chain(otherwise.iter().map(|e| &**e))
+                .collect(),
+            Expr::Function { args, .. } => args.iter().collect(),
+        }
+    }
+
+    /// Rebuilds an expression with each direct subexpression replaced by the result of `f`
+    pub fn map_children(&self, mut f: impl FnMut(&Expr) -> Result<Expr>) -> Result<Expr> {
+        let mut boxed = |e: &Expr| f(e).map(Box::new);
+        Ok(match self {
+            Expr::Literal(_) | Expr::Column(_) => self.clone(),
+            Expr::Unary(op, expr) => Expr::Unary(*op, boxed(expr)?),
+            Expr::Binary(left, op, right) => Expr::Binary(boxed(left)?, *op, boxed(right)?),
+            Expr::Case {
+                operand,
+                branches,
+                otherwise,
+            } => Expr::Case {
+                operand: operand.as_deref().map(&mut boxed).transpose()?,
+                branches: branches
+                    .iter()
+                    .map(|(when, then)| Ok((*boxed(when)?, *boxed(then)?)))
+                    .collect::<Result<_>>()?,
+                otherwise: otherwise.as_deref().map(&mut boxed).transpose()?,
+            },
+            Expr::Cast(expr, affinity) => Expr::Cast(boxed(expr)?, *affinity),
+            Expr::Collate(expr, collation) => Expr::Collate(boxed(expr)?, collation.clone()),
+            Expr::Function {
+                name,
+                args,
+                distinct,
+            } => Expr::Function {
+                name: name.clone(),
+                distinct: *distinct,
+                args: args
+                    .iter()
+                    .map(|arg| boxed(arg).map(|arg| *arg))
+                    .collect::<Result<_>>()?,
+            },
+        })
+    }
+
+    /// Returns the affinity of the expression: that of the column it reads or of the type it is
+    /// cast to, and none, which behaves like BLOB, otherwise
+    pub fn affinity(&self, columns: &Columns) -> Affinity {
+        match self {
+            Expr::Column(name) => columns.get(name).map_or(Affinity::Blob, |c| c.affinity),
+            Expr::Cast(_, affinity) => *affinity,
+            Expr::Collate(expr, _) => expr.affinity(columns),
+            _ => Affinity::Blob,
+        }
+    }
+
+    /// Returns the collating sequence of the expression, telling whether it is given explicitly by
+    /// `COLLATE` rather than by the column the expression reads
+    pub fn collation<'a>(&'a self, columns: &'a Columns) -> Option<(&'a str, bool)> {
+        match self {
+            Expr::Collate(_, collation) => Some((collation, true)),
+            Expr::Column(name) => columns
+                .get(name)
+                .and_then(|c| c.collation.as_deref())
+                .map(|collation| (collation, false)),
+            _ => None,
+        }
+    }
+
+    /// Whether the expression evaluates to JSON rather than to plain text
+    fn returns_json(&self) -> bool {
+        match self {
+            Expr::Function { name, .. } => json::returns_json(&name.to_lowercase()),
+            Expr::Binary(_, op, _) => *op == BinaryOperator::Extract,
+            _ => false,
+        }
+    }
+
+    /// Whether two expressions are written alike, regardless of the case of names and of the
+    /// tables columns are qualified with
+    pub fn is_equivalent(&self, other: &Expr) -> bool {
+        let unqualified = |name: &'_ str| name.rsplit('.').next().unwrap_or_default().to_owned();
+        let same_node = match (self, other) {
+            (Expr::Literal(a), Expr::Literal(b)) => format!("{:?}", a) == format!("{:?}", b),
+            (Expr::Column(a), Expr::Column(b)) => {
+                unqualified(a).eq_ignore_ascii_case(&unqualified(b))
+            }
+            (Expr::Unary(a, _), Expr::Unary(b, _)) => a == b,
+            (Expr::Binary(_, a, _), Expr::Binary(_, b, _)) => a == b,
+            (
+                Expr::Case {
+                    operand: a_operand,
+                    otherwise: a_otherwise,
+                    ..
+                },
+                Expr::Case {
+                    operand: b_operand,
+                    otherwise: b_otherwise,
+                    ..
+                },
+            ) => {
+                a_operand.is_some() == b_operand.is_some()
+                    && a_otherwise.is_some() == b_otherwise.is_some()
+            }
+            (Expr::Cast(_, a), Expr::Cast(_, b)) => a == b,
+            (Expr::Collate(_, a), Expr::Collate(_, b)) => a.eq_ignore_ascii_case(b),
+            (Expr::Function { name: a, .. }, Expr::Function { name: b, .. }) => {
+                a.eq_ignore_ascii_case(b)
+            }
+            _ => false,
+        };
+        let (children, other_children) = (self.children(), other.children());
+        same_node
+            && children.len() == other_children.len()
+            && children
+                .iter()
+                .zip(other_children)
+                .all(|(a, b)| a.is_equivalent(b))
+    }
+
+    /// Splits an expression into the terms of its top level `AND`s
+    pub fn conjuncts(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Binary(left, BinaryOperator::And, right) => {
+                let mut terms = left.conjuncts();
+                terms.extend(right.conjuncts());
+                terms
+            }
+            expr => vec![expr],
+        }
+    }
+}
+
+fn eval_function(name: &str, args: &[Expr], row: &Row) -> Result<Value> {
+    match (name.to_lowercase().as_str(), args) {
+        ("coalesce", args) if args.len() >= 2 => {
+            for arg in args {
+                let value = arg.eval(row)?;
+                if !value.is_null() {
+                    return Ok(value);
+                }
+            }
+            Ok(Value::Null)
+        }
+        ("ifnull", [first, second]) => match first.eval(row)? {
+            Value::Null => second.eval(row),
+            value => Ok(value),
+        },
+        ("nullif", [first, second]) => {
+            let first = first.eval(row)?;
+            match compare(&first, &second.eval(row)?, Collation::Binary) {
+                Some(Ordering::Equal) => Ok(Value::Null),
+                _ => Ok(first),
+            }
+        }
+        ("iif", [condition, then, otherwise]) => match condition.eval(row)?.is_truthy() {
+            Some(true) => then.eval(row),
+            _ => otherwise.eval(row),
+        },
+        ("coalesce", _) | ("ifnull", _) | ("nullif", _) | ("iif", _) => {
+            bail!("Wrong number of arguments to function {}()", name)
+        }
+        _ if row.functions.is_aggregate(name, args.len()) => {
+            bail!("Misuse of aggregate function {}()", name)
+        }
+        (name, args) if json::is_json_function(name) => {
+            let args = args
+                .iter()
+                .map(|arg| {
+                    Ok(JsonArg {
+                        value: arg.eval(row)?,
+                        is_json: arg.returns_json(),
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            json::call(name, &args)
+        }
+        _ => {
+            let args = args
+                .iter()
+                .map(|arg| arg.eval(row))
+                .collect::<Result<Vec<_>>>()?;
+            row.functions.call_scalar(name, &args)
+        }
+    }
+}
+
+/// Converts the operands of a comparison, given with their affinity, as mentioned here:
+/// [comparison affinity](https://www.sqlite.org/datatype3.html#type_conversions_prior_to_comparison)
+pub fn apply_comparison_affinity(
+    (left, left_affinity): (Value, Affinity),
+    (right, right_affinity): (Value, Affinity),
+) -> (Value, Value) {
+    let is_numeric = |affinity| {
+        matches!(
+            affinity,
+            Affinity::Integer | Affinity::Real | Affinity::Numeric
+        )
+    };
+    match (left_affinity, right_affinity) {
+        (l, r) if is_numeric(l) && !is_numeric(r) => {
+            (left, right.apply_affinity(Affinity::Numeric))
+        }
+        (l, r) if !is_numeric(l) && is_numeric(r) => {
+            (left.apply_affinity(Affinity::Numeric), right)
+        }
+        (Affinity::Text, Affinity::Blob) => (left, right.apply_affinity(Affinity::Text)),
+        (Affinity::Blob, Affinity::Text) => (left.apply_affinity(Affinity::Text), right),
+        _ => (left, right),
+    }
+}
+
+/// Chooses the collating sequence of a comparison: one given explicitly on the left, then on the
+/// right, or else the one of a column on the left, then on the right, as mentioned here:
+/// [collating sequence](https://www.sqlite.org/datatype3.html#assigning_collating_sequences_from_sql)
+pub fn comparison_collation<'a>(
+    left: &'a Expr,
+    right: &'a Expr,
+    columns: &'a Columns,
+) -> Option<&'a str> {
+    let (left, right) = (left.collation(columns), right.collation(columns));
+    let explicit = |collation: Option<(&'a str, bool)>| collation.filter(|&(_, e)| e);
+    [explicit(left), explicit(right), left, right]
+        .iter()
+        .flatten()
+        .next()
+        .map(|&(collation, _)| collation)
+}
+
+fn compare(left: &Value, right: &Value, collation: Collation) -> Option<Ordering> {
+    if left.is_null() || right.is_null() {
+        None
+    } else {
+        Some(collation.compare(left, right))
+    }
+}
+
+fn eval_unary(op: UnaryOperator, value: Value) -> Value {
+    match (op, value.to_numeric()) {
+        (_, Value::Null) => Value::Null,
+        (UnaryOperator::Plus, _) => value,
+        (UnaryOperator::Negate, Value::F(n)) => Value::F(-n),
+        (UnaryOperator::Negate, n) => {
+            let n = n.as_integer().unwrap_or_default();
+            n.checked_neg()
+                .map(Value::Integer)
+                .unwrap_or(Value::F(-(n as f64)))
+        }
+        (UnaryOperator::Not, n) => Value::Integer(!n.is_truthy().unwrap_or_default() as i64),
+        (UnaryOperator::BitNot, n) => Value::Integer(!integer_operand(&n)),
+    }
+}
+
+fn eval_binary(
+    left: Value,
+    op: BinaryOperator,
+    right: Value,
+    collation: Collation,
+) -> Result<Value> {
+    let boolean = |b: bool| Value::Integer(b as i64);
+    let ordering = compare(&left, &right, collation);
+    let value = match op {
+        BinaryOperator::Is => boolean(match (&left, &right) {
+            (Value::Null, Value::Null) => true,
+            _ => ordering == Some(Ordering::Equal),
+        }),
+        BinaryOperator::IsNot => boolean(match (&left, &right) {
+            (Value::Null, Value::Null) => false,
+            _ => ordering != Some(Ordering::Equal),
+        }),
+        BinaryOperator::Extract => json::arrow(&left, &right, false)?,
+        BinaryOperator::ExtractValue => json::arrow(&left, &right, true)?,
+        _ if left.is_null() || right.is_null() => Value::Null,
+        BinaryOperator::Eq => boolean(ordering == Some(Ordering::Equal)),
+        BinaryOperator::NotEq => boolean(ordering.is_some_and(|o| o != Ordering::Equal)),
+        BinaryOperator::Lt => boolean(ordering == Some(Ordering::Less)),
+        BinaryOperator::LtEq => boolean(matches!(ordering, Some(Ordering::Less | Ordering::Equal))),
+        BinaryOperator::Gt => boolean(ordering == Some(Ordering::Greater)),
+        BinaryOperator::GtEq => boolean(matches!(
+            ordering,
+            Some(Ordering::Greater | Ordering::Equal)
+        )),
+        BinaryOperator::Concat => Value::Text(left.to_text() + &right.to_text()),
+        BinaryOperator::BitAnd | BinaryOperator::BitOr => {
+            let (l, r) = (
+                integer_operand(&left.to_numeric()),
+                integer_operand(&right.to_numeric()),
+            );
+            Value::Integer(if op == BinaryOperator::BitAnd {
+                l & r
+            } else {
+                l | r
+            })
+        }
+        BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => {
+            let (l, r) = (
+                integer_operand(&left.to_numeric()),
+                integer_operand(&right.to_numeric()),
+            );
+            let r = if op == BinaryOperator::ShiftRight {
+                -r
+            } else {
+                r
+            };
+            Value::Integer(shift_left(l, r))
+        }
+        _ => eval_arithmetic(left.to_numeric(), op, right.to_numeric()),
+    };
+    Ok(value)
+}
+
+fn eval_arithmetic(left: Value, op: BinaryOperator, right: Value) -> Value {
+    if let (Some(l), Some(r)) = (left.as_integer(), right.as_integer()) {
+        let result = match op {
+            BinaryOperator::Add => l.checked_add(r),
+            BinaryOperator::Subtract => l.checked_sub(r),
+            BinaryOperator::Multiply => l.checked_mul(r),
+            BinaryOperator::Divide if r == 0 => return Value::Null,
+            BinaryOperator::Divide => l.checked_div(r),
+            BinaryOperator::Modulo if r == 0 => return Value::Null,
+            BinaryOperator::Modulo => Some(l.checked_rem(r).unwrap_or(0)),
+            _ => unreachable!("{:?} is not an arithmetic operator", op),
+        };
+        if let Some(n) = result {
+            return Value::Integer(n);
+        }
+    }
+
+    let (l, r) = (real_operand(&left), real_operand(&right));
+    match op {
+        BinaryOperator::Add => Value::F(l + r),
+        BinaryOperator::Subtract => Value::F(l - r),
+        BinaryOperator::Multiply => Value::F(l * r),
+        BinaryOperator::Divide if r == 0.0 => Value::Null,
+        BinaryOperator::Divide => Value::F(l / r),
+        _ => {
+            let (l, r) = (l as i64, r as i64);
+            if r == 0 {
+                Value::Null
+            } else {
+                Value::F(l.checked_rem(r).unwrap_or(0) as f64)
+            }
+        }
+    }
+}
+
+fn integer_operand(value: &Value) -> i64 {
+    match value {
+        Value::F(n) => *n as i64,
+        n => n.as_integer().unwrap_or_default(),
+    }
+}
+
+fn real_operand(value: &Value) -> f64 {
+    match value {
+        Value::F(n) => *n,
+        n => n.as_integer().unwrap_or_default() as f64,
+    }
+}
+
+fn shift_left(value: i64, by: i64) -> i64 {
+    match by {
+        by if by >= 64 => 0,
+        by if by <= -64 => {
+            if value < 0 {
+                -1
+            } else {
+                0
+            }
+        }
+        by if by >= 0 => value << by,
+        by => value >> -by,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::{ResultColumn, Select};
+
+    fn parse(sql: &str) -> Expr {
+        let select = Select::parse_select(&format!("SELECT {} FROM t", sql)).unwrap();
+        match select.columns.into_iter().next() {
+            Some(ResultColumn::Expr(expr, _)) => expr,
+            column => panic!("{:?} is not an expression", column),
+        }
+    }
+
+    /// Evaluates an expression that uses no column, rendering the value as `sqlite3` prints it
+    fn eval(sql: &str) -> String {
+        let row = Row {
+            columns: &Columns::new(vec![]),
+            values: &[],
+            functions: &Functions::default(),
+        };
+        match parse(sql).eval(&row).unwrap() {
+            Value::Null => "NULL".to_string(),
+            Value::Text(text) => format!("'{}'", text),
+            Value::Blob(blob) => format!("x'{}'", String::from_utf8_lossy(&blob)),
+            value => value.to_string(),
+        }
+    }
+
+    #[test]
+    fn case_expressions() {
+        let cases = [
+            ("CASE 2 WHEN 1 THEN 'one' WHEN 2 THEN 'two' END", "'two'"),
+            ("CASE WHEN NULL THEN 1 WHEN 0 THEN 2 ELSE 3 END", "3"),
+            // NULL equals nothing, not even NULL
+            ("CASE NULL WHEN NULL THEN 'x' ELSE 'y' END", "'y'"),
+            ("CASE 1 WHEN 2 THEN 1 END", "NULL"),
+            ("CASE 1 WHEN 1.0 THEN 'eq' END", "'eq'"),
+        ];
+        for (sql, expected) in cases {
+            assert_eq!(eval(sql), expected, "{}", sql);
+        }
+    }
+
+    #[test]
+    fn cast_rules() {
+        let cases = [
+            ("CAST('12abc' AS INTEGER)", "12"),
+            ("CAST(' -7.9e1x' AS INTEGER)", "-7"),
+            ("CAST('0x10' AS INTEGER)", "0"),
+            (
+                "CAST('9223372036854775808' AS INTEGER)",
+                "9223372036854775807",
+            ),
+            ("CAST(1e20 AS INTEGER)", "9223372036854775807"),
+            ("CAST(-3.9 AS INTEGER)", "-3"),
+            ("CAST(NULL AS INTEGER)", "NULL"),
+            ("CAST(' 5 ' AS REAL)", "5.0"),
+            ("CAST('1e3' AS NUMERIC)", "1000"),
+            ("CAST('3.0' AS NUMERIC)", "3"),
+            ("CAST('1.5e3' AS NUMERIC)", "1500"),
+            ("CAST('abc' AS NUMERIC)", "0"),
+            ("CAST('-0' AS NUMERIC)", "0"),
+            ("CAST(12 AS TEXT)", "'12'"),
+            ("CAST(1.0 AS TEXT)", "'1.0'"),
+            ("CAST(12 AS BLOB)", "x'12'"),
+        ];
+        for (sql, expected) in cases {
+            assert_eq!(eval(sql), expected, "{}", sql);
+        }
+    }
+
+    #[test]
+    fn null_handling_functions() {
+        let cases = [
+            ("coalesce(NULL, NULL, 3, 4)", "3"),
+            ("ifnull(NULL, 'b')", "'b'"),
+            ("nullif(1, 1)", "NULL"),
+            ("nullif(1, 2)", "1"),
+            ("iif(1 > 2, 'y', 'n')", "'n'"),
+            ("iif(NULL, 1, 2)", "2"),
+        ];
+        for (sql, expected) in cases {
+            assert_eq!(eval(sql), expected, "{}", sql);
+        }
+        let row = Row {
+            columns: &Columns::new(vec![]),
+            values: &[],
+            functions: &Functions::default(),
+        };
+        assert_eq!(
+            parse("coalesce(1)").eval(&row).unwrap_err().to_string(),
+            "Wrong number of arguments to function coalesce()"
+        );
+    }
+
+    #[test]
+    fn arithmetic_falls_back_to_reals_on_overflow() {
+        let cases = [
+            ("'12' + 3", "15"),
+            ("5 / 2", "2"),
+            ("5 % 3", "2"),
+            ("7.0 / 2", "3.5"),
+            ("1 / 0", "NULL"),
+            ("1 << 63", "-9223372036854775808"),
+            ("-9223372036854775807 - 2", "-9.22337203685478e+18"),
+            ("9223372036854775807 - 1", "9223372036854775806"),
+            ("9007199254740993 + 1", "9007199254740994"),
+            ("typeof(9223372036854775807 + 1)", "'real'"),
+            ("-9223372036854775808", "-9223372036854775808"),
+            ("typeof(-  9223372036854775808)", "'integer'"),
+            ("- -9223372036854775808", "9.22337203685478e+18"),
+            ("-9223372036854775809", "-9.22337203685478e+18"),
+            ("-9223372036854775808.0", "-9.22337203685478e+18"),
+        ];
+        for (sql, expected) in cases {
+            assert_eq!(eval(sql), expected, "{}", sql);
+        }
+    }
+
+    #[test]
+    fn comparisons_apply_column_affinity_and_collation() {
+        let column = |name: &str, affinity, collation: Option<&str>| SourceColumn {
+            name: name.to_string(),
+            affinity,
+            collation: collation.map(String::from),
+            hidden: false,
+        };
+        let columns = Columns::new(vec![
+            column("t.i", Affinity::Integer, None),
+            column("t.x", Affinity::Text, None),
+            column("t.b", Affinity::Blob, None),
+            column("t.n", Affinity::Text, Some("NOCASE")),
+        ]);
+        let text = |text: &str| Value::Text(text.to_string());
+        let values = [Value::Integer(10), text("10"), text("10"), text("abc")];
+        let row = Row {
+            columns: &columns,
+            values: &values,
+            functions: &Functions::default(),
+        };
+        let cases = [
+            ("i = '10'", "1"),
+            ("x = 10", "1"),
+            ("b = 10", "0"),
+            ("b = '10'", "1"),
+            ("i < '9'", "0"),
+            ("x < 9", "1"),
+            ("'10' = 10", "0"),
+            ("CAST('10' AS INTEGER) = '10'", "1"),
+            ("CASE x WHEN 10 THEN 'y' ELSE 'n' END", "y"),
+            ("CASE 10 WHEN x THEN 'y' ELSE 'n' END", "y"),
+            ("1 < 'a'", "1"),
+            ("'a' < x'00'", "1"),
+            ("NULL < 1", ""),
+            ("9223372036854775807 < 9223372036854775808.0", "1"),
+            ("1 = 1.0", "1"),
+            // Explicit collating sequences take precedence over those of columns
+            ("n = 'ABC'", "1"),
+            ("'ABC' = n", "1"),
+            ("n = 'ABC' COLLATE BINARY", "0"),
+            ("n > 'ABB'", "1"),
+            ("'a' = 'A' COLLATE NOCASE", "1"),
+            ("'a ' = 'a' COLLATE RTRIM", "1"),
+            ("'a ' = 'a'", "0"),
+            ("x COLLATE NOCASE = '10'", "1"),
+            ("'B' < 'a' COLLATE NOCASE", "0"),
+            ("'B' < 'a'", "1"),
+        ];
+        for (sql, expected) in cases {
+            let value = parse(sql).eval(&row).unwrap();
+            assert_eq!(value.to_text(), expected, "{}", sql);
+        }
+    }
+
+    #[test]
+    fn equivalent_expressions() {
+        let equivalent = |a: &str, b: &str| parse(a).is_equivalent(&parse(b));
+        assert!(equivalent("lower(t.Name)", "LOWER(name)"));
+        assert!(equivalent("a + 1 > b", "A+1>B"));
+        assert!(equivalent("x COLLATE nocase", "x collate NOCASE"));
+        assert!(equivalent(
+            "CASE WHEN a THEN 1 END",
+            "case when a then 1 end"
+        ));
+        assert!(!equivalent("a + 1", "1 + a"));
+        assert!(!equivalent("a - 1", "a + 1"));
+        assert!(!equivalent("1", "1.0"));
+        assert!(!equivalent("CAST(a AS TEXT)", "CAST(a AS INT)"));
+        assert!(!equivalent(
+            "CASE a WHEN 1 THEN 2 END",
+            "CASE WHEN a THEN 2 END"
+        ));
+    }
+}
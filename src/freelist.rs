@@ -0,0 +1,94 @@
+use std::convert::TryInto;
+
+use anyhow::{bail, Result};
+
+/// A trunk page of the freelist, which lists leaf pages that are free as well, as mentioned
+/// here: [freelist](https://www.sqlite.org/fileformat2.html#the_freelist)
+#[derive(Debug, Clone)]
+pub struct TrunkPage {
+    pub page_number: u32,
+    /// The next trunk page, 0 for the last one
+    pub next: u32,
+    pub leaves: Vec<u32>,
+}
+
+impl TrunkPage {
+    /// Parses a trunk page of a database whose pages have `usable_size` usable bytes
+    pub fn parse(page_number: u32, page: &[u8], usable_size: usize) -> Result<Self> {
+        let next = u32::from_be_bytes(page[..4].try_into()?);
+        let leaf_count = u32::from_be_bytes(page[4..8].try_into()?) as usize;
+        if leaf_count > usable_size / 4 - 2 {
+            bail!(
+                "Freelist trunk page {} has too many leaves: {}",
+                page_number,
+                leaf_count
+            );
+        }
+        let leaves = page[8..8 + 4 * leaf_count]
+            .chunks_exact(4)
+            .map(|leaf| u32::from_be_bytes([leaf[0], leaf[1], leaf[2], leaf[3]]))
+            .collect();
+        Ok(Self {
+            page_number,
+            next,
+            leaves,
+        })
+    }
+
+    /// The number of leaves SQLite stores in a trunk page. It avoids the last six entries, which
+    /// versions before 3.6.0 report as corrupt
+    pub fn capacity(usable_size: usize) -> usize {
+        usable_size / 4 - 8
+    }
+
+    /// Writes the trunk page into a page, whose other bytes are left untouched
+    pub fn write(&self, page: &mut [u8]) {
+        page[..4].copy_from_slice(&self.next.to_be_bytes());
+        page[4..8].copy_from_slice(&(self.leaves.len() as u32).to_be_bytes());
+        for (i, leaf) in self.leaves.iter().enumerate() {
+            page[8 + 4 * i..12 + 4 * i].copy_from_slice(&leaf.to_be_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_trunk_pages() {
+        let trunk = TrunkPage {
+            page_number: 3,
+            next: 9,
+            leaves: vec![4, 7, 0x0102_0304],
+        };
+        let mut page = vec![0xaa; 512];
+        trunk.write(&mut page);
+        assert_eq!(page[..8], [0, 0, 0, 9, 0, 0, 0, 3]);
+        assert_eq!(page[16..20], [1, 2, 3, 4]);
+        // The bytes after the last leaf are left as they were
+        assert_eq!(page[20], 0xaa);
+
+        let parsed = TrunkPage::parse(3, &page, 512).unwrap();
+        assert_eq!(
+            (parsed.page_number, parsed.next, parsed.leaves),
+            (3, 9, trunk.leaves)
+        );
+    }
+
+    #[test]
+    fn leaves_fit_in_the_usable_size() {
+        assert_eq!(TrunkPage::capacity(4096), 1016);
+        assert_eq!(TrunkPage::capacity(512 - 32), 112);
+
+        // Every entry after the header of the trunk may be a leaf when reading
+        let mut page = vec![0; 512];
+        page[4..8].copy_from_slice(&126u32.to_be_bytes());
+        assert_eq!(TrunkPage::parse(2, &page, 512).unwrap().leaves.len(), 126);
+        page[4..8].copy_from_slice(&127u32.to_be_bytes());
+        assert_eq!(
+            TrunkPage::parse(2, &page, 512).unwrap_err().to_string(),
+            "Freelist trunk page 2 has too many leaves: 127"
+        );
+    }
+}
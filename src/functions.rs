@@ -0,0 +1,1167 @@
+use std::cmp::Ordering;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::hash::{BuildHasher, Hasher};
+use std::rc::Rc;
+
+use anyhow::{bail, Error, Result};
+
+use crate::collation::{Collation, CollationFn};
+use crate::datetime;
+use crate::json;
+use crate::record::{Affinity, Value};
+
+/// Calls one of SQLite's core scalar functions, as mentioned here:
+/// [corefunc](https://www.sqlite.org/lang_corefunc.html)
+pub fn call_scalar(name: &str, args: &[Value]) -> Result<Value> {
+    let lower_name = name.to_lowercase();
+    let value = match (lower_name.as_str(), args) {
+        ("typeof", [x]) => Value::Text(type_name(x).to_string()),
+        ("quote", [x]) => Value::Text(quote(x)),
+        ("random", []) => Value::Integer(random()),
+        ("min", args) if args.len() >= 2 => min_max(args, Ordering::Less),
+        ("max", args) if args.len() >= 2 => min_max(args, Ordering::Greater),
+        ("printf", [format, args @ ..]) | ("format", [format, args @ ..]) => match format {
+            Value::Null => Value::Null,
+            format => Value::Text(printf(&format.to_text(), args)),
+        },
+        ("char", args) => Value::Text(
+            args.iter()
+                .map(|arg| {
+                    let code = arg.cast(Affinity::Integer).as_integer().unwrap_or_default();
+                    char::from_u32(code as u32).unwrap_or('\u{FFFD}')
+                })
+                .collect(),
+        ),
+        (name, args) if datetime::is_date_function(name) => datetime::call(name, args)?,
+        (_, args) if args.iter().any(Value::is_null) && lower_name != "hex" => Value::Null,
+        ("length", [x]) => Value::Integer(match x {
+            Value::Blob(bytes) => bytes.len(),
+            x => x.to_text().chars().count(),
+        } as i64),
+        ("lower", [x]) => Value::Text(x.to_text().to_ascii_lowercase()),
+        ("upper", [x]) => Value::Text(x.to_text().to_ascii_uppercase()),
+        ("substr", [x, start]) | ("substring", [x, start]) => substr(x, start, None),
+        ("substr", [x, start, length]) | ("substring", [x, start, length]) => {
+            substr(x, start, Some(length))
+        }
+        ("trim", [x]) => Value::Text(x.to_text().trim_matches(' ').to_string()),
+        ("ltrim", [x]) => Value::Text(x.to_text().trim_start_matches(' ').to_string()),
+        ("rtrim", [x]) => Value::Text(x.to_text().trim_end_matches(' ').to_string()),
+        ("trim", [x, chars]) => {
+            let chars = chars.to_text().chars().collect::<Vec<_>>();
+            Value::Text(x.to_text().trim_matches(&chars[..]).to_string())
+        }
+        ("ltrim", [x, chars]) => {
+            let chars = chars.to_text().chars().collect::<Vec<_>>();
+            Value::Text(x.to_text().trim_start_matches(&chars[..]).to_string())
+        }
+        ("rtrim", [x, chars]) => {
+            let chars = chars.to_text().chars().collect::<Vec<_>>();
+            Value::Text(x.to_text().trim_end_matches(&chars[..]).to_string())
+        }
+        ("replace", [x, pattern, replacement]) => match pattern.to_text().as_str() {
+            "" => Value::Text(x.to_text()),
+            pattern => Value::Text(x.to_text().replace(pattern, &replacement.to_text())),
+        },
+        ("instr", [Value::Blob(haystack), Value::Blob(needle)]) => Value::Integer(
+            haystack
+                .windows(needle.len().max(1))
+                .position(|w| needle.is_empty() || w == &needle[..])
+                .map_or(0, |i| i + 1) as i64,
+        ),
+        ("instr", [haystack, needle]) => {
+            let haystack = haystack.to_text();
+            Value::Integer(
+                haystack
+                    .find(&needle.to_text())
+                    .map_or(0, |i| haystack[..i].chars().count() + 1) as i64,
+            )
+        }
+        ("abs", [x]) => match x.as_integer() {
+            Some(n) => match n.checked_abs() {
+                Some(n) => Value::Integer(n),
+                None => bail!("integer overflow"),
+            },
+            None => Value::F(x.cast(Affinity::Real).get_numeric_value()?.abs()),
+        },
+        ("round", [x]) => round(x, &Value::Integer(0))?,
+        ("round", [x, digits]) => round(x, digits)?,
+        ("hex", [x]) => Value::Text(
+            match x {
+                Value::Blob(bytes) => bytes.clone(),
+                x => x.to_text().into_bytes(),
+            }
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect(),
+        ),
+        ("unicode", [x]) => match x.to_text().chars().next() {
+            Some(c) => Value::Integer(c as i64),
+            None => Value::Null,
+        },
+        ("zeroblob", [n]) => {
+            let n = n.cast(Affinity::Integer).as_integer().unwrap_or_default();
+            Value::Blob(vec![0; n.max(0) as usize])
+        }
+        ("like", [pattern, x]) => {
+            Value::Integer(like(&pattern.to_text(), &x.to_text(), None) as i64)
+        }
+        ("like", [pattern, x, escape]) => {
+            let escape = escape.to_text();
+            let mut chars = escape.chars();
+            match (chars.next(), chars.next()) {
+                (Some(escape), None) => {
+                    Value::Integer(like(&pattern.to_text(), &x.to_text(), Some(escape)) as i64)
+                }
+                _ => bail!("ESCAPE expression must be a single character"),
+            }
+        }
+        ("glob", [pattern, x]) => {
+            let pattern = pattern.to_text().chars().collect::<Vec<_>>();
+            let text = x.to_text().chars().collect::<Vec<_>>();
+            Value::Integer(glob(&pattern, &text) as i64)
+        }
+        (
+            "typeof" | "quote" | "length" | "lower" | "upper" | "substr" | "substring" | "trim"
+            | "ltrim" | "rtrim" | "replace" | "instr" | "abs" | "round" | "hex" | "unicode"
+            | "zeroblob" | "random" | "printf" | "format" | "min" | "max" | "like" | "glob",
+            _,
+        ) => bail!("Wrong number of arguments to function {}()", name),
+        _ => bail!("No such function: {}", name),
+    };
+    Ok(value)
+}
+
+/// Calls a table-valued function, returning its rows
+pub fn call_table(name: &str, args: &[Value]) -> Result<Vec<Vec<Value>>> {
+    match name.to_lowercase().as_str() {
+        name @ ("json_each" | "json_tree") => json::table(name, args),
+        _ => bail!("No such table-valued function: {}", name),
+    }
+}
+
+/// Returns the columns of the rows of a table-valued function
+pub fn table_columns(name: &str) -> Result<Vec<String>> {
+    match name.to_lowercase().as_str() {
+        "json_each" | "json_tree" => {
+            Ok(json::TABLE_COLUMNS.iter().map(|c| c.to_string()).collect())
+        }
+        _ => bail!("No such table-valued function: {}", name),
+    }
+}
+
+/// Functions and collating sequences registered by the application, which take precedence over
+/// the built-in ones of the same name (and arity), as mentioned here:
+/// [create_function](https://www.sqlite.org/c3ref/create_function.html)
+#[derive(Default)]
+pub struct Functions {
+    scalars: HashMap<(String, Option<usize>), ScalarFunction>,
+    aggregates: HashMap<(String, Option<usize>), AggregateFunction>,
+    collations: HashMap<String, Box<CollationFn>>,
+}
+
+type Callback = dyn Fn(&[Value]) -> Value;
+type StepCallback<S> = dyn Fn(&mut S, &[Value]);
+
+struct ScalarFunction {
+    deterministic: bool,
+    call: Box<Callback>,
+}
+
+struct AggregateFunction {
+    deterministic: bool,
+    new: Box<dyn Fn() -> Box<dyn Aggregate>>,
+}
+
+impl Functions {
+    /// Registers a scalar function taking `arity` arguments, or any number of them when `None`
+    pub fn register_function(
+        &mut self,
+        name: &str,
+        arity: Option<usize>,
+        deterministic: bool,
+        call: impl Fn(&[Value]) -> Value + 'static,
+    ) {
+        let function = ScalarFunction {
+            deterministic,
+            call: Box::new(call),
+        };
+        self.scalars.insert((name.to_lowercase(), arity), function);
+    }
+
+    /// Registers an aggregate function whose state is created by `init` for each group, updated
+    /// by `step` for each row of the group and turned into the result by `finalize`
+    pub fn register_aggregate<S: 'static>(
+        &mut self,
+        name: &str,
+        arity: Option<usize>,
+        deterministic: bool,
+        init: impl Fn() -> S + 'static,
+        step: impl Fn(&mut S, &[Value]) + 'static,
+        finalize: impl Fn(S) -> Value + 'static,
+    ) {
+        let step: Rc<StepCallback<S>> = Rc::new(step);
+        let finalize: Rc<dyn Fn(S) -> Value> = Rc::new(finalize);
+        let new = move || -> Box<dyn Aggregate> {
+            Box::new(UserAggregate {
+                state: Some(init()),
+                step: Rc::clone(&step),
+                finalize: Rc::clone(&finalize),
+            })
+        };
+        let function = AggregateFunction {
+            deterministic,
+            new: Box::new(new),
+        };
+        self.aggregates
+            .insert((name.to_lowercase(), arity), function);
+    }
+
+    /// Registers a collating sequence that orders text values
+    pub fn register_collation(
+        &mut self,
+        name: &str,
+        compare: impl Fn(&str, &str) -> Ordering + 'static,
+    ) {
+        self.collations
+            .insert(name.to_lowercase(), Box::new(compare));
+    }
+
+    /// Finds a collating sequence by name, BINARY being the default
+    pub fn collation(&self, name: Option<&str>) -> Result<Collation<'_>> {
+        let name = match name {
+            Some(name) => name,
+            None => return Ok(Collation::Binary),
+        };
+        match self.collations.get(&name.to_lowercase()) {
+            Some(compare) => Ok(Collation::Custom(compare.as_ref())),
+            None => Collation::builtin(name)
+                .ok_or_else(|| Error::msg(format!("No such collation sequence: {}", name))),
+        }
+    }
+
+    pub fn call_scalar(&self, name: &str, args: &[Value]) -> Result<Value> {
+        match find(&self.scalars, name, args.len()) {
+            Some(function) => Ok((function.call)(args)),
+            None => call_scalar(name, args),
+        }
+    }
+
+    pub fn is_aggregate(&self, name: &str, arg_count: usize) -> bool {
+        match find(&self.scalars, name, arg_count) {
+            Some(_) => false,
+            None => {
+                find(&self.aggregates, name, arg_count).is_some() || is_aggregate(name, arg_count)
+            }
+        }
+    }
+
+    pub fn new_aggregate(&self, name: &str, arg_count: usize) -> Result<Box<dyn Aggregate>> {
+        match find(&self.aggregates, name, arg_count) {
+            Some(function) => Ok((function.new)()),
+            None => new_aggregate(name),
+        }
+    }
+
+    /// Whether a function always gives the same result for the same arguments, so that a call
+    /// with constant arguments can be evaluated once for the whole query
+    pub fn is_deterministic(&self, name: &str, arg_count: usize) -> bool {
+        if let Some(function) = find(&self.scalars, name, arg_count) {
+            return function.deterministic;
+        }
+        if let Some(function) = find(&self.aggregates, name, arg_count) {
+            return function.deterministic;
+        }
+        // Date and time functions depend on the current time when given 'now'
+        let name = name.to_lowercase();
+        name != "random" && !datetime::is_date_function(&name)
+    }
+}
+
+/// Finds the function registered for exactly `arg_count` arguments, or else for any number
+fn find<'a, F>(
+    functions: &'a HashMap<(String, Option<usize>), F>,
+    name: &str,
+    arg_count: usize,
+) -> Option<&'a F> {
+    if functions.is_empty() {
+        return None;
+    }
+    let name = name.to_lowercase();
+    functions
+        .get(&(name.clone(), Some(arg_count)))
+        .or_else(|| functions.get(&(name, None)))
+}
+
+/// An aggregate function that is fed the arguments of each row of a group in turn
+pub trait Aggregate {
+    fn step(&mut self, args: &[Value]) -> Result<()>;
+    fn finalize(&mut self) -> Result<Value>;
+}
+
+pub fn is_aggregate(name: &str, arg_count: usize) -> bool {
+    match name.to_lowercase().as_str() {
+        "count" | "sum" | "total" | "avg" | "group_concat" => true,
+        "min" | "max" => arg_count == 1,
+        _ => false,
+    }
+}
+
+/// Creates the state of one of SQLite's built-in aggregate functions, as mentioned here:
+/// [aggfunc](https://www.sqlite.org/lang_aggfunc.html)
+pub fn new_aggregate(name: &str) -> Result<Box<dyn Aggregate>> {
+    let aggregate: Box<dyn Aggregate> = match name.to_lowercase().as_str() {
+        "count" => Box::new(Count(0)),
+        "sum" => Box::new(Sum {
+            total: None,
+            kind: SumKind::Sum,
+        }),
+        "total" => Box::new(Sum {
+            total: None,
+            kind: SumKind::Total,
+        }),
+        "avg" => Box::new(Sum {
+            total: None,
+            kind: SumKind::Avg(0),
+        }),
+        "min" => Box::new(MinMax(None, Ordering::Less)),
+        "max" => Box::new(MinMax(None, Ordering::Greater)),
+        "group_concat" => Box::new(GroupConcat(None)),
+        _ => bail!("No such aggregate function: {}", name),
+    };
+    Ok(aggregate)
+}
+
+/// The state of an aggregate function registered by the application
+struct UserAggregate<S> {
+    state: Option<S>,
+    step: Rc<StepCallback<S>>,
+    finalize: Rc<dyn Fn(S) -> Value>,
+}
+
+impl<S> Aggregate for UserAggregate<S> {
+    fn step(&mut self, args: &[Value]) -> Result<()> {
+        match &mut self.state {
+            Some(state) => (self.step)(state, args),
+            None => bail!("Aggregate function stepped after being finalized"),
+        }
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> Result<Value> {
+        match self.state.take() {
+            Some(state) => Ok((self.finalize)(state)),
+            None => bail!("Aggregate function finalized twice"),
+        }
+    }
+}
+
+struct Count(i64);
+
+impl Aggregate for Count {
+    fn step(&mut self, args: &[Value]) -> Result<()> {
+        if args.iter().all(|arg| !arg.is_null()) {
+            self.0 += 1;
+        }
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> Result<Value> {
+        Ok(Value::Integer(self.0))
+    }
+}
+
+enum SumKind {
+    Sum,
+    Total,
+    Avg(usize),
+}
+
+struct Sum {
+    total: Option<Value>,
+    kind: SumKind,
+}
+
+impl Aggregate for Sum {
+    fn step(&mut self, args: &[Value]) -> Result<()> {
+        let value = match args {
+            [Value::Null] => return Ok(()),
+            [value] => value.to_numeric(),
+            _ => bail!("Wrong number of arguments to aggregate function"),
+        };
+        if let SumKind::Avg(count) = &mut self.kind {
+            *count += 1;
+        }
+        self.total = Some(match (self.total.take(), value) {
+            (None, value) => value,
+            (Some(total), value) => match (total.as_integer(), value.as_integer()) {
+                (Some(t), Some(v)) => match t.checked_add(v) {
+                    Some(sum) => Value::Integer(sum),
+                    None if matches!(self.kind, SumKind::Sum) => bail!("integer overflow"),
+                    None => Value::F(t as f64 + v as f64),
+                },
+                _ => Value::F(total.get_numeric_value()? + value.get_numeric_value()?),
+            },
+        });
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> Result<Value> {
+        let total = self.total.as_ref();
+        Ok(match (&self.kind, total) {
+            (SumKind::Sum, None) => Value::Null,
+            (SumKind::Sum, Some(total)) => total.clone(),
+            (SumKind::Total, None) => Value::F(0.0),
+            (SumKind::Total, Some(total)) => Value::F(total.get_numeric_value()?),
+            (SumKind::Avg(_), None) => Value::Null,
+            (SumKind::Avg(count), Some(total)) => {
+                Value::F(total.get_numeric_value()? / *count as f64)
+            }
+        })
+    }
+}
+
+struct MinMax(Option<Value>, Ordering);
+
+impl Aggregate for MinMax {
+    fn step(&mut self, args: &[Value]) -> Result<()> {
+        match (args, &self.0) {
+            ([Value::Null], _) => {}
+            ([value], Some(current)) if value.partial_cmp(current) != Some(self.1) => {}
+            ([value], _) => self.0 = Some(value.clone()),
+            _ => bail!("Wrong number of arguments to aggregate function"),
+        }
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> Result<Value> {
+        Ok(self.0.take().unwrap_or(Value::Null))
+    }
+}
+
+struct GroupConcat(Option<String>);
+
+impl Aggregate for GroupConcat {
+    fn step(&mut self, args: &[Value]) -> Result<()> {
+        let (value, separator) = match args {
+            [value] => (value, ",".to_string()),
+            [value, separator] => (value, separator.to_text()),
+            _ => bail!("Wrong number of arguments to aggregate function"),
+        };
+        if !value.is_null() {
+            self.0 = Some(match self.0.take() {
+                None => value.to_text(),
+                Some(text) => text + &separator + &value.to_text(),
+            });
+        }
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> Result<Value> {
+        Ok(self.0.take().map_or(Value::Null, Value::Text))
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::F(_) => "real",
+        Value::Text(_) => "text",
+        Value::Blob(_) => "blob",
+        _ => "integer",
+    }
+}
+
+fn quote(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Text(text) => format!("'{}'", text.replace('\'', "''")),
+        Value::Blob(bytes) => format!(
+            "X'{}'",
+            bytes
+                .iter()
+                .map(|b| format!("{:02X}", b))
+                .collect::<String>()
+        ),
+        value => value.to_string(),
+    }
+}
+
+/// Returns a random integer, as `random()` does
+pub fn random() -> i64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default(),
+    );
+    hasher.finish() as i64
+}
+
+fn min_max(args: &[Value], keep: Ordering) -> Value {
+    if args.iter().any(Value::is_null) {
+        return Value::Null;
+    }
+    args.iter()
+        .skip(1)
+        .fold(&args[0], |best, arg| {
+            if arg.partial_cmp(best) == Some(keep) {
+                arg
+            } else {
+                best
+            }
+        })
+        .clone()
+}
+
+fn substr(x: &Value, start: &Value, length: Option<&Value>) -> Value {
+    let integer = |v: &Value| v.cast(Affinity::Integer).as_integer().unwrap_or_default();
+    let len = match x {
+        Value::Blob(bytes) => bytes.len(),
+        x => x.to_text().chars().count(),
+    } as i64;
+
+    let mut p1 = integer(start);
+    let (mut p2, negative_length) = match length {
+        Some(length) => {
+            let length = integer(length);
+            (length.abs(), length < 0)
+        }
+        None => (len, false),
+    };
+    if p1 < 0 {
+        p1 += len;
+        if p1 < 0 {
+            p2 = (p2 + p1).max(0);
+            p1 = 0;
+        }
+    } else if p1 > 0 {
+        p1 -= 1;
+    } else if p2 > 0 {
+        p2 -= 1;
+    }
+    if negative_length {
+        p1 -= p2;
+        if p1 < 0 {
+            p2 += p1;
+            p1 = 0;
+        }
+    }
+
+    let (skip, take) = (p1.max(0) as usize, p2.max(0) as usize);
+    match x {
+        Value::Blob(bytes) => Value::Blob(bytes.iter().skip(skip).take(take).copied().collect()),
+        x => Value::Text(x.to_text().chars().skip(skip).take(take).collect()),
+    }
+}
+
+/// Matches text against a `LIKE` pattern, where `%` matches any sequence of characters and `_`
+/// any single one, ignoring the case of ASCII letters. The escape character makes the character
+/// following it match itself
+fn like(pattern: &str, text: &str, escape: Option<char>) -> bool {
+    fn matches(pattern: &[char], text: &[char], escape: Option<char>) -> bool {
+        let same = |p: &char, t: &char| p.eq_ignore_ascii_case(t);
+        match pattern {
+            [] => text.is_empty(),
+            [c, p, rest @ ..] if Some(*c) == escape => match text {
+                [t, text @ ..] => same(p, t) && matches(rest, text, escape),
+                [] => false,
+            },
+            ['%', rest @ ..] => (0..=text.len()).any(|i| matches(rest, &text[i..], escape)),
+            ['_', rest @ ..] => !text.is_empty() && matches(rest, &text[1..], escape),
+            [p, rest @ ..] => match text {
+                [t, text @ ..] => same(p, t) && matches(rest, text, escape),
+                [] => false,
+            },
+        }
+    }
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    matches(&pattern, &text, escape)
+}
+
+/// Matches text against a `GLOB` pattern, where `*` matches any sequence of characters, `?` any
+/// single one and `[...]` one of a set, which `^` negates and which may hold ranges such as `a-z`
+fn glob(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', rest @ ..] => (0..=text.len()).any(|i| glob(rest, &text[i..])),
+        ['?', rest @ ..] => !text.is_empty() && glob(rest, &text[1..]),
+        ['[', set @ ..] => match text {
+            [t, text @ ..] => match glob_set(set, *t) {
+                Some((true, rest)) => glob(rest, text),
+                _ => false,
+            },
+            [] => false,
+        },
+        [p, rest @ ..] => text.first() == Some(p) && glob(rest, &text[1..]),
+    }
+}
+
+/// Matches a character against the set of a `GLOB` pattern that follows its `[`, returning
+/// whether it matches and the rest of the pattern after the closing `]`, which a set that is not
+/// closed lacks. A `]` right at the start of the set stands for itself
+fn glob_set(set: &[char], c: char) -> Option<(bool, &[char])> {
+    let (negated, mut rest) = match set {
+        ['^', rest @ ..] => (true, rest),
+        set => (false, set),
+    };
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        match rest {
+            [']', tail @ ..] if !first => return Some((matched != negated, tail)),
+            [low, '-', high, tail @ ..] if *high != ']' => {
+                matched |= (*low..=*high).contains(&c);
+                rest = tail;
+            }
+            [x, tail @ ..] => {
+                matched |= *x == c;
+                rest = tail;
+            }
+            [] => return None,
+        }
+        first = false;
+    }
+}
+
+fn round(x: &Value, digits: &Value) -> Result<Value> {
+    let x = x.cast(Affinity::Real).get_numeric_value()?;
+    let digits = digits
+        .cast(Affinity::Integer)
+        .as_integer()
+        .unwrap_or_default()
+        .clamp(0, 30) as i32;
+    if x.abs() >= 4503599627370496.0 {
+        return Ok(Value::F(x));
+    }
+    let factor = 10f64.powi(digits);
+    let rounded = (x * factor).round() / factor;
+    Ok(Value::F(if rounded.is_finite() { rounded } else { x }))
+}
+
+/// A conversion specification of `printf`: `%[flags][width][.precision]type`
+#[derive(Default)]
+struct Spec {
+    left_align: bool,
+    plus_sign: bool,
+    space_sign: bool,
+    zero_pad: bool,
+    alternate: bool,
+    thousands: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+/// Formats `args` following SQLite's `printf`, as mentioned here:
+/// [printf](https://www.sqlite.org/printf.html)
+fn printf(format: &str, args: &[Value]) -> String {
+    let mut args = args.iter();
+    let mut next_arg = || args.next().cloned().unwrap_or(Value::Null);
+    let mut result = String::new();
+    let mut chars = format.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+
+        let mut spec = Spec::default();
+        while let Some(&flag) = chars.peek() {
+            match flag {
+                '-' => spec.left_align = true,
+                '+' => spec.plus_sign = true,
+                ' ' => spec.space_sign = true,
+                '0' => spec.zero_pad = true,
+                '#' | '!' => spec.alternate = true,
+                ',' => spec.thousands = true,
+                _ => break,
+            }
+            chars.next();
+        }
+        if chars.peek() == Some(&'*') {
+            chars.next();
+            let width = next_arg().cast(Affinity::Integer).as_integer().unwrap_or(0);
+            spec.left_align |= width < 0;
+            spec.width = width.unsigned_abs() as usize;
+        } else {
+            while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+                spec.width = spec.width * 10 + digit as usize;
+                chars.next();
+            }
+        }
+        if chars.peek() == Some(&'.') {
+            chars.next();
+            if chars.peek() == Some(&'*') {
+                chars.next();
+                let precision = next_arg().cast(Affinity::Integer).as_integer();
+                spec.precision = Some(precision.unwrap_or(0).max(0) as usize);
+            } else {
+                let mut precision = 0;
+                while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+                    precision = precision * 10 + digit as usize;
+                    chars.next();
+                }
+                spec.precision = Some(precision);
+            }
+        }
+        while matches!(chars.peek(), Some('l') | Some('h')) {
+            chars.next();
+        }
+
+        let conversion = match chars.next() {
+            Some(conversion) => conversion,
+            None => break,
+        };
+        let (text, numeric) = match conversion {
+            '%' => ("%".to_string(), false),
+            'd' | 'i' | 'u' => {
+                let n = next_arg().cast(Affinity::Integer).as_integer().unwrap_or(0);
+                let n = if conversion == 'u' { n.max(0) } else { n };
+                let digits = n.unsigned_abs().to_string();
+                let digits = match spec.precision {
+                    Some(p) if p > digits.len() => "0".repeat(p - digits.len()) + &digits,
+                    _ => digits,
+                };
+                let digits = if spec.thousands {
+                    group_thousands(&digits)
+                } else {
+                    digits
+                };
+                (sign(&spec, n < 0) + &digits, true)
+            }
+            'x' | 'X' | 'o' => {
+                let n = next_arg().cast(Affinity::Integer).as_integer().unwrap_or(0);
+                let text = match conversion {
+                    'x' => format!("{:x}", n),
+                    'X' => format!("{:X}", n),
+                    _ => format!("{:o}", n),
+                };
+                let prefix = match (spec.alternate && n != 0, conversion) {
+                    (true, 'x') => "0x",
+                    (true, 'X') => "0X",
+                    (true, _) => "0",
+                    _ => "",
+                };
+                (prefix.to_string() + &text, true)
+            }
+            'f' | 'F' | 'e' | 'E' | 'g' | 'G' => {
+                let n = next_arg()
+                    .cast(Affinity::Real)
+                    .get_numeric_value()
+                    .unwrap_or(0.0);
+                let precision = spec.precision.unwrap_or(6);
+                // Infinities print as `Inf`, unless zero padded when they print as 9e999
+                let decimal = match n.abs() {
+                    n if n.is_nan() => None,
+                    n if n.is_infinite() && !spec.zero_pad => None,
+                    n if n.is_infinite() => Some(Decimal {
+                        digits: vec![9],
+                        point: 1000,
+                    }),
+                    n => Some(Decimal::new(n)),
+                };
+                let text = match (decimal, conversion) {
+                    (None, _) if n.is_nan() && spec.zero_pad => "null".to_string(),
+                    (None, _) if n.is_nan() => "NaN".to_string(),
+                    (None, _) => "Inf".to_string(),
+                    (Some(decimal), 'f' | 'F') => decimal.fixed(precision, spec.alternate),
+                    (Some(decimal), 'e' | 'E') => {
+                        decimal.exponential(precision, spec.alternate, conversion == 'E')
+                    }
+                    (Some(decimal), _) => {
+                        decimal.general(precision, spec.alternate, conversion == 'G')
+                    }
+                };
+                let negative = n.is_sign_negative() && n != 0.0 && !n.is_nan();
+                // NaN is padded with spaces even when zero padded
+                (sign(&spec, negative) + &text, !n.is_nan())
+            }
+            'c' => {
+                let arg = next_arg().to_text();
+                let c = arg.chars().next().map(String::from).unwrap_or_default();
+                (c.repeat(spec.precision.unwrap_or(1).max(1)), false)
+            }
+            's' | 'z' => {
+                let arg = next_arg();
+                let text = arg.to_text();
+                match spec.precision {
+                    Some(p) => (text.chars().take(p).collect(), false),
+                    None => (text, false),
+                }
+            }
+            'q' | 'Q' | 'w' => {
+                let quote = if conversion == 'w' { '"' } else { '\'' };
+                let text = match next_arg() {
+                    Value::Null if conversion == 'Q' => "NULL".to_string(),
+                    Value::Null if conversion == 'q' => "(NULL)".to_string(),
+                    arg => {
+                        let escaped = arg.to_text().replace(quote, &format!("{}{}", quote, quote));
+                        if conversion == 'Q' {
+                            format!("'{}'", escaped)
+                        } else {
+                            escaped
+                        }
+                    }
+                };
+                (text, false)
+            }
+            other => {
+                result.push('%');
+                result.push(other);
+                continue;
+            }
+        };
+        result.push_str(&pad(text, &spec, numeric));
+    }
+    result
+}
+
+fn sign(spec: &Spec, negative: bool) -> String {
+    match (negative, spec.plus_sign, spec.space_sign) {
+        (true, _, _) => "-",
+        (false, true, _) => "+",
+        (false, false, true) => " ",
+        _ => "",
+    }
+    .to_string()
+}
+
+fn pad(text: String, spec: &Spec, numeric: bool) -> String {
+    let len = text.chars().count();
+    if len >= spec.width {
+        return text;
+    }
+    let fill = spec.width - len;
+    if spec.left_align {
+        text + &" ".repeat(fill)
+    } else if spec.zero_pad && numeric {
+        let sign_len = text
+            .chars()
+            .take_while(|c| matches!(c, '-' | '+' | ' '))
+            .count();
+        format!(
+            "{}{}{}",
+            &text[..sign_len],
+            "0".repeat(fill),
+            &text[sign_len..]
+        )
+    } else {
+        " ".repeat(fill) + &text
+    }
+}
+
+fn group_thousands(digits: &str) -> String {
+    let mut grouped = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+/// The decimal digits of a non-negative real, with the position of the decimal point among
+/// them. Like SQLite's printf, at most 16 significant digits are rendered, the others being
+/// zeros
+struct Decimal {
+    digits: Vec<u8>,
+    point: i32,
+}
+
+/// The number of significant digits SQLite's printf renders
+const PRINTF_DIGITS: i32 = 16;
+
+impl Decimal {
+    /// Decodes a finite non-negative real, zero having no digits. Like SQLite, the digits are
+    /// those of the 18 or 19 digit integer below 2^63 the real scales to
+    fn new(n: f64) -> Self {
+        let decode = |count: usize| {
+            let text = format!("{:.*e}", count - 1, n);
+            text.split_once('e').and_then(|(mantissa, exponent)| {
+                let digits = mantissa
+                    .bytes()
+                    .filter(u8::is_ascii_digit)
+                    .map(|b| b - b'0')
+                    .collect::<Vec<_>>();
+                Some((digits, exponent.parse::<i32>().ok()? + 1))
+            })
+        };
+        let decoded = match decode(19) {
+            Some((digits, _))
+                if digits.iter().fold(0, |v, &d| v * 10 + d as u64) > i64::MAX as u64 - 1023 =>
+            {
+                decode(18)
+            }
+            decoded => decoded,
+        };
+        match decoded {
+            Some((digits, point)) if n != 0.0 => Self { digits, point },
+            _ => Self {
+                digits: vec![],
+                point: 1,
+            },
+        }
+    }
+
+    /// Rounds half up to `count` significant digits
+    fn round(&mut self, count: i32) {
+        let count = count.min(PRINTF_DIGITS);
+        if count < 0 || self.digits.is_empty() {
+            self.digits.clear();
+            return;
+        }
+        let count = count as usize;
+        if count >= self.digits.len() {
+            return;
+        }
+        let round_up = self.digits[count] >= 5;
+        self.digits.truncate(count);
+        if round_up {
+            match self.digits.iter().rposition(|&d| d < 9) {
+                Some(i) => {
+                    self.digits[i] += 1;
+                    self.digits.truncate(i + 1);
+                }
+                None => {
+                    self.digits = vec![1];
+                    self.point += 1;
+                }
+            }
+        }
+        while self.digits.last() == Some(&0) {
+            self.digits.pop();
+        }
+    }
+
+    /// The decimal exponent of the first significant digit
+    fn exponent(&self) -> i32 {
+        match self.digits.is_empty() {
+            true => 0,
+            false => self.point - 1,
+        }
+    }
+
+    /// The digit at a position counted from the first significant digit, zero outside of them
+    fn digit(&self, position: i32) -> char {
+        let digit = usize::try_from(position)
+            .ok()
+            .and_then(|i| self.digits.get(i))
+            .unwrap_or(&0);
+        (b'0' + digit) as char
+    }
+
+    /// Formats like C's `%f`
+    fn fixed(mut self, precision: usize, alternate: bool) -> String {
+        self.round(self.point + precision as i32);
+        let mut text = match self.digits.is_empty() || self.point <= 0 {
+            true => "0".to_string(),
+            false => (0..self.point).map(|i| self.digit(i)).collect(),
+        };
+        if precision > 0 || alternate {
+            text.push('.');
+        }
+        text.extend((0..precision as i32).map(|i| self.digit(self.point + i)));
+        text
+    }
+
+    /// Formats like C's `%e`, with at least two exponent digits
+    fn exponential(mut self, precision: usize, alternate: bool, upper: bool) -> String {
+        self.round(precision as i32 + 1);
+        let mut text = self.digit(0).to_string();
+        if precision > 0 || alternate {
+            text.push('.');
+        }
+        text.extend((1..=precision as i32).map(|i| self.digit(i)));
+        let exponent = self.exponent();
+        let sign = if exponent < 0 { '-' } else { '+' };
+        let e = if upper { 'E' } else { 'e' };
+        format!("{}{}{}{:02}", text, e, sign, exponent.abs())
+    }
+
+    /// Formats like C's `%g`, removing trailing zeros unless `alternate` is set
+    fn general(mut self, precision: usize, alternate: bool, upper: bool) -> String {
+        let precision = precision.max(1);
+        self.round(precision as i32);
+        let exponent = self.exponent();
+        let text = if exponent < -4 || exponent >= precision as i32 {
+            self.exponential(precision - 1, alternate, upper)
+        } else {
+            self.fixed((precision as i32 - 1 - exponent) as usize, alternate)
+        };
+        if alternate {
+            return text;
+        }
+        match text.find(['e', 'E']) {
+            Some(e) => {
+                let (mantissa, exponent) = text.split_at(e);
+                trim_fraction(mantissa) + exponent
+            }
+            None => trim_fraction(&text),
+        }
+    }
+}
+
+fn trim_fraction(text: &str) -> String {
+    if text.contains('.') {
+        text.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> Value {
+        Value::Text(text.to_string())
+    }
+
+    /// Calls a scalar function, rendering its result as `sqlite3` prints it
+    fn call(name: &str, args: &[Value]) -> String {
+        call_scalar(name, args).unwrap().to_string()
+    }
+
+    #[test]
+    fn scalar_functions() {
+        let cases = [
+            ("typeof", vec![Value::F(1.5)], "real"),
+            ("typeof", vec![Value::Blob(vec![0])], "blob"),
+            ("quote", vec![text("it's")], "'it''s'"),
+            ("quote", vec![Value::Blob(vec![0x0a, 0xff])], "X'0AFF'"),
+            ("quote", vec![Value::Null], "NULL"),
+            ("length", vec![text("héllo")], "5"),
+            ("length", vec![Value::Blob(vec![1, 2])], "2"),
+            ("lower", vec![text("ÀBC")], "Àbc"),
+            (
+                "substr",
+                vec![text("hello"), Value::Integer(2), Value::Integer(3)],
+                "ell",
+            ),
+            ("substr", vec![text("hello"), Value::Integer(-3)], "llo"),
+            (
+                "substr",
+                vec![text("hello"), Value::Integer(0), Value::Integer(2)],
+                "h",
+            ),
+            (
+                "substr",
+                vec![text("hello"), Value::Integer(3), Value::Integer(-2)],
+                "he",
+            ),
+            ("ltrim", vec![text("xxaxx"), text("x")], "axx"),
+            (
+                "replace",
+                vec![text("aaa"), text("a"), text("bb")],
+                "bbbbbb",
+            ),
+            ("instr", vec![text("héllo"), text("l")], "3"),
+            ("abs", vec![Value::F(-1.5)], "1.5"),
+            ("round", vec![Value::F(-2.5)], "-3.0"),
+            ("round", vec![Value::F(1.2345), Value::Integer(2)], "1.23"),
+            ("hex", vec![text("abc")], "616263"),
+            ("hex", vec![Value::Null], ""),
+            ("unicode", vec![text("é")], "233"),
+            ("char", vec![Value::Integer(72), Value::Integer(105)], "Hi"),
+            (
+                "min",
+                vec![Value::Integer(3), Value::Integer(1), Value::Integer(2)],
+                "1",
+            ),
+            ("max", vec![text("a"), text("b")], "b"),
+            ("min", vec![Value::Integer(1), Value::Null], "NULL"),
+        ];
+        for (name, args, expected) in cases {
+            assert_eq!(call(name, &args), expected, "{}({:?})", name, args);
+        }
+        assert_eq!(
+            call_scalar("abs", &[Value::Integer(i64::MIN)])
+                .unwrap_err()
+                .to_string(),
+            "integer overflow"
+        );
+        assert_eq!(
+            call_scalar("upper", &[]).unwrap_err().to_string(),
+            "Wrong number of arguments to function upper()"
+        );
+    }
+
+    #[test]
+    fn printf_conversions() {
+        let cases = [
+            (
+                "%d|%5d|%-5d|%05d|%+d",
+                vec![42, 42, 42, -42, 42],
+                "42|   42|42   |-0042|+42",
+            ),
+            ("%,d", vec![1234567], "1,234,567"),
+            ("%x|%X|%#x|%o", vec![255, 255, 255, 8], "ff|FF|0xff|10"),
+            ("%*d|%c%%", vec![4, 7], "   7|%"),
+        ];
+        for (format, args, expected) in cases {
+            let args = args.into_iter().map(Value::Integer).collect::<Vec<_>>();
+            assert_eq!(printf(format, &args), expected, "{}", format);
+        }
+
+        let reals = [
+            ("%.2f", 1.23456, "1.23"),
+            ("%10.3f", -2.5, "    -2.500"),
+            ("%e", 12345.678, "1.234568e+04"),
+            ("%.3E", 0.00012, "1.200E-04"),
+            ("%g", 100000.0, "100000"),
+            ("%g", 1000000.0, "1e+06"),
+            ("%g", 0.0001, "0.0001"),
+            ("%#g", 1.0, "1.00000"),
+            // Reals are rounded half up from their first 16 significant digits
+            ("%.1f", 2.25, "2.3"),
+            ("%.2f", 2.675, "2.67"),
+            ("%.2f", 0.125, "0.13"),
+            ("%.0f", 0.5, "1"),
+            ("%.20f", 0.1, "0.10000000000000000000"),
+            ("%.20g", 0.1, "0.1"),
+            ("%.16g|%.15g", 91.493, "91.493|91.493"),
+            ("%.3g", 0.00012345, "0.000123"),
+            ("%g", 9.9999999, "10"),
+            ("%g", 1e-5, "1e-05"),
+            ("%.3e", 1.0005, "1.000e+00"),
+            ("%#.0e|%.0e", 5.0, "5.e+00|5e+00"),
+            ("%#g|%g|%e", 0.0, "0.00000|0|0.000000e+00"),
+            ("%#.0f|%f", 2.0, "2.|2.000000"),
+            ("%f", 1e-7, "0.000000"),
+            ("%.2f", 1e301, &format!("1{}.00", "0".repeat(301))),
+            // Infinities print as Inf, or as 9e999 when zero padded
+            ("%e|%g|%f|%E", f64::INFINITY, "Inf|Inf|Inf|Inf"),
+            ("%+e|% f|%-6g|", f64::INFINITY, "+Inf| Inf|Inf   |"),
+            ("%10f", f64::NEG_INFINITY, "      -Inf"),
+            (
+                "%05e|%05g|%08g",
+                f64::INFINITY,
+                "9.000000e+999|9e+999|009e+999",
+            ),
+            ("%05.2e", f64::NEG_INFINITY, "-9.00e+999"),
+            ("%f|%05f", f64::NAN, "NaN| null"),
+        ];
+        for (format, n, expected) in reals {
+            let args = vec![Value::F(n); 4];
+            assert_eq!(printf(format, &args), expected, "{}", format);
+        }
+
+        let texts = [
+            ("%s|%.2s|%5s|%-5s|", "abc", "abc|ab|  abc|abc  |"),
+            ("%q|%Q|%w", "it's", "it''s|'it''s'|it's"),
+            ("%c", "xyz", "x"),
+            ("%d", "12abc", "12"),
+        ];
+        for (format, arg, expected) in texts {
+            let args = vec![text(arg); 4];
+            assert_eq!(printf(format, &args), expected, "{}", format);
+        }
+        assert_eq!(printf("%q|%Q", &[Value::Null, Value::Null]), "(NULL)|NULL");
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::hash_map::RandomState;
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::hash::{BuildHasher, Hasher};
+use std::io::ErrorKind;
+use std::os::unix::fs::FileExt;
+
+use anyhow::Result;
+
+/// The bytes every rollback journal header starts with
+const MAGIC: [u8; 8] = [0xd9, 0xd5, 0x05, 0xf9, 0x20, 0xa1, 0x63, 0xd7];
+
+/// The size of the sector the header is padded to
+const SECTOR_SIZE: usize = 512;
+
+/// Returns the path of the rollback journal of a database file
+pub fn journal_path(file_name: &str) -> String {
+    format!("{}-journal", file_name)
+}
+
+/// Writes a rollback journal holding the original content of the pages a transaction is about
+/// to overwrite, and flushes it to the disk, as mentioned here:
+/// [rollback journal](https://www.sqlite.org/fileformat2.html#the_rollback_journal)
+pub fn write_journal(
+    path: &str,
+    page_size: usize,
+    database_size: usize,
+    pages: &[(usize, Vec<u8>)],
+) -> Result<()> {
+    let nonce = RandomState::new().build_hasher().finish() as u32;
+    let mut journal = vec![0; SECTOR_SIZE];
+    journal[..8].copy_from_slice(&MAGIC);
+    journal[8..12].copy_from_slice(&(pages.len() as u32).to_be_bytes());
+    journal[12..16].copy_from_slice(&nonce.to_be_bytes());
+    journal[16..20].copy_from_slice(&(database_size as u32).to_be_bytes());
+    journal[20..24].copy_from_slice(&(SECTOR_SIZE as u32).to_be_bytes());
+    journal[24..28].copy_from_slice(&(page_size as u32).to_be_bytes());
+    for (page_number, page) in pages {
+        journal.extend_from_slice(&(*page_number as u32).to_be_bytes());
+        journal.extend_from_slice(page);
+        journal.extend_from_slice(&checksum(nonce, page).to_be_bytes());
+    }
+    let file = File::create(path)?;
+    file.write_all_at(&journal, 0)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Removes the journal of a transaction that committed, its changes being in the database file
+pub fn delete_journal(path: &str) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Restores the pages saved in a hot journal, left behind by a transaction that did not
+/// complete, into the database file and truncates it back to its original size. Records are
+/// played back until one fails its checksum, as the journal may not have been fully written
+pub fn roll_back_hot_journal(file: &File, path: &str) -> Result<()> {
+    let journal = match fs::read(path) {
+        Ok(journal) => journal,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    let read_u32 = |offset: usize| -> Result<u32> {
+        Ok(u32::from_be_bytes(journal[offset..offset + 4].try_into()?))
+    };
+
+    // A journal may be made of several segments, each starting with a header. The size of the
+    // database before the transaction is that of the first one
+    let mut offset = 0;
+    let mut original_size = None;
+    'segments: while offset + 28 <= journal.len() && journal[offset..offset + 8] == MAGIC {
+        let record_count = read_u32(offset + 8)?;
+        let nonce = read_u32(offset + 12)?;
+        let sector_size = read_u32(offset + 20)? as usize;
+        let page_size = read_u32(offset + 24)? as usize;
+        if !sector_size.is_power_of_two() || !page_size.is_power_of_two() {
+            break;
+        }
+        let database_size = read_u32(offset + 16)? as usize;
+        let (database_size, _) = *original_size.get_or_insert((database_size, page_size));
+        offset += sector_size;
+        let record_size = page_size + 8;
+        // A count of 0xffffffff stands for the records up to the end of the file
+        let record_count = match record_count {
+            u32::MAX => journal.len().saturating_sub(offset) / record_size,
+            count => count as usize,
+        };
+        for _ in 0..record_count {
+            if offset + record_size > journal.len() {
+                break 'segments;
+            }
+            let page_number = read_u32(offset)? as usize;
+            let page = &journal[offset + 4..offset + 4 + page_size];
+            if read_u32(offset + 4 + page_size)? != checksum(nonce, page) {
+                break 'segments;
+            }
+            if page_number != 0 && page_number <= database_size {
+                file.write_all_at(page, ((page_number - 1) * page_size) as u64)?;
+            }
+            offset += record_size;
+        }
+        // The next segment starts at a sector boundary
+        offset = offset.div_ceil(sector_size) * sector_size;
+    }
+    if let Some((database_size, page_size)) = original_size {
+        file.set_len((database_size * page_size) as u64)?;
+    }
+    file.sync_all()?;
+    delete_journal(path)
+}
+
+/// Computes the checksum of a page record, which adds up every 200th byte of the page to the
+/// nonce of the journal
+fn checksum(nonce: u32, page: &[u8]) -> u32 {
+    (1..)
+        .map(|i| page.len() as isize - 200 * i)
+        .take_while(|&i| i > 0)
+        .fold(nonce, |sum, i| sum.wrapping_add(page[i as usize] as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+
+    const PAGE_SIZE: usize = 1024;
+
+    /// Returns the path of a new file for a test, removing the file left by a previous run
+    fn test_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("journal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path.to_string_lossy().into_owned()
+    }
+
+    fn page(fill: u8) -> Vec<u8> {
+        (0..PAGE_SIZE).map(|i| fill.wrapping_add(i as u8)).collect()
+    }
+
+    #[test]
+    fn checksums_add_every_200th_byte_from_the_end() {
+        let page = page(0);
+        let expected = [824, 624, 424, 224, 24]
+            .iter()
+            .fold(7u32, |sum, &i| sum + page[i] as u32);
+        assert_eq!(checksum(7, &page), expected);
+        assert_eq!(checksum(u32::MAX, &[0xff; 201]), 0xfe);
+        assert_eq!(checksum(3, &[0xff; 200]), 3);
+    }
+
+    #[test]
+    fn writes_a_header_and_checksummed_records() {
+        let path = test_path("header");
+        write_journal(&path, PAGE_SIZE, 9, &[(2, page(2)), (5, page(5))]).unwrap();
+        let journal = fs::read(&path).unwrap();
+        let read_u32 =
+            |offset: usize| u32::from_be_bytes(journal[offset..offset + 4].try_into().unwrap());
+        assert_eq!(journal[..8], MAGIC);
+        assert_eq!(read_u32(8), 2);
+        assert_eq!(read_u32(16), 9);
+        assert_eq!(read_u32(20), SECTOR_SIZE as u32);
+        assert_eq!(read_u32(24), PAGE_SIZE as u32);
+        assert!(journal[28..SECTOR_SIZE].iter().all(|&b| b == 0));
+        assert_eq!(journal.len(), SECTOR_SIZE + 2 * (PAGE_SIZE + 8));
+
+        let nonce = read_u32(12);
+        let second = SECTOR_SIZE + PAGE_SIZE + 8;
+        assert_eq!(read_u32(second), 5);
+        assert_eq!(journal[second + 4..second + 4 + PAGE_SIZE], page(5)[..]);
+        assert_eq!(read_u32(second + 4 + PAGE_SIZE), checksum(nonce, &page(5)));
+        delete_journal(&path).unwrap();
+        delete_journal(&path).unwrap();
+    }
+
+    /// Leaves a database as a crash in the middle of a transaction would: its `original_size`
+    /// pages overwritten and two pages added, and a journal holding the original pages
+    fn crashed_transaction(name: &str, original_size: usize) -> (String, File) {
+        let database = test_path(name);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&database)
+            .unwrap();
+        let originals = (1..=original_size)
+            .map(|n| (n, page(n as u8)))
+            .collect::<Vec<_>>();
+        write_journal(
+            &journal_path(&database),
+            PAGE_SIZE,
+            original_size,
+            &originals,
+        )
+        .unwrap();
+        for n in 1..=original_size + 2 {
+            file.write_all_at(&page(100 + n as u8), ((n - 1) * PAGE_SIZE) as u64)
+                .unwrap();
+        }
+        (database, file)
+    }
+
+    fn read_page(file: &File, page_number: usize) -> Vec<u8> {
+        let mut page = vec![0; PAGE_SIZE];
+        file.read_exact_at(&mut page, ((page_number - 1) * PAGE_SIZE) as u64)
+            .unwrap();
+        page
+    }
+
+    #[test]
+    fn rolls_back_a_hot_journal() {
+        let (database, file) = crashed_transaction("hot", 3);
+        roll_back_hot_journal(&file, &journal_path(&database)).unwrap();
+        assert_eq!(file.metadata().unwrap().len(), 3 * PAGE_SIZE as u64);
+        for n in 1..=3 {
+            assert_eq!(read_page(&file, n), page(n as u8));
+        }
+        assert!(!std::path::Path::new(&journal_path(&database)).exists());
+        // Without a journal, there is nothing to roll back
+        roll_back_hot_journal(&file, &journal_path(&database)).unwrap();
+        fs::remove_file(database).unwrap();
+    }
+
+    #[test]
+    fn stops_at_the_first_torn_record() {
+        let (database, file) = crashed_transaction("torn", 3);
+        let journal = journal_path(&database);
+        // A byte the checksum of the second record adds up changes, as if it were not fully
+        // written
+        let second = SECTOR_SIZE + PAGE_SIZE + 8;
+        File::options()
+            .write(true)
+            .open(&journal)
+            .unwrap()
+            .write_all_at(&[0xee], (second + 4 + 24) as u64)
+            .unwrap();
+        roll_back_hot_journal(&file, &journal).unwrap();
+        assert_eq!(read_page(&file, 1), page(1));
+        assert_eq!(read_page(&file, 2), page(102));
+        assert_eq!(read_page(&file, 3), page(103));
+        assert_eq!(file.metadata().unwrap().len(), 3 * PAGE_SIZE as u64);
+        fs::remove_file(database).unwrap();
+    }
+
+    #[test]
+    fn reads_records_up_to_the_end_without_a_count() {
+        let (database, file) = crashed_transaction("count", 2);
+        let journal = journal_path(&database);
+        File::options()
+            .write(true)
+            .open(&journal)
+            .unwrap()
+            .write_all_at(&[0xff; 4], 8)
+            .unwrap();
+        roll_back_hot_journal(&file, &journal).unwrap();
+        assert_eq!(read_page(&file, 1), page(1));
+        assert_eq!(read_page(&file, 2), page(2));
+        fs::remove_file(database).unwrap();
+    }
+}
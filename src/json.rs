@@ -0,0 +1,761 @@
+use anyhow::{bail, Error, Result};
+
+use crate::record::Value;
+
+/// A parsed JSON document. Numbers and strings keep their original text so that documents are
+/// reproduced the way they were written, only without the whitespace
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    True,
+    False,
+    Number(String),
+    /// The escaped contents of a string, without the quotes
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+/// The columns of the rows returned by `json_each` and `json_tree`
+pub const TABLE_COLUMNS: &[&str] = &[
+    "key", "value", "type", "atom", "id", "parent", "fullkey", "path",
+];
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            position: 0,
+        };
+        let json = parser.value()?;
+        parser.skip_whitespace();
+        if parser.position != text.len() {
+            bail!("malformed JSON");
+        }
+        Ok(json)
+    }
+
+    /// Parses a JSON document from an SQL value, NULL being `None`
+    fn from_sql(value: &Value) -> Result<Option<Json>> {
+        match value {
+            Value::Null => Ok(None),
+            value => Json::parse(&value.to_text()).map(Some),
+        }
+    }
+
+    /// Converts an SQL value into a JSON value, text becoming a JSON string
+    pub fn from_value(value: &Value) -> Json {
+        match value {
+            Value::Null => Json::Null,
+            Value::Text(text) => Json::String(escape(text)),
+            Value::Blob(_) => Json::String(escape(&value.to_text())),
+            value => Json::Number(value.to_string()),
+        }
+    }
+
+    /// Converts a JSON value into an SQL value, arrays and objects becoming JSON text
+    pub fn to_value(&self) -> Value {
+        match self {
+            Json::Null => Value::Null,
+            Json::True => Value::Integer(1),
+            Json::False => Value::Integer(0),
+            Json::Number(n) => match n.parse::<i64>() {
+                Ok(n) => Value::Integer(n),
+                Err(_) => Value::F(n.parse().unwrap_or_default()),
+            },
+            Json::String(s) => Value::Text(unescape(s)),
+            json => Value::Text(json.to_string()),
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Json::Null => "null",
+            Json::True => "true",
+            Json::False => "false",
+            Json::Number(n) if n.parse::<i64>().is_ok() => "integer",
+            Json::Number(_) => "real",
+            Json::String(_) => "text",
+            Json::Array(_) => "array",
+            Json::Object(_) => "object",
+        }
+    }
+
+    /// Finds the element at a path such as `$.a[2]."b c"[#-1]`
+    pub fn lookup(&self, path: &str) -> Result<Option<&Json>> {
+        let mut rest = path
+            .strip_prefix('$')
+            .ok_or_else(|| Error::msg(format!("bad JSON path: {}", path)))?;
+        let mut current = self;
+        while !rest.is_empty() {
+            let (step, remaining) = parse_path_step(rest)
+                .ok_or_else(|| Error::msg(format!("bad JSON path: {}", path)))?;
+            rest = remaining;
+            let next = match (step, current) {
+                (PathStep::Key(key), Json::Object(members)) => members
+                    .iter()
+                    .rev()
+                    .find(|(k, _)| unescape(k) == key)
+                    .map(|(_, v)| v),
+                (PathStep::Index(i), Json::Array(items)) => items.get(i),
+                (PathStep::FromEnd(i), Json::Array(items)) => {
+                    items.len().checked_sub(i).and_then(|i| items.get(i))
+                }
+                _ => None,
+            };
+            match next {
+                Some(next) => current = next,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(current))
+    }
+}
+
+impl std::fmt::Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::True => write!(f, "true"),
+            Json::False => write!(f, "false"),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => write!(f, "\"{}\"", s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "\"{}\":{}", key, value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// An argument of `json_object` or `json_array`, which embeds values coming from other JSON
+/// functions as JSON rather than as strings
+pub struct JsonArg {
+    pub value: Value,
+    pub is_json: bool,
+}
+
+impl JsonArg {
+    fn to_json(&self) -> Result<Json> {
+        match (&self.value, self.is_json) {
+            (Value::Text(text), true) => Json::parse(text),
+            (value, _) => Ok(Json::from_value(value)),
+        }
+    }
+}
+
+pub fn is_json_function(name: &str) -> bool {
+    matches!(
+        name,
+        "json" | "json_extract" | "json_type" | "json_array_length" | "json_object" | "json_array"
+    )
+}
+
+/// Whether the values returned by a function are JSON rather than plain text
+pub fn returns_json(name: &str) -> bool {
+    matches!(name, "json" | "json_object" | "json_array")
+}
+
+/// Calls one of the JSON functions, as mentioned here:
+/// [json1](https://www.sqlite.org/json1.html)
+pub fn call(name: &str, args: &[JsonArg]) -> Result<Value> {
+    let values = args.iter().map(|arg| &arg.value).collect::<Vec<_>>();
+    let value = match (name, &values[..]) {
+        ("json_object", _) => {
+            if !args.len().is_multiple_of(2) {
+                bail!("json_object() requires an even number of arguments");
+            }
+            let members = args
+                .chunks(2)
+                .map(|pair| match &pair[0].value {
+                    Value::Text(label) => Ok((escape(label), pair[1].to_json()?)),
+                    _ => bail!("json_object() labels must be TEXT"),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Value::Text(Json::Object(members).to_string())
+        }
+        ("json_array", _) => {
+            let items = args
+                .iter()
+                .map(JsonArg::to_json)
+                .collect::<Result<Vec<_>>>()?;
+            Value::Text(Json::Array(items).to_string())
+        }
+        ("json", [json]) => match Json::from_sql(json)? {
+            Some(json) => Value::Text(json.to_string()),
+            None => Value::Null,
+        },
+        ("json_extract", [json, paths @ ..]) if !paths.is_empty() => {
+            let json = match Json::from_sql(json)? {
+                Some(json) => json,
+                None => return Ok(Value::Null),
+            };
+            match paths {
+                [path] => match path {
+                    Value::Null => Value::Null,
+                    path => json
+                        .lookup(&path.to_text())?
+                        .map_or(Value::Null, Json::to_value),
+                },
+                paths => {
+                    let items = paths
+                        .iter()
+                        .map(|path| {
+                            Ok(json.lookup(&path.to_text())?.cloned().unwrap_or(Json::Null))
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    Value::Text(Json::Array(items).to_string())
+                }
+            }
+        }
+        ("json_type", [json, path @ ..]) if path.len() <= 1 => {
+            match lookup(json, path.first().copied())? {
+                Some(json) => Value::Text(json.type_name().to_string()),
+                None => Value::Null,
+            }
+        }
+        ("json_array_length", [json, path @ ..]) if path.len() <= 1 => {
+            match lookup(json, path.first().copied())? {
+                Some(Json::Array(items)) => Value::Integer(items.len() as i64),
+                Some(_) => Value::Integer(0),
+                None => Value::Null,
+            }
+        }
+        _ => bail!("Wrong number of arguments to function {}()", name),
+    };
+    Ok(value)
+}
+
+/// Evaluates `json -> path` or, when `as_value` is set, `json ->> path`. The path may also be an
+/// object label or an array index
+pub fn arrow(json: &Value, path: &Value, as_value: bool) -> Result<Value> {
+    let json = match Json::from_sql(json)? {
+        Some(json) => json,
+        None => return Ok(Value::Null),
+    };
+    let path = match path {
+        Value::Null => return Ok(Value::Null),
+        Value::Text(path) if path.starts_with('$') => path.clone(),
+        Value::Text(label) => format!("$.\"{}\"", label),
+        index => format!("$[{}]", index),
+    };
+    Ok(match json.lookup(&path)? {
+        Some(found) if as_value => found.to_value(),
+        Some(found) => Value::Text(found.to_string()),
+        None => Value::Null,
+    })
+}
+
+fn lookup(json: &Value, path: Option<&Value>) -> Result<Option<Json>> {
+    let json = match Json::from_sql(json)? {
+        Some(json) => json,
+        None => return Ok(None),
+    };
+    match path {
+        None => Ok(Some(json)),
+        Some(Value::Null) => Ok(None),
+        Some(path) => Ok(json.lookup(&path.to_text())?.cloned()),
+    }
+}
+
+/// Lists the rows of the table-valued functions `json_each(json[, path])` and
+/// `json_tree(json[, path])`, whose columns are [TABLE_COLUMNS]
+pub fn table(name: &str, args: &[Value]) -> Result<Vec<Vec<Value>>> {
+    let (json, path) = match args {
+        [json] => (json, "$".to_string()),
+        [json, path] => (json, path.to_text()),
+        _ => bail!(
+            "Wrong number of arguments to table-valued function {}()",
+            name
+        ),
+    };
+    let json = match Json::from_sql(json)? {
+        Some(json) => json,
+        None => return Ok(vec![]),
+    };
+    let root = match json.lookup(&path)? {
+        Some(root) => root,
+        None => return Ok(vec![]),
+    };
+
+    let mut walker = TreeWalker {
+        rows: vec![],
+        next_id: 0,
+        recursive: name == "json_tree",
+    };
+    let parent_path = match path.rfind(['.', '[']) {
+        Some(i) if name == "json_tree" && i > 0 => path[..i].to_string(),
+        _ => "$".to_string(),
+    };
+    if walker.recursive || !matches!(root, Json::Array(_) | Json::Object(_)) {
+        walker.visit(root, Value::Null, None, &path, &parent_path);
+    } else {
+        let id = walker.next_id();
+        walker.visit_children(root, id, &path);
+    }
+    Ok(walker.rows)
+}
+
+struct TreeWalker {
+    rows: Vec<Vec<Value>>,
+    next_id: i64,
+    recursive: bool,
+}
+
+impl TreeWalker {
+    fn next_id(&mut self) -> i64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn visit(&mut self, json: &Json, key: Value, parent: Option<i64>, full_key: &str, path: &str) {
+        let id = self.next_id();
+        let (value, atom) = match json {
+            Json::Array(_) | Json::Object(_) => (Value::Text(json.to_string()), Value::Null),
+            json => (json.to_value(), json.to_value()),
+        };
+        self.rows.push(vec![
+            key,
+            value,
+            Value::Text(json.type_name().to_string()),
+            atom,
+            Value::Integer(id),
+            // Only json_tree reports parents
+            parent
+                .filter(|_| self.recursive)
+                .map_or(Value::Null, Value::Integer),
+            Value::Text(full_key.to_string()),
+            Value::Text(path.to_string()),
+        ]);
+        if self.recursive {
+            self.visit_children(json, id, full_key);
+        }
+    }
+
+    fn visit_children(&mut self, json: &Json, id: i64, path: &str) {
+        match json {
+            Json::Array(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    let full_key = format!("{}[{}]", path, i);
+                    self.visit(item, Value::Integer(i as i64), Some(id), &full_key, path);
+                }
+            }
+            Json::Object(members) => {
+                for (key, value) in members {
+                    let key = unescape(key);
+                    let full_key = format!("{}.{}", path, quote_label(&key));
+                    self.visit(value, Value::Text(key), Some(id), &full_key, path);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+enum PathStep {
+    Key(String),
+    Index(usize),
+    FromEnd(usize),
+}
+
+/// Parses the first step of a path, `.key`, `."key"`, `[N]` or `[#-N]`
+fn parse_path_step(path: &str) -> Option<(PathStep, &str)> {
+    if let Some(rest) = path.strip_prefix('.') {
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"')?;
+            return Some((PathStep::Key(quoted[..end].to_string()), &quoted[end + 1..]));
+        }
+        let end = rest.find(['.', '[']).unwrap_or(rest.len());
+        if end == 0 {
+            return None;
+        }
+        return Some((PathStep::Key(rest[..end].to_string()), &rest[end..]));
+    }
+    let rest = path.strip_prefix('[')?;
+    let end = rest.find(']')?;
+    let index = rest[..end].trim();
+    let step = match index.strip_prefix('#') {
+        Some(from_end) => {
+            let n = from_end
+                .trim()
+                .strip_prefix('-')
+                .map_or(Some(0), |n| n.trim().parse().ok())?;
+            PathStep::FromEnd(n)
+        }
+        None => PathStep::Index(index.parse().ok()?),
+    };
+    Some((step, &rest[end + 1..]))
+}
+
+/// Quotes an object label in a path unless it is a plain identifier
+fn quote_label(label: &str) -> String {
+    if !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        label.to_string()
+    } else {
+        format!("\"{}\"", label)
+    }
+}
+
+/// Escapes text to be the contents of a JSON string
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '\u{8}' => escaped.push_str("\\b"),
+            '\u{c}' => escaped.push_str("\\f"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Decodes the escaped contents of a JSON string
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('t') => unescaped.push('\t'),
+            Some('b') => unescaped.push('\u{8}'),
+            Some('f') => unescaped.push('\u{c}'),
+            Some('u') => {
+                let code = |chars: &mut std::str::Chars| {
+                    let hex = chars.by_ref().take(4).collect::<String>();
+                    u32::from_str_radix(&hex, 16).unwrap_or(0xFFFD)
+                };
+                let mut point = code(&mut chars);
+                if (0xD800..0xDC00).contains(&point) && chars.as_str().starts_with("\\u") {
+                    chars.nth(1);
+                    let low = code(&mut chars);
+                    point = 0x10000 + ((point - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+                }
+                unescaped.push(char::from_u32(point).unwrap_or('\u{FFFD}'));
+            }
+            Some(other) => unescaped.push(other),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.position).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<()> {
+        self.skip_whitespace();
+        if self.peek() != Some(byte) {
+            bail!("malformed JSON");
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Json> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => {
+                self.position += 1;
+                let mut members = vec![];
+                self.skip_whitespace();
+                if self.peek() == Some(b'}') {
+                    self.position += 1;
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    members.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.position += 1,
+                        Some(b'}') => {
+                            self.position += 1;
+                            return Ok(Json::Object(members));
+                        }
+                        _ => bail!("malformed JSON"),
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.position += 1;
+                let mut items = vec![];
+                self.skip_whitespace();
+                if self.peek() == Some(b']') {
+                    self.position += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.position += 1,
+                        Some(b']') => {
+                            self.position += 1;
+                            return Ok(Json::Array(items));
+                        }
+                        _ => bail!("malformed JSON"),
+                    }
+                }
+            }
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b't') => self.literal("true", Json::True),
+            Some(b'f') => self.literal("false", Json::False),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => bail!("malformed JSON"),
+        }
+    }
+
+    fn literal(&mut self, word: &str, json: Json) -> Result<Json> {
+        if self.text[self.position..].starts_with(word.as_bytes()) {
+            self.position += word.len();
+            Ok(json)
+        } else {
+            bail!("malformed JSON")
+        }
+    }
+
+    fn number(&mut self) -> Result<Json> {
+        let start = self.position;
+        let digits = |parser: &mut Self| {
+            let from = parser.position;
+            while matches!(parser.peek(), Some(b'0'..=b'9')) {
+                parser.position += 1;
+            }
+            parser.position > from
+        };
+        if self.peek() == Some(b'-') {
+            self.position += 1;
+        }
+        if !digits(self) {
+            bail!("malformed JSON");
+        }
+        if self.peek() == Some(b'.') {
+            self.position += 1;
+            if !digits(self) {
+                bail!("malformed JSON");
+            }
+        }
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            self.position += 1;
+            if matches!(self.peek(), Some(b'+' | b'-')) {
+                self.position += 1;
+            }
+            if !digits(self) {
+                bail!("malformed JSON");
+            }
+        }
+        let number = std::str::from_utf8(&self.text[start..self.position])?;
+        Ok(Json::Number(number.to_string()))
+    }
+
+    /// Reads a string, returning its contents still escaped
+    fn string(&mut self) -> Result<String> {
+        if self.peek() != Some(b'"') {
+            bail!("malformed JSON");
+        }
+        self.position += 1;
+        let start = self.position;
+        loop {
+            match self.peek() {
+                Some(b'"') => break,
+                Some(b'\\') => self.position += 2,
+                Some(c) if c < 0x20 => bail!("malformed JSON"),
+                Some(_) => self.position += 1,
+                None => bail!("malformed JSON"),
+            }
+        }
+        let contents = std::str::from_utf8(&self.text[start..self.position])?;
+        self.position += 1;
+        Ok(contents.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::{Columns, Row};
+    use crate::functions::Functions;
+    use crate::sql::{ResultColumn, Select};
+
+    /// Evaluates an expression without any table, rendering it like SQLite's `quote()`
+    fn eval(sql: &str) -> Result<String> {
+        let select = Select::parse_select(&format!("SELECT {}", sql))?;
+        let expr = match &select.columns[0] {
+            ResultColumn::Expr(expr, _) => expr,
+            ResultColumn::All => unreachable!(),
+        };
+        let columns = Columns::new(vec![]);
+        let row = Row {
+            columns: &columns,
+            values: &[],
+            functions: &Functions::default(),
+        };
+        Ok(match expr.eval(&row)? {
+            Value::Text(text) => format!("'{}'", text),
+            value => value.to_string(),
+        })
+    }
+
+    #[test]
+    fn json_functions() {
+        let cases = [
+            (
+                r#"json(' { "a" : [1, 2.5, "x", null, true] } ')"#,
+                r#"'{"a":[1,2.5,"x",null,true]}'"#,
+            ),
+            (r#"json('"A\n"')"#, r#"'"A\n"'"#),
+            (
+                r#"json_extract('{"a":{"b":[10,20,30]}}', '$.a.b[1]')"#,
+                "20",
+            ),
+            (
+                r#"json_extract('{"a":{"b":[10,20,30]}}', '$.a.b')"#,
+                "'[10,20,30]'",
+            ),
+            (
+                r#"json_extract('{"a":1,"b":"two"}', '$.a', '$.b', '$.c')"#,
+                r#"'[1,"two",null]'"#,
+            ),
+            ("json_extract('[1,2,3]', '$[#-1]')", "3"),
+            (r#"json_extract('{"a":"q\"uote"}', '$.a')"#, r#"'q"uote'"#),
+            (
+                r#"json_type('{"a":[1,2.5,"x",null,true]}', '$.a[1]')"#,
+                "'real'",
+            ),
+            (
+                r#"json_type('{"a":[1,2.5,"x",null,true]}', '$.a[4]')"#,
+                "'true'",
+            ),
+            (r#"json_type('{"a":[]}')"#, "'object'"),
+            ("json_array_length('[1,[2,3],4]')", "3"),
+            ("json_array_length('[1,[2,3],4]', '$[1]')", "2"),
+            (r#"json_array_length('{"a":1}')"#, "0"),
+        ];
+        for (sql, expected) in cases {
+            assert_eq!(eval(sql).unwrap(), expected, "{}", sql);
+        }
+        assert_eq!(
+            eval("json('{bad')").unwrap_err().to_string(),
+            "malformed JSON"
+        );
+    }
+
+    #[test]
+    fn json_values_nest_as_json() {
+        let cases = [
+            (
+                "json_object('a', 1, 'b', 'text', 'c', json('[1,2]'), 'd', NULL)",
+                r#"'{"a":1,"b":"text","c":[1,2],"d":null}'"#,
+            ),
+            (
+                r#"json_array(1, 2.5, 'x', NULL, json_array(3), json('{"k":"v"}'))"#,
+                r#"'[1,2.5,"x",null,[3],{"k":"v"}]'"#,
+            ),
+            ("json_array('[1]')", r#"'["[1]"]'"#),
+            (r#"'{"a":{"b":[1,2]}}' -> '$.a'"#, r#"'{"b":[1,2]}'"#),
+            (r#"'{"a":{"b":[1,2]}}' ->> '$.a.b[0]'"#, "1"),
+            (r#"'{"a":"x"}' -> 'a'"#, r#"'"x"'"#),
+            (r#"'{"a":"x"}' ->> 'a'"#, "'x'"),
+            ("'[5,6,7]' ->> 2", "7"),
+        ];
+        for (sql, expected) in cases {
+            assert_eq!(eval(sql).unwrap(), expected, "{}", sql);
+        }
+        assert_eq!(
+            eval("json_object('a')").unwrap_err().to_string(),
+            "json_object() requires an even number of arguments"
+        );
+    }
+
+    /// Lists the rows of `json_each` or `json_tree` with the given columns of [TABLE_COLUMNS]
+    fn rows(name: &str, args: &[&str], columns: &[&str]) -> Vec<String> {
+        let args = args
+            .iter()
+            .map(|arg| Value::Text(arg.to_string()))
+            .collect::<Vec<_>>();
+        let positions = columns
+            .iter()
+            .map(|c| TABLE_COLUMNS.iter().position(|t| t == c).unwrap())
+            .collect::<Vec<_>>();
+        table(name, &args)
+            .unwrap()
+            .iter()
+            .map(|row| {
+                let values = positions.iter().map(|&i| row[i].to_text());
+                values.collect::<Vec<_>>().join("|")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn json_table_valued_functions() {
+        let columns = ["key", "value", "type", "atom", "fullkey", "path"];
+        assert_eq!(
+            rows("json_each", &[r#"{"a":1,"b":[2,3]}"#], &columns),
+            ["a|1|integer|1|$.a|$", "b|[2,3]|array||$.b|$"]
+        );
+        assert_eq!(
+            rows("json_tree", &[r#"{"a":1,"b":[2,{"c":3}]}"#], &columns),
+            [
+                r#"|{"a":1,"b":[2,{"c":3}]}|object||$|$"#,
+                "a|1|integer|1|$.a|$",
+                r#"b|[2,{"c":3}]|array||$.b|$"#,
+                "0|2|integer|2|$.b[0]|$.b",
+                r#"1|{"c":3}|object||$.b[1]|$.b"#,
+                "c|3|integer|3|$.b[1].c|$.b[1]",
+            ]
+        );
+        assert_eq!(
+            rows(
+                "json_each",
+                &[r#"{"a":{"x":1,"y":[2]}}"#, "$.a"],
+                &["key", "value", "fullkey", "path"]
+            ),
+            ["x|1|$.a.x|$.a", "y|[2]|$.a.y|$.a"]
+        );
+    }
+}
@@ -1,9 +1,21 @@
+pub mod btree;
 pub mod cell;
+pub mod collation;
+pub mod datetime;
 pub mod db;
 pub mod db_header;
+pub mod expr;
+pub mod freelist;
+pub mod functions;
+pub mod journal;
+pub mod json;
+pub mod lock;
 pub mod page;
 pub mod page_header;
+pub mod pager;
+pub mod query;
 pub mod record;
 pub mod schema;
 pub mod sql;
 pub mod varint;
+pub mod wal;
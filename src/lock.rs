@@ -0,0 +1,237 @@
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::os::raw::{c_int, c_short};
+use std::os::unix::io::AsRawFd;
+
+use anyhow::{bail, Result};
+
+/// The byte whose write lock a writer takes before waiting for readers to leave, so that no new
+/// reader comes in meanwhile
+const PENDING_BYTE: i64 = 0x4000_0000;
+
+/// The byte whose write lock a connection takes to start writing
+const RESERVED_BYTE: i64 = PENDING_BYTE + 1;
+
+/// The bytes readers take a read lock on, which a writer takes a write lock on to commit
+const SHARED_FIRST: i64 = PENDING_BYTE + 2;
+const SHARED_SIZE: i64 = 510;
+
+/// The locks a connection holds on a database file in rollback journal mode, each one allowing
+/// what the ones before it do, as mentioned here:
+/// [file locking](https://www.sqlite.org/lockingv3.html)
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub enum LockLevel {
+    Unlocked,
+    /// Reading, which other connections may also do
+    Shared,
+    /// Reading and about to write, which only one connection at a time may do
+    Reserved,
+    /// Waiting for the readers to leave before writing to the file
+    Pending,
+    /// Writing to the file, with no other connection reading it
+    Exclusive,
+}
+
+/// The lock a connection holds on a database file, as byte-range locks shared with the other
+/// processes using the file, `sqlite3` included. The bytes start at 1 GiB, where no page data
+/// is ever read from
+pub struct FileLock {
+    level: Cell<LockLevel>,
+}
+
+impl FileLock {
+    pub fn new() -> Self {
+        Self {
+            level: Cell::new(LockLevel::Unlocked),
+        }
+    }
+
+    pub fn level(&self) -> LockLevel {
+        self.level.get()
+    }
+
+    /// Raises the lock to the given level, failing when another connection holds a lock it
+    /// conflicts with. A connection can only write once it holds a reserved lock, and commit once
+    /// it holds an exclusive one
+    pub fn lock(&self, file: &File, level: LockLevel) -> Result<()> {
+        let current = self.level.get();
+        if level <= current {
+            return Ok(());
+        }
+        if current == LockLevel::Unlocked {
+            // The pending byte is read locked while taking the shared lock, which fails while a
+            // writer waits for the readers to leave
+            set_lock(file, ReadLock, PENDING_BYTE, 1)?;
+            let shared = set_lock(file, ReadLock, SHARED_FIRST, SHARED_SIZE);
+            set_lock(file, Unlock, PENDING_BYTE, 1)?;
+            shared?;
+            self.level.set(LockLevel::Shared);
+        }
+        if level >= LockLevel::Reserved && self.level.get() < LockLevel::Reserved {
+            set_lock(file, WriteLock, RESERVED_BYTE, 1)?;
+            self.level.set(LockLevel::Reserved);
+        }
+        if level >= LockLevel::Pending && self.level.get() < LockLevel::Pending {
+            set_lock(file, WriteLock, PENDING_BYTE, 1)?;
+            self.level.set(LockLevel::Pending);
+        }
+        if level == LockLevel::Exclusive {
+            set_lock(file, WriteLock, SHARED_FIRST, SHARED_SIZE)?;
+            self.level.set(LockLevel::Exclusive);
+        }
+        Ok(())
+    }
+
+    /// Lowers the lock to a shared lock or to no lock at all
+    pub fn unlock(&self, file: &File, level: LockLevel) -> Result<()> {
+        let current = self.level.get();
+        if level >= current {
+            return Ok(());
+        }
+        match level {
+            LockLevel::Shared => {
+                if current == LockLevel::Exclusive {
+                    set_lock(file, ReadLock, SHARED_FIRST, SHARED_SIZE)?;
+                }
+                set_lock(file, Unlock, PENDING_BYTE, 2)?;
+            }
+            LockLevel::Unlocked => set_lock(file, Unlock, PENDING_BYTE, 2 + SHARED_SIZE)?,
+            _ => bail!("Cannot lower a lock to {:?}", level),
+        }
+        self.level.set(level);
+        Ok(())
+    }
+
+    /// Whether another connection holds a reserved lock or a stronger one, and so may be
+    /// writing to the file
+    pub fn is_reserved_elsewhere(&self, file: &File) -> Result<bool> {
+        if self.level.get() >= LockLevel::Reserved {
+            return Ok(false);
+        }
+        let mut lock = Flock::new(WriteLock, RESERVED_BYTE, 1);
+        fcntl_lock(file, F_GETLK, &mut lock)?;
+        Ok(lock.l_type != Unlock.raw())
+    }
+}
+
+impl Default for FileLock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+use LockType::{ReadLock, Unlock, WriteLock};
+
+/// A POSIX advisory lock, which many processes may hold for reading but only one for writing
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LockType {
+    ReadLock,
+    WriteLock,
+    Unlock,
+}
+
+/// Takes or releases a lock on a range of bytes of a file, failing at once when another process
+/// holds a conflicting one
+pub fn set_lock(file: &File, lock_type: LockType, start: i64, len: i64) -> Result<()> {
+    if !try_set_lock(file, lock_type, start, len)? {
+        bail!("database is locked");
+    }
+    Ok(())
+}
+
+/// Takes or releases a lock on a range of bytes of a file, returning whether it could
+pub fn try_set_lock(file: &File, lock_type: LockType, start: i64, len: i64) -> Result<bool> {
+    let mut lock = Flock::new(lock_type, start, len);
+    match fcntl_lock(file, F_SETLK, &mut lock) {
+        Ok(()) => Ok(true),
+        Err(err)
+            if matches!(
+                err.kind(),
+                ErrorKind::WouldBlock | ErrorKind::PermissionDenied
+            ) =>
+        {
+            Ok(false)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn fcntl_lock(file: &File, command: c_int, lock: &mut Flock) -> io::Result<()> {
+    // The lock outlives no call, and fcntl only reads and writes the lock it is given
+    match unsafe { fcntl(file.as_raw_fd(), command, lock as *mut Flock) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+extern "C" {
+    fn fcntl(fd: c_int, command: c_int, ...) -> c_int;
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::os::raw::{c_int, c_short};
+
+    pub const F_GETLK: c_int = 5;
+    pub const F_SETLK: c_int = 6;
+    pub const F_RDLCK: c_short = 0;
+    pub const F_WRLCK: c_short = 1;
+    pub const F_UNLCK: c_short = 2;
+
+    /// The `struct flock` of the C library
+    #[repr(C)]
+    pub struct Flock {
+        pub l_type: c_short,
+        pub l_whence: c_short,
+        pub l_start: i64,
+        pub l_len: i64,
+        pub l_pid: c_int,
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::os::raw::{c_int, c_short};
+
+    pub const F_GETLK: c_int = 7;
+    pub const F_SETLK: c_int = 8;
+    pub const F_RDLCK: c_short = 1;
+    pub const F_WRLCK: c_short = 3;
+    pub const F_UNLCK: c_short = 2;
+
+    /// The `struct flock` of the C library
+    #[repr(C)]
+    pub struct Flock {
+        pub l_start: i64,
+        pub l_len: i64,
+        pub l_pid: c_int,
+        pub l_type: c_short,
+        pub l_whence: c_short,
+    }
+}
+
+use platform::{Flock, F_GETLK, F_RDLCK, F_SETLK, F_UNLCK, F_WRLCK};
+
+impl LockType {
+    fn raw(self) -> c_short {
+        match self {
+            ReadLock => F_RDLCK,
+            WriteLock => F_WRLCK,
+            Unlock => F_UNLCK,
+        }
+    }
+}
+
+impl Flock {
+    /// A lock on `len` bytes from `start`, counted from the start of the file
+    fn new(lock_type: LockType, start: i64, len: i64) -> Self {
+        Flock {
+            l_type: lock_type.raw(),
+            l_whence: 0,
+            l_start: start,
+            l_len: len,
+            l_pid: 0,
+        }
+    }
+}
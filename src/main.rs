@@ -1,7 +1,9 @@
 use anyhow::{bail, Result};
 
 use sqlite_starter_rust::db::DB;
-use sqlite_starter_rust::sql::Select;
+use sqlite_starter_rust::sql::{
+    Delete, DropStatement, Insert, Select, Transaction, Update, WalCheckpoint,
+};
 
 fn main() -> Result<()> {
     let args = std::env::args().collect::<Vec<_>>();
@@ -11,15 +13,12 @@ fn main() -> Result<()> {
         _ => {}
     }
 
-    let db = DB::new(&args[1])?;
+    let mut db = DB::new(&args[1])?;
     let command = &args[2];
 
     match command.as_str() {
         ".dbinfo" => println!("number of tables: {}", db.tables()?.len()),
         ".tables" => println!("{}", db.tables()?.join(" ")),
-        query if query.to_lowercase().starts_with("select count(*)") => {
-            println!("{}", db.count(query.split(' ').last().unwrap())?)
-        }
         query if query.to_lowercase().starts_with("select") => {
             let select = Select::parse_select(query)?;
             println!(
@@ -35,7 +34,31 @@ fn main() -> Result<()> {
                     .join("\n")
             )
         }
-        _ => bail!("Missing or invalid command passed: {}", command),
+        query if query.to_lowercase().starts_with("insert") => {
+            db.insert(Insert::parse(query)?)?;
+        }
+        query if query.to_lowercase().starts_with("update") => {
+            db.update(Update::parse(query)?)?;
+        }
+        query if query.to_lowercase().starts_with("delete") => {
+            db.delete(Delete::parse(query)?)?;
+        }
+        query if query.to_lowercase().starts_with("create") => db.create(query)?,
+        query if query.to_lowercase().starts_with("drop") => {
+            db.drop_object(DropStatement::parse(query)?)?;
+        }
+        query if query.to_lowercase().starts_with("pragma") => {
+            match db.checkpoint(WalCheckpoint::parse(query)?)? {
+                Some((busy, log, checkpointed)) => {
+                    println!("{}|{}|{}", busy as u8, log, checkpointed)
+                }
+                None => println!("0|-1|-1"),
+            }
+        }
+        query => match Transaction::parse(query) {
+            Ok(transaction) => db.transaction(transaction)?,
+            Err(_) => bail!("Missing or invalid command passed: {}", command),
+        },
     }
 
     Ok(())
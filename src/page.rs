@@ -5,20 +5,25 @@ use anyhow::{Context, Result};
 
 use crate::cell::{IndexInteriorCell, IndexLeafCell, TableInteriorCell, TableLeafCell};
 use crate::page_header::PageHeader;
+use crate::pager::Pager;
 use crate::record::Value;
 
 pub fn parse_table_leaf(
+    pager: &Pager,
     stream: &[u8],
     db_header_offset: usize,
     page_header: PageHeader,
     column_count: usize,
-) -> Result<Vec<Vec<Value>>> {
+) -> Result<Vec<(i64, Vec<Value>)>> {
     collect_cell_pointers(
         &stream[db_header_offset + page_header.size()..],
         page_header.number_of_cells.into(),
     )
     .into_iter()
-    .map(|ptr| TableLeafCell::parse(&stream[ptr as usize..]).get_record(column_count))
+    .map(|ptr| {
+        let cell = TableLeafCell::parse(&stream[ptr as usize..], pager.usable_size());
+        Ok((cell.rowid, cell.get_record(pager, column_count)?))
+    })
     .collect::<Result<Vec<_>>>()
 }
 
@@ -54,6 +59,7 @@ pub fn parse_table_interior(
 }
 
 pub fn parse_index_leaf(
+    pager: &Pager,
     stream: &[u8],
     page_header: PageHeader,
     column_count: usize,
@@ -63,7 +69,10 @@ pub fn parse_index_leaf(
         page_header.number_of_cells.into(),
     )
     .into_iter()
-    .map(|ptr| IndexLeafCell::parse(&stream[ptr as usize..]).get_record(column_count))
+    .map(|ptr| {
+        IndexLeafCell::parse(&stream[ptr as usize..], pager.usable_size())
+            .get_record(pager, column_count)
+    })
     .collect::<Result<Vec<_>>>()
 }
 
@@ -73,6 +82,7 @@ pub struct IndexBTree {
 }
 
 pub fn parse_index_interior(
+    pager: &Pager,
     stream: &[u8],
     page_header: PageHeader,
     column_count: usize,
@@ -85,13 +95,13 @@ pub fn parse_index_interior(
     let mut left = Vec::with_capacity(cell_pointers.len());
 
     for pointer in cell_pointers {
-        let cell = IndexInteriorCell::parse(&stream[pointer as usize..]);
-        left.push((cell.left_child_page, cell.get_record(column_count + 1)?));
+        let cell = IndexInteriorCell::parse(&stream[pointer as usize..], pager.usable_size());
+        left.push((cell.left_child_page, cell.get_record(pager, column_count)?));
     }
     Ok(IndexBTree { left, right })
 }
 
-fn collect_cell_pointers(database: &[u8], number_of_cells: usize) -> Vec<u16> {
+pub fn collect_cell_pointers(database: &[u8], number_of_cells: usize) -> Vec<u16> {
     database
         .chunks_exact(2)
         .take(number_of_cells)
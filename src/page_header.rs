@@ -2,7 +2,7 @@ use std::convert::TryInto;
 
 use anyhow::{bail, Result};
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum BTreePage {
     InteriorIndex = 2,
     InteriorTable = 5,
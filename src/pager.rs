@@ -0,0 +1,686 @@
+use std::cell::{Cell, Ref, RefCell};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::ErrorKind;
+use std::os::unix::fs::FileExt;
+
+use anyhow::{bail, Result};
+
+use crate::db_header::DBHeader;
+use crate::freelist::TrunkPage;
+use crate::journal::{delete_journal, journal_path, roll_back_hot_journal, write_journal};
+use crate::lock::{FileLock, LockLevel};
+use crate::wal::{wal_path, CheckpointMode, Wal};
+
+/// Reads and writes the pages of a database file. Written pages are kept in memory until the
+/// transaction commits, when they are written to the file behind a rollback journal, or
+/// appended to the write-ahead log in WAL mode. Pages are then read from the log when it holds
+/// them. The file is locked while reading and writing, as other processes may be using it
+pub struct Pager {
+    file: File,
+    journal_path: String,
+    wal_path: String,
+    /// The write-ahead log, once the database was read in WAL mode
+    wal: RefCell<Option<Wal>>,
+    lock: FileLock,
+    /// The header as of the last change, which is read again from the file at the start of each
+    /// transaction
+    header: RefCell<DBHeader>,
+    page_count: Cell<usize>,
+    /// The number of pages of the file, as of the last commit
+    committed_page_count: Cell<usize>,
+    /// The pages written since the last commit
+    dirty: BTreeMap<usize, Vec<u8>>,
+    /// The savepoints open in the transaction, the innermost last
+    savepoints: Vec<Savepoint>,
+}
+
+/// The state of the pages at the start of a savepoint, to roll back to
+struct Savepoint {
+    header: DBHeader,
+    page_count: usize,
+    /// The pages written since the start of the savepoint, with the content they had in memory
+    /// before, if any
+    pages: BTreeMap<usize, Option<Vec<u8>>>,
+}
+
+impl Pager {
+    /// Opens a database file for reading and, when permitted, for writing. A transaction left
+    /// incomplete by a crash is rolled back first
+    pub fn open(file_name: &str) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(file_name)
+            .or_else(|_| File::open(file_name))?;
+        let journal_path = journal_path(file_name);
+        let lock = FileLock::new();
+        lock.lock(&file, LockLevel::Shared)?;
+        let read =
+            recover_hot_journal(&file, &lock, &journal_path).and_then(|_| read_header(&file, None));
+        lock.unlock(&file, LockLevel::Unlocked)?;
+        let (header, page_count) = read?;
+        let pager = Self {
+            file,
+            journal_path,
+            wal_path: wal_path(file_name),
+            wal: RefCell::new(None),
+            lock,
+            header: RefCell::new(header),
+            page_count: Cell::new(page_count),
+            committed_page_count: Cell::new(page_count),
+            dirty: BTreeMap::new(),
+            savepoints: vec![],
+        };
+        // The header and size of a database in WAL mode are those of the log
+        if pager.header().wal_mode {
+            pager.begin_read()?;
+            pager.end_read()?;
+        }
+        Ok(pager)
+    }
+
+    /// Takes a shared lock on the file for reading, unless a lock is already held. A transaction
+    /// left incomplete by a crash is rolled back first, and the header is read again, as other
+    /// connections may have changed the file since the last transaction. In WAL mode, a read
+    /// lock on the log is also taken, which keeps the frames read from it until `end_read`
+    pub fn begin_read(&self) -> Result<()> {
+        if self.lock.level() > LockLevel::Unlocked {
+            return Ok(());
+        }
+        self.lock.lock(&self.file, LockLevel::Shared)?;
+        let read = recover_hot_journal(&self.file, &self.lock, &self.journal_path)
+            .and_then(|_| self.begin_wal_read())
+            .and_then(|_| self.refresh_header());
+        if let Err(err) = read {
+            self.unlock()?;
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Releases the locks taken by `begin_read`, unless a write transaction is open
+    pub fn end_read(&self) -> Result<()> {
+        match self.lock.level() {
+            LockLevel::Shared => self.unlock(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Starts reading the write-ahead log, when the database is in WAL mode
+    fn begin_wal_read(&self) -> Result<()> {
+        let (header, _) = read_header(&self.file, None)?;
+        let mut wal = self.wal.borrow_mut();
+        if !header.wal_mode {
+            *wal = None;
+            return Ok(());
+        }
+        if wal.is_none() {
+            *wal = Some(Wal::open(&self.wal_path, header.page_size)?);
+        }
+        wal.as_mut().map_or(Ok(()), Wal::begin_read)
+    }
+
+    /// Releases the locks of the write-ahead log, if any, and the lock on the file
+    fn unlock(&self) -> Result<()> {
+        if let Some(wal) = self.wal.borrow_mut().as_mut() {
+            wal.end_write()?;
+            wal.end_read()?;
+        }
+        self.lock.unlock(&self.file, LockLevel::Unlocked)
+    }
+
+    /// Takes a reserved lock on the file before changing pages, which no other connection may
+    /// hold at the same time, along with the write lock of the write-ahead log in WAL mode
+    pub fn begin_write(&mut self) -> Result<()> {
+        let level = self.lock.level();
+        if level >= LockLevel::Reserved {
+            return Ok(());
+        }
+        self.begin_read()?;
+        let locked = self
+            .lock
+            .lock(&self.file, LockLevel::Reserved)
+            .and_then(|_| self.wal.get_mut().as_mut().map_or(Ok(()), Wal::begin_write));
+        if let Err(err) = locked {
+            match level {
+                LockLevel::Unlocked => self.unlock()?,
+                _ => self.lock.unlock(&self.file, level)?,
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Reads the header of the database again, with the number of pages it holds
+    fn refresh_header(&self) -> Result<()> {
+        let (header, page_count) = read_header(&self.file, self.wal.borrow().as_ref())?;
+        self.header.replace(header);
+        self.page_count.set(page_count);
+        self.committed_page_count.set(page_count);
+        Ok(())
+    }
+
+    pub fn header(&self) -> Ref<'_, DBHeader> {
+        self.header.borrow()
+    }
+
+    /// The header, to change along with the pages of a transaction
+    pub fn header_mut(&mut self) -> &mut DBHeader {
+        self.header.get_mut()
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.header.borrow().page_size
+    }
+
+    /// The number of bytes of each page that B-trees use
+    pub fn usable_size(&self) -> usize {
+        let header = self.header.borrow();
+        header.page_size - header.reserved_space
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.page_count.get()
+    }
+
+    pub fn read_page(&self, page_number: usize) -> Result<Vec<u8>> {
+        if page_number == 0 || page_number > self.page_count.get() {
+            bail!("Page {} is out of the database", page_number);
+        }
+        match self.dirty.get(&page_number) {
+            Some(page) => Ok(page.clone()),
+            None => self.read_committed_page(page_number),
+        }
+    }
+
+    /// Reads a page as committed, without the changes of the transaction
+    fn read_committed_page(&self, page_number: usize) -> Result<Vec<u8>> {
+        if let Some(page) = self
+            .wal
+            .borrow()
+            .as_ref()
+            .map(|wal| wal.read_page(page_number))
+        {
+            if let Some(page) = page? {
+                return Ok(page);
+            }
+        }
+        let page_address = ((page_number - 1) * self.page_size()) as u64;
+        let mut page = vec![0; self.page_size()];
+        self.file.read_exact_at(&mut page, page_address)?;
+        Ok(page)
+    }
+
+    pub fn write_page(&mut self, page_number: usize, page: &[u8]) -> Result<()> {
+        if let Some(savepoint) = self.savepoints.last_mut() {
+            let dirty = &self.dirty;
+            savepoint
+                .pages
+                .entry(page_number)
+                .or_insert_with(|| dirty.get(&page_number).cloned());
+        }
+        self.dirty.insert(page_number, page.to_vec());
+        self.page_count.set(self.page_count.get().max(page_number));
+        Ok(())
+    }
+
+    /// Returns an empty page for a new use, taken from the freelist when it is not empty and
+    /// added at the end of the database otherwise
+    pub fn allocate_page(&mut self) -> Result<usize> {
+        let first = self.header().freelist_trunk_page;
+        let page_number = match first {
+            0 => self.page_count.get() + 1,
+            trunk => {
+                let mut trunk = self.read_trunk(trunk)?;
+                let header = self.header_mut();
+                header.freelist_page_count = header.freelist_page_count.saturating_sub(1);
+                let page_number = match trunk.leaves.pop() {
+                    Some(leaf) => {
+                        let mut page = self.read_page(trunk.page_number as usize)?;
+                        trunk.write(&mut page);
+                        self.write_page(trunk.page_number as usize, &page)?;
+                        leaf as usize
+                    }
+                    // A trunk page without leaves is taken itself, the next one becoming first
+                    None => {
+                        self.header_mut().freelist_trunk_page = trunk.next;
+                        trunk.page_number as usize
+                    }
+                };
+                if page_number == 0 || page_number > self.page_count.get() {
+                    bail!("Free page {} is out of the database", page_number);
+                }
+                page_number
+            }
+        };
+        self.write_page(page_number, &vec![0; self.page_size()])?;
+        Ok(page_number)
+    }
+
+    /// Adds a page that is no longer used to the freelist, as a leaf of its first trunk page
+    /// when it has room, and as the new first trunk page otherwise
+    pub fn free_page(&mut self, page_number: usize) -> Result<()> {
+        let first = self.header().freelist_trunk_page;
+        if first != 0 {
+            let mut trunk = self.read_trunk(first)?;
+            if trunk.leaves.len() < TrunkPage::capacity(self.usable_size()) {
+                trunk.leaves.push(page_number as u32);
+                let mut page = self.read_page(first as usize)?;
+                trunk.write(&mut page);
+                self.write_page(first as usize, &page)?;
+                self.header_mut().freelist_page_count += 1;
+                return Ok(());
+            }
+        }
+        let trunk = TrunkPage {
+            page_number: page_number as u32,
+            next: first,
+            leaves: vec![],
+        };
+        let mut page = vec![0; self.page_size()];
+        trunk.write(&mut page);
+        self.write_page(page_number, &page)?;
+        let header = self.header_mut();
+        header.freelist_trunk_page = page_number as u32;
+        header.freelist_page_count += 1;
+        Ok(())
+    }
+
+    /// Returns the trunk pages of the freelist, in the order of the chain starting in the header
+    pub fn freelist(&self) -> Result<Vec<TrunkPage>> {
+        let mut trunks: Vec<TrunkPage> = vec![];
+        let mut next = self.header().freelist_trunk_page;
+        while next != 0 {
+            // A chain longer than the database loops, which a damaged file could do
+            if trunks.len() >= self.page_count.get() {
+                bail!("The freelist trunk pages form a cycle");
+            }
+            let trunk = self.read_trunk(next)?;
+            next = trunk.next;
+            trunks.push(trunk);
+        }
+        Ok(trunks)
+    }
+
+    /// Returns the number of every free page, trunk pages followed by their leaves
+    pub fn free_pages(&self) -> Result<Vec<u32>> {
+        Ok(self
+            .freelist()?
+            .into_iter()
+            .flat_map(|trunk| std::iter::once(trunk.page_number).chain(trunk.leaves))
+            .collect())
+    }
+
+    fn read_trunk(&self, page_number: u32) -> Result<TrunkPage> {
+        let page = self.read_page(page_number as usize)?;
+        TrunkPage::parse(page_number, &page, self.usable_size())
+    }
+
+    /// Adds the pages of the overflow chain starting at `first` to the freelist
+    pub fn free_overflow(&mut self, first: u32) -> Result<()> {
+        let mut next = first;
+        while next != 0 {
+            let page = self.read_page(next as usize)?;
+            self.free_page(next as usize)?;
+            next = u32::from_be_bytes([page[0], page[1], page[2], page[3]]);
+        }
+        Ok(())
+    }
+
+    /// Reads the payload of a cell, of which the cell holds `local` and the chain of overflow
+    /// pages starting at `overflow` holds the rest, as mentioned here:
+    /// [overflow pages](https://www.sqlite.org/fileformat2.html#ovflpgs)
+    pub fn read_payload(
+        &self,
+        local: &[u8],
+        size: usize,
+        overflow: Option<u32>,
+    ) -> Result<Vec<u8>> {
+        let mut payload = local.to_vec();
+        let mut next = overflow;
+        while payload.len() < size {
+            let page_number = match next {
+                Some(page_number) if page_number != 0 => page_number,
+                _ => bail!("Overflow chain ends before the end of the payload"),
+            };
+            let page = self.read_page(page_number as usize)?;
+            let end = (size - payload.len() + 4).min(self.usable_size());
+            payload.extend_from_slice(&page[4..end]);
+            next = Some(u32::from_be_bytes([page[0], page[1], page[2], page[3]]));
+        }
+        Ok(payload)
+    }
+
+    /// Stores the part of a payload that does not fit in its cell into a chain of overflow pages,
+    /// returning the number of the first one
+    pub fn write_overflow(&mut self, rest: &[u8]) -> Result<u32> {
+        let chunks = rest.chunks(self.usable_size() - 4).collect::<Vec<_>>();
+        let pages = chunks
+            .iter()
+            .map(|_| self.allocate_page())
+            .collect::<Result<Vec<_>>>()?;
+        for (i, chunk) in chunks.iter().enumerate() {
+            let next = pages.get(i + 1).copied().unwrap_or_default() as u32;
+            let mut page = vec![0; self.page_size()];
+            page[..4].copy_from_slice(&next.to_be_bytes());
+            page[4..4 + chunk.len()].copy_from_slice(chunk);
+            self.write_page(pages[i], &page)?;
+        }
+        Ok(pages[0] as u32)
+    }
+
+    /// Starts a savepoint, which the changes made after it can be rolled back to
+    pub fn begin_savepoint(&mut self) {
+        let header = self.header().clone();
+        self.savepoints.push(Savepoint {
+            header,
+            page_count: self.page_count.get(),
+            pages: BTreeMap::new(),
+        });
+    }
+
+    /// Ends the innermost savepoint, keeping its changes. They can still be rolled back with the
+    /// enclosing savepoint
+    pub fn release_savepoint(&mut self) {
+        let savepoint = match self.savepoints.pop() {
+            Some(savepoint) => savepoint,
+            None => return,
+        };
+        if let Some(parent) = self.savepoints.last_mut() {
+            for (page_number, previous) in savepoint.pages {
+                parent.pages.entry(page_number).or_insert(previous);
+            }
+        }
+    }
+
+    /// Undoes the changes made since the start of the innermost savepoint, which stays open
+    pub fn roll_back_savepoint(&mut self) {
+        let savepoint = match self.savepoints.last_mut() {
+            Some(savepoint) => savepoint,
+            None => return,
+        };
+        for (page_number, previous) in std::mem::take(&mut savepoint.pages) {
+            match previous {
+                Some(page) => self.dirty.insert(page_number, page),
+                None => self.dirty.remove(&page_number),
+            };
+        }
+        self.header.replace(savepoint.header.clone());
+        self.page_count.set(savepoint.page_count);
+    }
+
+    /// Writes the changes of the transaction to the file. The original content of the pages
+    /// is first saved to the rollback journal, whose deletion commits the transaction, as
+    /// mentioned here: [atomic commit](https://www.sqlite.org/atomiccommit.html). In WAL mode,
+    /// the pages are appended to the log instead
+    pub fn commit(&mut self) -> Result<()> {
+        self.savepoints.clear();
+        if self.dirty.is_empty() {
+            return self.unlock();
+        }
+        // The change is recorded in the header: the change counter is incremented, and the size
+        // of the database and its freelist updated
+        let page_count = self.page_count.get();
+        let header = self.header.get_mut();
+        header.file_change_counter = header.file_change_counter.wrapping_add(1);
+        header.database_size = Some(page_count as u32);
+        let mut page = self.read_page(1)?;
+        self.header().write(&mut page[..100]);
+        self.write_page(1, &page)?;
+        if let Some(wal) = self.wal.get_mut() {
+            wal.append(&self.dirty, page_count)?;
+            self.dirty.clear();
+            self.committed_page_count.set(page_count);
+            return self.unlock();
+        }
+
+        // Readers are kept from starting while the ones already reading finish
+        self.lock.lock(&self.file, LockLevel::Exclusive)?;
+
+        // Pages added by the transaction are dropped when rolling back, and need no journal
+        let originals = self
+            .dirty
+            .keys()
+            .filter(|&&page_number| page_number <= self.committed_page_count.get())
+            .map(|&page_number| Ok((page_number, self.read_committed_page(page_number)?)))
+            .collect::<Result<Vec<_>>>()?;
+        write_journal(
+            &self.journal_path,
+            self.page_size(),
+            self.committed_page_count.get(),
+            &originals,
+        )?;
+        for (page_number, page) in &self.dirty {
+            let page_address = ((page_number - 1) * self.page_size()) as u64;
+            self.file.write_all_at(page, page_address)?;
+        }
+        self.file.sync_all()?;
+        delete_journal(&self.journal_path)?;
+        self.dirty.clear();
+        self.committed_page_count.set(page_count);
+        self.unlock()
+    }
+
+    /// Copies the pages of the write-ahead log back into the database file, returning whether
+    /// the checkpoint could not do all its mode asks for, with the number of frames in the log
+    /// and the number of frames copied, unless not in WAL mode. The database file is read locked
+    /// meanwhile, but not the log
+    pub fn checkpoint(&mut self, mode: CheckpointMode) -> Result<Option<(bool, usize, usize)>> {
+        if self.lock.level() > LockLevel::Unlocked {
+            bail!("cannot checkpoint within a transaction");
+        }
+        self.begin_read()?;
+        let file = &self.file;
+        let checkpoint = match self.wal.get_mut() {
+            Some(wal) => wal
+                .end_read()
+                .and_then(|_| wal.checkpoint(file, mode))
+                .map(Some),
+            None => Ok(None),
+        };
+        self.end_read()?;
+        checkpoint
+    }
+
+    /// Discards the changes of the transaction, restoring the file from the journal when a
+    /// commit failed after writing to it. Only a connection holding a reserved lock may have
+    /// written to the file
+    pub fn rollback(&mut self) -> Result<()> {
+        self.savepoints.clear();
+        self.dirty.clear();
+        if self.lock.level() >= LockLevel::Reserved {
+            roll_back_hot_journal(&self.file, &self.journal_path)?;
+            self.refresh_header()?;
+        }
+        self.unlock()
+    }
+}
+
+/// Rolls back the journal of a transaction left incomplete by a crash. A journal is only hot
+/// when it is not empty and no other connection holds a reserved lock, as the one writing it
+/// would otherwise still be running. Rolling it back takes an exclusive lock, as mentioned here:
+/// [hot journals](https://www.sqlite.org/lockingv3.html#hot_journals)
+fn recover_hot_journal(file: &File, lock: &FileLock, journal_path: &str) -> Result<()> {
+    let hot = match fs::metadata(journal_path) {
+        Ok(metadata) => metadata.len() > 0,
+        Err(err) if err.kind() == ErrorKind::NotFound => false,
+        Err(err) => return Err(err.into()),
+    };
+    if !hot || lock.is_reserved_elsewhere(file)? {
+        return Ok(());
+    }
+    let level = lock.level();
+    let recovered = lock
+        .lock(file, LockLevel::Exclusive)
+        .and_then(|_| roll_back_hot_journal(file, journal_path));
+    lock.unlock(file, level)?;
+    recovered
+}
+
+/// Reads the header of a database, returning it with the number of pages of the database. The
+/// write-ahead log holds the latest header when it holds page 1, and the size of the database
+/// when it holds a transaction
+fn read_header(file: &File, wal: Option<&Wal>) -> Result<(DBHeader, usize)> {
+    let db_header_stream = &mut [0u8; 100];
+    file.read_exact_at(db_header_stream, 0)?;
+    let mut header = DBHeader::parse(db_header_stream)?;
+    if let Some(page) = wal.map(|wal| wal.read_page(1)).transpose()?.flatten() {
+        header = DBHeader::parse(&page[..100])?;
+    }
+    let page_count = match (wal.and_then(Wal::database_size), header.database_size) {
+        (Some(size), _) => size,
+        (None, Some(size)) => size as usize,
+        (None, None) => (file.metadata()?.len() / header.page_size as u64) as usize,
+    };
+    Ok((header, page_count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE_SIZE: usize = 1024;
+
+    /// Opens a new database made of a first page holding an empty `sqlite_schema`
+    fn test_pager(name: &str) -> (String, Pager) {
+        let path = std::env::temp_dir().join(format!("pager-{}-{}", name, std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let mut page = vec![0; PAGE_SIZE];
+        page[..16].copy_from_slice(b"SQLite format 3\0");
+        page[16..18].copy_from_slice(&(PAGE_SIZE as u16).to_be_bytes());
+        page[18..24].copy_from_slice(&[1, 1, 0, 64, 32, 32]);
+        page[28..32].copy_from_slice(&1u32.to_be_bytes());
+        page[44..48].copy_from_slice(&4u32.to_be_bytes());
+        page[56..60].copy_from_slice(&1u32.to_be_bytes());
+        page[100] = 0x0d;
+        page[105..107].copy_from_slice(&(PAGE_SIZE as u16).to_be_bytes());
+        std::fs::write(&path, page).unwrap();
+        let pager = Pager::open(&path).unwrap();
+        (path, pager)
+    }
+
+    fn page(fill: u8) -> Vec<u8> {
+        vec![fill; PAGE_SIZE]
+    }
+
+    #[test]
+    fn rolls_back_to_the_start_of_nested_savepoints() {
+        let (path, mut pager) = test_pager("nested");
+        pager.write_page(2, &page(1)).unwrap();
+        pager.begin_savepoint();
+        pager.write_page(2, &page(2)).unwrap();
+        pager.write_page(3, &page(2)).unwrap();
+        pager.begin_savepoint();
+        pager.write_page(2, &page(3)).unwrap();
+        pager.write_page(4, &page(3)).unwrap();
+        pager.header_mut().file_change_counter = 7;
+
+        pager.roll_back_savepoint();
+        assert_eq!(pager.read_page(2).unwrap(), page(2));
+        assert_eq!(pager.page_count(), 3);
+        assert_eq!(pager.header().file_change_counter, 0);
+        // The savepoint stays open after being rolled back to
+        pager.write_page(3, &page(4)).unwrap();
+        pager.roll_back_savepoint();
+        assert_eq!(pager.read_page(3).unwrap(), page(2));
+        pager.release_savepoint();
+
+        pager.roll_back_savepoint();
+        assert_eq!(pager.read_page(2).unwrap(), page(1));
+        assert_eq!(pager.page_count(), 2);
+        pager.release_savepoint();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn released_savepoints_roll_back_with_the_enclosing_one() {
+        let (path, mut pager) = test_pager("released");
+        pager.begin_savepoint();
+        pager.write_page(2, &page(1)).unwrap();
+        pager.begin_savepoint();
+        pager.write_page(2, &page(2)).unwrap();
+        pager.write_page(3, &page(2)).unwrap();
+        pager.release_savepoint();
+        assert_eq!(pager.read_page(2).unwrap(), page(2));
+
+        pager.roll_back_savepoint();
+        assert_eq!(pager.page_count(), 1);
+        pager.write_page(2, &page(5)).unwrap();
+        pager.release_savepoint();
+        pager.commit().unwrap();
+        // Committing ends every savepoint, leaving nothing to roll back or release
+        pager.roll_back_savepoint();
+        pager.release_savepoint();
+
+        let pager = Pager::open(&path).unwrap();
+        assert_eq!(pager.page_count(), 2);
+        assert_eq!(pager.read_page(2).unwrap(), page(5));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn free_pages_fill_trunks_before_becoming_trunks() {
+        let (path, mut pager) = test_pager("freelist");
+        let capacity = TrunkPage::capacity(PAGE_SIZE);
+        for page_number in 2..capacity + 4 {
+            pager.write_page(page_number, &page(1)).unwrap();
+        }
+        for page_number in 2..capacity + 4 {
+            pager.free_page(page_number).unwrap();
+        }
+        let trunks = pager.freelist().unwrap();
+        let last = capacity as u32 + 3;
+        assert_eq!(
+            trunks
+                .iter()
+                .map(|trunk| (trunk.page_number, trunk.next, trunk.leaves.len()))
+                .collect::<Vec<_>>(),
+            [(last, 2, 0), (2, 0, capacity)]
+        );
+        assert_eq!(pager.header().freelist_page_count, capacity as u32 + 2);
+        pager.commit().unwrap();
+
+        // A trunk page without leaves is taken itself, and leaves are taken last first
+        let mut pager = Pager::open(&path).unwrap();
+        assert_eq!(pager.free_pages().unwrap().len(), capacity + 2);
+        assert_eq!(pager.allocate_page().unwrap(), last as usize);
+        assert_eq!(pager.allocate_page().unwrap(), last as usize - 1);
+        assert_eq!(
+            pager.read_page(last as usize - 1).unwrap(),
+            vec![0; PAGE_SIZE]
+        );
+        for _ in 0..capacity - 1 {
+            pager.allocate_page().unwrap();
+        }
+        assert_eq!(pager.free_pages().unwrap(), [2]);
+        assert_eq!(pager.allocate_page().unwrap(), 2);
+        assert_eq!(pager.header().freelist_trunk_page, 0);
+        assert_eq!(pager.header().freelist_page_count, 0);
+        assert_eq!(pager.allocate_page().unwrap(), last as usize + 1);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_freelists_pointing_out_of_the_database() {
+        let (path, mut pager) = test_pager("damaged");
+        pager.write_page(2, &page(0)).unwrap();
+        pager.free_page(2).unwrap();
+        let mut trunk = pager.read_page(2).unwrap();
+        trunk[4..12].copy_from_slice(&[0, 0, 0, 1, 0, 0, 0, 9]);
+        pager.write_page(2, &trunk).unwrap();
+        assert_eq!(
+            pager.allocate_page().unwrap_err().to_string(),
+            "Free page 9 is out of the database"
+        );
+
+        // A trunk page leading back to itself
+        trunk[..8].copy_from_slice(&[0, 0, 0, 2, 0, 0, 0, 0]);
+        pager.write_page(2, &trunk).unwrap();
+        assert_eq!(
+            pager.freelist().unwrap_err().to_string(),
+            "The freelist trunk pages form a cycle"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,566 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+
+use anyhow::Result;
+
+use crate::collation::Collation;
+use crate::expr::{Columns, Expr, Row, SourceColumn};
+use crate::functions::Functions;
+use crate::record::{Affinity, Value};
+use crate::sql::{OrderingTerm, ResultColumn, Select};
+
+/// Filters, groups, sorts and projects the rows of a table as described by a `SELECT`
+pub fn run(
+    select: &Select,
+    columns: &Columns,
+    rows: Vec<Vec<Value>>,
+    functions: &Functions,
+) -> Result<Vec<Vec<Value>>> {
+    let outputs = expand_result_columns(&select.columns, columns);
+    let group_by = select
+        .group_by
+        .iter()
+        .map(|e| resolve_aliases(e, columns, &outputs))
+        .collect::<Result<Vec<_>>>()?;
+    let having = select
+        .having
+        .as_ref()
+        .map(|e| resolve_aliases(e, columns, &outputs))
+        .transpose()?;
+    let order_by = select
+        .order_by
+        .iter()
+        .map(|term| resolve_aliases(&term.expr, columns, &outputs))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut filtered = vec![];
+    for values in rows {
+        if let Some(filter) = &select.filter {
+            let row = Row {
+                columns,
+                values: &values,
+                functions,
+            };
+            if filter.eval(&row)?.is_truthy() != Some(true) {
+                continue;
+            }
+        }
+        filtered.push(values);
+    }
+
+    let is_aggregate = !group_by.is_empty()
+        || having.is_some()
+        || outputs.iter().any(|(e, _)| e.has_aggregate(functions))
+        || order_by.iter().any(|e| e.has_aggregate(functions));
+
+    // Each result is kept together with the values it is sorted by
+    let mut results = vec![];
+    if is_aggregate {
+        for group in group_rows(columns, &group_by, filtered, functions)? {
+            if let Some(having) = &having {
+                if having.eval_group(columns, &group, functions)?.is_truthy() != Some(true) {
+                    continue;
+                }
+            }
+            let output = outputs
+                .iter()
+                .map(|(e, _)| e.eval_group(columns, &group, functions))
+                .collect::<Result<Vec<_>>>()?;
+            let keys = select
+                .order_by
+                .iter()
+                .zip(&order_by)
+                .map(|(term, e)| match output_position(term, &outputs) {
+                    Some(i) => Ok(output[i].clone()),
+                    None => e.eval_group(columns, &group, functions),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            results.push((output, keys));
+        }
+    } else {
+        for values in filtered {
+            let row = Row {
+                columns,
+                values: &values,
+                functions,
+            };
+            let output = outputs
+                .iter()
+                .map(|(e, _)| e.eval(&row))
+                .collect::<Result<Vec<_>>>()?;
+            let keys = select
+                .order_by
+                .iter()
+                .zip(&order_by)
+                .map(|(term, e)| match output_position(term, &outputs) {
+                    Some(i) => Ok(output[i].clone()),
+                    None => e.eval(&row),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            results.push((output, keys));
+        }
+    }
+
+    // The first of the results with equal values is kept, NULL being equal to NULL here
+    if select.distinct {
+        let collations = outputs
+            .iter()
+            .map(|(e, _)| functions.collation(e.collation(columns).map(|(name, _)| name)))
+            .collect::<Result<Vec<_>>>()?;
+        let mut order = (0..results.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| {
+            compare_keys(&results[a].0, &results[b].0, &collations).then(a.cmp(&b))
+        });
+        let mut duplicates = vec![false; results.len()];
+        for pair in order.windows(2) {
+            if compare_keys(&results[pair[0]].0, &results[pair[1]].0, &collations)
+                == Ordering::Equal
+            {
+                duplicates[pair[1]] = true;
+            }
+        }
+        results = results
+            .into_iter()
+            .zip(duplicates)
+            .filter(|(_, duplicate)| !duplicate)
+            .map(|(result, _)| result)
+            .collect();
+    }
+
+    if !select.order_by.is_empty() {
+        let collations = select
+            .order_by
+            .iter()
+            .map(|term| {
+                let expr = match output_position(term, &outputs) {
+                    Some(i) => &outputs[i].0,
+                    None => &term.expr,
+                };
+                functions.collation(expr.collation(columns).map(|(name, _)| name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        results.sort_by(|(_, a), (_, b)| {
+            a.iter()
+                .zip(b)
+                .zip(&select.order_by)
+                .zip(&collations)
+                .map(|(((a, b), term), collation)| match term.descending {
+                    false => collation.compare(a, b),
+                    true => collation.compare(b, a),
+                })
+                .find(|&o| o != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+    }
+
+    let offset = match &select.offset {
+        Some(offset) => constant_integer(offset, functions)?.max(0) as usize,
+        None => 0,
+    };
+    let limit = match &select.limit {
+        Some(limit) => usize::try_from(constant_integer(limit, functions)?).unwrap_or(usize::MAX),
+        None => usize::MAX,
+    };
+    Ok(results
+        .into_iter()
+        .skip(offset)
+        .take(limit)
+        .map(|(output, _)| output)
+        .collect())
+}
+
+/// Returns the columns of the result of a query. Each is named by its alias, by the column it
+/// reads or by its position otherwise, and keeps the affinity and collation of its expression
+pub fn output_columns(select: &Select, columns: &Columns) -> Vec<SourceColumn> {
+    expand_result_columns(&select.columns, columns)
+        .into_iter()
+        .enumerate()
+        .map(|(i, (expr, alias))| {
+            let name = match (alias, &expr) {
+                (Some(alias), _) => alias,
+                (None, Expr::Column(name)) => name.rsplit('.').next().unwrap_or(name).to_owned(),
+                (None, _) => format!("column{}", i + 1),
+            };
+            SourceColumn {
+                name,
+                affinity: expr.affinity(columns),
+                collation: expr
+                    .collation(columns)
+                    .map(|(collation, _)| collation.to_owned()),
+                hidden: false,
+            }
+        })
+        .collect()
+}
+
+/// Replaces `*` by the columns of the table, keeping the alias of every result column
+fn expand_result_columns(
+    result_columns: &[ResultColumn],
+    columns: &Columns,
+) -> Vec<(Expr, Option<String>)> {
+    result_columns
+        .iter()
+        .flat_map(|column| match column {
+            ResultColumn::All => columns
+                .names()
+                .map(|name| (Expr::Column(name.to_owned()), None))
+                .collect(),
+            ResultColumn::Expr(e, alias) => vec![(e.clone(), alias.clone())],
+        })
+        .collect()
+}
+
+/// Finds the result column an `ORDER BY` term refers to, either by its alias or by its position
+fn output_position(term: &OrderingTerm, outputs: &[(Expr, Option<String>)]) -> Option<usize> {
+    match &term.expr {
+        Expr::Literal(n) => match n.as_integer() {
+            Some(n) if n >= 1 && n as usize <= outputs.len() => Some(n as usize - 1),
+            _ => None,
+        },
+        Expr::Column(name) => outputs.iter().position(|(_, alias)| {
+            alias
+                .as_ref()
+                .is_some_and(|alias| alias.eq_ignore_ascii_case(name))
+        }),
+        _ => None,
+    }
+}
+
+/// Replaces the names that are not columns of the table but aliases of result columns by the
+/// expressions they stand for, as SQLite allows in `GROUP BY`, `HAVING` and `ORDER BY`
+fn resolve_aliases(
+    expr: &Expr,
+    columns: &Columns,
+    outputs: &[(Expr, Option<String>)],
+) -> Result<Expr> {
+    match expr {
+        Expr::Column(name) if !columns.contains(name) => {
+            let aliased = outputs.iter().find(|(_, alias)| {
+                alias
+                    .as_ref()
+                    .is_some_and(|alias| alias.eq_ignore_ascii_case(name))
+            });
+            Ok(aliased.map_or_else(|| expr.clone(), |(e, _)| e.clone()))
+        }
+        expr => expr.map_children(|child| resolve_aliases(child, columns, outputs)),
+    }
+}
+
+/// Splits rows into groups of equal `GROUP BY` values, ordered by those values. Without
+/// `GROUP BY` all rows form a single group, even when there are none
+fn group_rows(
+    columns: &Columns,
+    group_by: &[Expr],
+    rows: Vec<Vec<Value>>,
+    functions: &Functions,
+) -> Result<Vec<Vec<Vec<Value>>>> {
+    if group_by.is_empty() {
+        return Ok(vec![rows]);
+    }
+    let collations = group_by
+        .iter()
+        .map(|e| functions.collation(e.collation(columns).map(|(name, _)| name)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut keyed = rows
+        .into_iter()
+        .map(|values| {
+            let row = Row {
+                columns,
+                values: &values,
+                functions,
+            };
+            let key = group_by
+                .iter()
+                .map(|e| e.eval(&row))
+                .collect::<Result<Vec<_>>>()?;
+            Ok((key, values))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    keyed.sort_by(|(a, _), (b, _)| compare_keys(a, b, &collations));
+
+    let mut groups: Vec<(Vec<Value>, Vec<Vec<Value>>)> = vec![];
+    for (key, values) in keyed {
+        match groups.last_mut() {
+            Some((last, group)) if compare_keys(last, &key, &collations) == Ordering::Equal => {
+                group.push(values)
+            }
+            _ => groups.push((key, vec![values])),
+        }
+    }
+    Ok(groups.into_iter().map(|(_, group)| group).collect())
+}
+
+fn compare_keys(a: &[Value], b: &[Value], collations: &[Collation]) -> Ordering {
+    a.iter()
+        .zip(b)
+        .zip(collations)
+        .map(|((a, b), collation)| collation.compare(a, b))
+        .find(|&o| o != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Evaluates an expression that does not depend on any row, such as a `LIMIT`
+fn constant_integer(expr: &Expr, functions: &Functions) -> Result<i64> {
+    let columns = Columns::new(vec![]);
+    let row = Row {
+        columns: &columns,
+        values: &[],
+        functions,
+    };
+    Ok(expr
+        .eval(&row)?
+        .cast(Affinity::Integer)
+        .as_integer()
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::SourceColumn;
+
+    /// Runs a query on the rows of a table `t (a, b)`, returning them as `sqlite3` prints them
+    fn query(sql: &str) -> Vec<String> {
+        query_with(sql, &Functions::default())
+    }
+
+    fn query_with(sql: &str, functions: &Functions) -> Vec<String> {
+        let text = |text: &str| Value::Text(text.to_string());
+        let rows = vec![
+            vec![Value::Integer(1), text("x")],
+            vec![Value::Integer(2), text("y")],
+            vec![Value::Integer(1), text("z")],
+            vec![Value::Null, text("w")],
+            vec![Value::Integer(3), Value::Null],
+            vec![Value::Integer(2), text("v")],
+        ];
+        let columns = vec![
+            column("t.a", Affinity::Integer, None),
+            column("t.b", Affinity::Text, None),
+        ];
+        query_rows(sql, columns, rows, functions)
+    }
+
+    fn column(name: &str, affinity: Affinity, collation: Option<&str>) -> SourceColumn {
+        SourceColumn {
+            name: name.to_string(),
+            affinity,
+            collation: collation.map(String::from),
+            hidden: false,
+        }
+    }
+
+    fn query_rows(
+        sql: &str,
+        columns: Vec<SourceColumn>,
+        rows: Vec<Vec<Value>>,
+        functions: &Functions,
+    ) -> Vec<String> {
+        let select = Select::parse_select(sql).unwrap();
+        run(&select, &Columns::new(columns), rows, functions)
+            .unwrap()
+            .into_iter()
+            .map(|row| row.iter().map(Value::to_text).collect::<Vec<_>>().join("|"))
+            .collect()
+    }
+
+    #[test]
+    fn groups_rows_with_nulls_first() {
+        assert_eq!(
+            query("SELECT a, count(*), count(b), group_concat(b), sum(a), min(b), max(b) FROM t GROUP BY a"),
+            ["|1|1|w||w|w", "1|2|2|x,z|2|x|z", "2|2|2|y,v|4|v|y", "3|1|0||3||"]
+        );
+        assert_eq!(
+            query("SELECT a, count(*) FROM t GROUP BY a HAVING count(*) > 1 ORDER BY count(*) DESC, a"),
+            ["1|2", "2|2"]
+        );
+    }
+
+    #[test]
+    fn aggregates_without_group_by_make_one_row() {
+        assert_eq!(
+            query("SELECT count(*), sum(a), avg(a), group_concat(a, ';') FROM t"),
+            ["6|9|1.8|1;2;1;3;2"]
+        );
+        assert_eq!(
+            query("SELECT sum(a), total(a), avg(a), count(*) FROM t WHERE a > 5"),
+            ["|0.0||0"]
+        );
+    }
+
+    #[test]
+    fn orders_by_aliases_and_positions() {
+        assert_eq!(
+            query("SELECT b FROM t ORDER BY a DESC, 1 LIMIT 3 OFFSET 1"),
+            ["v", "y", "x"]
+        );
+        assert_eq!(
+            query("SELECT a * 2 AS d FROM t ORDER BY d LIMIT 2"),
+            ["", "2"]
+        );
+        assert_eq!(
+            query("SELECT a + 1 AS a FROM t ORDER BY a LIMIT 3"),
+            ["", "2", "2"]
+        );
+    }
+
+    #[test]
+    fn resolves_aliases_in_group_by_and_having() {
+        assert_eq!(
+            query("SELECT upper(b) u, count(*) c FROM t GROUP BY u HAVING c >= 1 ORDER BY c DESC, u LIMIT 3"),
+            ["|1", "V|1", "W|1"]
+        );
+        assert_eq!(
+            query("SELECT a * 2 d, count(*) FROM t GROUP BY d HAVING d > 2 ORDER BY d + 1 DESC"),
+            ["6|1", "4|2"]
+        );
+    }
+
+    #[test]
+    fn aggregates_distinct_values() {
+        assert_eq!(
+            query("SELECT a % 2 p, count(DISTINCT a), sum(distinct a), group_concat(DISTINCT b) FROM t GROUP BY p"),
+            ["|0||w", "0|1|2|y,v", "1|2|4|x,z"]
+        );
+        assert_eq!(
+            query("SELECT count(DISTINCT a), count(ALL a) FROM t"),
+            ["3|5"]
+        );
+        let select = Select::parse_select("SELECT group_concat(DISTINCT b, ';') FROM t").unwrap();
+        assert_eq!(
+            run(
+                &select,
+                &Columns::new(vec![]),
+                vec![],
+                &Functions::default()
+            )
+            .unwrap_err()
+            .to_string(),
+            "DISTINCT aggregates must have exactly one argument"
+        );
+    }
+
+    #[test]
+    fn registered_functions_take_precedence() {
+        let mut functions = Functions::default();
+        functions.register_function("double", Some(1), true, |args| match &args[0] {
+            Value::Integer(n) => Value::Integer(n * 2),
+            _ => Value::Null,
+        });
+        functions.register_function("upper", Some(1), true, |_| Value::Text("up".to_string()));
+        functions.register_function(
+            "arity",
+            None,
+            true,
+            |args| Value::Integer(args.len() as i64),
+        );
+        functions.register_aggregate(
+            "product",
+            Some(1),
+            true,
+            || 1,
+            |product, args| {
+                if let Value::Integer(n) = args[0] {
+                    *product *= n;
+                }
+            },
+            Value::Integer,
+        );
+        assert_eq!(
+            query_with(
+                "SELECT double(a), upper(b), lower(b), arity(), arity(a, b, 1) FROM t WHERE a = 1",
+                &functions
+            ),
+            ["2|up|x|0|3", "2|up|z|0|3"]
+        );
+        assert_eq!(
+            query_with("SELECT a, product(a) FROM t GROUP BY a", &functions),
+            ["|1", "1|1", "2|4", "3|3"]
+        );
+    }
+
+    #[test]
+    fn sorts_groups_and_distinguishes_by_collation() {
+        let rows = ["x", "X", "y", "Y ", "b"]
+            .iter()
+            .enumerate()
+            .map(|(i, b)| vec![Value::Integer(i as i64 + 1), Value::Text(b.to_string())])
+            .collect::<Vec<_>>();
+        let mut functions = Functions::default();
+        functions.register_collation("reverse", |a, b| b.cmp(a));
+        let query = |sql| {
+            let columns = vec![
+                column("t.a", Affinity::Blob, None),
+                column("t.b", Affinity::Blob, Some("nocase")),
+            ];
+            query_rows(sql, columns, rows.clone(), &functions)
+        };
+        assert_eq!(
+            query("SELECT count(DISTINCT b), count(DISTINCT b COLLATE BINARY), count(DISTINCT a) FROM t"),
+            ["4|5|5"]
+        );
+        assert_eq!(
+            query("SELECT b FROM t ORDER BY b"),
+            ["b", "x", "X", "y", "Y "]
+        );
+        assert_eq!(
+            query("SELECT b FROM t ORDER BY b COLLATE BINARY"),
+            ["X", "Y ", "b", "x", "y"]
+        );
+        assert_eq!(
+            query("SELECT count(*) FROM t GROUP BY b"),
+            ["1", "2", "1", "1"]
+        );
+        assert_eq!(
+            query("SELECT b FROM t ORDER BY b COLLATE reverse LIMIT 2"),
+            ["y", "x"]
+        );
+
+        let select = Select::parse_select("SELECT * FROM t WHERE 'a' = 'b' COLLATE foo").unwrap();
+        let columns = Columns::new(vec![]);
+        assert_eq!(
+            run(&select, &columns, vec![vec![]], &functions)
+                .unwrap_err()
+                .to_string(),
+            "No such collation sequence: foo"
+        );
+    }
+
+    #[test]
+    fn names_output_columns_by_alias_column_or_position() {
+        let columns = Columns::new(vec![
+            column("t.a", Affinity::Integer, None),
+            column("t.b", Affinity::Text, Some("NOCASE")),
+        ]);
+        let select =
+            Select::parse_select("SELECT *, a + 1, b AS c, t.b COLLATE rtrim FROM t").unwrap();
+        let outputs = output_columns(&select, &columns)
+            .into_iter()
+            .map(|c| format!("{} {:?} {:?}", c.name, c.affinity, c.collation))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outputs,
+            [
+                "a Integer None",
+                "b Text Some(\"NOCASE\")",
+                "column3 Blob None",
+                "c Text Some(\"NOCASE\")",
+                "column5 Text Some(\"rtrim\")",
+            ]
+        );
+    }
+
+    #[test]
+    fn distinct_keeps_the_first_of_equal_rows() {
+        assert_eq!(query("SELECT DISTINCT a FROM t"), ["1", "2", "", "3"]);
+        assert_eq!(
+            query("SELECT DISTINCT a % 2, upper(b) IS NULL FROM t ORDER BY 1"),
+            ["|0", "0|0", "1|0", "1|1"]
+        );
+        assert_eq!(
+            query("SELECT DISTINCT count(*) FROM t GROUP BY a"),
+            ["1", "2"]
+        );
+    }
+}
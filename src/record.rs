@@ -4,31 +4,185 @@ use std::fmt::{Display, Formatter};
 
 use anyhow::{bail, Result};
 
-use crate::varint::parse_varint;
+use crate::varint::{encode_varint, parse_varint};
 
 #[derive(Debug, Clone)]
 pub enum Value {
     Null,
-    I8(i8),
-    I16(i16),
-    I24(i32),
-    I32(i32),
-    I48(i64),
-    I64(i64),
+    Integer(i64),
     F(f64),
     Blob(Vec<u8>),
     Text(String),
 }
 
+/// Type affinity of a column or `CAST` target, as mentioned here:
+/// [affinity](https://www.sqlite.org/datatype3.html#type_affinity)
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Affinity {
+    Integer,
+    Text,
+    Blob,
+    Real,
+    Numeric,
+}
+
+impl Affinity {
+    /// Determines the affinity of a declared type name
+    pub fn from_type_name(type_name: &str) -> Self {
+        let type_name = type_name.to_uppercase();
+        if type_name.contains("INT") {
+            Affinity::Integer
+        } else if ["CHAR", "CLOB", "TEXT"]
+            .iter()
+            .any(|t| type_name.contains(t))
+        {
+            Affinity::Text
+        } else if type_name.contains("BLOB") || type_name.trim().is_empty() {
+            Affinity::Blob
+        } else if ["REAL", "FLOA", "DOUB"]
+            .iter()
+            .any(|t| type_name.contains(t))
+        {
+            Affinity::Real
+        } else {
+            Affinity::Numeric
+        }
+    }
+}
+
 impl Value {
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    /// Returns the value if it is stored as an integer
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Converts a value into the operand of an arithmetic operator: integers and reals are kept,
+    /// text and blobs are read as a number prefix and NULL stays NULL
+    pub fn to_numeric(&self) -> Value {
+        match self {
+            Value::Null | Value::F(_) => self.clone(),
+            Value::Text(_) | Value::Blob(_) => {
+                let text = self.to_text();
+                let (integer, real) = parse_number_prefix(&text);
+                integer.map(Value::Integer).unwrap_or(Value::F(real))
+            }
+            _ => Value::Integer(self.as_integer().unwrap_or_default()),
+        }
+    }
+
+    /// Returns the value rendered as text, NULL being the empty string
+    pub fn to_text(&self) -> String {
+        match self {
+            Value::Null => String::new(),
+            Value::Blob(v) => String::from_utf8_lossy(v).to_string(),
+            Value::Text(v) => v.clone(),
+            _ => self.to_string(),
+        }
+    }
+
+    /// Evaluates the value as a boolean, `None` for NULL
+    pub fn is_truthy(&self) -> Option<bool> {
+        match self.to_numeric() {
+            Value::Null => None,
+            Value::F(n) => Some(n != 0.0),
+            n => Some(n.as_integer() != Some(0)),
+        }
+    }
+
+    /// Converts a value following the rules of `CAST(value AS type)`, as mentioned here:
+    /// [cast](https://www.sqlite.org/lang_expr.html#castexpr)
+    pub fn cast(&self, affinity: Affinity) -> Value {
+        if self.is_null() {
+            return Value::Null;
+        }
+        match affinity {
+            Affinity::Blob => match self {
+                Value::Blob(_) => self.clone(),
+                _ => Value::Blob(self.to_text().into_bytes()),
+            },
+            Affinity::Text => Value::Text(self.to_text()),
+            Affinity::Integer => match self {
+                Value::F(n) => Value::Integer(*n as i64),
+                Value::Text(_) | Value::Blob(_) => {
+                    Value::Integer(parse_integer_prefix(&self.to_text()))
+                }
+                _ => self.clone(),
+            },
+            Affinity::Real => match self {
+                Value::F(_) => self.clone(),
+                Value::Text(_) | Value::Blob(_) => Value::F(parse_number_prefix(&self.to_text()).1),
+                _ => Value::F(self.as_integer().unwrap_or_default() as f64),
+            },
+            Affinity::Numeric => match self {
+                Value::Text(_) | Value::Blob(_) => {
+                    let (integer, real) = parse_number_prefix(&self.to_text());
+                    match integer {
+                        Some(n) => Value::Integer(n),
+                        None if is_small_integral(real) => Value::Integer(real as i64),
+                        None => Value::F(real),
+                    }
+                }
+                _ => self.clone(),
+            },
+        }
+    }
+
+    /// Converts a value as SQLite does when storing it into a column of the given affinity, or when
+    /// comparing it with such a column, as mentioned here:
+    /// [affinity](https://www.sqlite.org/datatype3.html#type_affinity)
+    pub fn apply_affinity(&self, affinity: Affinity) -> Value {
+        match (affinity, self) {
+            (Affinity::Blob, _) | (_, Value::Null) | (_, Value::Blob(_)) => self.clone(),
+            (Affinity::Text, Value::Text(_)) => self.clone(),
+            (Affinity::Text, _) => Value::Text(self.to_text()),
+            (Affinity::Real, _) => match self.apply_affinity(Affinity::Numeric) {
+                Value::F(n) => Value::F(n),
+                Value::Text(text) => Value::Text(text),
+                n => Value::F(n.as_integer().unwrap_or_default() as f64),
+            },
+            (_, Value::Text(text)) => parse_numeric_text(text).unwrap_or_else(|| self.clone()),
+            (_, Value::F(n)) if is_small_integral(*n) => Value::Integer(*n as i64),
+            _ => self.clone(),
+        }
+    }
+
+    /// Orders values the way SQLite does without collation: NULLs first, then numbers by their
+    /// value, text and finally blobs, as mentioned here:
+    /// [comparisons](https://www.sqlite.org/datatype3.html#comparison_expressions)
+    pub fn compare(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Text(a), Value::Text(b)) => a.as_bytes().cmp(b.as_bytes()),
+            (Value::Blob(a), Value::Blob(b)) => a.cmp(b),
+            (Value::F(a), Value::F(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+            _ => match (self.as_integer(), other.as_integer(), self, other) {
+                (Some(a), Some(b), _, _) => a.cmp(&b),
+                (Some(a), _, _, Value::F(b)) => compare_integer_real(a, *b),
+                (_, Some(b), Value::F(a), _) => compare_integer_real(b, *a).reverse(),
+                _ => self.storage_class().cmp(&other.storage_class()),
+            },
+        }
+    }
+
+    /// Ranks the storage classes in the order they compare in
+    fn storage_class(&self) -> u8 {
+        match self {
+            Value::Null => 0,
+            Value::Text(_) => 2,
+            Value::Blob(_) => 3,
+            _ => 1,
+        }
+    }
+
     pub fn get_numeric_value(&self) -> Result<f64> {
         match self {
-            Value::I8(n) => Ok(*n as f64),
-            Value::I16(n) => Ok(*n as f64),
-            Value::I24(n) => Ok(*n as f64),
-            Value::I32(n) => Ok(*n as f64),
-            Value::I48(n) => Ok(*n as f64),
-            Value::I64(n) => Ok(*n as f64),
+            Value::Integer(n) => Ok(*n as f64),
             Value::F(n) => Ok(*n),
             _ => bail!("No numeric value"),
         }
@@ -37,29 +191,13 @@ impl Value {
 
 impl PartialOrd for Value {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        if let (Ok(s), Ok(o)) = (self.get_numeric_value(), other.get_numeric_value()) {
-            s.partial_cmp(&o)
-        } else {
-            match (self, other) {
-                (Value::Null, Value::Null) => Some(Ordering::Equal),
-                (Value::Text(s), Value::Text(o)) => s.partial_cmp(o),
-                _ => None,
-            }
-        }
+        Some(self.compare(other))
     }
 }
 
 impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
-        if let (Ok(s), Ok(o)) = (self.get_numeric_value(), other.get_numeric_value()) {
-            s == o
-        } else {
-            match (self, other) {
-                (Value::Null, Value::Null) => true,
-                (Value::Text(a), Value::Text(b)) => a == b,
-                _ => false,
-            }
-        }
+        self.compare(other) == Ordering::Equal
     }
 }
 
@@ -67,37 +205,195 @@ impl Display for Value {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Value::Null => write!(f, "NULL"),
-            Value::I8(v) => write!(f, "{}", v),
-            Value::I16(v) => write!(f, "{}", v),
-            Value::I24(v) => write!(f, "{}", v),
-            Value::I32(v) => write!(f, "{}", v),
-            Value::I48(v) => write!(f, "{}", v),
-            Value::I64(v) => write!(f, "{}", v),
-            Value::F(v) => write!(f, "{}", v),
-            Value::Blob(v) => write!(f, "{}", String::from_utf8_lossy(v).to_string()),
+            Value::Integer(v) => write!(f, "{}", v),
+            Value::F(v) => write!(f, "{}", format_real(*v)),
+            Value::Blob(v) => write!(f, "{}", String::from_utf8_lossy(v)),
             Value::Text(v) => write!(f, "{}", v),
         }
     }
 }
 
+/// Formats a real the way SQLite's `%!.15g` does: 15 significant digits and always a decimal point
+fn format_real(v: f64) -> String {
+    if v.is_infinite() {
+        return if v > 0.0 { "Inf" } else { "-Inf" }.to_string();
+    }
+    if v == 0.0 {
+        return "0.0".to_string();
+    }
+    let scientific = format!("{:.14e}", v);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let with_point = |digits: &str| {
+        let digits = digits.trim_end_matches('0');
+        if digits.ends_with('.') {
+            format!("{}0", digits)
+        } else {
+            digits.to_string()
+        }
+    };
+
+    if !(-4..15).contains(&exponent) {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", with_point(mantissa), sign, exponent.abs())
+    } else {
+        let decimals = (14 - exponent) as usize;
+        with_point(&format!("{:.*}", decimals, v))
+    }
+}
+
+/// Compares an integer with a real without losing the precision of either
+fn compare_integer_real(integer: i64, real: f64) -> Ordering {
+    if real.is_nan() || real < -9223372036854775808.0 {
+        return Ordering::Greater;
+    }
+    if real >= 9223372036854775808.0 {
+        return Ordering::Less;
+    }
+    integer.cmp(&(real as i64)).then_with(|| {
+        (integer as f64)
+            .partial_cmp(&real)
+            .unwrap_or(Ordering::Equal)
+    })
+}
+
+/// Whether a real converts back and forth to a 51-bit integer without loss
+fn is_small_integral(v: f64) -> bool {
+    v.fract() == 0.0 && v.abs() < (1i64 << 51) as f64
+}
+
+/// Reads the longest prefix of `text` that is an integer, saturating at the `i64` bounds
+fn parse_integer_prefix(text: &str) -> i64 {
+    let text = text.trim_start();
+    let (negative, digits) = match text.as_bytes().first() {
+        Some(b'-') => (true, &text[1..]),
+        Some(b'+') => (false, &text[1..]),
+        _ => (false, text),
+    };
+    let mut n: i64 = 0;
+    for digit in digits.bytes().take_while(u8::is_ascii_digit) {
+        let digit = (digit - b'0') as i64;
+        n = n
+            .checked_mul(10)
+            .and_then(|n| {
+                if negative {
+                    n.checked_sub(digit)
+                } else {
+                    n.checked_add(digit)
+                }
+            })
+            .unwrap_or(if negative { i64::MIN } else { i64::MAX });
+    }
+    n
+}
+
+/// Reads the longest prefix of `text` that is a number. Returns the integer value when the prefix
+/// has neither a decimal point nor an exponent and fits into an `i64`, and the real value otherwise
+fn parse_number_prefix(text: &str) -> (Option<i64>, f64) {
+    let text = text.trim_start();
+    let (end, is_integer) = number_prefix(text);
+    if end == 0 {
+        return (Some(0), 0.0);
+    }
+    let prefix = &text[..end];
+    let real = prefix.parse::<f64>().unwrap_or_default();
+    let integer = if is_integer {
+        prefix.parse::<i64>().ok()
+    } else {
+        None
+    };
+    (integer, real)
+}
+
+/// Reads text that is a well-formed number, apart from surrounding spaces, as an integer when it
+/// can be one without loss and as a real otherwise
+fn parse_numeric_text(text: &str) -> Option<Value> {
+    let text = text.trim();
+    let (end, is_integer) = number_prefix(text);
+    if end == 0 || end != text.len() {
+        return None;
+    }
+    if is_integer {
+        if let Ok(n) = text.parse::<i64>() {
+            return Some(Value::Integer(n));
+        }
+    }
+    let real = text.parse::<f64>().ok()?;
+    if is_small_integral(real) {
+        Some(Value::Integer(real as i64))
+    } else {
+        Some(Value::F(real))
+    }
+}
+
+/// Measures the longest prefix of `text` that is a number, telling whether it has neither a
+/// decimal point nor an exponent. The length is 0 when there is no number
+fn number_prefix(text: &str) -> (usize, bool) {
+    let bytes = text.as_bytes();
+    let digits_from = |start: usize| {
+        start
+            + bytes[start.min(bytes.len())..]
+                .iter()
+                .take_while(|b| b.is_ascii_digit())
+                .count()
+    };
+
+    let mut end = match bytes.first() {
+        Some(b'-') | Some(b'+') => 1,
+        _ => 0,
+    };
+    let sign_end = end;
+    end = digits_from(end);
+    let mut has_digits = end > sign_end;
+    let mut is_integer = true;
+    if bytes.get(end) == Some(&b'.') {
+        let fraction_end = digits_from(end + 1);
+        if has_digits || fraction_end > end + 1 {
+            has_digits = true;
+            is_integer = false;
+            end = fraction_end;
+        }
+    }
+    if has_digits && matches!(bytes.get(end), Some(b'e') | Some(b'E')) {
+        let exponent_start = match bytes.get(end + 1) {
+            Some(b'-') | Some(b'+') => end + 2,
+            _ => end + 1,
+        };
+        let exponent_end = digits_from(exponent_start);
+        if exponent_end > exponent_start {
+            is_integer = false;
+            end = exponent_end;
+        }
+    }
+
+    if has_digits {
+        (end, is_integer)
+    } else {
+        (0, true)
+    }
+}
+
 /// Reads SQLite's "Record Format" as mentioned here:
 /// [record_format](https://www.sqlite.org/fileformat.html#record_format)
+/// Parses the first `column_count` values of a record. Records written before columns were added
+/// to their table hold fewer values, which are all returned
 pub fn parse_record(stream: &[u8], column_count: usize) -> Result<Vec<Value>> {
     // Parse number of bytes in header, and use bytes_read as offset
-    let (_, mut offset) = parse_varint(stream);
+    let (header_size, mut offset) = parse_varint(stream);
 
     // Read each varint into serial types and modify the offset
     let mut serial_types = vec![];
-    for _ in 0..column_count {
+    while serial_types.len() < column_count && offset < header_size {
         let (varint, read_bytes) = parse_varint(&stream[offset..]);
         offset += read_bytes;
         serial_types.push(varint);
     }
 
     // Parse each serial type as column into record and modify the offset
+    offset = header_size;
     let mut record = vec![];
     for serial_type in serial_types {
-        let (column, column_len) = parse_column_value(&stream[offset..], serial_type as usize)?;
+        let (column, column_len) = parse_column_value(&stream[offset..], serial_type)?;
         offset += column_len;
         record.push(column);
     }
@@ -105,30 +401,99 @@ pub fn parse_record(stream: &[u8], column_count: usize) -> Result<Vec<Value>> {
     Ok(record)
 }
 
+/// Encodes values into a record, the inverse of `parse_record`, as mentioned here:
+/// [record format](https://www.sqlite.org/fileformat2.html#record_format)
+/// The integers 0 and 1 take no space in databases of schema format 4, which introduced their
+/// serial types
+pub fn encode_record(values: &[Value], schema_format: u32) -> Vec<u8> {
+    let (serial_types, bodies): (Vec<_>, Vec<_>) = values
+        .iter()
+        .map(|value| encode_column_value(value, schema_format >= 4))
+        .unzip();
+    let header = serial_types
+        .into_iter()
+        .flat_map(encode_varint)
+        .collect::<Vec<_>>();
+    // The size of the header counts the varint holding it
+    let mut header_size = header.len() + 1;
+    while header.len() + encode_varint(header_size as u64).len() != header_size {
+        header_size = header.len() + encode_varint(header_size as u64).len();
+    }
+
+    let mut record = encode_varint(header_size as u64);
+    record.extend(header);
+    record.extend(bodies.into_iter().flatten());
+    record
+}
+
+/// Returns the serial type of a value and its encoding, using the smallest integer type that
+/// holds the value
+fn encode_column_value(value: &Value, has_constants: bool) -> (u64, Vec<u8>) {
+    match value {
+        Value::Null => (0, vec![]),
+        Value::Integer(0) if has_constants => (8, vec![]),
+        Value::Integer(1) if has_constants => (9, vec![]),
+        Value::Integer(i) => {
+            let bytes = i.to_be_bytes();
+            let (serial_type, len) = match *i {
+                -0x80..=0x7f => (1, 1),
+                -0x8000..=0x7fff => (2, 2),
+                -0x80_0000..=0x7f_ffff => (3, 3),
+                -0x8000_0000..=0x7fff_ffff => (4, 4),
+                -0x8000_0000_0000..=0x7fff_ffff_ffff => (5, 6),
+                _ => (6, 8),
+            };
+            (serial_type, bytes[8 - len..].to_vec())
+        }
+        Value::F(f) => (7, f.to_be_bytes().to_vec()),
+        Value::Text(text) => (text.len() as u64 * 2 + 13, text.as_bytes().to_vec()),
+        Value::Blob(blob) => (blob.len() as u64 * 2 + 12, blob.clone()),
+    }
+}
+
 fn parse_column_value(stream: &[u8], serial_type: usize) -> Result<(Value, usize)> {
     let (column_value, offset) = match serial_type {
         0 => (Value::Null, 0),
         // 8 bit twos-complement integer
-        1 => (Value::I8(i8::from_be_bytes([stream[0]])), 1),
-        2 => (Value::I16(i16::from_be_bytes(stream[0..2].try_into()?)), 2),
+        1 => (Value::Integer(i8::from_be_bytes([stream[0]]) as i64), 1),
+        2 => (
+            Value::Integer(i16::from_be_bytes(stream[0..2].try_into()?) as i64),
+            2,
+        ),
+        // Shifting back and forth sign-extends the 24 and 48 bit integers
         3 => (
-            Value::I24(i32::from_be_bytes([0, stream[0], stream[1], stream[2]])),
+            Value::Integer((i32::from_be_bytes([stream[0], stream[1], stream[2], 0]) >> 8) as i64),
             3,
         ),
-        4 => (Value::I32(i32::from_be_bytes(stream[0..4].try_into()?)), 4),
-        9 => (Value::I8(1), 0),
+        4 => (
+            Value::Integer(i32::from_be_bytes(stream[0..4].try_into()?) as i64),
+            4,
+        ),
+        5 => {
+            let mut bytes = [0; 8];
+            bytes[..6].copy_from_slice(&stream[0..6]);
+            (Value::Integer(i64::from_be_bytes(bytes) >> 16), 6)
+        }
+        6 => (
+            Value::Integer(i64::from_be_bytes(stream[0..8].try_into()?)),
+            8,
+        ),
+        // Big-endian IEEE 754-2008 64-bit floating point number
+        7 => (Value::F(f64::from_be_bytes(stream[0..8].try_into()?)), 8),
+        8 => (Value::Integer(0), 0),
+        9 => (Value::Integer(1), 0),
         // Text encoding
         n if serial_type >= 13 && serial_type % 2 == 1 => {
             let n_bytes = (n - 13) / 2;
-            let bytes = stream[0..n_bytes as usize].to_vec();
+            let bytes = stream[0..n_bytes].to_vec();
             (
                 Value::Text(String::from_utf8_lossy(&bytes).to_string()),
                 bytes.len(),
             )
         }
-        n if serial_type >= 12 && serial_type % 2 == 0 => {
+        n if serial_type >= 12 && serial_type.is_multiple_of(2) => {
             let n_bytes = (n - 12) / 2;
-            let bytes = stream[0..n_bytes as usize].to_vec();
+            let bytes = stream[0..n_bytes].to_vec();
             let len = bytes.len();
             (Value::Blob(bytes), len)
         }
@@ -136,3 +501,239 @@ fn parse_column_value(stream: &[u8], serial_type: usize) -> Result<(Value, usize
     };
     Ok((column_value, offset))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> Value {
+        Value::Text(text.to_string())
+    }
+
+    #[test]
+    fn applies_column_affinity() {
+        let cases = [
+            (text(" 42 "), Affinity::Integer, "Integer(42)"),
+            (text("1e3"), Affinity::Integer, "Integer(1000)"),
+            (text("3.5"), Affinity::Integer, "F(3.5)"),
+            (Value::F(9.0), Affinity::Integer, "Integer(9)"),
+            (text("42"), Affinity::Real, "F(42.0)"),
+            (Value::Integer(7), Affinity::Real, "F(7.0)"),
+            (
+                text("9223372036854775808"),
+                Affinity::Real,
+                "F(9.223372036854776e18)",
+            ),
+            (Value::Integer(42), Affinity::Text, "Text(\"42\")"),
+            (Value::F(1.5), Affinity::Text, "Text(\"1.5\")"),
+            (Value::Blob(vec![1]), Affinity::Text, "Blob([1])"),
+            (text("42"), Affinity::Blob, "Text(\"42\")"),
+            (text("4.0e1"), Affinity::Numeric, "Integer(40)"),
+            (text("abc"), Affinity::Numeric, "Text(\"abc\")"),
+            (text("0x10"), Affinity::Numeric, "Text(\"0x10\")"),
+            (text("12abc"), Affinity::Numeric, "Text(\"12abc\")"),
+            (Value::Null, Affinity::Numeric, "Null"),
+        ];
+        for (value, affinity, expected) in cases {
+            let converted = value.apply_affinity(affinity);
+            assert_eq!(format!("{:?}", converted), expected, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn compares_across_storage_classes() {
+        let ordered = [
+            Value::Null,
+            Value::Integer(-5),
+            Value::F(2.0),
+            Value::Integer(3),
+            Value::F(10.5),
+            Value::Integer(i64::MAX),
+            Value::F(9223372036854775808.0),
+            text("10"),
+            text("9"),
+            text("a"),
+            Value::Blob(vec![0]),
+        ];
+        for pair in ordered.windows(2) {
+            assert_eq!(pair[0].compare(&pair[1]), Ordering::Less, "{:?}", pair);
+            assert_eq!(pair[1].compare(&pair[0]), Ordering::Greater, "{:?}", pair);
+        }
+        assert_eq!(Value::Integer(1).compare(&Value::F(1.0)), Ordering::Equal);
+        assert_eq!(text("10").compare(&Value::Integer(10)), Ordering::Greater);
+    }
+
+    #[test]
+    fn sign_extends_short_integers() {
+        let record = [
+            4, 3, 5, 1, 0xff, 0xff, 0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfd, 0x7f,
+        ];
+        let values = parse_record(&record, 3).unwrap();
+        assert_eq!(
+            format!("{:?}", values),
+            "[Integer(-2), Integer(-3), Integer(127)]"
+        );
+    }
+
+    #[test]
+    fn keeps_large_integers_exact() {
+        let mut record = vec![3, 6, 6];
+        record.extend(i64::MAX.to_be_bytes());
+        record.extend((i64::MIN + 1).to_be_bytes());
+        let values = parse_record(&record, 2).unwrap();
+        assert_eq!(
+            format!("{:?}", values),
+            "[Integer(9223372036854775807), Integer(-9223372036854775807)]"
+        );
+        assert_eq!(values[0].to_text(), "9223372036854775807");
+        assert_eq!(
+            values[0].compare(&Value::Integer(i64::MAX - 1)),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn reads_short_records_up_to_their_header() {
+        // A header of two values, a one-byte integer and the text "ab"
+        let record = [3, 1, 17, 7, b'a', b'b'];
+        let values = parse_record(&record, 4).unwrap();
+        assert_eq!(format!("{:?}", values), "[Integer(7), Text(\"ab\")]");
+        let values = parse_record(&record, 1).unwrap();
+        assert_eq!(format!("{:?}", values), "[Integer(7)]");
+    }
+
+    /// Returns the serial types of the header of a record
+    fn serial_types(record: &[u8]) -> Vec<usize> {
+        let (header_size, mut offset) = parse_varint(record);
+        let mut serial_types = vec![];
+        while offset < header_size {
+            let (serial_type, read_bytes) = parse_varint(&record[offset..]);
+            serial_types.push(serial_type);
+            offset += read_bytes;
+        }
+        serial_types
+    }
+
+    fn assert_round_trip(values: &[Value], schema_format: u32) -> Vec<usize> {
+        let record = encode_record(values, schema_format);
+        let parsed = parse_record(&record, values.len()).unwrap();
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", values));
+        serial_types(&record)
+    }
+
+    #[test]
+    fn round_trips_integers_around_every_size() {
+        // The smallest and largest integer of each size, and those just outside of them
+        let mut bounds = vec![i64::MIN, i64::MIN + 1, i64::MAX - 1, i64::MAX, -1, 0, 1, 2];
+        for bits in [8, 16, 24, 32, 48] {
+            let max = (1i64 << (bits - 1)) - 1;
+            bounds.extend([max, max + 1, -max - 1, -max - 2]);
+        }
+        for &i in &bounds {
+            let size = match i {
+                -0x80..=0x7f => 1,
+                -0x8000..=0x7fff => 2,
+                -0x80_0000..=0x7f_ffff => 3,
+                -0x8000_0000..=0x7fff_ffff => 4,
+                -0x8000_0000_0000..=0x7fff_ffff_ffff => 6,
+                _ => 8,
+            };
+            for schema_format in 1..=4 {
+                let record = encode_record(&[Value::Integer(i)], schema_format);
+                let serial_type = assert_round_trip(&[Value::Integer(i)], schema_format)[0];
+                match (i, schema_format) {
+                    (0 | 1, 4) => assert_eq!((serial_type, record.len()), (8 + i as usize, 2)),
+                    _ => assert_eq!(record.len(), 2 + size, "{}", i),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_reals() {
+        let reals = [
+            0.0,
+            -0.0,
+            1.0,
+            -1.5,
+            0.1,
+            1e300,
+            -1e-300,
+            f64::MAX,
+            f64::MIN_POSITIVE,
+        ];
+        for f in reals.iter().chain(&[f64::INFINITY, f64::NEG_INFINITY]) {
+            // Reals holding integers keep their serial type, unlike in SQLite's own files
+            assert_eq!(assert_round_trip(&[Value::F(*f)], 4), [7]);
+            let record = encode_record(&[Value::F(*f)], 4);
+            assert_eq!(record[2..], f.to_be_bytes());
+        }
+    }
+
+    #[test]
+    fn round_trips_text_and_blobs_of_every_length() {
+        for len in (0..300).chain([1000, 10_000, 100_000]) {
+            let text = "é".repeat(len / 2) + &"a".repeat(len % 2);
+            let blob = (0..len).map(|i| i as u8).collect::<Vec<_>>();
+            let values = [Value::Text(text), Value::Blob(blob)];
+            assert_eq!(assert_round_trip(&values, 4), [len * 2 + 13, len * 2 + 12]);
+        }
+    }
+
+    #[test]
+    fn round_trips_generated_records() {
+        // A xorshift generator picks the kind and the content of each value
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..1000 {
+            let len = (next() % 20) as usize;
+            let values = (0..len)
+                .map(|_| {
+                    let n = next();
+                    match n % 6 {
+                        0 => Value::Null,
+                        1 => Value::Integer(n as i64 >> (next() % 64)),
+                        2 => Value::Integer((n % 3) as i64),
+                        3 => Value::F(f64::from_bits(n >> 2) * if n & 1 == 0 { 1.0 } else { -1.0 }),
+                        4 => Value::Text("x".repeat((n >> 8) as usize % 200)),
+                        _ => Value::Blob(vec![n as u8; (n >> 8) as usize % 200]),
+                    }
+                })
+                .collect::<Vec<_>>();
+            for schema_format in [1, 4] {
+                let serial_types = assert_round_trip(&values, schema_format);
+                let has_constants = serial_types.iter().any(|&t| t == 8 || t == 9);
+                assert!(schema_format == 4 || !has_constants);
+            }
+        }
+    }
+
+    #[test]
+    fn zero_and_one_take_no_space_from_schema_format_4() {
+        let values = vec![Value::Integer(0), Value::Integer(1)];
+        assert_eq!(assert_round_trip(&values, 4), [8, 9]);
+        assert_eq!(encode_record(&values, 4), [3, 8, 9]);
+        for schema_format in 1..4 {
+            assert_eq!(assert_round_trip(&values, schema_format), [1, 1]);
+            assert_eq!(encode_record(&values, schema_format), [3, 1, 1, 0, 1]);
+        }
+    }
+
+    #[test]
+    fn header_sizes_count_their_own_varint() {
+        // 127 serial types and a one-byte size would take 128 bytes, which needs two
+        let values = vec![Value::Null; 127];
+        let record = encode_record(&values, 4);
+        assert_eq!(parse_varint(&record), (129, 2));
+        assert_eq!(record.len(), 129);
+        assert_eq!(assert_round_trip(&values, 4), [0; 127]);
+
+        let values = vec![Value::Null; 126];
+        assert_eq!(parse_varint(&encode_record(&values, 4)), (127, 1));
+    }
+}
@@ -1,7 +1,10 @@
 use anyhow::{bail, Error, Result};
 
+use crate::expr::Expr;
 use crate::record::Value;
-use crate::sql::CreateStatement;
+use crate::sql::{
+    Check, Column, ColumnConstraint, CreateStatement, IndexedColumn, TableConstraint,
+};
 
 #[derive(Debug)]
 pub struct Schema {
@@ -20,27 +23,33 @@ impl Schema {
             items.next(),
             items.next(),
             items.next(),
-            items.next().map(|page| page.get_numeric_value()),
+            items.next().map(|page| page.as_integer()),
             items.next(),
         ) {
             (
                 Some(Value::Text(kind)),
                 Some(Value::Text(name)),
                 Some(Value::Text(table_name)),
-                Some(Ok(root_page)),
+                Some(Some(root_page)),
                 Some(Value::Text(sql)),
             ) => Ok(Self {
                 kind,
                 name,
                 table_name,
                 root_page: root_page as usize,
-                sql: Some(CreateStatement::parse(sql)?),
+                // A statement that cannot be parsed only fails the queries using its object
+                sql: Some(CreateStatement::parse(sql.clone()).unwrap_or_else(|err| {
+                    CreateStatement::Unparsed {
+                        sql,
+                        error: err.to_string(),
+                    }
+                })),
             }),
             (
                 Some(Value::Text(kind)),
                 Some(Value::Text(name)),
                 Some(Value::Text(table_name)),
-                Some(Ok(root_page)),
+                Some(Some(root_page)),
                 Some(Value::Null),
             ) => Ok(Self {
                 kind,
@@ -53,16 +62,396 @@ impl Schema {
         }
     }
 
-    pub fn columns(&self) -> Result<Vec<&String>> {
-        match self
-            .sql
-            .as_ref()
-            .ok_or_else(|| Error::msg(format!("No create statement found for {}", self.name)))?
-        {
-            CreateStatement::CreateTable { columns, .. } => {
-                Ok(columns.iter().map(|c| &c.name).collect())
+    /// Returns the error for an object used as a `kind` it is not, or whose statement could not
+    /// be parsed
+    pub fn wrong_kind(&self, kind: &str) -> Error {
+        match &self.sql {
+            Some(CreateStatement::Unparsed { error, .. }) => Error::msg(format!(
+                "malformed database schema ({}) - {}",
+                self.name, error
+            )),
+            _ => Error::msg(format!("{} is not {}", self.name, kind)),
+        }
+    }
+
+    /// Whether this is an index SQLite creates for a `UNIQUE` or `PRIMARY KEY` constraint, which
+    /// has no SQL of its own
+    pub fn is_autoindex(&self) -> bool {
+        self.kind == "index" && self.name.starts_with("sqlite_autoindex_")
+    }
+
+    /// Returns the column definitions of a table
+    pub fn table_columns(&self) -> Result<&[Column]> {
+        match &self.sql {
+            Some(CreateStatement::CreateTable { columns, .. }) => Ok(columns),
+            _ => Err(self.wrong_kind("a table")),
+        }
+    }
+
+    /// Returns the positions of the columns the records of a table hold, in the order of their
+    /// values. A WITHOUT ROWID table stores its primary key first, and no table stores its
+    /// VIRTUAL generated columns, as mentioned here:
+    /// [generated columns](https://www.sqlite.org/gencol.html)
+    pub fn stored_columns(&self) -> Result<Vec<usize>> {
+        let columns = self.table_columns()?;
+        let mut order = vec![];
+        if self.is_without_rowid() {
+            for (i, _) in self.primary_key()? {
+                if !order.contains(&i) {
+                    order.push(i);
+                }
+            }
+        }
+        let rest = columns
+            .iter()
+            .enumerate()
+            .filter(|(i, column)| {
+                !order.contains(i) && !matches!(column.generated(), Some((_, false)))
+            })
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        order.extend(rest);
+        Ok(order)
+    }
+
+    /// Returns the columns of an index
+    pub fn index_columns(&self) -> Result<&[IndexedColumn]> {
+        match &self.sql {
+            Some(CreateStatement::CreateIndex { columns, .. }) => Ok(columns),
+            _ => Err(self.wrong_kind("an index")),
+        }
+    }
+
+    /// Whether this is a table stored in an index B-tree keyed by its primary key, as mentioned
+    /// here: [without rowid](https://www.sqlite.org/withoutrowid.html)
+    pub fn is_without_rowid(&self) -> bool {
+        matches!(
+            self.sql,
+            Some(CreateStatement::CreateTable {
+                without_rowid: true,
+                ..
+            })
+        )
+    }
+
+    /// Returns the columns of the primary key of a table, with their position among the columns
+    /// of the table
+    pub fn primary_key(&self) -> Result<Vec<(usize, IndexedColumn)>> {
+        let (columns, constraints) = match &self.sql {
+            Some(CreateStatement::CreateTable {
+                columns,
+                constraints,
+                ..
+            }) => (columns, constraints),
+            _ => return Err(self.wrong_kind("a table")),
+        };
+        let position = |name: &str| {
+            columns
+                .iter()
+                .position(|c| c.name.eq_ignore_ascii_case(name))
+                .ok_or_else(|| Error::msg(format!("No such column: {}", name)))
+        };
+
+        for constraint in constraints {
+            if let TableConstraint::PrimaryKey { columns, .. } = constraint {
+                return columns
+                    .iter()
+                    .map(|column| match column.name() {
+                        Some(name) => Ok((position(name)?, column.clone())),
+                        None => bail!("Expressions are not allowed in a PRIMARY KEY"),
+                    })
+                    .collect();
+            }
+        }
+        Ok(columns
+            .iter()
+            .enumerate()
+            .find_map(|(i, column)| {
+                column
+                    .constraints
+                    .iter()
+                    .find_map(|constraint| match constraint {
+                        ColumnConstraint::PrimaryKey { descending, .. } => Some(vec![(
+                            i,
+                            IndexedColumn {
+                                expr: Expr::Column(column.name.clone()),
+                                collation: None,
+                                descending: *descending,
+                            },
+                        )]),
+                        _ => None,
+                    })
+            })
+            .unwrap_or_default())
+    }
+
+    /// Returns the `CHECK` constraints of a table, those of its columns first
+    pub fn checks(&self) -> Result<Vec<&Check>> {
+        let (columns, constraints) = match &self.sql {
+            Some(CreateStatement::CreateTable {
+                columns,
+                constraints,
+                ..
+            }) => (columns, constraints),
+            _ => return Err(self.wrong_kind("a table")),
+        };
+        let column_checks = columns.iter().flat_map(|column| {
+            column.constraints.iter().filter_map(|c| match c {
+                ColumnConstraint::Check(check) => Some(check),
+                _ => None,
+            })
+        });
+        let table_checks = constraints.iter().filter_map(|c| match c {
+            TableConstraint::Check(check) => Some(check),
+            _ => None,
+        });
+        Ok(column_checks.chain(table_checks).collect())
+    }
+
+    /// Whether the rowid alias of a table is declared `AUTOINCREMENT`, its rowids then never
+    /// being reused, as mentioned here: [autoincrement](https://www.sqlite.org/autoinc.html)
+    pub fn is_autoincrement(&self) -> bool {
+        match (self.table_columns(), self.rowid_alias()) {
+            (Ok(columns), Some(i)) => columns[i].constraints.iter().any(|c| {
+                matches!(
+                    c,
+                    ColumnConstraint::PrimaryKey {
+                        autoincrement: true,
+                        ..
+                    }
+                )
+            }),
+            _ => false,
+        }
+    }
+
+    /// Returns the columns of the indexes SQLite creates for the `UNIQUE` and `PRIMARY KEY`
+    /// constraints of a table, in the order of their `sqlite_autoindex_<table>_<n>` names.
+    /// Constraints on the same columns as an earlier one share its index, and a primary key
+    /// stored as the rowid or as the table itself needs none
+    pub fn constraint_indexes(&self) -> Result<Vec<Vec<IndexedColumn>>> {
+        let (columns, constraints, without_rowid) = match &self.sql {
+            Some(CreateStatement::CreateTable {
+                columns,
+                constraints,
+                without_rowid,
+                ..
+            }) => (columns, constraints, *without_rowid),
+            _ => return Err(self.wrong_kind("a table")),
+        };
+        let has_key_index = self.rowid_alias().is_none() && !without_rowid;
+
+        let mut keys = vec![];
+        for column in columns {
+            for constraint in &column.constraints {
+                let has_index = match constraint {
+                    ColumnConstraint::PrimaryKey { .. } => has_key_index,
+                    ColumnConstraint::Unique(_) => true,
+                    _ => false,
+                };
+                if has_index {
+                    keys.push(vec![IndexedColumn {
+                        expr: Expr::Column(column.name.clone()),
+                        collation: None,
+                        descending: false,
+                    }]);
+                }
+            }
+        }
+        for constraint in constraints {
+            match constraint {
+                TableConstraint::PrimaryKey { columns, .. } if has_key_index => {
+                    keys.push(columns.clone())
+                }
+                TableConstraint::Unique { columns, .. } => keys.push(columns.clone()),
+                _ => {}
+            }
+        }
+
+        let mut indexes: Vec<Vec<IndexedColumn>> = vec![];
+        for key in keys {
+            let is_same = |index: &Vec<IndexedColumn>| {
+                index.len() == key.len()
+                    && index.iter().zip(&key).all(|(a, b)| {
+                        a.expr.is_equivalent(&b.expr)
+                            && a.collation.as_deref().map(str::to_lowercase)
+                                == b.collation.as_deref().map(str::to_lowercase)
+                    })
+            };
+            if !indexes.iter().any(is_same) {
+                indexes.push(key);
+            }
+        }
+        Ok(indexes)
+    }
+
+    /// Returns the position of the column that is an alias for the rowid, which SQLite stores as
+    /// NULL. It is the only column of the primary key of a rowid table, declared exactly as
+    /// `INTEGER`, as mentioned here: [rowid](https://www.sqlite.org/lang_createtable.html#rowid)
+    pub fn rowid_alias(&self) -> Option<usize> {
+        if self.is_without_rowid() {
+            return None;
+        }
+        let columns = self.table_columns().ok()?;
+        match &self.primary_key().ok()?[..] {
+            [(i, key)] => {
+                let column = &columns[*i];
+                let is_integer = column
+                    .data_type
+                    .as_deref()
+                    .is_some_and(|data_type| data_type.eq_ignore_ascii_case("integer"));
+                // A quirk of SQLite keeps `INTEGER PRIMARY KEY DESC` columns apart from the rowid
+                let is_quirk = key.descending && column.is_primary_key();
+                (is_integer && !is_quirk).then_some(*i)
             }
-            CreateStatement::CreateIndex { columns, .. } => Ok(columns.iter().collect()),
+            _ => None,
+        }
+    }
+
+    /// Returns the condition of a partial index
+    pub fn index_filter(&self) -> Option<&Expr> {
+        match &self.sql {
+            Some(CreateStatement::CreateIndex { filter, .. }) => filter.as_ref(),
+            _ => None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema_row(kind: &str, name: &str, sql: &str) -> Vec<Value> {
+        vec![
+            Value::Text(kind.to_string()),
+            Value::Text(name.to_string()),
+            Value::Text(name.to_string()),
+            Value::Integer(2),
+            Value::Text(sql.to_string()),
+        ]
+    }
+
+    #[test]
+    fn unparsed_statements_only_fail_their_object() {
+        let schema = Schema::parse(schema_row(
+            "table",
+            "t",
+            "CREATE TABLE t (a INTEGER PRIMARY KEY, b TEXT)",
+        ))
+        .unwrap();
+        assert_eq!(schema.table_columns().unwrap().len(), 2);
+
+        let schema = Schema::parse(schema_row("table", "t", "CREATE TABLE t (a b c (")).unwrap();
+        assert!(matches!(schema.sql, Some(CreateStatement::Unparsed { .. })));
+        let err = schema.table_columns().unwrap_err().to_string();
+        assert!(err.starts_with("malformed database schema (t)"), "{}", err);
+        assert!(schema.index_columns().is_err());
+    }
+
+    #[test]
+    fn constraint_indexes_follow_sqlite_numbering() {
+        let indexes = |sql: &str| {
+            let schema = Schema::parse(schema_row("table", "t", sql)).unwrap();
+            schema
+                .constraint_indexes()
+                .unwrap()
+                .iter()
+                .map(|columns| {
+                    columns
+                        .iter()
+                        .map(|c| match &c.collation {
+                            Some(collation) => format!("{} {}", c.name().unwrap(), collation),
+                            None => c.name().unwrap().to_string(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(",")
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            indexes("CREATE TABLE t (a TEXT PRIMARY KEY, b UNIQUE, c, UNIQUE (b), UNIQUE (c COLLATE NOCASE, b))"),
+            ["a", "b", "c NOCASE,b"]
+        );
+        assert_eq!(
+            indexes("CREATE TABLE t (a INTEGER PRIMARY KEY, b UNIQUE)"),
+            ["b"]
+        );
+        assert_eq!(
+            indexes("CREATE TABLE t (a integer, b, PRIMARY KEY (a DESC))"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            indexes("CREATE TABLE t (a INTEGER PRIMARY KEY DESC, b)"),
+            ["a"]
+        );
+        assert_eq!(
+            indexes("CREATE TABLE t (a INT, b, PRIMARY KEY (a, b))"),
+            ["a,b"]
+        );
+        assert_eq!(
+            indexes("CREATE TABLE t (a, b UNIQUE, PRIMARY KEY (a)) WITHOUT ROWID"),
+            ["b"]
+        );
+    }
+
+    #[test]
+    fn without_rowid_tables_store_their_primary_key_first() {
+        let stored = |sql: &str| {
+            let schema = Schema::parse(schema_row("table", "t", sql)).unwrap();
+            schema.stored_columns().unwrap()
+        };
+        assert_eq!(stored("CREATE TABLE t (a, b PRIMARY KEY, c)"), [0, 1, 2]);
+        assert_eq!(
+            stored("CREATE TABLE t (a, b PRIMARY KEY, c) WITHOUT ROWID"),
+            [1, 0, 2]
+        );
+        assert_eq!(
+            stored("CREATE TABLE t (a, b, c, PRIMARY KEY (c, a, c)) WITHOUT ROWID"),
+            [2, 0, 1]
+        );
+        assert_eq!(
+            stored("CREATE TABLE t (a AS (b), b PRIMARY KEY, c) WITHOUT ROWID"),
+            [1, 2]
+        );
+
+        let schema = Schema::parse(schema_row(
+            "table",
+            "t",
+            "CREATE TABLE t (a, b, PRIMARY KEY (b DESC, a)) WITHOUT ROWID",
+        ))
+        .unwrap();
+        assert!(schema.is_without_rowid());
+        let key = schema.primary_key().unwrap();
+        assert_eq!(key.iter().map(|(i, _)| *i).collect::<Vec<_>>(), [1, 0]);
+        assert!(key[0].1.descending && !key[1].1.descending);
+        let schema = Schema::parse(schema_row("table", "t", "CREATE TABLE t (a, b)")).unwrap();
+        assert!(!schema.is_without_rowid());
+        assert!(schema.primary_key().unwrap().is_empty());
+    }
+
+    #[test]
+    fn rowid_aliases_are_lone_integer_primary_keys() {
+        let alias = |sql: &str| {
+            Schema::parse(schema_row("table", "t", sql))
+                .unwrap()
+                .rowid_alias()
+        };
+        assert_eq!(alias("CREATE TABLE t (a, b INTEGER PRIMARY KEY)"), Some(1));
+        assert_eq!(
+            alias("CREATE TABLE t (a integer, b, PRIMARY KEY (a DESC))"),
+            Some(0)
+        );
+        assert_eq!(alias("CREATE TABLE t (a INTEGER PRIMARY KEY ASC)"), Some(0));
+        assert_eq!(alias("CREATE TABLE t (a INTEGER PRIMARY KEY DESC)"), None);
+        assert_eq!(alias("CREATE TABLE t (a INT PRIMARY KEY)"), None);
+        assert_eq!(
+            alias("CREATE TABLE t (a INTEGER, b, PRIMARY KEY (a, b))"),
+            None
+        );
+        assert_eq!(
+            alias("CREATE TABLE t (a INTEGER PRIMARY KEY) WITHOUT ROWID"),
+            None
+        );
+        assert_eq!(alias("CREATE TABLE t (a INTEGER)"), None);
+    }
+}
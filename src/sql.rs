@@ -1,164 +1,2537 @@
-use anyhow::{Error, Result};
+use anyhow::{bail, Error, Result};
 use nom::branch::alt;
-use nom::bytes::complete::{is_not, tag, tag_no_case};
-use nom::character::complete::{multispace0, multispace1};
-use nom::combinator::opt;
-use nom::multi::separated_list1;
-use nom::number::complete::double;
-use nom::sequence::{delimited, preceded, separated_pair, terminated, tuple};
-use nom::{error, Err, Parser};
-
-use crate::record::Value;
+use nom::bytes::complete::{is_not, tag, tag_no_case, take_while, take_while1};
+use nom::character::complete::{char, digit0, digit1, multispace0, multispace1, one_of, satisfy};
+use nom::combinator::{consumed, eof, map, not, opt, peek, recognize, rest, value, verify};
+use nom::multi::{many0, many1, separated_list1};
+use nom::sequence::{delimited, pair, preceded, separated_pair, terminated, tuple};
+use nom::{error, Err, IResult, Parser};
+
+use crate::expr::{BinaryOperator, Expr, UnaryOperator};
+use crate::record::{Affinity, Value};
 use crate::sql::CreateStatement::CreateIndex;
+use crate::wal::CheckpointMode;
+
+/// Words that end an expression and so cannot be used as bare column names
+const RESERVED_WORDS: &[&str] = &[
+    "all", "and", "as", "between", "by", "case", "cast", "collate", "cross", "distinct", "else",
+    "end", "escape", "from", "full", "glob", "group", "having", "in", "inner", "is", "isnull",
+    "join", "left", "like", "limit", "natural", "not", "notnull", "null", "offset", "on", "or",
+    "order", "outer", "right", "select", "then", "using", "when", "where",
+];
+
+#[derive(Debug, Clone)]
+pub enum ResultColumn {
+    All,
+    Expr(Expr, Option<String>),
+}
 
-pub struct Select<'a> {
-    pub columns: Vec<&'a str>,
-    pub table: &'a str,
-    pub filter: Option<(&'a str, Value)>,
+#[derive(Debug, Clone)]
+pub struct OrderingTerm {
+    pub expr: Expr,
+    pub descending: bool,
 }
 
-impl<'a> Select<'a> {
-    pub fn parse_select(query: &'a str) -> Result<Self> {
-        let (_, (columns, table, filter)) = tuple((
+/// A table or table-valued function in the `FROM` clause
+#[derive(Debug, Clone)]
+pub enum TableSource {
+    Table {
+        name: String,
+        alias: Option<String>,
+    },
+    Function {
+        name: String,
+        args: Vec<Expr>,
+        alias: Option<String>,
+    },
+}
+
+impl TableSource {
+    /// The name columns of this source are qualified with
+    pub fn alias(&self) -> &str {
+        match self {
+            TableSource::Table { name, alias } | TableSource::Function { name, alias, .. } => {
+                alias.as_ref().unwrap_or(name)
+            }
+        }
+    }
+}
+
+/// A `LEFT JOIN` of a source of the `FROM` clause, given by its position. Rows that no row of
+/// the source matches under its constraint are kept, with NULL for the columns of the source
+#[derive(Debug, Clone)]
+pub struct LeftJoin {
+    pub source: usize,
+    pub constraint: Option<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Select {
+    /// Whether rows equal to an earlier one are left out, as `SELECT DISTINCT` does
+    pub distinct: bool,
+    pub columns: Vec<ResultColumn>,
+    pub from: Vec<TableSource>,
+    pub left_joins: Vec<LeftJoin>,
+    pub filter: Option<Expr>,
+    pub group_by: Vec<Expr>,
+    pub having: Option<Expr>,
+    pub order_by: Vec<OrderingTerm>,
+    pub limit: Option<Expr>,
+    pub offset: Option<Expr>,
+}
+
+impl Select {
+    pub fn parse_select(query: &str) -> Result<Self> {
+        let query = strip_comments(query);
+        let (_, select) = terminated(select, end_of_statement)(query.as_str())
+            .map_err(|err: Err<error::Error<&str>>| Error::msg(err.to_string()))?;
+        Ok(select)
+    }
+}
+
+fn select(input: &str) -> IResult<&str, Select> {
+    let (input, (distinct, columns, from, filter, group_by, having, order_by, limit)) =
+        tuple((
             preceded(
-                tag_no_case("select"),
-                delimited(
-                    multispace0,
-                    separated_list1(
-                        tag(","),
-                        delimited(multispace0, is_not(" \t\r\n,"), multispace0),
+                keyword("select"),
+                opt(preceded(
+                    multispace1,
+                    alt((
+                        value(true, keyword("distinct")),
+                        value(false, keyword("all")),
+                    )),
+                )),
+            ),
+            delimited(multispace0, comma_separated(result_column), multispace0),
+            opt(preceded(
+                keyword("from"),
+                delimited(multispace0, from_clause, multispace0),
+            )),
+            opt(preceded(
+                keyword("where"),
+                delimited(multispace0, expr, multispace0),
+            )),
+            opt(preceded(
+                tuple((keyword("group"), multispace1, keyword("by"))),
+                comma_separated(expr),
+            )),
+            opt(preceded(
+                keyword("having"),
+                delimited(multispace0, expr, multispace0),
+            )),
+            opt(preceded(
+                tuple((keyword("order"), multispace1, keyword("by"))),
+                comma_separated(ordering_term),
+            )),
+            opt(pair(
+                preceded(keyword("limit"), delimited(multispace0, expr, multispace0)),
+                opt(preceded(
+                    keyword("offset"),
+                    delimited(multispace0, expr, multispace0),
+                )),
+            )),
+        ))(input)?;
+
+    let (limit, offset) = match limit {
+        Some((limit, offset)) => (Some(limit), offset),
+        None => (None, None),
+    };
+    // Without `FROM` there are no sources, and the query evaluates on a single empty row
+    let mut sources = vec![];
+    let mut constraints = vec![];
+    let mut left_joins = vec![];
+    for (i, (source, left_join, constraint)) in from.unwrap_or_default().into_iter().enumerate() {
+        sources.push(source);
+        match left_join {
+            true => left_joins.push(LeftJoin {
+                source: i,
+                constraint,
+            }),
+            false => constraints.extend(constraint),
+        }
+    }
+    // Constraints of inner joins filter the joined rows just like the `WHERE` clause does
+    let filter = constraints
+        .into_iter()
+        .chain(filter)
+        .reduce(|left, right| Expr::Binary(Box::new(left), BinaryOperator::And, Box::new(right)));
+    Ok((
+        input,
+        Select {
+            distinct: distinct.unwrap_or(false),
+            columns,
+            from: sources,
+            left_joins,
+            filter,
+            group_by: group_by.unwrap_or_default(),
+            having,
+            order_by: order_by.unwrap_or_default(),
+            limit,
+            offset,
+        },
+    ))
+}
+
+/// A source of the `FROM` clause, with whether it is left joined and its join constraint
+type JoinedSource = (TableSource, bool, Option<Expr>);
+
+/// Parses the sources of a `FROM` clause, joined by commas or `JOIN`. `RIGHT`, `FULL` and
+/// `NATURAL` joins and `USING` constraints are not supported
+fn from_clause(input: &str) -> IResult<&str, Vec<JoinedSource>> {
+    let (mut input, first) = table_source(input)?;
+    let mut sources = vec![(first, false, None)];
+    loop {
+        let separator = preceded(
+            multispace0,
+            alt((
+                value(false, char(',')),
+                value(
+                    true,
+                    tuple((
+                        keyword("left"),
+                        multispace1,
+                        opt(pair(keyword("outer"), multispace1)),
+                        keyword("join"),
+                    )),
+                ),
+                value(
+                    false,
+                    pair(
+                        opt(pair(alt((keyword("inner"), keyword("cross"))), multispace1)),
+                        keyword("join"),
                     ),
-                    multispace0,
                 ),
+            )),
+        )(input);
+        match separator {
+            Ok((rest, left_join)) => {
+                let (rest, source) = preceded(multispace0, table_source)(rest)?;
+                let (rest, constraint) = opt(preceded(
+                    tuple((multispace1, keyword("on"), multispace0)),
+                    expr,
+                ))(rest)?;
+                sources.push((source, left_join, constraint));
+                input = rest;
+            }
+            Err(Err::Error(_)) => return Ok((input, sources)),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn table_source(input: &str) -> IResult<&str, TableSource> {
+    let alias = || {
+        opt(preceded(
+            pair(multispace1, opt(pair(keyword("as"), multispace1))),
+            identifier,
+        ))
+    };
+    alt((
+        map(
+            tuple((
+                identifier,
+                delimited(
+                    tuple((multispace0, char('('), multispace0)),
+                    opt(comma_separated(expr)),
+                    pair(multispace0, char(')')),
+                ),
+                alias(),
+            )),
+            |(name, args, alias)| TableSource::Function {
+                name,
+                args: args.unwrap_or_default(),
+                alias,
+            },
+        ),
+        map(pair(identifier, alias()), |(name, alias)| {
+            TableSource::Table { name, alias }
+        }),
+    ))(input)
+}
+
+fn result_column(input: &str) -> IResult<&str, ResultColumn> {
+    alt((
+        value(ResultColumn::All, char('*')),
+        map(
+            pair(
+                expr,
+                opt(preceded(
+                    pair(multispace1, opt(pair(keyword("as"), multispace1))),
+                    identifier,
+                )),
             ),
-            preceded(
-                tag_no_case("from"),
-                delimited(multispace0, is_not(" \t\r\n,"), multispace0),
-            ),
+            |(e, alias)| ResultColumn::Expr(e, alias),
+        ),
+    ))(input)
+}
+
+fn ordering_term(input: &str) -> IResult<&str, OrderingTerm> {
+    map(
+        pair(
+            expr,
             opt(preceded(
-                tag_no_case("where"),
-                delimited(
-                    multispace0,
-                    separated_pair(
-                        is_not(" \t\r\n="),
-                        delimited(multispace0, tag("="), multispace0),
+                multispace1,
+                alt((value(false, keyword("asc")), value(true, keyword("desc")))),
+            )),
+        ),
+        |(expr, descending)| OrderingTerm {
+            expr,
+            descending: descending.unwrap_or_default(),
+        },
+    )(input)
+}
+
+/// The rows an `INSERT` statement adds
+#[derive(Debug, Clone)]
+pub enum InsertSource {
+    Values(Vec<Vec<Expr>>),
+    Select(Box<Select>),
+    DefaultValues,
+}
+
+/// An `INSERT` statement, as mentioned here: [insert](https://www.sqlite.org/lang_insert.html)
+#[derive(Debug, Clone)]
+pub struct Insert {
+    pub table: String,
+    /// The columns the values are assigned to, all the columns of the table when empty
+    pub columns: Vec<String>,
+    pub source: InsertSource,
+}
+
+impl Insert {
+    pub fn parse(query: &str) -> Result<Self> {
+        let query = strip_comments(query);
+        let (_, insert) = terminated(insert, end_of_statement)(query.as_str())
+            .map_err(|err: Err<error::Error<&str>>| Error::msg(err.to_string()))?;
+        Ok(insert)
+    }
+}
+
+fn insert(input: &str) -> IResult<&str, Insert> {
+    let values = preceded(
+        pair(keyword("values"), multispace0),
+        comma_separated(parenthesized(comma_separated(expr))),
+    );
+    let (input, ((_, table), columns, source)) = tuple((
+        delimited(
+            tuple((
+                multispace0,
+                keyword("insert"),
+                multispace1,
+                keyword("into"),
+                multispace1,
+            )),
+            qualified_name,
+            multispace0,
+        ),
+        opt(terminated(
+            parenthesized(comma_separated(object_name)),
+            multispace0,
+        )),
+        alt((
+            map(values, InsertSource::Values),
+            map(select, |select| InsertSource::Select(Box::new(select))),
+            value(
+                InsertSource::DefaultValues,
+                tuple((keyword("default"), multispace1, keyword("values"))),
+            ),
+        )),
+    ))(input)?;
+    Ok((
+        input,
+        Insert {
+            table,
+            columns: columns.unwrap_or_default(),
+            source,
+        },
+    ))
+}
+
+/// An `UPDATE` statement, as mentioned here: [update](https://www.sqlite.org/lang_update.html)
+#[derive(Debug, Clone)]
+pub struct Update {
+    pub table: String,
+    /// The columns set by the statement, with the expressions giving their new value
+    pub assignments: Vec<(String, Expr)>,
+    pub filter: Option<Expr>,
+}
+
+impl Update {
+    pub fn parse(query: &str) -> Result<Self> {
+        let query = strip_comments(query);
+        let (_, update) = terminated(update, end_of_statement)(query.as_str())
+            .map_err(|err: Err<error::Error<&str>>| Error::msg(err.to_string()))?;
+        Ok(update)
+    }
+}
+
+fn update(input: &str) -> IResult<&str, Update> {
+    let (input, ((_, table), assignments, filter)) = tuple((
+        delimited(
+            tuple((multispace0, keyword("update"), multispace1)),
+            qualified_name,
+            tuple((multispace1, keyword("set"), multispace0)),
+        ),
+        comma_separated(separated_pair(
+            object_name,
+            tuple((multispace0, char('='), multispace0)),
+            expr,
+        )),
+        opt(preceded(
+            keyword("where"),
+            delimited(multispace0, expr, multispace0),
+        )),
+    ))(input)?;
+    Ok((
+        input,
+        Update {
+            table,
+            assignments,
+            filter,
+        },
+    ))
+}
+
+/// A `DELETE` statement, as mentioned here: [delete](https://www.sqlite.org/lang_delete.html)
+#[derive(Debug, Clone)]
+pub struct Delete {
+    pub table: String,
+    pub filter: Option<Expr>,
+}
+
+impl Delete {
+    pub fn parse(query: &str) -> Result<Self> {
+        let query = strip_comments(query);
+        let (_, delete) = terminated(delete, end_of_statement)(query.as_str())
+            .map_err(|err: Err<error::Error<&str>>| Error::msg(err.to_string()))?;
+        Ok(delete)
+    }
+}
+
+fn delete(input: &str) -> IResult<&str, Delete> {
+    let (input, ((_, table), filter)) = pair(
+        delimited(
+            tuple((
+                multispace0,
+                keyword("delete"),
+                multispace1,
+                keyword("from"),
+                multispace1,
+            )),
+            qualified_name,
+            multispace0,
+        ),
+        opt(preceded(
+            keyword("where"),
+            delimited(multispace0, expr, multispace0),
+        )),
+    )(input)?;
+    Ok((input, Delete { table, filter }))
+}
+
+/// The kind of object a `DROP` statement removes
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DropKind {
+    Table,
+    Index,
+    View,
+    Trigger,
+}
+
+impl DropKind {
+    /// Returns the kind as named in `sqlite_schema`
+    pub fn name(&self) -> &'static str {
+        match self {
+            DropKind::Table => "table",
+            DropKind::Index => "index",
+            DropKind::View => "view",
+            DropKind::Trigger => "trigger",
+        }
+    }
+}
+
+/// A `DROP` statement, as mentioned here:
+/// [drop table](https://www.sqlite.org/lang_droptable.html)
+#[derive(Debug, Clone)]
+pub struct DropStatement {
+    pub kind: DropKind,
+    pub name: String,
+    pub if_exists: bool,
+}
+
+impl DropStatement {
+    pub fn parse(query: &str) -> Result<Self> {
+        let query = strip_comments(query);
+        let (_, drop) = terminated(drop_statement, end_of_statement)(query.as_str())
+            .map_err(|err: Err<error::Error<&str>>| Error::msg(err.to_string()))?;
+        Ok(drop)
+    }
+}
+
+fn drop_statement(input: &str) -> IResult<&str, DropStatement> {
+    let (input, (kind, if_exists, (_, name))) = tuple((
+        delimited(
+            tuple((multispace0, keyword("drop"), multispace1)),
+            alt((
+                value(DropKind::Table, keyword("table")),
+                value(DropKind::Index, keyword("index")),
+                value(DropKind::View, keyword("view")),
+                value(DropKind::Trigger, keyword("trigger")),
+            )),
+            multispace1,
+        ),
+        map(
+            opt(tuple((
+                keyword("if"),
+                multispace1,
+                keyword("exists"),
+                multispace1,
+            ))),
+            |if_exists| if_exists.is_some(),
+        ),
+        qualified_name,
+    ))(input)?;
+    Ok((
+        input,
+        DropStatement {
+            kind,
+            name,
+            if_exists,
+        },
+    ))
+}
+
+/// A statement controlling transactions, as mentioned here:
+/// [transactions](https://www.sqlite.org/lang_transaction.html) and
+/// [savepoints](https://www.sqlite.org/lang_savepoint.html)
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Transaction {
+    Begin,
+    Commit,
+    Rollback,
+    Savepoint(String),
+    Release(String),
+    RollbackTo(String),
+}
+
+impl Transaction {
+    pub fn parse(query: &str) -> Result<Self> {
+        let query = strip_comments(query);
+        let (_, transaction) = terminated(transaction, end_of_statement)(query.as_str())
+            .map_err(|err: Err<error::Error<&str>>| Error::msg(err.to_string()))?;
+        Ok(transaction)
+    }
+}
+
+fn transaction(input: &str) -> IResult<&str, Transaction> {
+    let optional_transaction = || opt(preceded(multispace1, keyword("transaction")));
+    let savepoint_name = || preceded(opt(pair(keyword("savepoint"), multispace1)), object_name);
+    preceded(
+        multispace0,
+        alt((
+            value(
+                Transaction::Begin,
+                tuple((
+                    keyword("begin"),
+                    opt(preceded(
+                        multispace1,
                         alt((
-                            double.map(Value::F),
-                            delimited(tag("'"), is_not("'"), tag("'"))
-                                .map(|v: &str| Value::Text(v.to_string())),
+                            keyword("deferred"),
+                            keyword("immediate"),
+                            keyword("exclusive"),
                         )),
-                    ),
-                    multispace0,
+                    )),
+                    optional_transaction(),
+                )),
+            ),
+            value(
+                Transaction::Commit,
+                pair(
+                    alt((keyword("commit"), keyword("end"))),
+                    optional_transaction(),
+                ),
+            ),
+            map(
+                preceded(
+                    pair(keyword("rollback"), optional_transaction()),
+                    opt(preceded(
+                        tuple((multispace1, keyword("to"), multispace1)),
+                        savepoint_name(),
+                    )),
                 ),
+                |name| match name {
+                    Some(name) => Transaction::RollbackTo(name),
+                    None => Transaction::Rollback,
+                },
+            ),
+            map(
+                preceded(pair(keyword("savepoint"), multispace1), object_name),
+                Transaction::Savepoint,
+            ),
+            map(
+                preceded(pair(keyword("release"), multispace1), savepoint_name()),
+                Transaction::Release,
+            ),
+        )),
+    )(input)
+}
+
+/// A `PRAGMA wal_checkpoint` statement, as mentioned here:
+/// [wal_checkpoint](https://www.sqlite.org/pragma.html#pragma_wal_checkpoint)
+#[derive(Debug, Clone, Copy)]
+pub struct WalCheckpoint {
+    pub mode: CheckpointMode,
+}
+
+impl WalCheckpoint {
+    pub fn parse(query: &str) -> Result<Self> {
+        let query = strip_comments(query);
+        let (_, checkpoint) = terminated(wal_checkpoint, end_of_statement)(query.as_str())
+            .map_err(|err: Err<error::Error<&str>>| Error::msg(err.to_string()))?;
+        Ok(checkpoint)
+    }
+}
+
+fn wal_checkpoint(input: &str) -> IResult<&str, WalCheckpoint> {
+    let mode = || {
+        alt((
+            value(CheckpointMode::Passive, keyword("passive")),
+            value(CheckpointMode::Full, keyword("full")),
+            value(CheckpointMode::Restart, keyword("restart")),
+            value(CheckpointMode::Truncate, keyword("truncate")),
+        ))
+    };
+    let (input, mode) = preceded(
+        tuple((
+            multispace0,
+            keyword("pragma"),
+            multispace1,
+            opt(terminated(
+                object_name,
+                delimited(multispace0, char('.'), multispace0),
             )),
-        ))(query)
-        .map_err(|err: Err<error::Error<&str>>| Error::msg(err.to_string()))?;
-        Ok(Self {
-            columns,
-            table,
-            filter,
-        })
+            keyword("wal_checkpoint"),
+            multispace0,
+        )),
+        opt(alt((
+            parenthesized(mode()),
+            preceded(pair(char('='), multispace0), mode()),
+        ))),
+    )(input)?;
+    Ok((
+        input,
+        WalCheckpoint {
+            mode: mode.unwrap_or(CheckpointMode::Passive),
+        },
+    ))
+}
+
+fn end_of_statement(input: &str) -> IResult<&str, ()> {
+    value((), tuple((multispace0, opt(tag(";")), multispace0, eof)))(input)
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Matches a keyword case-insensitively, making sure it is not the prefix of a longer word
+fn keyword<'a>(kw: &'static str) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+    terminated(tag_no_case(kw), not(satisfy(is_identifier_char)))
+}
+
+/// Matches an operator that must not be followed by any of `excluded`, e.g. `<` but not `<<`
+fn operator<'a>(
+    op: &'static str,
+    excluded: &'static str,
+) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+    terminated(tag(op), not(one_of(excluded)))
+}
+
+fn identifier(input: &str) -> IResult<&str, String> {
+    alt((
+        quoted('"', '"'),
+        quoted('`', '`'),
+        map(delimited(char('['), is_not("]"), char(']')), String::from),
+        bare_identifier,
+    ))(input)
+}
+
+fn bare_identifier(input: &str) -> IResult<&str, String> {
+    let (rest, name) = recognize(pair(
+        satisfy(|c| c.is_alphabetic() || c == '_'),
+        take_while(is_identifier_char),
+    ))(input)?;
+    if RESERVED_WORDS.iter().any(|w| w.eq_ignore_ascii_case(name)) {
+        return Err(Err::Error(error::Error::new(input, error::ErrorKind::Tag)));
     }
+    Ok((rest, name.to_string()))
 }
 
-#[derive(Debug)]
+/// Parses text enclosed in `open` and `close`, where a doubled `close` escapes itself
+fn quoted<'a>(open: char, close: char) -> impl FnMut(&'a str) -> IResult<&'a str, String> {
+    move |input: &'a str| {
+        let (mut rest, _) = char(open)(input)?;
+        let mut text = String::new();
+        loop {
+            let end = rest
+                .find(close)
+                .ok_or_else(|| Err::Error(error::Error::new(rest, error::ErrorKind::Char)))?;
+            text.push_str(&rest[..end]);
+            rest = &rest[end + close.len_utf8()..];
+            if rest.starts_with(close) {
+                text.push(close);
+                rest = &rest[close.len_utf8()..];
+            } else {
+                return Ok((rest, text));
+            }
+        }
+    }
+}
+
+/// Parses a type name such as `INTEGER`, `VARCHAR(255)` or `UNSIGNED BIG INT`
+fn type_name(input: &str) -> IResult<&str, String> {
+    let type_word = |input| {
+        let (rest, word) = alt((quoted('"', '"'), bare_identifier))(input)?;
+        if TYPE_NAME_TERMINATORS
+            .iter()
+            .any(|w| w.eq_ignore_ascii_case(&word))
+        {
+            return Err(Err::Error(error::Error::new(input, error::ErrorKind::Tag)));
+        }
+        Ok((rest, word))
+    };
+    let signed_number = || {
+        delimited(
+            multispace0,
+            recognize(pair(opt(one_of("+-")), number_literal)),
+            multispace0,
+        )
+    };
+    map(
+        recognize(pair(
+            separated_list1(multispace1, type_word),
+            opt(preceded(
+                multispace0,
+                delimited(
+                    char('('),
+                    pair(signed_number(), opt(preceded(char(','), signed_number()))),
+                    char(')'),
+                ),
+            )),
+        )),
+        String::from,
+    )(input)
+}
+
+/// Words that may follow a type name without being part of it
+const TYPE_NAME_TERMINATORS: &[&str] = &[
+    "as",
+    "check",
+    "collate",
+    "constraint",
+    "default",
+    "generated",
+    "not",
+    "null",
+    "primary",
+    "references",
+    "unique",
+];
+
+fn expr(input: &str) -> IResult<&str, Expr> {
+    binary_chain(input, and_expr, value(BinaryOperator::Or, keyword("or")))
+}
+
+fn and_expr(input: &str) -> IResult<&str, Expr> {
+    binary_chain(input, not_expr, value(BinaryOperator::And, keyword("and")))
+}
+
+fn not_expr(input: &str) -> IResult<&str, Expr> {
+    alt((
+        map(preceded(pair(keyword("not"), multispace0), not_expr), |e| {
+            Expr::Unary(UnaryOperator::Not, Box::new(e))
+        }),
+        equality_expr,
+    ))(input)
+}
+
+fn equality_expr(input: &str) -> IResult<&str, Expr> {
+    let (mut input, mut left) = comparison_expr(input)?;
+    let is_null = |operator| {
+        move |left| {
+            Expr::Binary(
+                Box::new(left),
+                operator,
+                Box::new(Expr::Literal(Value::Null)),
+            )
+        }
+    };
+    loop {
+        let postfix = preceded(
+            multispace0,
+            alt((
+                value(BinaryOperator::Is, keyword("isnull")),
+                value(BinaryOperator::IsNot, keyword("notnull")),
+                value(
+                    BinaryOperator::IsNot,
+                    tuple((keyword("not"), multispace1, keyword("null"))),
+                ),
+            )),
+        )(input);
+        if let Ok((rest, operator)) = postfix {
+            left = is_null(operator)(left);
+            input = rest;
+            continue;
+        }
+        let rewritten = preceded(multispace0, |i| rewritten_operator(i, &left))(input);
+        if let Ok((rest, expr)) = rewritten {
+            left = expr;
+            input = rest;
+            continue;
+        }
+
+        let operator = preceded(
+            multispace0,
+            alt((
+                value(BinaryOperator::Eq, tag("==")),
+                value(BinaryOperator::Eq, tag("=")),
+                value(BinaryOperator::NotEq, tag("!=")),
+                value(BinaryOperator::NotEq, tag("<>")),
+                value(
+                    BinaryOperator::IsNot,
+                    tuple((keyword("is"), multispace1, keyword("not"))),
+                ),
+                value(BinaryOperator::Is, keyword("is")),
+            )),
+        )(input);
+        match operator {
+            Ok((rest, operator)) => {
+                let (rest, right) = preceded(multispace0, comparison_expr)(rest)?;
+                left = Expr::Binary(Box::new(left), operator, Box::new(right));
+                input = rest;
+            }
+            Err(Err::Error(_)) => return Ok((input, left)),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Parses the operators of the equality level that are rewritten into other expressions:
+/// `[NOT] IN` with a list of values into comparisons, `[NOT] BETWEEN` into a range, and
+/// `[NOT] LIKE` and `[NOT] GLOB` into calls of the `like()` and `glob()` functions, as
+/// mentioned here: [like](https://www.sqlite.org/lang_expr.html#like)
+fn rewritten_operator<'a>(input: &'a str, left: &Expr) -> IResult<&'a str, Expr> {
+    let binary = |op, right: Expr| Expr::Binary(Box::new(left.clone()), op, Box::new(right));
+    let (input, negated) = opt(pair(keyword("not"), multispace1))(input)?;
+    let (input, expr) = alt((
+        map(
+            preceded(
+                pair(keyword("in"), multispace0),
+                parenthesized(opt(comma_separated(expr))),
+            ),
+            |list| {
+                // An empty list holds no value, not even NULL
+                list.unwrap_or_default()
+                    .into_iter()
+                    .map(|value| binary(BinaryOperator::Eq, value))
+                    .reduce(|left, right| {
+                        Expr::Binary(Box::new(left), BinaryOperator::Or, Box::new(right))
+                    })
+                    .unwrap_or(Expr::Literal(Value::Integer(0)))
+            },
+        ),
+        map(
+            tuple((
+                pair(keyword("between"), multispace0),
+                comparison_expr,
+                tuple((multispace0, keyword("and"), multispace0)),
+                comparison_expr,
+            )),
+            |(_, low, _, high)| {
+                Expr::Binary(
+                    Box::new(binary(BinaryOperator::GtEq, low)),
+                    BinaryOperator::And,
+                    Box::new(binary(BinaryOperator::LtEq, high)),
+                )
+            },
+        ),
+        map(
+            tuple((
+                pair(keyword("like"), multispace0),
+                comparison_expr,
+                opt(preceded(
+                    tuple((multispace1, keyword("escape"), multispace0)),
+                    comparison_expr,
+                )),
+            )),
+            |(_, pattern, escape)| Expr::Function {
+                name: "like".to_string(),
+                args: vec![pattern, left.clone()]
+                    .into_iter()
+                    .chain(escape)
+                    .collect(),
+                distinct: false,
+            },
+        ),
+        map(
+            preceded(pair(keyword("glob"), multispace0), comparison_expr),
+            |pattern| Expr::Function {
+                name: "glob".to_string(),
+                args: vec![pattern, left.clone()],
+                distinct: false,
+            },
+        ),
+    ))(input)?;
+    let expr = match negated {
+        Some(_) => Expr::Unary(UnaryOperator::Not, Box::new(expr)),
+        None => expr,
+    };
+    Ok((input, expr))
+}
+
+fn comparison_expr(input: &str) -> IResult<&str, Expr> {
+    binary_chain(
+        input,
+        bitwise_expr,
+        alt((
+            value(BinaryOperator::LtEq, tag("<=")),
+            value(BinaryOperator::GtEq, tag(">=")),
+            value(BinaryOperator::Lt, operator("<", "<>")),
+            value(BinaryOperator::Gt, operator(">", ">")),
+        )),
+    )
+}
+
+fn bitwise_expr(input: &str) -> IResult<&str, Expr> {
+    binary_chain(
+        input,
+        additive_expr,
+        alt((
+            value(BinaryOperator::ShiftLeft, tag("<<")),
+            value(BinaryOperator::ShiftRight, tag(">>")),
+            value(BinaryOperator::BitAnd, tag("&")),
+            value(BinaryOperator::BitOr, operator("|", "|")),
+        )),
+    )
+}
+
+fn additive_expr(input: &str) -> IResult<&str, Expr> {
+    binary_chain(
+        input,
+        multiplicative_expr,
+        alt((
+            value(BinaryOperator::Add, tag("+")),
+            value(BinaryOperator::Subtract, operator("-", ">")),
+        )),
+    )
+}
+
+fn multiplicative_expr(input: &str) -> IResult<&str, Expr> {
+    binary_chain(
+        input,
+        concat_expr,
+        alt((
+            value(BinaryOperator::Multiply, tag("*")),
+            value(BinaryOperator::Divide, tag("/")),
+            value(BinaryOperator::Modulo, tag("%")),
+        )),
+    )
+}
+
+fn concat_expr(input: &str) -> IResult<&str, Expr> {
+    binary_chain(
+        input,
+        unary_expr,
+        alt((
+            value(BinaryOperator::Concat, tag("||")),
+            value(BinaryOperator::ExtractValue, tag("->>")),
+            value(BinaryOperator::Extract, tag("->")),
+        )),
+    )
+}
+
+fn unary_expr(input: &str) -> IResult<&str, Expr> {
+    alt((
+        // The smallest integer is only reachable by negating a literal that is too large for one
+        value(
+            Expr::Literal(Value::Integer(i64::MIN)),
+            verify(
+                preceded(pair(char('-'), multispace0), number_literal),
+                |n: &str| n == "9223372036854775808",
+            ),
+        ),
+        map(
+            pair(
+                alt((
+                    value(UnaryOperator::Negate, char('-')),
+                    value(UnaryOperator::Plus, char('+')),
+                    value(UnaryOperator::BitNot, char('~')),
+                )),
+                preceded(multispace0, unary_expr),
+            ),
+            |(operator, e)| Expr::Unary(operator, Box::new(e)),
+        ),
+        collate_expr,
+    ))(input)
+}
+
+fn collate_expr(input: &str) -> IResult<&str, Expr> {
+    let (mut input, mut expr) = primary_expr(input)?;
+    while let Ok((rest, collation)) = collation(input) {
+        expr = Expr::Collate(Box::new(expr), collation);
+        input = rest;
+    }
+    Ok((input, expr))
+}
+
+/// Parses a `COLLATE` clause, preceded by at least one space, into the name of the collation
+fn collation(input: &str) -> IResult<&str, String> {
+    preceded(
+        tuple((multispace1, keyword("collate"), multispace1)),
+        identifier,
+    )(input)
+}
+
+fn primary_expr(input: &str) -> IResult<&str, Expr> {
+    alt((
+        map(literal, Expr::Literal),
+        case_expr,
+        cast_expr,
+        current_time,
+        function_call,
+        map(
+            pair(identifier, opt(preceded(char('.'), identifier))),
+            |(first, second)| match second {
+                Some(column) => Expr::Column(format!("{}.{}", first, column)),
+                None => Expr::Column(first),
+            },
+        ),
+        delimited(
+            pair(char('('), multispace0),
+            expr,
+            pair(multispace0, char(')')),
+        ),
+    ))(input)
+}
+
+/// Parses `CURRENT_TIME`, `CURRENT_DATE` or `CURRENT_TIMESTAMP` into a call of the matching date
+/// and time function
+fn current_time(input: &str) -> IResult<&str, Expr> {
+    map(
+        alt((
+            value("datetime", keyword("current_timestamp")),
+            value("time", keyword("current_time")),
+            value("date", keyword("current_date")),
+        )),
+        |function| Expr::Function {
+            name: function.to_string(),
+            args: vec![Expr::Literal(Value::Text("now".to_string()))],
+            distinct: false,
+        },
+    )(input)
+}
+
+fn literal(input: &str) -> IResult<&str, Value> {
+    alt((
+        map(number_literal, |n: &str| {
+            if n.contains(['.', 'e', 'E']) {
+                Value::F(n.parse().unwrap())
+            } else {
+                n.parse()
+                    .map(Value::Integer)
+                    .unwrap_or_else(|_| Value::F(n.parse().unwrap()))
+            }
+        }),
+        map(quoted('\'', '\''), Value::Text),
+        map(preceded(one_of("xX"), quoted('\'', '\'')), |hex: String| {
+            Value::Blob(
+                (0..hex.len() / 2)
+                    .filter_map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok())
+                    .collect(),
+            )
+        }),
+        value(Value::Null, keyword("null")),
+        value(Value::Integer(1), keyword("true")),
+        value(Value::Integer(0), keyword("false")),
+    ))(input)
+}
+
+fn number_literal(input: &str) -> IResult<&str, &str> {
+    recognize(tuple((
+        alt((
+            recognize(pair(digit1, opt(pair(char('.'), digit0)))),
+            recognize(pair(char('.'), digit1)),
+        )),
+        opt(tuple((one_of("eE"), opt(one_of("+-")), digit1))),
+    )))(input)
+}
+
+fn case_expr(input: &str) -> IResult<&str, Expr> {
+    let (input, (operand, branches, otherwise)) = delimited(
+        keyword("case"),
+        tuple((
+            opt(preceded(multispace0, expr)),
+            many1(preceded(
+                multispace0,
+                separated_pair(
+                    preceded(pair(keyword("when"), multispace0), expr),
+                    delimited(multispace0, keyword("then"), multispace0),
+                    expr,
+                ),
+            )),
+            opt(preceded(
+                tuple((multispace0, keyword("else"), multispace0)),
+                expr,
+            )),
+        )),
+        pair(multispace0, keyword("end")),
+    )(input)?;
+    Ok((
+        input,
+        Expr::Case {
+            operand: operand.map(Box::new),
+            branches,
+            otherwise: otherwise.map(Box::new),
+        },
+    ))
+}
+
+fn cast_expr(input: &str) -> IResult<&str, Expr> {
+    map(
+        preceded(
+            pair(keyword("cast"), multispace0),
+            delimited(
+                pair(char('('), multispace0),
+                separated_pair(
+                    expr,
+                    delimited(multispace1, keyword("as"), multispace1),
+                    type_name,
+                ),
+                pair(multispace0, char(')')),
+            ),
+        ),
+        |(e, type_name): (Expr, String)| {
+            Expr::Cast(Box::new(e), Affinity::from_type_name(&type_name))
+        },
+    )(input)
+}
+
+fn function_call(input: &str) -> IResult<&str, Expr> {
+    map(
+        pair(
+            terminated(
+                take_while1(is_identifier_char),
+                pair(multispace0, char('(')),
+            ),
+            terminated(
+                alt((
+                    value(
+                        (false, Some(vec![])),
+                        delimited(multispace0, char('*'), multispace0),
+                    ),
+                    pair(
+                        map(
+                            opt(preceded(
+                                multispace0,
+                                alt((keyword("distinct"), keyword("all"))),
+                            )),
+                            |quantifier| {
+                                quantifier.is_some_and(|q| q.eq_ignore_ascii_case("distinct"))
+                            },
+                        ),
+                        opt(separated_list1(
+                            char(','),
+                            delimited(multispace0, expr, multispace0),
+                        )),
+                    ),
+                )),
+                pair(multispace0, char(')')),
+            ),
+        ),
+        |(name, (distinct, args)): (&str, _)| Expr::Function {
+            name: name.to_string(),
+            args: args.unwrap_or_default(),
+            distinct,
+        },
+    )(input)
+}
+
+/// Parses a non-empty list of items separated by commas
+fn comma_separated<'a, O>(
+    item: impl FnMut(&'a str) -> IResult<&'a str, O>,
+) -> impl FnMut(&'a str) -> IResult<&'a str, Vec<O>> {
+    separated_list1(char(','), delimited(multispace0, item, multispace0))
+}
+
+/// Parses `operand (operator operand)*` into left-associative binary expressions
+fn binary_chain<'a>(
+    input: &'a str,
+    mut operand: impl FnMut(&'a str) -> IResult<&'a str, Expr>,
+    mut operator: impl Parser<&'a str, BinaryOperator, error::Error<&'a str>>,
+) -> IResult<&'a str, Expr> {
+    let (mut input, mut left) = operand(input)?;
+    loop {
+        match preceded(multispace0, |i| operator.parse(i))(input) {
+            Ok((rest, op)) => {
+                let (rest, right) = preceded(multispace0, &mut operand)(rest)?;
+                left = Expr::Binary(Box::new(left), op, Box::new(right));
+                input = rest;
+            }
+            Err(Err::Error(_)) => return Ok((input, left)),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// A column definition of a `CREATE TABLE` statement
+#[derive(Debug, Clone)]
 pub struct Column {
     pub name: String,
     pub data_type: Option<String>,
-    pub is_primary_key: bool,
+    pub constraints: Vec<ColumnConstraint>,
+}
+
+impl Column {
+    /// Returns the affinity given by the declared type of the column
+    pub fn affinity(&self) -> Affinity {
+        Affinity::from_type_name(self.data_type.as_deref().unwrap_or_default())
+    }
+
+    pub fn is_primary_key(&self) -> bool {
+        self.constraints
+            .iter()
+            .any(|c| matches!(c, ColumnConstraint::PrimaryKey { .. }))
+    }
+
+    pub fn collation(&self) -> Option<&str> {
+        self.constraints.iter().find_map(|c| match c {
+            ColumnConstraint::Collate(collation) => Some(collation.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn default_value(&self) -> Option<&Expr> {
+        self.constraints.iter().find_map(|c| match c {
+            ColumnConstraint::Default(value) => Some(value),
+            _ => None,
+        })
+    }
+
+    /// Returns the expression of a generated column, telling whether the column is STORED
+    pub fn generated(&self) -> Option<(&Expr, bool)> {
+        self.constraints.iter().find_map(|c| match c {
+            ColumnConstraint::Generated { expr, stored } => Some((expr, *stored)),
+            _ => None,
+        })
+    }
+}
+
+/// The algorithm resolving a violated constraint, as mentioned here:
+/// [on conflict](https://www.sqlite.org/lang_conflict.html)
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ConflictResolution {
+    Rollback,
+    Abort,
+    Fail,
+    Ignore,
+    Replace,
+}
+
+/// A constraint of a column definition, as mentioned here:
+/// [column-constraint](https://www.sqlite.org/syntax/column-constraint.html)
+#[derive(Debug, Clone)]
+pub enum ColumnConstraint {
+    PrimaryKey {
+        descending: bool,
+        on_conflict: Option<ConflictResolution>,
+        autoincrement: bool,
+    },
+    NotNull(Option<ConflictResolution>),
+    Null,
+    Unique(Option<ConflictResolution>),
+    Check(Check),
+    Default(Expr),
+    Collate(String),
+    References(ForeignKey),
+    Generated {
+        expr: Expr,
+        stored: bool,
+    },
+}
+
+/// A constraint following the column definitions, as mentioned here:
+/// [table-constraint](https://www.sqlite.org/syntax/table-constraint.html)
+#[derive(Debug, Clone)]
+pub enum TableConstraint {
+    PrimaryKey {
+        columns: Vec<IndexedColumn>,
+        on_conflict: Option<ConflictResolution>,
+    },
+    Unique {
+        columns: Vec<IndexedColumn>,
+        on_conflict: Option<ConflictResolution>,
+    },
+    Check(Check),
+    ForeignKey {
+        columns: Vec<String>,
+        references: ForeignKey,
+    },
+}
+
+/// A `CHECK` constraint, as mentioned here:
+/// [check constraints](https://www.sqlite.org/lang_createtable.html#check_constraints)
+#[derive(Debug, Clone)]
+pub struct Check {
+    /// The name a violation is reported with: that of the constraint, or else the text of its
+    /// expression
+    pub name: String,
+    pub expr: Expr,
+}
+
+/// The parent key of a foreign key constraint, as mentioned here:
+/// [foreign-key-clause](https://www.sqlite.org/syntax/foreign-key-clause.html)
+#[derive(Debug, Clone)]
+pub struct ForeignKey {
+    pub table: String,
+    pub columns: Vec<String>,
+    pub on_delete: Option<ForeignKeyAction>,
+    pub on_update: Option<ForeignKeyAction>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ForeignKeyAction {
+    SetNull,
+    SetDefault,
+    Cascade,
+    Restrict,
+    NoAction,
 }
 
 #[derive(Debug)]
 pub enum CreateStatement {
     CreateTable {
+        schema: Option<String>,
         name: String,
+        if_not_exists: bool,
         columns: Vec<Column>,
+        constraints: Vec<TableConstraint>,
+        without_rowid: bool,
+        strict: bool,
     },
     CreateIndex {
+        schema: Option<String>,
         name: String,
         table: String,
+        if_not_exists: bool,
+        unique: bool,
+        columns: Vec<IndexedColumn>,
+        /// The condition of a partial index, which only holds the rows meeting it
+        filter: Option<Expr>,
+    },
+    CreateView {
+        schema: Option<String>,
+        name: String,
+        if_not_exists: bool,
+        /// The names given to the result columns of the query, if any
         columns: Vec<String>,
+        /// The query of the view, unless it uses SQL this crate cannot run, such as compound
+        /// selects or common table expressions, in which case only querying the view fails
+        select: Option<Box<Select>>,
+    },
+    CreateTrigger {
+        schema: Option<String>,
+        name: String,
+        if_not_exists: bool,
+        timing: TriggerTiming,
+        event: TriggerEvent,
+        table: String,
+        when: Option<Expr>,
+        /// The statements between `BEGIN` and `END`, kept as text
+        body: String,
     },
+    /// A statement of another kind, such as `CREATE VIRTUAL TABLE`, kept as text
+    Other(String),
+    /// A statement that could not be parsed, kept as text with the parsing error, so that only
+    /// the statements using its object fail
+    Unparsed { sql: String, error: String },
+}
+
+/// When a trigger fires relative to the statement that fires it
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TriggerTiming {
+    Before,
+    After,
+    InsteadOf,
+}
+
+/// The statement that fires a trigger, with the columns an `UPDATE OF` is restricted to
+#[derive(Debug, Clone)]
+pub enum TriggerEvent {
+    Delete,
+    Insert,
+    Update(Vec<String>),
+}
+
+/// A column of an index or of a `PRIMARY KEY` or `UNIQUE` table constraint, which indexes may
+/// also give as an expression. It is ordered by its own collation when one is given, and by its
+/// column's otherwise
+#[derive(Debug, Clone)]
+pub struct IndexedColumn {
+    pub expr: Expr,
+    pub collation: Option<String>,
+    pub descending: bool,
+}
+
+impl IndexedColumn {
+    /// Returns the name of the column, unless it is an expression
+    pub fn name(&self) -> Option<&str> {
+        match &self.expr {
+            Expr::Column(name) => Some(name),
+            _ => None,
+        }
+    }
 }
 
 impl CreateStatement {
+    /// Parses a statement of the schema. Statements that do not create a table, an index, a
+    /// view or a trigger are kept as text
     pub fn parse(query: String) -> Result<Self> {
-        if query.to_lowercase().starts_with("create table") {
-            Self::parse_create_table(query)
-        } else {
-            Self::parse_create_index(query)
+        let stripped = strip_comments(&query);
+        let result = terminated(
+            alt((create_table, create_index, create_view, create_trigger)),
+            end_of_statement,
+        )(stripped.as_str());
+        match result {
+            Ok((_, statement)) => Ok(statement),
+            Err(err) => match create_prefix(known_kind)(stripped.as_str()) {
+                Ok(_) => Err(Error::msg(err.to_string())),
+                Err(_) => Ok(CreateStatement::Other(query)),
+            },
         }
     }
-    pub fn parse_create_index(query: String) -> Result<Self> {
-        let (_, (name, table, columns)) = tuple((
-            preceded(
-                tag_no_case("create index "),
-                terminated(is_not(" \t\r\n,)("), multispace0),
-            ),
-            preceded(
-                tag_no_case("on "),
-                terminated(is_not(" \t\r\n,)("), multispace0),
-            ),
-            delimited(
-                terminated(tag("("), multispace0),
-                separated_list1(
-                    delimited(multispace0, tag(","), multispace0),
-                    is_not(" \t\r\n,)("),
+
+    /// Returns the text SQLite stores for the statement in `sqlite_schema`: the keywords before
+    /// the name of the object are normalized, without `IF NOT EXISTS` nor the name of the schema,
+    /// and the rest is kept as written, as mentioned here:
+    /// [schema table](https://www.sqlite.org/schematab.html#interpretation_of_the_schema_table)
+    pub fn schema_text(&self, query: &str) -> Result<String> {
+        let kind = match self {
+            CreateStatement::CreateTable { .. } => "TABLE",
+            CreateStatement::CreateIndex { unique: true, .. } => "UNIQUE INDEX",
+            CreateStatement::CreateIndex { .. } => "INDEX",
+            CreateStatement::CreateView { .. } => "VIEW",
+            CreateStatement::CreateTrigger { .. } => "TRIGGER",
+            CreateStatement::Other(_) | CreateStatement::Unparsed { .. } => {
+                bail!("Unsupported statement: {}", query)
+            }
+        };
+        // The text is kept with its comments, unless they come before the name of the object
+        let stripped = strip_comments(query);
+        let mut definition = preceded(
+            create_prefix(known_kind),
+            opt(terminated(
+                object_name,
+                delimited(multispace0, char('.'), multispace0),
+            )),
+        );
+        let (rest, _) = definition(query)
+            .or_else(|_| definition(stripped.as_str()))
+            .map_err(|err| Error::msg(err.to_string()))?;
+        let rest = rest.trim_end().trim_end_matches(';').trim_end();
+        Ok(format!("CREATE {} {}", kind, rest))
+    }
+}
+
+/// Parses the kinds of objects the schema keeps statements for
+fn known_kind(input: &str) -> IResult<&str, &str> {
+    alt((
+        keyword("table"),
+        recognize(pair(
+            opt(pair(keyword("unique"), multispace1)),
+            keyword("index"),
+        )),
+        keyword("view"),
+        keyword("trigger"),
+    ))(input)
+}
+
+/// Replaces the comments of a statement by spaces, leaving string literals and quoted identifiers
+/// untouched
+fn strip_comments(sql: &str) -> String {
+    let mut stripped = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    let mut closing_quote = None;
+    while let Some(c) = chars.next() {
+        match (closing_quote, c) {
+            (Some(close), c) => {
+                if c == close {
+                    closing_quote = None;
+                }
+                stripped.push(c);
+            }
+            (None, '-') if chars.peek() == Some(&'-') => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+                stripped.push(' ');
+            }
+            (None, '/') if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = None;
+                for c in chars.by_ref() {
+                    if previous == Some('*') && c == '/' {
+                        break;
+                    }
+                    previous = Some(c);
+                }
+                stripped.push(' ');
+            }
+            (None, c) => {
+                closing_quote = match c {
+                    '\'' | '"' | '`' => Some(c),
+                    '[' => Some(']'),
+                    _ => None,
+                };
+                stripped.push(c);
+            }
+        }
+    }
+    stripped
+}
+
+/// Parses `CREATE [TEMP] <kind> [IF NOT EXISTS]`, telling whether `IF NOT EXISTS` is present
+fn create_prefix<'a, O>(
+    kind: impl FnMut(&'a str) -> IResult<&'a str, O>,
+) -> impl FnMut(&'a str) -> IResult<&'a str, (O, bool)> {
+    pair(
+        delimited(
+            tuple((
+                multispace0,
+                keyword("create"),
+                multispace1,
+                opt(pair(
+                    alt((keyword("temporary"), keyword("temp"))),
+                    multispace1,
+                )),
+            )),
+            kind,
+            multispace0,
+        ),
+        map(
+            opt(tuple((
+                keyword("if"),
+                multispace1,
+                keyword("not"),
+                multispace1,
+                keyword("exists"),
+                multispace0,
+            ))),
+            |if_not_exists| if_not_exists.is_some(),
+        ),
+    )
+}
+
+/// Parses the name of a table, index or column, which may also be a string literal
+fn object_name(input: &str) -> IResult<&str, String> {
+    alt((identifier, quoted('\'', '\'')))(input)
+}
+
+/// Parses a name that may be qualified by the name of a schema, such as `main.apples`
+fn qualified_name(input: &str) -> IResult<&str, (Option<String>, String)> {
+    pair(
+        opt(terminated(
+            object_name,
+            delimited(multispace0, char('.'), multispace0),
+        )),
+        object_name,
+    )(input)
+}
+
+fn parenthesized<'a, O>(
+    item: impl FnMut(&'a str) -> IResult<&'a str, O>,
+) -> impl FnMut(&'a str) -> IResult<&'a str, O> {
+    delimited(
+        pair(char('('), multispace0),
+        item,
+        pair(multispace0, char(')')),
+    )
+}
+
+/// Parses a `CREATE TABLE` statement, as mentioned here:
+/// [create table](https://www.sqlite.org/lang_createtable.html)
+fn create_table(input: &str) -> IResult<&str, CreateStatement> {
+    enum Definition {
+        Column(Column),
+        Constraint(TableConstraint),
+    }
+
+    let (input, (if_not_exists, (schema, name), definitions, options)) = tuple((
+        map(create_prefix(keyword("table")), |(_, if_not_exists)| {
+            if_not_exists
+        }),
+        qualified_name,
+        preceded(
+            multispace0,
+            parenthesized(comma_separated(alt((
+                map(table_constraint, Definition::Constraint),
+                map(column_definition, Definition::Column),
+            )))),
+        ),
+        opt(preceded(
+            multispace0,
+            comma_separated(alt((
+                value(
+                    (true, false),
+                    tuple((keyword("without"), multispace1, keyword("rowid"))),
                 ),
-                terminated(tag(")"), multispace0),
-            ),
-        ))(&*query)
-        .map_err(|err: Err<error::Error<&str>>| Error::msg(err.to_string()))?;
-        Ok(CreateIndex {
-            name: name.to_string(),
-            table: table.to_string(),
-            columns: columns.into_iter().map(|s| s.to_string()).collect(),
-        })
+                value((false, true), keyword("strict")),
+            ))),
+        )),
+    ))(input)?;
+
+    let mut columns = vec![];
+    let mut constraints = vec![];
+    for definition in definitions {
+        match definition {
+            Definition::Column(column) => columns.push(column),
+            Definition::Constraint(constraint) => constraints.push(constraint),
+        }
     }
+    let options = options.unwrap_or_default();
+    Ok((
+        input,
+        CreateStatement::CreateTable {
+            schema,
+            name,
+            if_not_exists,
+            columns,
+            constraints,
+            without_rowid: options.iter().any(|&(without_rowid, _)| without_rowid),
+            strict: options.iter().any(|&(_, strict)| strict),
+        },
+    ))
+}
 
-    pub fn parse_create_table(query: String) -> Result<Self> {
-        let (_, (name, cols)) = tuple((
-            preceded(
-                tag_no_case("create table "),
-                terminated(is_not(" \t\r\n,)("), multispace0),
-            ),
-            delimited(
-                terminated(tag("("), multispace0),
-                separated_list1(
-                    delimited(multispace0, tag(","), multispace0),
+fn column_definition(input: &str) -> IResult<&str, Column> {
+    map(
+        tuple((
+            object_name,
+            opt(preceded(multispace1, type_name)),
+            many0(preceded(multispace0, column_constraint)),
+        )),
+        |(name, data_type, constraints)| Column {
+            name,
+            data_type,
+            constraints,
+        },
+    )(input)
+}
+
+/// Parses the optional `CONSTRAINT name` that precedes a constraint
+fn constraint_name(input: &str) -> IResult<&str, Option<String>> {
+    opt(delimited(
+        pair(keyword("constraint"), multispace1),
+        object_name,
+        multispace0,
+    ))(input)
+}
+
+fn column_constraint(input: &str) -> IResult<&str, ColumnConstraint> {
+    map(
+        pair(
+            constraint_name,
+            alt((
+                map(
                     tuple((
-                        alt((
-                            is_not(" \t\r\n\",)"),
-                            delimited(tag("\""), is_not("\""), tag("\"")),
-                        )),
-                        opt(preceded(multispace1, is_not(" \t\r\n,)"))),
-                        opt(preceded(multispace1, tag_no_case("not null"))),
+                        keyword("primary"),
+                        multispace1,
+                        keyword("key"),
+                        opt(preceded(multispace1, sort_order)),
+                        opt(conflict_clause),
+                        opt(pair(multispace1, keyword("autoincrement"))),
+                    )),
+                    |(_, _, _, descending, on_conflict, autoincrement)| {
+                        ColumnConstraint::PrimaryKey {
+                            descending: descending.unwrap_or_default(),
+                            on_conflict,
+                            autoincrement: autoincrement.is_some(),
+                        }
+                    },
+                ),
+                map(
+                    preceded(
+                        tuple((keyword("not"), multispace1, keyword("null"))),
+                        opt(conflict_clause),
+                    ),
+                    ColumnConstraint::NotNull,
+                ),
+                value(ColumnConstraint::Null, keyword("null")),
+                map(
+                    preceded(keyword("unique"), opt(conflict_clause)),
+                    ColumnConstraint::Unique,
+                ),
+                map(check, ColumnConstraint::Check),
+                map(
+                    preceded(pair(keyword("default"), multispace0), default_value),
+                    ColumnConstraint::Default,
+                ),
+                map(
+                    preceded(pair(keyword("collate"), multispace1), object_name),
+                    ColumnConstraint::Collate,
+                ),
+                map(foreign_key_clause, ColumnConstraint::References),
+                map(
+                    tuple((
+                        opt(tuple((
+                            keyword("generated"),
+                            multispace1,
+                            keyword("always"),
+                            multispace1,
+                        ))),
+                        keyword("as"),
+                        multispace0,
+                        parenthesized(expr),
                         opt(preceded(
                             multispace1,
-                            terminated(
-                                preceded(opt(tag("primary ")), tag_no_case("key")),
-                                opt(tag(" autoincrement")),
-                            ),
+                            alt((
+                                value(true, keyword("stored")),
+                                value(false, keyword("virtual")),
+                            )),
                         )),
                     )),
+                    |(_, _, _, expr, stored)| ColumnConstraint::Generated {
+                        expr,
+                        stored: stored.unwrap_or_default(),
+                    },
+                ),
+            )),
+        ),
+        |(name, constraint)| match (name, constraint) {
+            (Some(name), ColumnConstraint::Check(check)) => {
+                ColumnConstraint::Check(Check { name, ..check })
+            }
+            (_, constraint) => constraint,
+        },
+    )(input)
+}
+
+fn table_constraint(input: &str) -> IResult<&str, TableConstraint> {
+    map(
+        pair(
+            constraint_name,
+            alt((
+                map(
+                    tuple((
+                        keyword("primary"),
+                        multispace1,
+                        keyword("key"),
+                        multispace0,
+                        parenthesized(comma_separated(indexed_column)),
+                        opt(conflict_clause),
+                    )),
+                    |(_, _, _, _, columns, on_conflict)| TableConstraint::PrimaryKey {
+                        columns,
+                        on_conflict,
+                    },
+                ),
+                map(
+                    tuple((
+                        keyword("unique"),
+                        multispace0,
+                        parenthesized(comma_separated(indexed_column)),
+                        opt(conflict_clause),
+                    )),
+                    |(_, _, columns, on_conflict)| TableConstraint::Unique {
+                        columns,
+                        on_conflict,
+                    },
+                ),
+                map(check, TableConstraint::Check),
+                map(
+                    tuple((
+                        keyword("foreign"),
+                        multispace1,
+                        keyword("key"),
+                        multispace0,
+                        parenthesized(comma_separated(object_name)),
+                        multispace0,
+                        foreign_key_clause,
+                    )),
+                    |(_, _, _, _, columns, _, references)| TableConstraint::ForeignKey {
+                        columns,
+                        references,
+                    },
                 ),
-                preceded(multispace0, tag(")")),
+            )),
+        ),
+        |(name, constraint)| match (name, constraint) {
+            (Some(name), TableConstraint::Check(check)) => {
+                TableConstraint::Check(Check { name, ..check })
+            }
+            (_, constraint) => constraint,
+        },
+    )(input)
+}
+
+/// Parses a `CHECK` constraint, named after the text of its expression
+fn check(input: &str) -> IResult<&str, Check> {
+    map(
+        preceded(
+            pair(keyword("check"), multispace0),
+            parenthesized(consumed(expr)),
+        ),
+        |(text, expr)| Check {
+            name: text.to_string(),
+            expr,
+        },
+    )(input)
+}
+
+/// Parses a column of an index or of a table constraint, with its collation and order. As in
+/// SQLite, a string literal is taken as the name of a column
+fn indexed_column(input: &str) -> IResult<&str, IndexedColumn> {
+    map(
+        pair(expr, opt(preceded(multispace1, sort_order))),
+        |(expr, descending)| {
+            let (expr, collation) = match expr {
+                Expr::Collate(expr, collation) => (*expr, Some(collation)),
+                expr => (expr, None),
+            };
+            let expr = match expr {
+                Expr::Literal(Value::Text(name)) => Expr::Column(name),
+                expr => expr,
+            };
+            IndexedColumn {
+                expr,
+                collation,
+                descending: descending.unwrap_or_default(),
+            }
+        },
+    )(input)
+}
+
+/// Parses `ASC` or `DESC`, telling whether the order is descending
+fn sort_order(input: &str) -> IResult<&str, bool> {
+    alt((value(false, keyword("asc")), value(true, keyword("desc"))))(input)
+}
+
+/// Parses an `ON CONFLICT` clause, preceded by at least one space
+fn conflict_clause(input: &str) -> IResult<&str, ConflictResolution> {
+    preceded(
+        tuple((
+            multispace1,
+            keyword("on"),
+            multispace1,
+            keyword("conflict"),
+            multispace1,
+        )),
+        alt((
+            value(ConflictResolution::Rollback, keyword("rollback")),
+            value(ConflictResolution::Abort, keyword("abort")),
+            value(ConflictResolution::Fail, keyword("fail")),
+            value(ConflictResolution::Ignore, keyword("ignore")),
+            value(ConflictResolution::Replace, keyword("replace")),
+        )),
+    )(input)
+}
+
+/// Parses the value of a `DEFAULT` constraint: a literal, possibly signed, a parenthesized
+/// expression, the current time or a bare word taken as text
+fn default_value(input: &str) -> IResult<&str, Expr> {
+    alt((
+        parenthesized(expr),
+        map(
+            pair(terminated(one_of("+-"), multispace0), literal),
+            |(sign, value)| match sign {
+                '-' => Expr::Unary(UnaryOperator::Negate, Box::new(Expr::Literal(value))),
+                _ => Expr::Literal(value),
+            },
+        ),
+        map(literal, Expr::Literal),
+        current_time,
+        map(object_name, |name| Expr::Literal(Value::Text(name))),
+    ))(input)
+}
+
+fn foreign_key_clause(input: &str) -> IResult<&str, ForeignKey> {
+    let action = || {
+        alt((
+            value(
+                ForeignKeyAction::SetNull,
+                tuple((keyword("set"), multispace1, keyword("null"))),
+            ),
+            value(
+                ForeignKeyAction::SetDefault,
+                tuple((keyword("set"), multispace1, keyword("default"))),
+            ),
+            value(ForeignKeyAction::Cascade, keyword("cascade")),
+            value(ForeignKeyAction::Restrict, keyword("restrict")),
+            value(
+                ForeignKeyAction::NoAction,
+                tuple((keyword("no"), multispace1, keyword("action"))),
             ),
-        ))(&*query)
-        .map_err(|err: Err<error::Error<&str>>| Error::msg(err.to_string()))?;
+        ))
+    };
+    // `ON DELETE` and `ON UPDATE` actions, or a `MATCH` clause, which SQLite ignores
+    let clause = alt((
+        map(
+            tuple((
+                keyword("on"),
+                multispace1,
+                alt((
+                    value(true, keyword("delete")),
+                    value(false, keyword("update")),
+                )),
+                multispace1,
+                action(),
+            )),
+            |(_, _, is_delete, _, action)| Some((is_delete, action)),
+        ),
+        value(None, tuple((keyword("match"), multispace1, object_name))),
+    ));
+    let deferrable = tuple((
+        opt(pair(keyword("not"), multispace1)),
+        keyword("deferrable"),
+        opt(tuple((
+            multispace1,
+            keyword("initially"),
+            multispace1,
+            alt((keyword("deferred"), keyword("immediate"))),
+        ))),
+    ));
 
-        let columns = cols
-            .into_iter()
-            .map(|(name, data_type, _not_null, pk)| Column {
-                name: name.to_owned(),
-                data_type: data_type.map(|s| s.to_owned()),
-                is_primary_key: pk.is_some(),
-            })
-            .collect();
+    let (input, (_, table, columns, clauses, _)) = tuple((
+        pair(keyword("references"), multispace1),
+        object_name,
+        opt(preceded(
+            multispace0,
+            parenthesized(comma_separated(object_name)),
+        )),
+        many0(preceded(multispace1, clause)),
+        opt(preceded(multispace1, deferrable)),
+    ))(input)?;
+
+    let clauses = clauses.into_iter().flatten().collect::<Vec<_>>();
+    let find_action = |delete: bool| {
+        clauses
+            .iter()
+            .find(|&&(is_delete, _)| is_delete == delete)
+            .map(|&(_, action)| action)
+    };
+    Ok((
+        input,
+        ForeignKey {
+            table,
+            columns: columns.unwrap_or_default(),
+            on_delete: find_action(true),
+            on_update: find_action(false),
+        },
+    ))
+}
 
-        Ok(Self::CreateTable {
-            name: name.to_owned(),
+/// Parses a `CREATE INDEX` statement, as mentioned here:
+/// [create index](https://www.sqlite.org/lang_createindex.html)
+fn create_index(input: &str) -> IResult<&str, CreateStatement> {
+    map(
+        tuple((
+            create_prefix(map(
+                pair(opt(pair(keyword("unique"), multispace1)), keyword("index")),
+                |(unique, _)| unique.is_some(),
+            )),
+            qualified_name,
+            delimited(multispace0, keyword("on"), multispace0),
+            object_name,
+            preceded(multispace0, parenthesized(comma_separated(indexed_column))),
+            opt(preceded(
+                tuple((multispace0, keyword("where"), multispace1)),
+                expr,
+            )),
+        )),
+        |((unique, if_not_exists), (schema, name), _, table, columns, filter)| CreateIndex {
+            schema,
+            name,
+            table,
+            if_not_exists,
+            unique,
             columns,
-        })
+            filter,
+        },
+    )(input)
+}
+
+/// Parses a `CREATE VIEW` statement, as mentioned here:
+/// [create view](https://www.sqlite.org/lang_createview.html)
+fn create_view(input: &str) -> IResult<&str, CreateStatement> {
+    map(
+        tuple((
+            create_prefix(keyword("view")),
+            qualified_name,
+            opt(preceded(
+                multispace0,
+                parenthesized(comma_separated(object_name)),
+            )),
+            delimited(multispace0, keyword("as"), multispace0),
+            alt((
+                map(terminated(select, peek(end_of_statement)), |select| {
+                    Some(Box::new(select))
+                }),
+                value(
+                    None,
+                    pair(
+                        alt((keyword("select"), keyword("with"), keyword("values"))),
+                        rest,
+                    ),
+                ),
+            )),
+        )),
+        |((_, if_not_exists), (schema, name), columns, _, select)| CreateStatement::CreateView {
+            schema,
+            name,
+            if_not_exists,
+            columns: columns.unwrap_or_default(),
+            select,
+        },
+    )(input)
+}
+
+/// Parses a `CREATE TRIGGER` statement, as mentioned here:
+/// [create trigger](https://www.sqlite.org/lang_createtrigger.html)
+fn create_trigger(input: &str) -> IResult<&str, CreateStatement> {
+    let timing = alt((
+        value(TriggerTiming::Before, keyword("before")),
+        value(TriggerTiming::After, keyword("after")),
+        value(
+            TriggerTiming::InsteadOf,
+            tuple((keyword("instead"), multispace1, keyword("of"))),
+        ),
+    ));
+    let event = alt((
+        value(TriggerEvent::Delete, keyword("delete")),
+        value(TriggerEvent::Insert, keyword("insert")),
+        map(
+            preceded(
+                keyword("update"),
+                opt(preceded(
+                    tuple((multispace1, keyword("of"), multispace1)),
+                    comma_separated(object_name),
+                )),
+            ),
+            |columns| TriggerEvent::Update(columns.unwrap_or_default()),
+        ),
+    ));
+
+    map(
+        tuple((
+            create_prefix(keyword("trigger")),
+            qualified_name,
+            opt(preceded(multispace1, timing)),
+            preceded(multispace1, event),
+            delimited(
+                tuple((multispace0, keyword("on"), multispace1)),
+                object_name,
+                multispace0,
+            ),
+            opt(tuple((
+                keyword("for"),
+                multispace1,
+                keyword("each"),
+                multispace1,
+                keyword("row"),
+                multispace0,
+            ))),
+            opt(delimited(
+                pair(keyword("when"), multispace1),
+                expr,
+                multispace0,
+            )),
+            preceded(pair(keyword("begin"), multispace1), trigger_body),
+        )),
+        |((_, if_not_exists), (schema, name), timing, event, table, _, when, body)| {
+            CreateStatement::CreateTrigger {
+                schema,
+                name,
+                if_not_exists,
+                timing: timing.unwrap_or(TriggerTiming::Before),
+                event,
+                table,
+                when,
+                body,
+            }
+        },
+    )(input)
+}
+
+/// Takes the statements of a trigger, up to the `END` closing the statement
+fn trigger_body(input: &str) -> IResult<&str, String> {
+    let trimmed = input.trim_end().trim_end_matches(';').trim_end();
+    let body_end = trimmed.len().saturating_sub("end".len());
+    match trimmed.get(body_end..) {
+        Some(end) if end.eq_ignore_ascii_case("end") => Ok((
+            &input[trimmed.len()..],
+            trimmed[..body_end].trim().to_string(),
+        )),
+        _ => Err(Err::Error(error::Error::new(input, error::ErrorKind::Tag))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::{Columns, Row};
+    use crate::functions::Functions;
+
+    fn sources(select: &Select) -> Vec<String> {
+        select
+            .from
+            .iter()
+            .map(|source| match source {
+                TableSource::Table { name, .. } => format!("{} {}", name, source.alias()),
+                TableSource::Function { name, args, .. } => {
+                    format!("{}({}) {}", name, args.len(), source.alias())
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_joined_sources() {
+        let select = Select::parse_select(
+            "SELECT * FROM t AS a JOIN json_each(a.j, '$') e ON e.value = a.id, u CROSS JOIN v WHERE a.id > 1",
+        )
+        .unwrap();
+        assert_eq!(sources(&select), ["t a", "json_each(2) e", "u u", "v v"]);
+        // ON constraints are added to the WHERE clause
+        assert_eq!(select.filter.unwrap().conjuncts().len(), 2);
+
+        let select = Select::parse_select("SELECT 1 + 1").unwrap();
+        assert!(select.from.is_empty());
+    }
+
+    #[test]
+    fn rejects_unsupported_joins() {
+        // These would otherwise parse as an alias followed by an inner join
+        for join in ["RIGHT", "RIGHT OUTER", "FULL", "NATURAL", "NATURAL LEFT"] {
+            let sql = format!("SELECT * FROM t {} JOIN u ON t.a = u.a", join);
+            assert!(Select::parse_select(&sql).is_err(), "{}", sql);
+        }
+        assert!(Select::parse_select("SELECT * FROM t JOIN u USING (a)").is_err());
+    }
+
+    #[test]
+    fn left_joins_keep_their_constraint() {
+        let select = Select::parse_select(
+            "SELECT * FROM s LEFT JOIN t ON a = c JOIN u ON b = e left outer join v",
+        )
+        .unwrap();
+        assert_eq!(select.from.len(), 4);
+        let left_joins = select
+            .left_joins
+            .iter()
+            .map(|join| (join.source, join.constraint.is_some()))
+            .collect::<Vec<_>>();
+        assert_eq!(left_joins, [(1, true), (3, false)]);
+        // Only the constraint of the inner join filters the joined rows
+        assert_eq!(select.filter.unwrap().conjuncts().len(), 1);
+    }
+
+    #[test]
+    fn distinct_and_all() {
+        let distinct = |sql: &str| Select::parse_select(sql).unwrap().distinct;
+        assert!(distinct("SELECT DISTINCT a FROM t"));
+        assert!(!distinct("select all a from t"));
+        assert!(!distinct("SELECT allowed FROM t"));
+        assert!(Select::parse_select("SELECT distinct, a FROM t").is_err());
+    }
+
+    #[test]
+    fn parses_create_table_constraints() {
+        let sql = "CREATE TEMP TABLE IF NOT EXISTS main.\"order items\" (
+            id INTEGER PRIMARY KEY DESC ON CONFLICT REPLACE AUTOINCREMENT, -- the rowid
+            name VARCHAR(10) NOT NULL UNIQUE COLLATE NOCASE DEFAULT 'x',
+            price UNSIGNED BIG INT CHECK (price >= 0) DEFAULT -1,
+            parent REFERENCES items(id) ON DELETE CASCADE ON UPDATE SET NULL,
+            total GENERATED ALWAYS AS (price * 2) STORED,
+            /* untyped */ note,
+            PRIMARY KEY (id, name DESC),
+            UNIQUE (name COLLATE BINARY, price) ON CONFLICT IGNORE,
+            CHECK (total < 100),
+            FOREIGN KEY (parent) REFERENCES items
+        ) WITHOUT ROWID, STRICT";
+        let (schema, name, if_not_exists, columns, constraints, without_rowid, strict) =
+            match CreateStatement::parse(sql.to_string()).unwrap() {
+                CreateStatement::CreateTable {
+                    schema,
+                    name,
+                    if_not_exists,
+                    columns,
+                    constraints,
+                    without_rowid,
+                    strict,
+                } => (
+                    schema,
+                    name,
+                    if_not_exists,
+                    columns,
+                    constraints,
+                    without_rowid,
+                    strict,
+                ),
+                statement => panic!("{:?}", statement),
+            };
+        assert_eq!(
+            (schema.as_deref(), name.as_str(), if_not_exists),
+            (Some("main"), "order items", true)
+        );
+        assert!(without_rowid && strict);
+
+        let described = columns
+            .iter()
+            .map(|c| {
+                format!(
+                    "{} {:?} {:?} {}",
+                    c.name,
+                    c.data_type,
+                    c.affinity(),
+                    c.constraints.len()
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            described,
+            [
+                "id Some(\"INTEGER\") Integer 1",
+                "name Some(\"VARCHAR(10)\") Text 4",
+                "price Some(\"UNSIGNED BIG INT\") Integer 2",
+                "parent None Blob 1",
+                "total None Blob 1",
+                "note None Blob 0",
+            ]
+        );
+        assert!(matches!(
+            columns[0].constraints[0],
+            ColumnConstraint::PrimaryKey {
+                descending: true,
+                on_conflict: Some(ConflictResolution::Replace),
+                autoincrement: true,
+            }
+        ));
+        assert_eq!(columns[1].collation(), Some("NOCASE"));
+        assert!(columns[1].default_value().is_some());
+        assert!(matches!(
+            &columns[3].constraints[0],
+            ColumnConstraint::References(ForeignKey {
+                table,
+                on_delete: Some(ForeignKeyAction::Cascade),
+                on_update: Some(ForeignKeyAction::SetNull),
+                ..
+            }) if table == "items"
+        ));
+        assert!(matches!(
+            columns[4].constraints[0],
+            ColumnConstraint::Generated { stored: true, .. }
+        ));
+
+        assert_eq!(constraints.len(), 4);
+        match &constraints[0] {
+            TableConstraint::PrimaryKey { columns, .. } => {
+                assert_eq!(columns.len(), 2);
+                assert!(!columns[0].descending && columns[1].descending);
+            }
+            constraint => panic!("{:?}", constraint),
+        }
+        match &constraints[1] {
+            TableConstraint::Unique {
+                columns,
+                on_conflict,
+            } => {
+                assert_eq!(columns[0].collation.as_deref(), Some("BINARY"));
+                assert_eq!(*on_conflict, Some(ConflictResolution::Ignore));
+            }
+            constraint => panic!("{:?}", constraint),
+        }
+        assert!(matches!(constraints[2], TableConstraint::Check(_)));
+        assert!(matches!(
+            &constraints[3],
+            TableConstraint::ForeignKey { columns, references } if columns == &["parent"] && references.columns.is_empty()
+        ));
+    }
+
+    /// Parses and evaluates an expression that reads no column, formatting its value
+    fn eval(sql: &str) -> String {
+        let (_, e) = terminated(expr, end_of_statement)(sql).unwrap();
+        let row = Row {
+            columns: &Columns::new(vec![]),
+            values: &[],
+            functions: &Functions::default(),
+        };
+        e.eval(&row).unwrap().to_string()
+    }
+
+    #[test]
+    fn in_lists() {
+        assert_eq!(eval("2 IN (1, 2, 3)"), "1");
+        assert_eq!(eval("4 in (1, 2, 3)"), "0");
+        assert_eq!(eval("4 IN (1, NULL)"), "NULL");
+        assert_eq!(eval("4 NOT IN (1, 2)"), "1");
+        assert_eq!(eval("1 NOT IN (1, NULL)"), "0");
+        assert_eq!(eval("NULL IN ()"), "0");
+        assert_eq!(eval("'b' IN ('a', 'b') AND 1"), "1");
+    }
+
+    #[test]
+    fn between() {
+        assert_eq!(eval("3 BETWEEN 1 AND 5"), "1");
+        assert_eq!(eval("6 between 1 and 5"), "0");
+        assert_eq!(eval("6 NOT BETWEEN 1 AND 5"), "1");
+        assert_eq!(eval("3 BETWEEN 1 AND 5 AND 0"), "0");
+        assert_eq!(eval("NULL BETWEEN 1 AND 5"), "NULL");
+    }
+
+    #[test]
+    fn like_and_glob() {
+        assert_eq!(eval("'Joe@Example.com' LIKE '%@example.%'"), "1");
+        assert_eq!(eval("'abc' LIKE 'a_c'"), "1");
+        assert_eq!(eval("'abc' NOT LIKE 'a_'"), "1");
+        assert_eq!(eval("'10%' LIKE '10!%' ESCAPE '!'"), "1");
+        assert_eq!(eval("'100' LIKE '10!%' ESCAPE '!'"), "0");
+        assert_eq!(eval("'abc' GLOB 'a*'"), "1");
+        assert_eq!(eval("'Abc' GLOB 'a*'"), "0");
+        assert_eq!(eval("'b1' GLOB '[a-c][0-9]'"), "1");
+        assert_eq!(eval("'d1' GLOB '[^a-c]?'"), "1");
+        assert_eq!(eval("']' GLOB '[]]'"), "1");
+        assert_eq!(eval("NULL LIKE 'a'"), "NULL");
+    }
+
+    #[test]
+    fn views_with_unsupported_queries() {
+        let has_query = |sql: &str| match CreateStatement::parse(sql.to_string()).unwrap() {
+            CreateStatement::CreateView { select, .. } => select.is_some(),
+            statement => panic!("{:?} is not a view", statement),
+        };
+        assert!(has_query(
+            "CREATE VIEW v AS SELECT a FROM t WHERE a IN (1, 2)"
+        ));
+        assert!(!has_query(
+            "CREATE VIEW v AS SELECT a FROM t UNION SELECT b FROM u"
+        ));
+        assert!(!has_query(
+            "CREATE VIEW v AS SELECT a FROM t WHERE a IN (SELECT b FROM u)"
+        ));
+        assert!(!has_query(
+            "CREATE VIEW v(x) AS WITH c(x) AS (SELECT 1) SELECT x FROM c;"
+        ));
+        assert!(CreateStatement::parse("CREATE VIEW v AS garbage".to_string()).is_err());
+    }
+
+    #[test]
+    fn check_constraints_with_rewritten_operators() {
+        let statements = [
+            "CREATE TABLE t (x CHECK (x IN ('a','b')))",
+            "CREATE TABLE t (n CHECK (n BETWEEN 1 AND 5))",
+            "CREATE TABLE t (e TEXT, CHECK (e LIKE '%@%'))",
+        ];
+        for statement in statements {
+            assert!(
+                CreateStatement::parse(statement.to_string()).is_ok(),
+                "{}",
+                statement
+            );
+        }
+    }
+
+    #[test]
+    fn parses_create_index() {
+        let sql = "create unique index if not exists main.'idx' on [t] \
+            (a, b collate nocase desc, lower(c)) where a > 0 and c is not null";
+        match CreateStatement::parse(sql.to_string()).unwrap() {
+            CreateStatement::CreateIndex {
+                schema,
+                name,
+                table,
+                if_not_exists,
+                unique,
+                columns,
+                filter,
+            } => {
+                assert_eq!(schema.as_deref(), Some("main"));
+                assert_eq!((name.as_str(), table.as_str()), ("idx", "t"));
+                assert!(if_not_exists && unique);
+                let names = columns.iter().map(|c| c.name()).collect::<Vec<_>>();
+                assert_eq!(names, [Some("a"), Some("b"), None]);
+                assert_eq!(columns[1].collation.as_deref(), Some("nocase"));
+                assert!(columns[1].descending && !columns[2].descending);
+                assert!(matches!(&columns[2].expr, Expr::Function { name, .. } if name == "lower"));
+                assert_eq!(filter.unwrap().conjuncts().len(), 2);
+            }
+            statement => panic!("{:?}", statement),
+        }
+        match CreateStatement::parse("CREATE INDEX i ON t (a)".to_string()).unwrap() {
+            CreateStatement::CreateIndex { unique, filter, .. } => {
+                assert!(!unique && filter.is_none())
+            }
+            statement => panic!("{:?}", statement),
+        }
+        assert!(CreateStatement::parse("CREATE TABLE t (a,)".to_string()).is_err());
+        assert!(CreateStatement::parse("CREATE TABLE t ()".to_string()).is_err());
+    }
+
+    #[test]
+    fn parses_views_triggers_and_other_statements() {
+        let sql = "CREATE VIEW IF NOT EXISTS main.v (x, y) AS SELECT a, b FROM t WHERE a > 1";
+        match CreateStatement::parse(sql.to_string()).unwrap() {
+            CreateStatement::CreateView {
+                schema,
+                name,
+                if_not_exists,
+                columns,
+                select,
+            } => {
+                assert_eq!((schema.as_deref(), name.as_str()), (Some("main"), "v"));
+                assert!(if_not_exists);
+                assert_eq!(columns, ["x", "y"]);
+                let select = select.unwrap();
+                assert_eq!(select.columns.len(), 2);
+                assert!(select.filter.is_some());
+            }
+            statement => panic!("{:?}", statement),
+        }
+
+        let sql = "CREATE TEMP TRIGGER tr BEFORE UPDATE OF a, b ON t FOR EACH ROW \
+            WHEN new.a > 0 BEGIN UPDATE u SET x = 1; DELETE FROM w; END;";
+        match CreateStatement::parse(sql.to_string()).unwrap() {
+            CreateStatement::CreateTrigger {
+                name,
+                timing,
+                event: TriggerEvent::Update(columns),
+                table,
+                when,
+                body,
+                ..
+            } => {
+                assert_eq!((name.as_str(), table.as_str()), ("tr", "t"));
+                assert_eq!(timing, TriggerTiming::Before);
+                assert_eq!(columns, ["a", "b"]);
+                assert!(when.is_some());
+                assert_eq!(body, "UPDATE u SET x = 1; DELETE FROM w;");
+            }
+            statement => panic!("{:?}", statement),
+        }
+        let sql = "CREATE TRIGGER tr INSTEAD OF INSERT ON v BEGIN SELECT 1; END";
+        assert!(matches!(
+            CreateStatement::parse(sql.to_string()).unwrap(),
+            CreateStatement::CreateTrigger {
+                timing: TriggerTiming::InsteadOf,
+                event: TriggerEvent::Insert,
+                when: None,
+                ..
+            }
+        ));
+
+        let sql = "CREATE VIRTUAL TABLE f USING fts5(a, b)";
+        assert!(matches!(
+            CreateStatement::parse(sql.to_string()).unwrap(),
+            CreateStatement::Other(text) if text == sql
+        ));
+        // A statement of a known kind that does not parse is an error, not another kind
+        assert!(CreateStatement::parse("CREATE VIEW v AS".to_string()).is_err());
+    }
+
+    #[test]
+    fn parses_inserts() {
+        let insert =
+            Insert::parse("insert into [t] (a, \"b\") values (1, 'x'), (2, null);").unwrap();
+        assert_eq!(insert.table, "t");
+        assert_eq!(insert.columns, ["a", "b"]);
+        assert!(matches!(&insert.source, InsertSource::Values(rows) if rows.len() == 2));
+
+        let insert = Insert::parse("INSERT INTO t SELECT a, b FROM u WHERE a > 1").unwrap();
+        assert!(insert.columns.is_empty());
+        assert!(matches!(&insert.source, InsertSource::Select(select) if select.filter.is_some()));
+
+        let insert = Insert::parse("INSERT INTO t DEFAULT VALUES").unwrap();
+        assert!(matches!(insert.source, InsertSource::DefaultValues));
+        assert!(Insert::parse("INSERT INTO t VALUES").is_err());
+        assert!(Insert::parse("INSERT INTO t (a) VALUES (1) garbage").is_err());
+    }
+
+    #[test]
+    fn names_checks_by_their_constraint_or_text() {
+        let sql = "CREATE TABLE t (a CHECK (a  >  0), b CONSTRAINT positive CHECK(b > 0))";
+        let columns = match CreateStatement::parse(sql.to_string()).unwrap() {
+            CreateStatement::CreateTable { columns, .. } => columns,
+            statement => panic!("{:?}", statement),
+        };
+        let names = columns
+            .iter()
+            .flat_map(|column| &column.constraints)
+            .filter_map(|c| match c {
+                ColumnConstraint::Check(check) => Some(check.name.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a  >  0", "positive"]);
+    }
+
+    #[test]
+    fn parses_updates_and_deletes() {
+        let update = Update::parse("UPDATE t SET a = a + 1, \"b\" = 'x' WHERE a > 1").unwrap();
+        assert_eq!(update.table, "t");
+        let names = update.assignments.iter().map(|(name, _)| name.as_str());
+        assert_eq!(names.collect::<Vec<_>>(), ["a", "b"]);
+        assert!(update.filter.is_some());
+        assert!(Update::parse("UPDATE t SET").is_err());
+
+        let delete = Delete::parse("delete from [t];").unwrap();
+        assert_eq!(delete.table, "t");
+        assert!(delete.filter.is_none());
+        let delete = Delete::parse("DELETE FROM t WHERE a IS NULL").unwrap();
+        assert!(delete.filter.is_some());
+    }
+
+    #[test]
+    fn parses_wal_checkpoints() {
+        let modes = [
+            ("PRAGMA wal_checkpoint", CheckpointMode::Passive),
+            ("pragma main.wal_checkpoint(full);", CheckpointMode::Full),
+            ("PRAGMA wal_checkpoint = RESTART", CheckpointMode::Restart),
+            (
+                "PRAGMA wal_checkpoint ( truncate )",
+                CheckpointMode::Truncate,
+            ),
+        ];
+        for (sql, mode) in modes {
+            assert_eq!(WalCheckpoint::parse(sql).unwrap().mode, mode, "{}", sql);
+        }
+        assert!(WalCheckpoint::parse("PRAGMA wal_checkpoint(NONE)").is_err());
+        assert!(WalCheckpoint::parse("PRAGMA journal_mode").is_err());
+    }
+
+    #[test]
+    fn parses_drops_and_normalizes_schema_text() {
+        let drop = DropStatement::parse("drop table if exists main.[t];").unwrap();
+        assert_eq!(drop.kind, DropKind::Table);
+        assert_eq!(drop.name, "t");
+        assert!(drop.if_exists);
+        let drop = DropStatement::parse("DROP TRIGGER -- a comment\n tr").unwrap();
+        assert_eq!((drop.kind, drop.name.as_str()), (DropKind::Trigger, "tr"));
+        assert!(!drop.if_exists);
+        assert!(DropStatement::parse("DROP COLUMN c").is_err());
+
+        let texts = [
+            (
+                "create table if not exists main.t (a,  b) ;",
+                "CREATE TABLE t (a,  b)",
+            ),
+            (
+                "create unique index i on t (a) where a > 0",
+                "CREATE UNIQUE INDEX i on t (a) where a > 0",
+            ),
+            (
+                "CREATE VIEW v AS SELECT 1 -- last",
+                "CREATE VIEW v AS SELECT 1 -- last",
+            ),
+        ];
+        for (sql, text) in texts {
+            let statement = CreateStatement::parse(sql.to_string()).unwrap();
+            assert_eq!(statement.schema_text(sql).unwrap(), text, "{}", sql);
+        }
     }
 }
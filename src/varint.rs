@@ -11,17 +11,17 @@ pub fn parse_varint(stream: &[u8]) -> (usize, usize) {
     let varint = usable_bytes
         .into_iter()
         .enumerate()
-        .fold(0, |value, (i, usable_byte)| {
+        .fold(0, |value: usize, (i, usable_byte)| {
             let usable_size = if i == 8 { 8 } else { 7 };
-            (value << usable_size) + usable_value(usable_size, usable_byte) as usize
+            (value << usable_size) | usable_value(usable_size, usable_byte) as usize
         });
     (varint, bytes_read)
 }
 
-/// Usable size is either 8 or 7
+/// Usable size is either 8, for the ninth byte, or 7
 fn usable_value(usable_size: u8, byte: u8) -> u8 {
     if usable_size == 8 {
-        usable_size
+        byte
     } else {
         byte & LAST_SEVEN_BITS_MASK
     }
@@ -30,8 +30,7 @@ fn usable_value(usable_size: u8, byte: u8) -> u8 {
 fn read_usable_bytes(stream: &[u8]) -> Vec<u8> {
     let mut usable_bytes = vec![];
 
-    for i in 0..8 {
-        let byte = stream[i];
+    for &byte in stream.iter().take(9) {
         usable_bytes.push(byte);
         if starts_with_zero(byte) {
             break;
@@ -44,3 +43,94 @@ fn read_usable_bytes(stream: &[u8]) -> Vec<u8> {
 fn starts_with_zero(byte: u8) -> bool {
     (byte & IS_FIRST_BIT_ZERO_MASK) == 0
 }
+
+/// Encodes a value as a varint, the inverse of `parse_varint`
+pub fn encode_varint(value: u64) -> Vec<u8> {
+    if value >> 56 != 0 {
+        // The first eight bytes hold seven bits each and the ninth holds the last eight
+        let mut bytes = (0..8)
+            .rev()
+            .map(|i| ((value >> (8 + 7 * i)) & LAST_SEVEN_BITS_MASK as u64) as u8)
+            .map(|byte| byte | IS_FIRST_BIT_ZERO_MASK)
+            .collect::<Vec<_>>();
+        bytes.push(value as u8);
+        return bytes;
+    }
+    let mut bytes = vec![(value & LAST_SEVEN_BITS_MASK as u64) as u8];
+    let mut rest = value >> 7;
+    while rest != 0 {
+        bytes.push((rest & LAST_SEVEN_BITS_MASK as u64) as u8 | IS_FIRST_BIT_ZERO_MASK);
+        rest >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_varints_of_every_length() {
+        assert_eq!(parse_varint(&[0x7f, 0xff]), (127, 1));
+        assert_eq!(parse_varint(&[0x81, 0x00]), (128, 2));
+        assert_eq!(parse_varint(&[0x82, 0xac, 0x02]), (38402, 3));
+        // The ninth byte contributes all of its 8 bits
+        let max = [0xbf, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert_eq!(parse_varint(&max), (i64::MAX as usize, 9));
+        let minus_one = [0xff; 9];
+        assert_eq!(parse_varint(&minus_one).0 as i64, -1);
+    }
+
+    #[test]
+    fn round_trips_at_every_length_boundary() {
+        // The largest value of each length and the smallest of the next one
+        let mut boundaries = (1..=8)
+            .flat_map(|n| vec![(1u64 << (7 * n)) - 1, 1u64 << (7 * n)])
+            .collect::<Vec<_>>();
+        boundaries.extend(vec![0, u64::MAX, i64::MAX as u64, i64::MIN as u64]);
+        for value in boundaries {
+            let bytes = encode_varint(value);
+            let expected_len = match value {
+                0 => 1,
+                _ if value >> 56 != 0 => 9,
+                _ => (64 - value.leading_zeros() as usize).div_ceil(7),
+            };
+            assert_eq!(bytes.len(), expected_len, "length of {:#x}", value);
+            assert_eq!(parse_varint(&bytes), (value as usize, bytes.len()));
+        }
+    }
+
+    #[test]
+    fn round_trips_generated_values() {
+        // A xorshift generator, shifted by a varying amount to spread values over every length
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        for i in 0..10_000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let value = state >> (i % 64);
+            let mut bytes = encode_varint(value);
+            bytes.extend([0xff, 0xff]);
+            assert_eq!(parse_varint(&bytes).0, value as usize, "{:#x}", value);
+        }
+    }
+
+    #[test]
+    fn encodes_like_sqlite() {
+        assert_eq!(encode_varint(0x7f), [0x7f]);
+        assert_eq!(encode_varint(0x80), [0x81, 0x00]);
+        assert_eq!(encode_varint(0x3fff), [0xff, 0x7f]);
+        assert_eq!(encode_varint(0x4000), [0x81, 0x80, 0x00]);
+        assert_eq!(
+            encode_varint((1 << 56) - 1),
+            [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f]
+        );
+        // The ninth byte holds eight bits, so the first eight hold the bits above them
+        assert_eq!(
+            encode_varint(1 << 56),
+            [0x80, 0xc0, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00]
+        );
+        assert_eq!(encode_varint(u64::MAX), [0xff; 9]);
+    }
+}
@@ -0,0 +1,1490 @@
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
+use std::fs::{File, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{ErrorKind, Read};
+use std::os::unix::fs::FileExt;
+
+use anyhow::{bail, Result};
+
+use crate::lock::{set_lock, try_set_lock, LockType};
+
+/// The magic number of a log whose checksums read words as little-endian, the big-endian one
+/// being one more
+const MAGIC: u32 = 0x377f0682;
+
+/// The version of the log and wal-index formats
+const VERSION: u32 = 3007000;
+
+const HEADER_SIZE: usize = 32;
+const FRAME_HEADER_SIZE: usize = 24;
+
+/// The size of the header of the wal-index, in the shared memory file: two copies of the
+/// 48-byte header followed by the 40 bytes of checkpoint information
+const INDEX_HEADER_SIZE: usize = 136;
+const INDEX_COPY_SIZE: usize = 48;
+
+/// The wal-index is made of 32 KiB blocks, each holding the page numbers of 4096 frames followed
+/// by a hash table of 8192 slots, except for the first one whose header takes the place of 34
+/// page numbers
+const INDEX_BLOCK_SIZE: usize = 32768;
+const INDEX_BLOCK_FRAMES: usize = 4096;
+const INDEX_FIRST_BLOCK_FRAMES: usize = INDEX_BLOCK_FRAMES - INDEX_HEADER_SIZE / 4;
+const INDEX_HASH_SLOTS: usize = 8192;
+
+/// The read mark of a reader that is not used
+const READ_MARK_NOT_USED: u32 = 0xffffffff;
+
+/// The locks of the wal-index, given by the byte of the shared memory file they lock, as
+/// mentioned here: [locks](https://www.sqlite.org/walformat.html#locks). There are five read
+/// locks, and connections hold a shared lock on the last byte while they use the wal-index
+const WRITE_LOCK: i64 = 120;
+const CHECKPOINT_LOCK: i64 = 121;
+const READ_LOCK: i64 = 123;
+const READ_LOCK_COUNT: usize = 5;
+const CONNECTION_LOCK: i64 = 128;
+
+/// The read locks but the first one, held by readers of the log
+const LOG_READ_LOCK: i64 = READ_LOCK + 1;
+const LOG_READ_LOCK_COUNT: i64 = READ_LOCK_COUNT as i64 - 1;
+
+/// How many times starting a read is attempted while other connections change the wal-index
+const READ_ATTEMPTS: usize = 100;
+
+/// Returns the path of the write-ahead log of a database file
+pub fn wal_path(file_name: &str) -> String {
+    format!("{}-wal", file_name)
+}
+
+/// How much a checkpoint does, as mentioned here:
+/// [checkpoints](https://www.sqlite.org/c3ref/wal_checkpoint_v2.html). `PASSIVE` copies the
+/// frames no reader may still need, `FULL` also makes sure no writer adds frames meanwhile and
+/// that all the frames were copied, `RESTART` that the next transaction starts the log over, and
+/// `TRUNCATE` empties the log
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub enum CheckpointMode {
+    Passive,
+    Full,
+    Restart,
+    Truncate,
+}
+
+/// The write-ahead log of a database in WAL mode, which holds the pages changed by transactions
+/// that were not yet copied back into the database file, as mentioned here:
+/// [write-ahead log](https://www.sqlite.org/fileformat2.html#the_write_ahead_log).
+///
+/// Which frames of the log were committed is told by the wal-index SQLite shares between its
+/// connections. A read transaction holds one of its read locks, whose read mark keeps the
+/// frames it uses from being overwritten by other connections until it ends
+pub struct Wal {
+    path: String,
+    /// The log, once read from
+    file: Option<File>,
+    index: WalIndex,
+    page_size: usize,
+    /// The header of the wal-index the frames were read for
+    header: Option<IndexHeader>,
+    /// The frame holding the latest committed version of each page, numbered from 0
+    frames: HashMap<usize, usize>,
+    /// The read lock held by the read transaction, the first one meaning that the log is not used
+    read_lock: Option<usize>,
+    /// Whether the write lock is held, which only one connection may hold
+    writing: bool,
+}
+
+impl Wal {
+    /// Opens the log of a database along with its wal-index, which is created when missing
+    pub fn open(path: &str, page_size: usize) -> Result<Self> {
+        Ok(Wal {
+            path: path.to_string(),
+            file: None,
+            index: WalIndex::open(&format!("{}-shm", path.trim_end_matches("-wal")))?,
+            page_size,
+            header: None,
+            frames: HashMap::new(),
+            read_lock: None,
+            writing: false,
+        })
+    }
+
+    /// Starts a read transaction on the frames committed so far, taking a read lock that keeps
+    /// them in the log until `end_read`. The frames are read again when the wal-index changed
+    /// since the last transaction, as mentioned here:
+    /// [reading](https://www.sqlite.org/walformat.html#reader_algorithm)
+    pub fn begin_read(&mut self) -> Result<()> {
+        if self.read_lock.is_some() {
+            return Ok(());
+        }
+        for _ in 0..READ_ATTEMPTS {
+            if self.try_begin_read()? {
+                return Ok(());
+            }
+        }
+        bail!("database is locked")
+    }
+
+    /// Ends the read transaction, releasing its read lock
+    pub fn end_read(&mut self) -> Result<()> {
+        if let Some(read_lock) = self.read_lock.take() {
+            self.index
+                .set_lock(LockType::Unlock, READ_LOCK + read_lock as i64)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the latest committed version of a page, unless the log holds none
+    pub fn read_page(&self, page_number: usize) -> Result<Option<Vec<u8>>> {
+        let (file, frame) = match (&self.file, self.frames.get(&page_number)) {
+            (Some(file), Some(&frame)) if self.uses_log() => (file, frame),
+            _ => return Ok(None),
+        };
+        let mut page = vec![0; self.page_size];
+        file.read_exact_at(
+            &mut page,
+            self.frame_offset(frame) + FRAME_HEADER_SIZE as u64,
+        )?;
+        Ok(Some(page))
+    }
+
+    /// The size of the database in pages, when the read transaction uses the log
+    pub fn database_size(&self) -> Option<usize> {
+        match &self.header {
+            Some(header) if self.uses_log() => Some(header.database_size),
+            _ => None,
+        }
+    }
+
+    /// Takes the write lock before the frames of a transaction are added, failing when another
+    /// connection holds it, or added frames since the read transaction started
+    pub fn begin_write(&mut self) -> Result<()> {
+        if self.writing {
+            return Ok(());
+        }
+        self.index.set_lock(LockType::WriteLock, WRITE_LOCK)?;
+        if self.read_lock.is_none() || self.index.header()? != self.header {
+            self.index.set_lock(LockType::Unlock, WRITE_LOCK)?;
+            bail!("database is locked");
+        }
+        self.writing = true;
+        Ok(())
+    }
+
+    /// Releases the write lock, if held
+    pub fn end_write(&mut self) -> Result<()> {
+        if self.writing {
+            self.writing = false;
+            self.index.set_lock(LockType::Unlock, WRITE_LOCK)?;
+        }
+        Ok(())
+    }
+
+    /// Appends the pages of a transaction to the log and flushes it to the disk, the last frame
+    /// committing the transaction with the size of the database, then tells readers about them
+    /// in the wal-index. A log whose frames were all copied back into the database file starts
+    /// over, unless a reader may still read them, as mentioned here:
+    /// [writing](https://www.sqlite.org/walformat.html#writer_algorithm)
+    pub fn append(&mut self, pages: &BTreeMap<usize, Vec<u8>>, database_size: usize) -> Result<()> {
+        let mut header = match (&self.header, self.writing) {
+            (Some(header), true) => header.clone(),
+            _ => bail!("Cannot write to the log without its write lock"),
+        };
+        if self.read_lock == Some(0)
+            && header.frame_count > 0
+            && try_set_lock(
+                &self.index.file,
+                LockType::WriteLock,
+                LOG_READ_LOCK,
+                LOG_READ_LOCK_COUNT,
+            )?
+        {
+            let restarted = self.restart(&header);
+            set_lock(
+                &self.index.file,
+                LockType::Unlock,
+                LOG_READ_LOCK,
+                LOG_READ_LOCK_COUNT,
+            )?;
+            header = restarted?;
+        }
+        let file = self.log_file()?;
+        if header.frame_count == 0 {
+            header.big_endian = cfg!(target_endian = "big");
+            header.page_size = self.page_size;
+            if header.salts == [0, 0] {
+                header.salts = [random(), random()];
+            }
+            header.checksums = write_log_header(&file, &header)?;
+        }
+
+        let mut sums = header.checksums;
+        let mut frames = Vec::with_capacity(pages.len() * (FRAME_HEADER_SIZE + self.page_size));
+        for (i, (page_number, page)) in pages.iter().enumerate() {
+            let commit_size = match i + 1 == pages.len() {
+                true => database_size as u32,
+                false => 0,
+            };
+            let mut frame_header = [0; FRAME_HEADER_SIZE];
+            frame_header[..4].copy_from_slice(&(*page_number as u32).to_be_bytes());
+            frame_header[4..8].copy_from_slice(&commit_size.to_be_bytes());
+            frame_header[8..12].copy_from_slice(&header.salts[0].to_be_bytes());
+            frame_header[12..16].copy_from_slice(&header.salts[1].to_be_bytes());
+            sums = checksum(&frame_header[..8], sums, header.big_endian);
+            sums = checksum(page, sums, header.big_endian);
+            frame_header[16..20].copy_from_slice(&sums[0].to_be_bytes());
+            frame_header[20..24].copy_from_slice(&sums[1].to_be_bytes());
+            frames.extend_from_slice(&frame_header);
+            frames.extend_from_slice(page);
+        }
+        file.write_all_at(&frames, self.frame_offset(header.frame_count))?;
+        file.sync_all()?;
+
+        let page_numbers = pages
+            .keys()
+            .map(|&page_number| page_number as u32)
+            .collect::<Vec<_>>();
+        self.index.append(header.frame_count + 1, &page_numbers)?;
+        for (i, &page_number) in pages.keys().enumerate() {
+            self.frames.insert(page_number, header.frame_count + i);
+        }
+        header.change = header.change.wrapping_add(1);
+        header.frame_count += pages.len();
+        header.database_size = database_size;
+        header.checksums = sums;
+        self.index.write_header(&header)?;
+        self.header = Some(header);
+        Ok(())
+    }
+
+    /// Copies the latest version of the pages of the log back into the database file, as
+    /// mentioned here: [checkpoint](https://www.sqlite.org/walformat.html#checkpoint_algorithm).
+    /// Returns whether the checkpoint could not do all its mode asks for, with the number of
+    /// frames in the log and the number of frames copied, as `PRAGMA wal_checkpoint` does.
+    ///
+    /// Frames a reader may still need are not copied: readers of the database file alone keep
+    /// any frame from being copied, and readers of the log the frames after their read mark.
+    /// Modes other than `PASSIVE` are blocked by another connection writing, and then only do
+    /// what `PASSIVE` does. The frames read by the connection are read again by its next read
+    /// transaction
+    pub fn checkpoint(
+        &mut self,
+        database: &File,
+        mode: CheckpointMode,
+    ) -> Result<(bool, usize, usize)> {
+        if self.read_lock.is_some() {
+            bail!("Cannot checkpoint the log while reading it");
+        }
+        if !self
+            .index
+            .try_set_lock(LockType::WriteLock, CHECKPOINT_LOCK)?
+        {
+            bail!("database is locked");
+        }
+        let writing = mode != CheckpointMode::Passive
+            && self.index.try_set_lock(LockType::WriteLock, WRITE_LOCK)?;
+        let checkpoint = self.checkpoint_locked(database, mode, writing);
+        if writing {
+            self.index.set_lock(LockType::Unlock, WRITE_LOCK)?;
+        }
+        self.index.set_lock(LockType::Unlock, CHECKPOINT_LOCK)?;
+        let (busy, frame_count, backfilled) = checkpoint?;
+        let blocked = mode != CheckpointMode::Passive && !writing;
+        Ok((busy || blocked, frame_count, backfilled))
+    }
+
+    fn checkpoint_locked(
+        &mut self,
+        database: &File,
+        mode: CheckpointMode,
+        writing: bool,
+    ) -> Result<(bool, usize, usize)> {
+        if self.index.header()?.is_none() {
+            self.recover()?;
+        }
+        let header = match self.index.header()? {
+            Some(header) => header,
+            None => bail!("database is locked"),
+        };
+        let mut info = self.index.checkpoint_info()?;
+
+        // The frames after the read mark of a reader of the log are not copied, unless the
+        // reader is gone, in which case its read mark is moved to the last frame
+        let mut safe_frame = header.frame_count as u32;
+        for i in 1..READ_LOCK_COUNT {
+            let mark = info.read_marks[i];
+            if safe_frame <= mark {
+                continue;
+            }
+            let lock = READ_LOCK + i as i64;
+            if self.index.try_set_lock(LockType::WriteLock, lock)? {
+                info.read_marks[i] = match i {
+                    1 => safe_frame,
+                    _ => READ_MARK_NOT_USED,
+                };
+                self.index.write_read_mark(i, info.read_marks[i])?;
+                self.index.set_lock(LockType::Unlock, lock)?;
+            } else {
+                safe_frame = mark;
+            }
+        }
+
+        if info.backfilled < safe_frame
+            && self.index.try_set_lock(LockType::WriteLock, READ_LOCK)?
+        {
+            let copied = self.copy_frames(database, &header, &mut info, safe_frame);
+            self.index.set_lock(LockType::Unlock, READ_LOCK)?;
+            copied?;
+        }
+
+        let mut busy = false;
+        let (mut frame_count, mut backfilled) = (header.frame_count, info.backfilled as usize);
+        if writing {
+            if backfilled < header.frame_count {
+                busy = true;
+            } else if mode >= CheckpointMode::Restart {
+                let log_readers_gone = try_set_lock(
+                    &self.index.file,
+                    LockType::WriteLock,
+                    LOG_READ_LOCK,
+                    LOG_READ_LOCK_COUNT,
+                )?;
+                if log_readers_gone {
+                    let restarted = self.restart(&header).and_then(|_| match mode {
+                        CheckpointMode::Truncate => self.truncate(),
+                        _ => Ok(()),
+                    });
+                    set_lock(
+                        &self.index.file,
+                        LockType::Unlock,
+                        LOG_READ_LOCK,
+                        LOG_READ_LOCK_COUNT,
+                    )?;
+                    restarted?;
+                    frame_count = 0;
+                    backfilled = 0;
+                } else {
+                    busy = true;
+                }
+            }
+        }
+        Ok((busy, frame_count, backfilled))
+    }
+
+    /// Copies the frames after those already copied up to `safe_frame` into the database file,
+    /// with no reader of the database file alone left to see them change. The database file
+    /// shrinks to the size of the database once all the frames are copied
+    fn copy_frames(
+        &mut self,
+        database: &File,
+        header: &IndexHeader,
+        info: &mut CheckpointInfo,
+        safe_frame: u32,
+    ) -> Result<()> {
+        info.backfill_attempted = safe_frame;
+        self.index.write_checkpoint_info(info)?;
+        let file = self.log_file()?;
+        file.sync_all()?;
+        let page_numbers = self.index.page_numbers(safe_frame as usize)?;
+        let latest = page_numbers
+            .iter()
+            .enumerate()
+            .skip(info.backfilled as usize)
+            .map(|(frame, &page_number)| (page_number as usize, frame))
+            .collect::<BTreeMap<_, _>>();
+        let mut page = vec![0; self.page_size];
+        for (&page_number, &frame) in &latest {
+            file.read_exact_at(
+                &mut page,
+                self.frame_offset(frame) + FRAME_HEADER_SIZE as u64,
+            )?;
+            database.write_all_at(&page, ((page_number - 1) * self.page_size) as u64)?;
+        }
+        if safe_frame as usize == header.frame_count {
+            database.set_len((header.database_size * self.page_size) as u64)?;
+        }
+        database.sync_all()?;
+        info.backfilled = safe_frame;
+        self.index.write_checkpoint_info(info)
+    }
+
+    /// Starts the log over once all its frames were copied back into the database file, under
+    /// the read locks of readers of the log. New salts are chosen, which the next transaction
+    /// writes to the header of the log, invalidating the frames after its own
+    fn restart(&mut self, header: &IndexHeader) -> Result<IndexHeader> {
+        let header = IndexHeader {
+            change: header.change.wrapping_add(1),
+            frame_count: 0,
+            salts: [header.salts[0].wrapping_add(1), random()],
+            ..header.clone()
+        };
+        self.index.write_header(&header)?;
+        let mut read_marks = [READ_MARK_NOT_USED; READ_LOCK_COUNT];
+        read_marks[0] = 0;
+        read_marks[1] = 0;
+        self.index.write_checkpoint_info(&CheckpointInfo {
+            backfilled: 0,
+            read_marks,
+            backfill_attempted: 0,
+        })?;
+        Ok(header)
+    }
+
+    /// Empties the log once it was started over
+    fn truncate(&mut self) -> Result<()> {
+        let file = self.log_file()?;
+        file.set_len(0)?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    /// Returns the file of the log, creating it when it does not exist yet
+    fn log_file(&mut self) -> Result<File> {
+        if let Some(file) = &self.file {
+            return Ok(file.try_clone()?);
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.path)?;
+        self.file = Some(file.try_clone()?);
+        Ok(file)
+    }
+
+    fn uses_log(&self) -> bool {
+        matches!(self.read_lock, Some(read_lock) if read_lock != 0)
+    }
+
+    /// Takes a read lock for the frames the wal-index holds, returning whether the wal-index
+    /// stayed the same meanwhile. Readers take the first read lock when the database file holds
+    /// all the frames, and otherwise one whose read mark is the last frame
+    fn try_begin_read(&mut self) -> Result<bool> {
+        let header = match self.index.header()? {
+            Some(header) => header,
+            None => {
+                self.recover()?;
+                return Ok(false);
+            }
+        };
+        let info = self.index.checkpoint_info()?;
+        let (read_lock, mark) = match header.frame_count as u32 == info.backfilled {
+            true => (0, 0),
+            false => match self.read_mark(&header, &info)? {
+                Some(read_mark) => read_mark,
+                None => return Ok(false),
+            },
+        };
+        let lock = READ_LOCK + read_lock as i64;
+        if !self.index.try_set_lock(LockType::ReadLock, lock)? {
+            return Ok(false);
+        }
+        // The wal-index may have changed before the lock was taken
+        if self.index.header()?.as_ref() != Some(&header)
+            || self.index.checkpoint_info()?.read_marks[read_lock] != mark
+        {
+            self.index.set_lock(LockType::Unlock, lock)?;
+            return Ok(false);
+        }
+        self.read_lock = Some(read_lock);
+        if let Err(err) = self.read_frames(header) {
+            self.end_read()?;
+            return Err(err);
+        }
+        Ok(true)
+    }
+
+    /// Returns the read lock whose read mark is the closest to the last frame, with its read
+    /// mark, setting the read mark of one no other connection holds to the last frame when none
+    /// is
+    fn read_mark(
+        &self,
+        header: &IndexHeader,
+        info: &CheckpointInfo,
+    ) -> Result<Option<(usize, u32)>> {
+        let frame_count = header.frame_count as u32;
+        let best = (1..READ_LOCK_COUNT)
+            .filter(|&i| info.read_marks[i] <= frame_count)
+            .max_by_key(|&i| info.read_marks[i]);
+        let best = best.map(|i| (i, info.read_marks[i]));
+        if matches!(best, Some((_, mark)) if mark == frame_count) {
+            return Ok(best);
+        }
+        for i in 1..READ_LOCK_COUNT {
+            let lock = READ_LOCK + i as i64;
+            if self.index.try_set_lock(LockType::WriteLock, lock)? {
+                self.index.write_read_mark(i, frame_count)?;
+                self.index.set_lock(LockType::Unlock, lock)?;
+                return Ok(Some((i, frame_count)));
+            }
+        }
+        Ok(best)
+    }
+
+    /// Indexes the frames of the log the wal-index holds, unless they already are
+    fn read_frames(&mut self, header: IndexHeader) -> Result<()> {
+        if self.uses_log() && self.file.is_none() {
+            let file = OpenOptions::new().read(true).write(true).open(&self.path);
+            self.file = Some(file.or_else(|_| File::open(&self.path))?);
+        }
+        if self.header.as_ref() == Some(&header) {
+            return Ok(());
+        }
+        let page_numbers = self.index.page_numbers(header.frame_count)?;
+        self.frames = page_numbers
+            .iter()
+            .enumerate()
+            .map(|(frame, &page_number)| (page_number as usize, frame))
+            .collect();
+        self.header = Some(header);
+        Ok(())
+    }
+
+    /// Rebuilds the wal-index from the log, as SQLite does when it is not valid, under the write
+    /// and checkpoint locks. Another connection holding them is rebuilding it already
+    fn recover(&mut self) -> Result<()> {
+        if !self.index.try_set_lock(LockType::WriteLock, WRITE_LOCK)? {
+            return Ok(());
+        }
+        let recovered = match self
+            .index
+            .try_set_lock(LockType::WriteLock, CHECKPOINT_LOCK)
+        {
+            Ok(true) => {
+                let recovered = match self.index.header() {
+                    Ok(Some(_)) => Ok(()),
+                    Ok(None) => self.read_log().and_then(|log| self.index.write(&log)),
+                    Err(err) => Err(err),
+                };
+                self.index
+                    .set_lock(LockType::Unlock, CHECKPOINT_LOCK)
+                    .and(recovered)
+            }
+            Ok(false) => Ok(()),
+            Err(err) => Err(err),
+        };
+        self.index
+            .set_lock(LockType::Unlock, WRITE_LOCK)
+            .and(recovered)
+    }
+
+    /// Reads the frames of the log, which is empty unless it exists with a valid header. Frames
+    /// are valid when their salts match those of the header and their checksums those computed
+    /// over the log up to them, and only those up to the last valid frame committing a
+    /// transaction are used
+    fn read_log(&self) -> Result<Log> {
+        let mut log = Log {
+            page_size: self.page_size,
+            big_endian: false,
+            salts: [0, 0],
+            checksums: [0, 0],
+            page_numbers: vec![],
+            database_size: 0,
+        };
+        let mut bytes = vec![];
+        match File::open(&self.path) {
+            Ok(mut file) => file.read_to_end(&mut bytes)?,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(log),
+            Err(err) => return Err(err.into()),
+        };
+        if bytes.len() < HEADER_SIZE {
+            return Ok(log);
+        }
+        let read_u32 = |offset: usize| -> Result<u32> {
+            Ok(u32::from_be_bytes(bytes[offset..offset + 4].try_into()?))
+        };
+        let magic = read_u32(0)?;
+        if magic & !1 != MAGIC || read_u32(8)? as usize != self.page_size {
+            return Ok(log);
+        }
+        let big_endian = magic & 1 == 1;
+        let mut sums = checksum(&bytes[..24], [0, 0], big_endian);
+        if sums != [read_u32(24)?, read_u32(28)?] {
+            return Ok(log);
+        }
+        log.big_endian = big_endian;
+        log.salts = [read_u32(16)?, read_u32(20)?];
+        log.checksums = sums;
+
+        let frame_size = FRAME_HEADER_SIZE + self.page_size;
+        let mut pending = vec![];
+        let mut offset = HEADER_SIZE;
+        while offset + frame_size <= bytes.len() {
+            let page_number = read_u32(offset)?;
+            let commit_size = read_u32(offset + 4)? as usize;
+            if [read_u32(offset + 8)?, read_u32(offset + 12)?] != log.salts {
+                break;
+            }
+            sums = checksum(&bytes[offset..offset + 8], sums, big_endian);
+            sums = checksum(&bytes[offset + 24..offset + frame_size], sums, big_endian);
+            if sums != [read_u32(offset + 16)?, read_u32(offset + 20)?] {
+                break;
+            }
+            pending.push(page_number);
+            // A frame with the size of the database commits the frames of its transaction
+            if commit_size != 0 {
+                log.page_numbers.append(&mut pending);
+                log.checksums = sums;
+                log.database_size = commit_size;
+            }
+            offset += frame_size;
+        }
+        Ok(log)
+    }
+
+    fn frame_offset(&self, frame: usize) -> u64 {
+        (HEADER_SIZE + frame * (FRAME_HEADER_SIZE + self.page_size)) as u64
+    }
+}
+
+/// The committed frames of a log, as read when rebuilding the wal-index
+struct Log {
+    page_size: usize,
+    /// Whether the checksums read words as big-endian
+    big_endian: bool,
+    salts: [u32; 2],
+    /// The checksums of the last frame committing a transaction
+    checksums: [u32; 2],
+    /// The page of each frame
+    page_numbers: Vec<u32>,
+    /// The size of the database in pages after the last committed transaction
+    database_size: usize,
+}
+
+/// The header of the wal-index, which tells the frames of the log that were committed, as
+/// mentioned here: [header](https://www.sqlite.org/walformat.html#the_wal_index_header). Its
+/// integers are in the byte order of the machine, but for the salts copied from the log
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IndexHeader {
+    /// Changed by every transaction
+    change: u32,
+    big_endian: bool,
+    page_size: usize,
+    /// The number of committed frames
+    frame_count: usize,
+    database_size: usize,
+    /// The checksums of the last committed frame
+    checksums: [u32; 2],
+    salts: [u32; 2],
+}
+
+impl IndexHeader {
+    /// Parses the header of a wal-index, which is only valid when both its copies match and
+    /// its checksum is right
+    fn parse(shm: &[u8]) -> Option<Self> {
+        if shm.len() < 2 * INDEX_COPY_SIZE
+            || shm[..INDEX_COPY_SIZE] != shm[INDEX_COPY_SIZE..2 * INDEX_COPY_SIZE]
+        {
+            return None;
+        }
+        let read_u32 = |offset: usize| {
+            u32::from_ne_bytes([
+                shm[offset],
+                shm[offset + 1],
+                shm[offset + 2],
+                shm[offset + 3],
+            ])
+        };
+        let sums = checksum(&shm[..40], [0, 0], cfg!(target_endian = "big"));
+        if shm[12] != 1 || sums != [read_u32(40), read_u32(44)] {
+            return None;
+        }
+        // A page size of 65536 is stored as 1
+        let page_size = u16::from_ne_bytes([shm[14], shm[15]]) as usize;
+        Some(IndexHeader {
+            change: read_u32(8),
+            big_endian: shm[13] == 1,
+            page_size: (page_size & 0xfe00) | (page_size & 1) << 16,
+            frame_count: read_u32(16) as usize,
+            database_size: read_u32(20) as usize,
+            checksums: [read_u32(24), read_u32(28)],
+            salts: [
+                u32::from_be_bytes([shm[32], shm[33], shm[34], shm[35]]),
+                u32::from_be_bytes([shm[36], shm[37], shm[38], shm[39]]),
+            ],
+        })
+    }
+
+    fn to_bytes(&self) -> [u8; INDEX_COPY_SIZE] {
+        let page_size = (self.page_size & 0xff00 | self.page_size >> 16) as u16;
+        let mut header = [0; INDEX_COPY_SIZE];
+        header[..4].copy_from_slice(&VERSION.to_ne_bytes());
+        header[8..12].copy_from_slice(&self.change.to_ne_bytes());
+        header[12] = 1;
+        header[13] = self.big_endian as u8;
+        header[14..16].copy_from_slice(&page_size.to_ne_bytes());
+        header[16..20].copy_from_slice(&(self.frame_count as u32).to_ne_bytes());
+        header[20..24].copy_from_slice(&(self.database_size as u32).to_ne_bytes());
+        header[24..28].copy_from_slice(&self.checksums[0].to_ne_bytes());
+        header[28..32].copy_from_slice(&self.checksums[1].to_ne_bytes());
+        header[32..36].copy_from_slice(&self.salts[0].to_be_bytes());
+        header[36..40].copy_from_slice(&self.salts[1].to_be_bytes());
+        let sums = checksum(&header[..40], [0, 0], cfg!(target_endian = "big"));
+        header[40..44].copy_from_slice(&sums[0].to_ne_bytes());
+        header[44..48].copy_from_slice(&sums[1].to_ne_bytes());
+        header
+    }
+}
+
+/// The checkpoint information following the header of the wal-index
+struct CheckpointInfo {
+    /// The number of frames copied back into the database file
+    backfilled: u32,
+    /// The last frame each reader holding the read lock of the same index may use
+    read_marks: [u32; READ_LOCK_COUNT],
+    /// The last frame a checkpoint started copying
+    backfill_attempted: u32,
+}
+
+/// The wal-index SQLite shares between its connections, in the shared memory file next to the
+/// log, as mentioned here:
+/// [wal-index](https://www.sqlite.org/walformat.html#the_wal_index_file_format). Its locks are
+/// POSIX advisory locks on bytes of the file
+struct WalIndex {
+    file: File,
+}
+
+impl WalIndex {
+    /// Opens a wal-index, holding the shared lock of the connections using it for as long as it
+    /// is open. The first connection to use it clears it, as it may have been left invalid by a
+    /// connection that crashed
+    fn open(path: &str) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        if try_set_lock(&file, LockType::WriteLock, CONNECTION_LOCK, 1)? {
+            file.set_len(0)?;
+        }
+        set_lock(&file, LockType::ReadLock, CONNECTION_LOCK, 1)?;
+        Ok(WalIndex { file })
+    }
+
+    fn set_lock(&self, lock_type: LockType, lock: i64) -> Result<()> {
+        set_lock(&self.file, lock_type, lock, 1)
+    }
+
+    fn try_set_lock(&self, lock_type: LockType, lock: i64) -> Result<bool> {
+        try_set_lock(&self.file, lock_type, lock, 1)
+    }
+
+    /// Reads the header of the wal-index, unless it is not valid
+    fn header(&self) -> Result<Option<IndexHeader>> {
+        let mut shm = [0; 2 * INDEX_COPY_SIZE];
+        match self.file.read_exact_at(&mut shm, 0) {
+            Ok(()) => Ok(IndexHeader::parse(&shm)),
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn checkpoint_info(&self) -> Result<CheckpointInfo> {
+        let mut info = [0; INDEX_HEADER_SIZE - 2 * INDEX_COPY_SIZE];
+        self.file
+            .read_exact_at(&mut info, 2 * INDEX_COPY_SIZE as u64)?;
+        let read_u32 = |offset: usize| {
+            u32::from_ne_bytes([
+                info[offset],
+                info[offset + 1],
+                info[offset + 2],
+                info[offset + 3],
+            ])
+        };
+        let mut read_marks = [0; READ_LOCK_COUNT];
+        for (i, mark) in read_marks.iter_mut().enumerate() {
+            *mark = read_u32(4 + 4 * i);
+        }
+        Ok(CheckpointInfo {
+            backfilled: read_u32(0),
+            read_marks,
+            backfill_attempted: read_u32(32),
+        })
+    }
+
+    fn write_read_mark(&self, read_lock: usize, mark: u32) -> Result<()> {
+        let offset = 2 * INDEX_COPY_SIZE + 4 + 4 * read_lock;
+        self.file.write_all_at(&mark.to_ne_bytes(), offset as u64)?;
+        Ok(())
+    }
+
+    /// Reads the page of each of the first frames from the blocks of the wal-index
+    fn page_numbers(&self, frame_count: usize) -> Result<Vec<u32>> {
+        if frame_count == 0 {
+            return Ok(vec![]);
+        }
+        let mut index = vec![0; index_page_offset(frame_count) + 4];
+        self.file.read_exact_at(&mut index, 0)?;
+        Ok((1..=frame_count)
+            .map(|frame| {
+                let offset = index_page_offset(frame);
+                u32::from_ne_bytes([
+                    index[offset],
+                    index[offset + 1],
+                    index[offset + 2],
+                    index[offset + 3],
+                ])
+            })
+            .collect())
+    }
+
+    /// Writes the wal-index of the frames of a log, as SQLite does when recovering it. Readers
+    /// are given the last frame as the read mark of the second read lock, when no reader holds
+    /// it
+    fn write(&self, log: &Log) -> Result<()> {
+        let frame_count = log.page_numbers.len();
+        // The first block is always there, even with no frames to index
+        if self.file.metadata()?.len() < INDEX_BLOCK_SIZE as u64 {
+            self.file.set_len(INDEX_BLOCK_SIZE as u64)?;
+        }
+        self.append(1, &log.page_numbers)?;
+        let mut info = match self.checkpoint_info() {
+            Ok(info) => info,
+            Err(_) => CheckpointInfo {
+                backfilled: 0,
+                read_marks: [READ_MARK_NOT_USED; READ_LOCK_COUNT],
+                backfill_attempted: 0,
+            },
+        };
+        info.backfilled = 0;
+        info.read_marks[0] = 0;
+        info.backfill_attempted = frame_count as u32;
+        for (i, mark) in info.read_marks.iter_mut().enumerate().skip(1) {
+            let lock = READ_LOCK + i as i64;
+            if self.try_set_lock(LockType::WriteLock, lock)? {
+                *mark = match (i, frame_count) {
+                    (1, n) if n > 0 => n as u32,
+                    _ => READ_MARK_NOT_USED,
+                };
+                self.set_lock(LockType::Unlock, lock)?;
+            }
+        }
+        self.write_checkpoint_info(&info)?;
+        let change = self.header()?.map_or(0, |header| header.change);
+        self.write_header(&IndexHeader {
+            change: change.wrapping_add(1),
+            big_endian: log.big_endian,
+            page_size: log.page_size,
+            frame_count,
+            database_size: log.database_size,
+            checksums: log.checksums,
+            salts: log.salts,
+        })
+    }
+
+    /// Records the pages of frames added to the log, numbered from `first_frame`, in the blocks
+    /// of the wal-index, before the header tells readers about them. The hash tables of the
+    /// blocks holding them are rebuilt, which drops the entries left by frames of transactions
+    /// that did not commit
+    fn append(&self, first_frame: usize, page_numbers: &[u32]) -> Result<()> {
+        if page_numbers.is_empty() {
+            return Ok(());
+        }
+        let first_block = index_block(first_frame);
+        let last_frame = first_frame + page_numbers.len() - 1;
+        let mut index = vec![0; (index_block(last_frame) + 1) * INDEX_BLOCK_SIZE];
+        let block_start = index_block_first_frame(first_block);
+        let mut pages = self
+            .page_numbers(first_frame - 1)?
+            .split_off(block_start - 1);
+        pages.extend_from_slice(page_numbers);
+        for (i, &page_number) in pages.iter().enumerate() {
+            add_to_index(&mut index, block_start + i, page_number);
+        }
+        let offset = match first_block {
+            0 => INDEX_HEADER_SIZE,
+            block => block * INDEX_BLOCK_SIZE,
+        };
+        self.file.write_all_at(&index[offset..], offset as u64)?;
+        Ok(())
+    }
+
+    /// Writes the header of the wal-index, the second copy before the first one, as readers
+    /// expect
+    fn write_header(&self, header: &IndexHeader) -> Result<()> {
+        let header = header.to_bytes();
+        self.file.write_all_at(&header, INDEX_COPY_SIZE as u64)?;
+        self.file.write_all_at(&header, 0)?;
+        Ok(())
+    }
+
+    /// Writes the checkpoint information, leaving the lock bytes alone
+    fn write_checkpoint_info(&self, info: &CheckpointInfo) -> Result<()> {
+        let mut bytes = info.backfilled.to_ne_bytes().to_vec();
+        for mark in &info.read_marks {
+            bytes.extend_from_slice(&mark.to_ne_bytes());
+        }
+        self.file.write_all_at(&bytes, 2 * INDEX_COPY_SIZE as u64)?;
+        self.file.write_all_at(
+            &info.backfill_attempted.to_ne_bytes(),
+            (INDEX_HEADER_SIZE - 8) as u64,
+        )?;
+        Ok(())
+    }
+}
+
+/// Returns the block of the wal-index holding a frame, numbered from 1
+fn index_block(frame: usize) -> usize {
+    (frame + INDEX_BLOCK_FRAMES - INDEX_FIRST_BLOCK_FRAMES - 1) / INDEX_BLOCK_FRAMES
+}
+
+/// Returns the first frame of a block of the wal-index, numbered from 1
+fn index_block_first_frame(block: usize) -> usize {
+    match block {
+        0 => 1,
+        _ => INDEX_FIRST_BLOCK_FRAMES + (block - 1) * INDEX_BLOCK_FRAMES + 1,
+    }
+}
+
+/// Returns where the wal-index holds the page of a frame, numbered from 1, in the list of pages
+/// of its block
+fn index_page_offset(frame: usize) -> usize {
+    let block = index_block(frame);
+    let pages = match block {
+        0 => INDEX_HEADER_SIZE,
+        _ => block * INDEX_BLOCK_SIZE,
+    };
+    pages + 4 * (frame - index_block_first_frame(block))
+}
+
+/// Records the page of a frame, numbered from 1, in the block of the wal-index holding it: in
+/// the list of pages of the block, and in its hash table, whose slots hold the position of the
+/// frame in the block, numbered from 1, at the first free slot from the hash of the page
+fn add_to_index(index: &mut [u8], frame: usize, page_number: u32) {
+    let page = index_page_offset(frame);
+    index[page..page + 4].copy_from_slice(&page_number.to_ne_bytes());
+
+    let block = index_block(frame);
+    let position = frame - index_block_first_frame(block) + 1;
+    let hash_table = block * INDEX_BLOCK_SIZE + 4 * INDEX_BLOCK_FRAMES;
+    let mut slot = (page_number as usize * 383) & (INDEX_HASH_SLOTS - 1);
+    while index[hash_table + 2 * slot..hash_table + 2 * slot + 2] != [0, 0] {
+        slot = (slot + 1) & (INDEX_HASH_SLOTS - 1);
+    }
+    let entry = hash_table + 2 * slot;
+    index[entry..entry + 2].copy_from_slice(&(position as u16).to_ne_bytes());
+}
+
+/// Writes the header of a new log, returning its checksums, which those of the first frame
+/// continue
+fn write_log_header(file: &File, header: &IndexHeader) -> Result<[u32; 2]> {
+    let sequence = {
+        let mut previous = [0; 4];
+        match file.read_exact_at(&mut previous, 12) {
+            Ok(()) => u32::from_be_bytes(previous).wrapping_add(1),
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => 0,
+            Err(err) => return Err(err.into()),
+        }
+    };
+    let mut bytes = [0; HEADER_SIZE];
+    bytes[..4].copy_from_slice(&(MAGIC | header.big_endian as u32).to_be_bytes());
+    bytes[4..8].copy_from_slice(&VERSION.to_be_bytes());
+    bytes[8..12].copy_from_slice(&(header.page_size as u32).to_be_bytes());
+    bytes[12..16].copy_from_slice(&sequence.to_be_bytes());
+    bytes[16..20].copy_from_slice(&header.salts[0].to_be_bytes());
+    bytes[20..24].copy_from_slice(&header.salts[1].to_be_bytes());
+    let sums = checksum(&bytes[..24], [0, 0], header.big_endian);
+    bytes[24..28].copy_from_slice(&sums[0].to_be_bytes());
+    bytes[28..32].copy_from_slice(&sums[1].to_be_bytes());
+    file.write_all_at(&bytes, 0)?;
+    Ok(sums)
+}
+
+/// Returns a random number, for the salts of a new log
+fn random() -> u32 {
+    RandomState::new().build_hasher().finish() as u32
+}
+
+/// Adds the words of `data` to the running checksums of the log, as mentioned here:
+/// [checksum algorithm](https://www.sqlite.org/fileformat2.html#checksum_algorithm)
+fn checksum(data: &[u8], [mut s0, mut s1]: [u32; 2], big_endian: bool) -> [u32; 2] {
+    let word = |bytes: &[u8]| {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        match big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        }
+    };
+    for pair in data.chunks_exact(8) {
+        s0 = s0.wrapping_add(word(&pair[..4])).wrapping_add(s1);
+        s1 = s1.wrapping_add(word(&pair[4..])).wrapping_add(s0);
+    }
+    [s0, s1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::process::Command;
+
+    const PAGE_SIZE: usize = 512;
+
+    /// Returns a new empty directory for the files of a test
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("wal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Returns the path of the log of a database in a new directory
+    fn test_wal(name: &str) -> String {
+        test_dir(name)
+            .join("test.db-wal")
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn remove_test_wal(path: &str) {
+        fs::remove_dir_all(std::path::Path::new(path).parent().unwrap()).unwrap();
+    }
+
+    fn page(page_number: u32, fill: u8) -> Vec<u8> {
+        vec![fill + page_number as u8; PAGE_SIZE]
+    }
+
+    fn frame_offset(frame: usize) -> usize {
+        HEADER_SIZE + frame * (FRAME_HEADER_SIZE + PAGE_SIZE)
+    }
+
+    /// Builds a log of transactions, each given by its pages, the size of the database it
+    /// commits and the byte its pages are filled with, added to their number
+    fn build_log(transactions: &[(&[u32], u32, u8)], big_endian: bool) -> Vec<u8> {
+        let mut log = vec![0; HEADER_SIZE];
+        log[..4].copy_from_slice(&(MAGIC | big_endian as u32).to_be_bytes());
+        log[4..8].copy_from_slice(&VERSION.to_be_bytes());
+        log[8..12].copy_from_slice(&(PAGE_SIZE as u32).to_be_bytes());
+        log[16..24].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        let mut sums = checksum(&log[..24], [0, 0], big_endian);
+        log[24..28].copy_from_slice(&sums[0].to_be_bytes());
+        log[28..32].copy_from_slice(&sums[1].to_be_bytes());
+        for &(page_numbers, database_size, fill) in transactions {
+            for (i, &page_number) in page_numbers.iter().enumerate() {
+                let commit_size = match i + 1 == page_numbers.len() {
+                    true => database_size,
+                    false => 0,
+                };
+                let mut header = [0; FRAME_HEADER_SIZE];
+                header[..4].copy_from_slice(&page_number.to_be_bytes());
+                header[4..8].copy_from_slice(&commit_size.to_be_bytes());
+                header[8..16].copy_from_slice(&log[16..24]);
+                let page = page(page_number, fill);
+                sums = checksum(&header[..8], sums, big_endian);
+                sums = checksum(&page, sums, big_endian);
+                header[16..20].copy_from_slice(&sums[0].to_be_bytes());
+                header[20..24].copy_from_slice(&sums[1].to_be_bytes());
+                log.extend_from_slice(&header);
+                log.extend_from_slice(&page);
+            }
+        }
+        log
+    }
+
+    /// Opens a log and starts reading it, which builds its wal-index from it
+    fn read_wal(path: &str) -> Wal {
+        let mut wal = Wal::open(path, PAGE_SIZE).unwrap();
+        wal.begin_read().unwrap();
+        wal
+    }
+
+    fn shm_path(wal_path: &str) -> String {
+        format!("{}-shm", wal_path.trim_end_matches("-wal"))
+    }
+
+    #[test]
+    fn reads_the_latest_committed_frame_of_each_page() {
+        let path = test_wal("frames");
+        let transactions: &[(&[u32], u32, u8)] = &[(&[1, 2], 2, 10), (&[2, 3], 3, 20)];
+        fs::write(&path, build_log(transactions, false)).unwrap();
+        let wal = read_wal(&path);
+        assert_eq!(wal.read_lock, Some(1));
+        assert_eq!(wal.database_size(), Some(3));
+        assert_eq!(wal.read_page(1).unwrap(), Some(page(1, 10)));
+        assert_eq!(wal.read_page(2).unwrap(), Some(page(2, 20)));
+        assert_eq!(wal.read_page(4).unwrap(), None);
+
+        // The wal-index tells the frames of the log, with the read mark of the reader
+        let shm = fs::read(shm_path(&path)).unwrap();
+        let header = IndexHeader::parse(&shm).unwrap();
+        assert_eq!(header.frame_count, 4);
+        assert_eq!(header.database_size, 3);
+        assert_eq!(header.salts, [0x01020304, 0x05060708]);
+        assert_eq!(wal.index.page_numbers(4).unwrap(), [1, 2, 2, 3]);
+        let info = wal.index.checkpoint_info().unwrap();
+        assert_eq!(info.backfilled, 0);
+        assert_eq!(
+            info.read_marks,
+            [
+                0,
+                4,
+                READ_MARK_NOT_USED,
+                READ_MARK_NOT_USED,
+                READ_MARK_NOT_USED
+            ]
+        );
+        remove_test_wal(&path);
+    }
+
+    #[test]
+    fn ignores_frames_of_transactions_that_did_not_commit() {
+        let path = test_wal("commit");
+        let transactions: &[(&[u32], u32, u8)] = &[(&[1, 2], 2, 10), (&[2, 3], 3, 20)];
+        let log = build_log(transactions, false);
+        // The first frame of the second transaction is valid, but does not commit it
+        for &length in &[frame_offset(3), frame_offset(3) + 100] {
+            fs::write(&path, &log[..length]).unwrap();
+            let wal = read_wal(&path);
+            assert_eq!(wal.header.as_ref().unwrap().frame_count, 2);
+            assert_eq!(wal.database_size(), Some(2));
+            assert_eq!(wal.read_page(2).unwrap(), Some(page(2, 10)));
+            assert_eq!(wal.read_page(3).unwrap(), None);
+        }
+        remove_test_wal(&path);
+    }
+
+    #[test]
+    fn stops_at_the_first_frame_failing_its_checksum_or_salts() {
+        let path = test_wal("checksum");
+        let transactions: &[(&[u32], u32, u8)] = &[(&[1], 3, 0), (&[2], 3, 0), (&[3], 3, 0)];
+        let log = build_log(transactions, false);
+        let mut corrupted = log.clone();
+        corrupted[frame_offset(1) + FRAME_HEADER_SIZE + 7] ^= 0xff;
+        fs::write(&path, &corrupted).unwrap();
+        let wal = read_wal(&path);
+        assert_eq!(wal.header.as_ref().unwrap().frame_count, 1);
+        assert_eq!(wal.read_page(1).unwrap(), Some(page(1, 0)));
+        assert_eq!(wal.read_page(2).unwrap(), None);
+
+        // A frame of a previous log has other salts
+        let mut corrupted = log.clone();
+        corrupted[frame_offset(2) + 15] ^= 0xff;
+        fs::write(&path, &corrupted).unwrap();
+        assert_eq!(read_wal(&path).header.unwrap().frame_count, 2);
+
+        // A header failing its checksum leaves the log empty, with no frames to read
+        let mut corrupted = log;
+        corrupted[13] ^= 0xff;
+        fs::write(&path, &corrupted).unwrap();
+        let wal = read_wal(&path);
+        assert_eq!(wal.header.as_ref().unwrap().frame_count, 0);
+        assert_eq!(wal.read_lock, Some(0));
+        assert_eq!(wal.read_page(1).unwrap(), None);
+        assert_eq!(wal.database_size(), None);
+        remove_test_wal(&path);
+    }
+
+    #[test]
+    fn reads_big_endian_checksums() {
+        let path = test_wal("endianness");
+        let transactions: &[(&[u32], u32, u8)] = &[(&[1, 2], 2, 10)];
+        let log = build_log(transactions, true);
+        // The checksums differ from little-endian ones
+        assert_ne!(build_log(transactions, false)[24..32], log[24..32]);
+        fs::write(&path, &log).unwrap();
+        let wal = read_wal(&path);
+        assert!(wal.header.as_ref().unwrap().big_endian);
+        assert_eq!(wal.read_page(2).unwrap(), Some(page(2, 10)));
+        remove_test_wal(&path);
+    }
+
+    #[test]
+    fn reads_the_frames_again_when_the_wal_index_changes() {
+        let path = test_wal("refresh");
+        let transactions: &[(&[u32], u32, u8)] = &[(&[1, 2], 2, 10), (&[2], 2, 20)];
+        let log = build_log(transactions, false);
+        fs::write(&path, &log[..frame_offset(2)]).unwrap();
+        let mut wal = read_wal(&path);
+        assert_eq!(wal.read_page(2).unwrap(), Some(page(2, 10)));
+
+        // The frames are those of the transaction until it ends
+        fs::write(&path, &log).unwrap();
+        fs::write(shm_path(&path), []).unwrap();
+        wal.begin_read().unwrap();
+        assert_eq!(wal.read_page(2).unwrap(), Some(page(2, 10)));
+        wal.end_read().unwrap();
+        assert_eq!(wal.read_lock, None);
+        wal.begin_read().unwrap();
+        assert_eq!(wal.read_page(2).unwrap(), Some(page(2, 20)));
+        wal.end_read().unwrap();
+
+        // Once the database file holds all the frames, the log is no longer read
+        wal.index
+            .file
+            .write_all_at(&3u32.to_ne_bytes(), 2 * INDEX_COPY_SIZE as u64)
+            .unwrap();
+        wal.begin_read().unwrap();
+        assert_eq!(wal.read_lock, Some(0));
+        assert_eq!(wal.read_page(2).unwrap(), None);
+        remove_test_wal(&path);
+    }
+
+    fn pages(numbers: &[u32], fill: u8) -> BTreeMap<usize, Vec<u8>> {
+        numbers
+            .iter()
+            .map(|&n| (n as usize, page(n, fill)))
+            .collect()
+    }
+
+    /// Appends the pages of a transaction to a log, as a connection does to commit
+    fn commit(wal: &mut Wal, pages: &BTreeMap<usize, Vec<u8>>, database_size: usize) {
+        wal.begin_read().unwrap();
+        wal.begin_write().unwrap();
+        wal.append(pages, database_size).unwrap();
+        wal.end_write().unwrap();
+        wal.end_read().unwrap();
+    }
+
+    /// Creates the empty database file a log belongs to
+    fn test_database(path: &str) -> File {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path.trim_end_matches("-wal"))
+            .unwrap()
+    }
+
+    #[test]
+    fn appends_transactions_that_can_be_read_back() {
+        let path = test_wal("append");
+        let mut wal = Wal::open(&path, PAGE_SIZE).unwrap();
+        commit(&mut wal, &pages(&[1, 2], 10), 2);
+        commit(&mut wal, &pages(&[2, 3], 20), 3);
+        let header = wal.index.header().unwrap().unwrap();
+        assert_eq!(header.frame_count, 4);
+        assert_eq!(wal.index.page_numbers(4).unwrap(), [1, 2, 2, 3]);
+        drop(wal);
+
+        let log = fs::read(&path).unwrap();
+        let read_u32 =
+            |offset: usize| u32::from_be_bytes(log[offset..offset + 4].try_into().unwrap());
+        assert_eq!(read_u32(0), MAGIC | cfg!(target_endian = "big") as u32);
+        assert_eq!(read_u32(4), VERSION);
+        assert_eq!(read_u32(8), PAGE_SIZE as u32);
+        assert_eq!(log.len(), frame_offset(4));
+        // Every frame has the salts of the header, and the last one of each transaction the
+        // size of the database
+        for (frame, &commit_size) in [0, 2, 0, 3].iter().enumerate() {
+            let offset = frame_offset(frame);
+            assert_eq!(read_u32(offset + 4), commit_size);
+            assert_eq!(log[offset + 8..offset + 16], log[16..24]);
+        }
+        // The wal-index rebuilt from the log is the one written along with it, but for its
+        // change counter
+        let wal = read_wal(&path);
+        let rebuilt = wal.index.header().unwrap().unwrap();
+        assert_eq!(
+            IndexHeader {
+                change: header.change,
+                ..rebuilt
+            },
+            header
+        );
+        assert_eq!(wal.read_page(2).unwrap(), Some(page(2, 20)));
+        remove_test_wal(&path);
+    }
+
+    #[test]
+    fn writers_fail_once_another_connection_added_frames() {
+        let path = test_wal("stale");
+        let mut wal = Wal::open(&path, PAGE_SIZE).unwrap();
+        commit(&mut wal, &pages(&[1], 10), 1);
+        wal.begin_read().unwrap();
+        let mut other = Wal::open(&path, PAGE_SIZE).unwrap();
+        commit(&mut other, &pages(&[1], 20), 1);
+        let err = wal.begin_write().unwrap_err();
+        assert_eq!(err.to_string(), "database is locked");
+        assert_eq!(wal.read_page(1).unwrap(), Some(page(1, 10)));
+
+        // The next transaction reads the frames of the other connection
+        wal.end_read().unwrap();
+        commit(&mut wal, &pages(&[1], 30), 1);
+        assert_eq!(other.index.page_numbers(3).unwrap(), [1, 1, 1]);
+        remove_test_wal(&path);
+    }
+
+    #[test]
+    fn checkpoints_start_the_log_over_with_new_salts() {
+        let path = test_wal("salts");
+        let database = test_database(&path);
+        let mut wal = Wal::open(&path, PAGE_SIZE).unwrap();
+        commit(&mut wal, &pages(&[1, 2, 3], 10), 3);
+        let log = fs::read(&path).unwrap();
+        assert_eq!(
+            wal.checkpoint(&database, CheckpointMode::Passive).unwrap(),
+            (false, 3, 3)
+        );
+        assert_eq!(database.metadata().unwrap().len(), 3 * PAGE_SIZE as u64);
+        let mut copied = vec![0; PAGE_SIZE];
+        database
+            .read_exact_at(&mut copied, 2 * PAGE_SIZE as u64)
+            .unwrap();
+        assert_eq!(copied, page(3, 10));
+
+        // Readers of the database file alone let the next transaction start the log over, and
+        // the frames after the new one are those of the previous log
+        wal.begin_read().unwrap();
+        assert_eq!(wal.read_lock, Some(0));
+        wal.end_read().unwrap();
+        commit(&mut wal, &pages(&[2], 20), 3);
+        let restarted = fs::read(&path).unwrap();
+        assert_eq!(restarted.len(), frame_offset(3));
+        let read_u32 = |log: &[u8], offset: usize| {
+            u32::from_be_bytes(log[offset..offset + 4].try_into().unwrap())
+        };
+        assert_eq!(read_u32(&restarted, 12), read_u32(&log, 12) + 1);
+        assert_eq!(read_u32(&restarted, 16), read_u32(&log, 16) + 1);
+        drop(wal);
+        let wal = read_wal(&path);
+        assert_eq!(wal.header.as_ref().unwrap().frame_count, 1);
+        assert_eq!(wal.read_page(2).unwrap(), Some(page(2, 20)));
+        assert_eq!(wal.read_page(3).unwrap(), None);
+        remove_test_wal(&path);
+    }
+
+    #[test]
+    fn checkpoint_modes_restart_and_truncate_the_log() {
+        let path = test_wal("modes");
+        let database = test_database(&path);
+        let mut wal = Wal::open(&path, PAGE_SIZE).unwrap();
+        commit(&mut wal, &pages(&[1, 2], 10), 2);
+        commit(&mut wal, &pages(&[1], 20), 2);
+        assert_eq!(
+            wal.checkpoint(&database, CheckpointMode::Full).unwrap(),
+            (false, 3, 3)
+        );
+        // Copied frames are not copied again
+        assert_eq!(
+            wal.checkpoint(&database, CheckpointMode::Passive).unwrap(),
+            (false, 3, 3)
+        );
+        assert_eq!(
+            wal.checkpoint(&database, CheckpointMode::Restart).unwrap(),
+            (false, 0, 0)
+        );
+        assert_eq!(fs::metadata(&path).unwrap().len(), frame_offset(3) as u64);
+        commit(&mut wal, &pages(&[2], 30), 2);
+        assert_eq!(
+            wal.checkpoint(&database, CheckpointMode::Truncate).unwrap(),
+            (false, 0, 0)
+        );
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+        let mut copied = vec![0; PAGE_SIZE];
+        database.read_exact_at(&mut copied, 0).unwrap();
+        assert_eq!(copied, page(1, 20));
+        database
+            .read_exact_at(&mut copied, PAGE_SIZE as u64)
+            .unwrap();
+        assert_eq!(copied, page(2, 30));
+
+        commit(&mut wal, &pages(&[2], 40), 2);
+        let wal = read_wal(&path);
+        assert_eq!(wal.read_page(2).unwrap(), Some(page(2, 40)));
+        let err = Wal::open(&path, PAGE_SIZE)
+            .and_then(|mut wal| {
+                wal.begin_read()?;
+                wal.checkpoint(&database, CheckpointMode::Passive)
+            })
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Cannot checkpoint the log while reading it"
+        );
+        remove_test_wal(&path);
+    }
+
+    #[test]
+    fn writes_the_wal_index_sqlite_writes() {
+        let dir = test_dir("index");
+        // The copies are made while sqlite3 is connected, as it removes the files when done
+        let output = Command::new("sqlite3")
+            .current_dir(&dir)
+            .arg("w.db")
+            .arg(
+                "PRAGMA journal_mode = WAL;
+                CREATE TABLE t (a, b);
+                INSERT INTO t VALUES (1, randomblob(3000));
+                INSERT INTO t SELECT a + 1, b FROM t;
+                INSERT INTO t SELECT a + 2, b FROM t;",
+            )
+            .arg(".shell cp w.db-wal copy.db-wal && cp w.db-shm sqlite.db-shm")
+            .output();
+        match output {
+            Ok(output) if output.status.success() => {}
+            _ => {
+                eprintln!("sqlite3 is not installed, skipping");
+                return;
+            }
+        }
+        let wal_path = dir.join("copy.db-wal").to_string_lossy().into_owned();
+        let mut wal = Wal::open(&wal_path, 4096).unwrap();
+        wal.begin_read().unwrap();
+        let sqlite_shm = fs::read(dir.join("sqlite.db-shm")).unwrap();
+        let shm = fs::read(dir.join("copy.db-shm")).unwrap();
+        // The headers only differ by their change counter
+        let sqlite_header = IndexHeader::parse(&sqlite_shm).unwrap();
+        let header = IndexHeader::parse(&shm).unwrap();
+        assert!(header.frame_count > 0);
+        assert_eq!(
+            header,
+            IndexHeader {
+                change: header.change,
+                ..sqlite_header
+            }
+        );
+        assert_eq!(shm[96..100], sqlite_shm[96..100]);
+        assert_eq!(shm[INDEX_HEADER_SIZE..], sqlite_shm[INDEX_HEADER_SIZE..]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn indexes_frames_across_blocks() {
+        let mut index = vec![0; 3 * INDEX_BLOCK_SIZE];
+        let frames = INDEX_FIRST_BLOCK_FRAMES + INDEX_BLOCK_FRAMES + 1;
+        for frame in 1..=frames {
+            add_to_index(&mut index, frame, (frame % 7 + 1) as u32);
+        }
+        let page =
+            |offset: usize| u32::from_ne_bytes(index[offset..offset + 4].try_into().unwrap());
+        assert_eq!(page(INDEX_HEADER_SIZE), 2);
+        assert_eq!(
+            page(4 * INDEX_BLOCK_FRAMES - 4),
+            (INDEX_FIRST_BLOCK_FRAMES % 7 + 1) as u32
+        );
+        assert_eq!(
+            page(INDEX_BLOCK_SIZE),
+            ((INDEX_FIRST_BLOCK_FRAMES + 1) % 7 + 1) as u32
+        );
+        assert_eq!(page(2 * INDEX_BLOCK_SIZE), (frames % 7 + 1) as u32);
+        // Every frame of a block takes a slot of its hash table
+        for &(block, count) in &[
+            (0, INDEX_FIRST_BLOCK_FRAMES),
+            (1, INDEX_BLOCK_FRAMES),
+            (2, 1),
+        ] {
+            let hash_table = block * INDEX_BLOCK_SIZE + 4 * INDEX_BLOCK_FRAMES;
+            let used = index[hash_table..(block + 1) * INDEX_BLOCK_SIZE]
+                .chunks_exact(2)
+                .filter(|slot| slot != &[0, 0])
+                .count();
+            assert_eq!(used, count);
+        }
+
+        // Appending frames to the wal-index writes the same blocks, whatever the transactions
+        let path = test_wal("blocks");
+        let wal = Wal::open(&path, PAGE_SIZE).unwrap();
+        let page_numbers = (1..=frames)
+            .map(|frame| (frame % 7 + 1) as u32)
+            .collect::<Vec<_>>();
+        let split = INDEX_FIRST_BLOCK_FRAMES - 10;
+        wal.index.append(1, &page_numbers[..split]).unwrap();
+        wal.index.append(split + 1, &page_numbers[split..]).unwrap();
+        let shm = fs::read(shm_path(&path)).unwrap();
+        assert_eq!(shm[INDEX_HEADER_SIZE..], index[INDEX_HEADER_SIZE..]);
+        assert_eq!(wal.index.page_numbers(frames).unwrap(), page_numbers);
+        remove_test_wal(&path);
+    }
+}
@@ -0,0 +1,190 @@
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use sqlite_starter_rust::db::DB;
+use sqlite_starter_rust::sql::{Delete, DropStatement, Insert, Select, Transaction, Update};
+
+/// A database file in a directory of its own, which is removed when dropped
+pub struct TestDb {
+    dir: PathBuf,
+    pub path: String,
+}
+
+impl TestDb {
+    /// Creates an empty database, made of a first page holding an empty `sqlite_schema`
+    pub fn new() -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "sqlite-starter-rust-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.db").to_string_lossy().into_owned();
+
+        let mut page = vec![0; 4096];
+        page[..16].copy_from_slice(b"SQLite format 3\0");
+        page[16..18].copy_from_slice(&4096u16.to_be_bytes());
+        page[18..24].copy_from_slice(&[1, 1, 0, 64, 32, 32]);
+        page[24..28].copy_from_slice(&1u32.to_be_bytes());
+        page[28..32].copy_from_slice(&1u32.to_be_bytes());
+        page[44..48].copy_from_slice(&4u32.to_be_bytes());
+        page[56..60].copy_from_slice(&1u32.to_be_bytes());
+        page[92..96].copy_from_slice(&1u32.to_be_bytes());
+        page[96..100].copy_from_slice(&3045000u32.to_be_bytes());
+        // An empty leaf table page, whose cell content area starts at the end of the page
+        page[100] = 0x0d;
+        page[105..107].copy_from_slice(&4096u16.to_be_bytes());
+        fs::write(&path, page).unwrap();
+        Self { dir, path }
+    }
+
+    pub fn open(&self) -> DB {
+        DB::new(&self.path).unwrap()
+    }
+
+    /// Returns the error opening the database fails with, if any
+    pub fn open_error(&self) -> Option<anyhow::Error> {
+        DB::new(&self.path).err()
+    }
+
+    /// Runs statements with the `sqlite3` shell, returning its output, or `None` when it is not
+    /// installed
+    pub fn sqlite3(&self, sql: &str) -> Option<String> {
+        let output = self.run_sqlite3(sql)?;
+        assert!(
+            output.status.success(),
+            "sqlite3 failed on {}: {}",
+            sql,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        Some(
+            String::from_utf8(output.stdout)
+                .unwrap()
+                .trim_end()
+                .to_string(),
+        )
+    }
+
+    /// Runs statements with the `sqlite3` shell expecting them to fail, returning its error
+    /// message, or `None` when it is not installed
+    pub fn sqlite3_error(&self, sql: &str) -> Option<String> {
+        let output = self.run_sqlite3(sql)?;
+        assert!(!output.status.success(), "sqlite3 did not fail on {}", sql);
+        Some(String::from_utf8(output.stderr).unwrap())
+    }
+
+    fn run_sqlite3(&self, sql: &str) -> Option<Output> {
+        match Command::new("sqlite3").arg(&self.path).arg(sql).output() {
+            Ok(output) => Some(output),
+            Err(_) => {
+                eprintln!("sqlite3 is not installed, skipping the check of {}", sql);
+                None
+            }
+        }
+    }
+
+    /// Makes sure `sqlite3` finds the database well formed, when it is installed
+    pub fn check_integrity(&self) {
+        if let Some(result) = self.sqlite3("PRAGMA integrity_check") {
+            assert_eq!(result, "ok");
+        }
+    }
+}
+
+/// A `sqlite3` shell kept connected to a database, so that it holds its locks on the file between
+/// the statements it runs
+pub struct Sqlite3Session {
+    child: Child,
+    dir: PathBuf,
+    output: PathBuf,
+    statements: usize,
+}
+
+impl Sqlite3Session {
+    /// Connects a `sqlite3` shell to a database, unless it is not installed
+    pub fn connect(test_db: &TestDb) -> Option<Self> {
+        let output = test_db.dir.join("session.out");
+        let child = Command::new("sqlite3")
+            .arg(&test_db.path)
+            .current_dir(&test_db.dir)
+            .stdin(Stdio::piped())
+            .stdout(fs::File::create(&output).unwrap())
+            .stderr(Stdio::inherit())
+            .spawn();
+        match child {
+            Ok(child) => Some(Self {
+                child,
+                dir: test_db.dir.clone(),
+                output,
+                statements: 0,
+            }),
+            Err(_) => {
+                eprintln!("sqlite3 is not installed, skipping the session");
+                None
+            }
+        }
+    }
+
+    /// Runs statements, waiting until the shell is done with them
+    pub fn run(&mut self, sql: &str) {
+        self.statements += 1;
+        let marker = format!("session-{}.done", self.statements);
+        let stdin = self.child.stdin.as_mut().unwrap();
+        writeln!(stdin, "{}\n.shell touch {}", sql, marker).unwrap();
+        stdin.flush().unwrap();
+        let start = Instant::now();
+        while !self.dir.join(&marker).exists() {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "sqlite3 hangs on {}",
+                sql
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Disconnects the shell, returning everything it printed
+    pub fn finish(mut self) -> String {
+        drop(self.child.stdin.take());
+        assert!(self.child.wait().unwrap().success());
+        fs::read_to_string(&self.output).unwrap()
+    }
+}
+
+impl Drop for TestDb {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Runs a statement the way the command line does
+pub fn execute(db: &mut DB, sql: &str) -> anyhow::Result<()> {
+    let lowercase = sql.trim_start().to_lowercase();
+    match lowercase.split_whitespace().next().unwrap_or_default() {
+        "create" => db.create(sql),
+        "insert" => db.insert(Insert::parse(sql)?).map(|_| ()),
+        "update" => db.update(Update::parse(sql)?).map(|_| ()),
+        "delete" => db.delete(Delete::parse(sql)?).map(|_| ()),
+        "drop" => db.drop_object(DropStatement::parse(sql)?),
+        _ => db.transaction(Transaction::parse(sql)?),
+    }
+}
+
+/// Runs a query, returning its rows with their values separated by `|` as `sqlite3` prints them
+pub fn query(db: &DB, sql: &str) -> anyhow::Result<Vec<String>> {
+    Ok(db
+        .select(Select::parse_select(sql)?)?
+        .into_iter()
+        .map(|row| {
+            row.iter()
+                .map(|value| value.to_string())
+                .collect::<Vec<_>>()
+                .join("|")
+        })
+        .collect())
+}
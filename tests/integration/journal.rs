@@ -0,0 +1,173 @@
+use std::fs;
+
+use crate::common::{execute, query, Sqlite3Session, TestDb};
+
+#[test]
+fn rolls_back_journals_left_by_sqlite3() {
+    // Without syncing, sqlite3 leaves the number of records of the journal unset
+    for synchronous in ["FULL", "OFF"] {
+        let test_db = TestDb::new();
+        let created = test_db.sqlite3(
+            "CREATE TABLE t (a, b);
+            WITH RECURSIVE n(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM n WHERE x < 200)
+            INSERT INTO t SELECT x, randomblob(500) FROM n;
+            CREATE INDEX ta ON t (a);",
+        );
+        let mut session = match (created, Sqlite3Session::connect(&test_db)) {
+            (Some(_), Some(session)) => session,
+            _ => return,
+        };
+        let original = fs::read(&test_db.path).unwrap();
+        let contents = "SELECT a, hex(b) FROM t ORDER BY a";
+        let expected = test_db.sqlite3(contents).unwrap();
+
+        // A small cache makes sqlite3 write pages to the database file before committing, which
+        // is how a crash leaves them
+        session.run(&format!(
+            "PRAGMA synchronous = {};
+            PRAGMA cache_size = 10;
+            BEGIN;
+            UPDATE t SET a = -a, b = randomblob(600);
+            DELETE FROM t WHERE a < -150;
+            INSERT INTO t SELECT a - 1000, b FROM t;",
+            synchronous
+        ));
+        let journal_path = format!("{}-journal", test_db.path);
+        let crashed = fs::read(&test_db.path).unwrap();
+        let journal = fs::read(&journal_path).unwrap();
+        session.run("ROLLBACK;");
+        session.finish();
+        assert_ne!(crashed, original, "sqlite3 wrote nothing before committing");
+        fs::write(&test_db.path, crashed).unwrap();
+        fs::write(&journal_path, journal).unwrap();
+
+        let db = test_db.open();
+        assert!(!std::path::Path::new(&journal_path).exists());
+        assert_eq!(fs::read(&test_db.path).unwrap(), original);
+        let rows = query(&db, "SELECT count(*), sum(a) FROM t").unwrap();
+        assert_eq!(rows, ["200|20100"]);
+        assert_eq!(test_db.sqlite3(contents).unwrap(), expected);
+        test_db.check_integrity();
+    }
+}
+
+#[test]
+fn statements_and_transactions_commit_as_a_whole() {
+    let test_db = TestDb::new();
+    let mut db = test_db.open();
+    execute(&mut db, "CREATE TABLE t (id INTEGER PRIMARY KEY, a UNIQUE)").unwrap();
+    execute(&mut db, "INSERT INTO t (a) VALUES ('x')").unwrap();
+    // A statement failing half way leaves none of its rows behind
+    let err = execute(&mut db, "INSERT INTO t (a) VALUES ('y'), ('z'), ('x')").unwrap_err();
+    assert_eq!(err.to_string(), "UNIQUE constraint failed: t.a");
+    assert_eq!(query(&db, "SELECT a FROM t").unwrap(), ["x"]);
+
+    execute(&mut db, "BEGIN").unwrap();
+    execute(&mut db, "INSERT INTO t (a) VALUES ('y')").unwrap();
+    execute(&mut db, "CREATE INDEX t_a ON t (a DESC)").unwrap();
+    execute(&mut db, "INSERT INTO t (a) VALUES ('y')").unwrap_err();
+    execute(&mut db, "DELETE FROM t WHERE a = 'x'").unwrap();
+    assert_eq!(query(&db, "SELECT a FROM t").unwrap(), ["y"]);
+    execute(&mut db, "ROLLBACK").unwrap();
+    assert_eq!(query(&db, "SELECT a FROM t").unwrap(), ["x"]);
+    assert_eq!(db.tables().unwrap(), ["t"]);
+
+    execute(&mut db, "BEGIN TRANSACTION").unwrap();
+    execute(&mut db, "INSERT INTO t (a) VALUES ('y')").unwrap();
+    execute(&mut db, "UPDATE t SET a = a || a").unwrap();
+    execute(&mut db, "END").unwrap();
+    let failures = [
+        ("COMMIT", "cannot commit - no transaction is active"),
+        ("ROLLBACK", "cannot rollback - no transaction is active"),
+    ];
+    for (statement, message) in failures {
+        let err = execute(&mut db, statement).unwrap_err();
+        assert_eq!(err.to_string(), message);
+    }
+    execute(&mut db, "BEGIN").unwrap();
+    let err = execute(&mut db, "BEGIN").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "cannot start a transaction within a transaction"
+    );
+    execute(&mut db, "COMMIT").unwrap();
+
+    assert!(!std::path::Path::new(&format!("{}-journal", test_db.path)).exists());
+    test_db.check_integrity();
+    if let Some(rows) = test_db.sqlite3("SELECT * FROM t") {
+        assert_eq!(rows, "1|xx\n2|yy");
+    }
+}
+
+#[test]
+fn journals_of_running_writers_are_not_rolled_back() {
+    let test_db = TestDb::new();
+    let created = test_db.sqlite3("CREATE TABLE t (a); INSERT INTO t VALUES (1);");
+    let mut session = match (created, Sqlite3Session::connect(&test_db)) {
+        (Some(_), Some(session)) => session,
+        _ => return,
+    };
+    // Writing takes a reserved lock and creates a journal, which stays in memory until then
+    session.run("BEGIN; UPDATE t SET a = 2;");
+    let journal_path = format!("{}-journal", test_db.path);
+    assert!(std::path::Path::new(&journal_path).exists());
+
+    let mut db = test_db.open();
+    assert_eq!(query(&db, "SELECT a FROM t").unwrap(), ["1"]);
+    assert!(std::path::Path::new(&journal_path).exists());
+    let err = execute(&mut db, "INSERT INTO t VALUES (3)").unwrap_err();
+    assert_eq!(err.to_string(), "database is locked");
+
+    // Once the cache spills, sqlite3 writes to the file under an exclusive lock, which keeps
+    // readers out
+    session.run(
+        "PRAGMA cache_size = 1;
+        WITH RECURSIVE n(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM n WHERE x < 100)
+        INSERT INTO t SELECT randomblob(1000) FROM n;",
+    );
+    let err = query(&db, "SELECT count(*) FROM t").unwrap_err();
+    assert_eq!(err.to_string(), "database is locked");
+    assert_eq!(
+        test_db.open_error().map(|err| err.to_string()),
+        Some("database is locked".to_string())
+    );
+
+    session.run("COMMIT;");
+    session.finish();
+    assert_eq!(
+        query(&db, "SELECT count(*), min(a) FROM t").unwrap(),
+        ["101|2"]
+    );
+    execute(&mut db, "DELETE FROM t WHERE typeof(a) = 'blob'").unwrap();
+    test_db.check_integrity();
+}
+
+#[test]
+fn sqlite3_waits_for_transactions_to_end() {
+    let test_db = TestDb::new();
+    let created = test_db.sqlite3("CREATE TABLE t (a); INSERT INTO t VALUES (1);");
+    let mut session = match (created, Sqlite3Session::connect(&test_db)) {
+        (Some(_), Some(session)) => session,
+        _ => return,
+    };
+    let mut db = test_db.open();
+    execute(&mut db, "BEGIN").unwrap();
+    execute(&mut db, "INSERT INTO t VALUES (2)").unwrap();
+    // sqlite3 still reads the database as of before the transaction, but cannot write to it
+    assert_eq!(test_db.sqlite3("SELECT count(*) FROM t").unwrap(), "1");
+    let err = test_db.sqlite3_error("INSERT INTO t VALUES (3)").unwrap();
+    assert!(err.contains("database is locked"), "{}", err);
+    execute(&mut db, "COMMIT").unwrap();
+    assert_eq!(test_db.sqlite3("SELECT count(*) FROM t").unwrap(), "2");
+
+    // A transaction of sqlite3 reading the database keeps changes from being committed, and
+    // the statement that could not commit leaves nothing behind
+    session.run("BEGIN; SELECT count(*) FROM t;");
+    let err = execute(&mut db, "INSERT INTO t VALUES (4)").unwrap_err();
+    assert_eq!(err.to_string(), "database is locked");
+    assert_eq!(query(&db, "SELECT count(*) FROM t").unwrap(), ["2"]);
+    session.run("COMMIT;");
+    execute(&mut db, "INSERT INTO t VALUES (4)").unwrap();
+    assert_eq!(session.finish(), "2\n");
+    assert_eq!(test_db.sqlite3("SELECT sum(a) FROM t").unwrap(), "7");
+}
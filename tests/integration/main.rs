@@ -0,0 +1,8 @@
+//! Tests running statements on database files, checked against the `sqlite3` command line shell
+//! when it is installed
+
+mod common;
+mod journal;
+mod queries;
+mod wal;
+mod writes;
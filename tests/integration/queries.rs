@@ -0,0 +1,327 @@
+use crate::common::{query, TestDb};
+
+#[test]
+fn generated_columns_of_files_written_by_sqlite3() {
+    let test_db = TestDb::new();
+    let created = test_db.sqlite3(
+        "CREATE TABLE g (a, b AS (a * 2) VIRTUAL, c, d AS (b + a) STORED, e AS (upper(c)));
+        INSERT INTO g (a, c) VALUES (1, 'x'), (3, 'y');
+        CREATE INDEX ge ON g (e);",
+    );
+    if created.is_none() {
+        return;
+    }
+    let db = test_db.open();
+    assert_eq!(
+        query(&db, "SELECT * FROM g").unwrap(),
+        ["1|2|x|3|X", "3|6|y|9|Y"]
+    );
+    assert_eq!(
+        query(&db, "SELECT a, b FROM g WHERE e = 'Y'").unwrap(),
+        ["3|6"]
+    );
+}
+
+#[test]
+fn seeks_unique_descending_expression_and_partial_indexes() {
+    let test_db = TestDb::new();
+    let created = test_db.sqlite3(
+        "CREATE TABLE t (a INTEGER, b TEXT COLLATE NOCASE, c);
+        WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 2000)
+        INSERT INTO t SELECT i % 50, 'name' || (i % 7), i FROM n;
+        CREATE INDEX ta ON t (a DESC);
+        CREATE INDEX tb ON t (b COLLATE BINARY);
+        CREATE UNIQUE INDEX tc ON t (c);
+        CREATE INDEX tl ON t (lower(b) || a);
+        CREATE INDEX tp ON t (a) WHERE c > 1990;",
+    );
+    if created.is_none() {
+        return;
+    }
+    let db = test_db.open();
+    let queries = [
+        "SELECT count(*), min(c), max(c) FROM t WHERE a = 7",
+        "SELECT count(*) FROM t WHERE b = 'NAME3'",
+        "SELECT count(*) FROM t WHERE b COLLATE BINARY = 'name3'",
+        "SELECT a, b FROM t WHERE c = 1234",
+        "SELECT count(*) FROM t WHERE lower(b) || a = 'name57'",
+        "SELECT c FROM t WHERE a = 41 AND c > 1990",
+        "SELECT count(*) FROM t WHERE c > 1990 AND a = 41 AND b = 'name5'",
+    ];
+    for sql in queries {
+        let rows = query(&db, sql).unwrap().join("\n");
+        if let Some(expected) = test_db.sqlite3(sql) {
+            assert_eq!(rows, expected, "{}", sql);
+        }
+    }
+}
+
+#[test]
+fn loads_schemas_with_views_triggers_and_virtual_tables() {
+    let test_db = TestDb::new();
+    let created = test_db.sqlite3(
+        "CREATE TABLE t (a, b);
+        INSERT INTO t VALUES (1, 'x'), (2, 'y');
+        CREATE VIEW v AS SELECT a FROM t WHERE b = 'y';
+        CREATE TRIGGER tr AFTER INSERT ON t BEGIN UPDATE t SET b = upper(b); END;
+        CREATE VIRTUAL TABLE f USING fts5(a);",
+    );
+    if created.is_none() {
+        return;
+    }
+    let db = test_db.open();
+    assert_eq!(query(&db, "SELECT * FROM t").unwrap(), ["1|x", "2|y"]);
+    let tables = db.tables().unwrap();
+    for name in ["t", "f"] {
+        assert!(tables.iter().any(|table| table == name), "{:?}", tables);
+    }
+}
+
+#[test]
+fn seeks_the_indexes_of_unique_and_primary_key_constraints() {
+    let test_db = TestDb::new();
+    let created = test_db.sqlite3(
+        "CREATE TABLE t (k TEXT PRIMARY KEY, n TEXT COLLATE NOCASE UNIQUE, a, b, UNIQUE (a, b));
+        WITH RECURSIVE s(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM s WHERE i < 1500)
+        INSERT INTO t SELECT 'key' || i, 'Name' || i, i % 30, i FROM s;",
+    );
+    if created.is_none() {
+        return;
+    }
+    let db = test_db.open();
+    let queries = [
+        "SELECT n FROM t WHERE k = 'key777'",
+        "SELECT k FROM t WHERE n = 'NAME1234'",
+        "SELECT k FROM t WHERE n = 'NAME1234' COLLATE BINARY",
+        "SELECT count(*), sum(b) FROM t WHERE a = 17",
+        "SELECT count(*) FROM t WHERE k = 'KEY777'",
+    ];
+    for sql in queries {
+        let rows = query(&db, sql).unwrap().join("\n");
+        if let Some(expected) = test_db.sqlite3(sql) {
+            assert_eq!(rows, expected, "{}", sql);
+        }
+    }
+}
+
+#[test]
+fn queries_views_through_their_definition() {
+    let test_db = TestDb::new();
+    let created = test_db.sqlite3(
+        "CREATE TABLE t (a INTEGER, b TEXT COLLATE NOCASE);
+        CREATE INDEX tb ON t (b);
+        WITH RECURSIVE s(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM s WHERE i < 500)
+        INSERT INTO t SELECT i, 'Name' || (i % 40) FROM s;
+        CREATE VIEW v AS SELECT a, b, a * 2 AS d FROM t WHERE a > 100;
+        CREATE VIEW w (name, total) AS SELECT b, sum(a) FROM t GROUP BY b;
+        CREATE VIEW nested AS SELECT * FROM v WHERE d < 400;
+        CREATE VIEW compound AS SELECT a FROM t UNION SELECT b FROM t;",
+    );
+    if created.is_none() {
+        return;
+    }
+    let db = test_db.open();
+    let queries = [
+        "SELECT * FROM v WHERE b = 'NAME7'",
+        "SELECT count(*), max(d) FROM v WHERE d > 500",
+        "SELECT * FROM w WHERE name = 'name3'",
+        "SELECT total FROM w ORDER BY total DESC LIMIT 2",
+        "SELECT a, b FROM nested WHERE b = 'name9' ORDER BY a DESC",
+        "SELECT v.a, w.total FROM v, w WHERE v.b = w.name AND v.a < 105",
+    ];
+    for sql in queries {
+        let rows = query(&db, sql).unwrap().join("\n");
+        if let Some(expected) = test_db.sqlite3(sql) {
+            assert_eq!(rows, expected, "{}", sql);
+        }
+    }
+    let err = query(&db, "SELECT * FROM compound").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "cannot query view compound: its query is not supported"
+    );
+}
+
+#[test]
+fn left_joins_pad_unmatched_rows_with_null() {
+    let test_db = TestDb::new();
+    let created = test_db.sqlite3(
+        "CREATE TABLE s (a, b);
+        CREATE TABLE t (c, d);
+        INSERT INTO s VALUES (1, 'x'), (2, 'y'), (3, 'z');
+        INSERT INTO t VALUES (1, 10), (1, 11), (3, 30), (4, 40);
+        CREATE VIEW vl AS SELECT a, d FROM s LEFT JOIN t ON a = c;",
+    );
+    if created.is_none() {
+        return;
+    }
+    let db = test_db.open();
+    let queries = [
+        (
+            "SELECT * FROM s LEFT JOIN t ON a = c",
+            vec!["1|x|1|10", "1|x|1|11", "2|y|NULL|NULL", "3|z|3|30"],
+        ),
+        (
+            "SELECT a, d FROM s LEFT OUTER JOIN t ON a = c AND d > 10",
+            vec!["1|11", "2|NULL", "3|30"],
+        ),
+        (
+            "SELECT a FROM s LEFT JOIN t ON a = c WHERE d IS NULL",
+            vec!["2"],
+        ),
+        (
+            "SELECT a, e.value FROM s LEFT JOIN json_each('[1, 3]') AS e ON e.value = a",
+            vec!["1|1", "2|NULL", "3|3"],
+        ),
+        ("SELECT count(*), count(d) FROM vl", vec!["4|3"]),
+        ("SELECT * FROM vl WHERE d IS NULL", vec!["2|NULL"]),
+    ];
+    for (sql, expected) in queries {
+        assert_eq!(query(&db, sql).unwrap(), expected, "{}", sql);
+        assert_sqlite3_rows(&test_db, sql, &expected);
+    }
+}
+
+#[test]
+fn distinct_leaves_out_equal_rows() {
+    let test_db = TestDb::new();
+    let created = test_db.sqlite3(
+        "CREATE TABLE t (id, a, b TEXT COLLATE NOCASE);
+        INSERT INTO t (id, a, b)
+        VALUES (1, 1, 'x'), (2, 2, 'X'), (3, 1, 'x'), (4, NULL, NULL), (5, NULL, NULL), (6, 3, 'y');
+        CREATE VIEW d AS SELECT DISTINCT b FROM t;",
+    );
+    if created.is_none() {
+        return;
+    }
+    let db = test_db.open();
+    let queries = [
+        ("SELECT DISTINCT a FROM t", vec!["1", "2", "NULL", "3"]),
+        ("SELECT DISTINCT b FROM t", vec!["x", "NULL", "y"]),
+        (
+            "SELECT DISTINCT a, b FROM t ORDER BY a DESC",
+            vec!["3|y", "2|X", "1|x", "NULL|NULL"],
+        ),
+        (
+            "SELECT DISTINCT a FROM t LIMIT 2 OFFSET 2",
+            vec!["NULL", "3"],
+        ),
+        ("SELECT ALL b FROM t WHERE a = 1", vec!["x", "x"]),
+        ("SELECT * FROM d WHERE b = 'X'", vec!["x"]),
+    ];
+    for (sql, expected) in queries {
+        assert_eq!(query(&db, sql).unwrap(), expected, "{}", sql);
+        assert_sqlite3_rows(&test_db, sql, &expected);
+    }
+}
+
+#[test]
+fn scans_and_seeks_without_rowid_tables() {
+    let test_db = TestDb::new();
+    let created = test_db.sqlite3(
+        "CREATE TABLE w (a, k TEXT PRIMARY KEY, b) WITHOUT ROWID;
+        CREATE TABLE p (a, b, c, PRIMARY KEY (c, a DESC)) WITHOUT ROWID;
+        WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 2000)
+        INSERT INTO w SELECT i, printf('key%05d', i), hex(randomblob(20)) FROM n;
+        INSERT INTO p VALUES (1, 'one', 'x'), (2, 'two', 'x'), (3, 'three', 'y');",
+    );
+    if created.is_none() {
+        return;
+    }
+    let db = test_db.open();
+    let queries = [
+        ("SELECT count(*), sum(a) FROM w", vec!["2000|2001000"]),
+        (
+            "SELECT a, k FROM w WHERE k = 'key01234'",
+            vec!["1234|key01234"],
+        ),
+        ("SELECT a FROM w WHERE k = 'key99999'", vec![]),
+        ("SELECT k FROM w WHERE a = 1999", vec!["key01999"]),
+        ("SELECT * FROM p", vec!["2|two|x", "1|one|x", "3|three|y"]),
+        ("SELECT a, b FROM p WHERE c = 'x'", vec!["2|two", "1|one"]),
+    ];
+    for (sql, expected) in queries {
+        assert_eq!(query(&db, sql).unwrap(), expected, "{}", sql);
+        assert_sqlite3_rows(&test_db, sql, &expected);
+    }
+}
+
+#[test]
+fn reads_rowids_and_their_integer_primary_key_aliases() {
+    let test_db = TestDb::new();
+    let created = test_db.sqlite3(
+        "CREATE TABLE a (id INTEGER PRIMARY KEY, v);
+        CREATE TABLE d (id INTEGER PRIMARY KEY DESC, v);
+        CREATE TABLE i (id INT PRIMARY KEY, v);
+        CREATE TABLE r (oid TEXT, v);
+        WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 1000)
+        INSERT INTO a SELECT i * 2, 'v' || i FROM n;
+        INSERT INTO d VALUES (5, 'x'), (7, 'y');
+        INSERT INTO i VALUES (5, 'x'), (7, 'y');
+        INSERT INTO r VALUES ('text', 'x');",
+    );
+    if created.is_none() {
+        return;
+    }
+    let db = test_db.open();
+    let queries = [
+        ("SELECT count(*), max(id) FROM a", vec!["1000|2000"]),
+        ("SELECT * FROM a WHERE id = 1234", vec!["1234|v617"]),
+        ("SELECT * FROM a WHERE rowid = '1234'", vec!["1234|v617"]),
+        ("SELECT v FROM a WHERE _rowid_ = 12.5", vec![]),
+        ("SELECT rowid, oid, v FROM a WHERE id = 4", vec!["4|4|v2"]),
+        ("SELECT rowid, * FROM d", vec!["1|5|x", "2|7|y"]),
+        ("SELECT rowid, id FROM i WHERE rowid = 2", vec!["2|7"]),
+        ("SELECT rowid, oid, * FROM r", vec!["1|text|text|x"]),
+    ];
+    for (sql, expected) in queries {
+        assert_eq!(query(&db, sql).unwrap(), expected, "{}", sql);
+        assert_sqlite3_rows(&test_db, sql, &expected);
+    }
+}
+
+#[test]
+fn rows_written_before_added_columns_read_their_defaults() {
+    let test_db = TestDb::new();
+    let created = test_db.sqlite3(
+        "CREATE TABLE t (id INTEGER PRIMARY KEY, a);
+        CREATE TABLE w (k PRIMARY KEY, a) WITHOUT ROWID;
+        INSERT INTO t VALUES (1, 'old'), (2, 'old');
+        INSERT INTO w VALUES (1, 'old');
+        ALTER TABLE t ADD COLUMN b;
+        ALTER TABLE t ADD COLUMN c INTEGER DEFAULT '7';
+        ALTER TABLE t ADD COLUMN d TEXT DEFAULT (-1);
+        ALTER TABLE w ADD COLUMN b DEFAULT 'none';
+        INSERT INTO t VALUES (3, 'new', 'b', 8, 'd');
+        INSERT INTO w VALUES (2, 'new', 'b');",
+    );
+    if created.is_none() {
+        return;
+    }
+    let db = test_db.open();
+    let queries = [
+        (
+            "SELECT * FROM t",
+            vec!["1|old|NULL|7|-1", "2|old|NULL|7|-1", "3|new|b|8|d"],
+        ),
+        (
+            "SELECT id FROM t WHERE c = 7 AND typeof(d) = 'text'",
+            vec!["1", "2"],
+        ),
+        ("SELECT * FROM w", vec!["1|old|none", "2|new|b"]),
+        ("SELECT k FROM w WHERE b = 'none'", vec!["1"]),
+    ];
+    for (sql, expected) in queries {
+        assert_eq!(query(&db, sql).unwrap(), expected, "{}", sql);
+        assert_sqlite3_rows(&test_db, sql, &expected);
+    }
+}
+
+/// Checks the rows `sqlite3` returns for a query, where NULL prints as nothing and trailing
+/// blank lines are trimmed
+fn assert_sqlite3_rows(test_db: &TestDb, sql: &str, expected: &[&str]) {
+    if let Some(rows) = test_db.sqlite3(sql) {
+        let expected = expected.join("\n").replace("NULL", "");
+        assert_eq!(rows, expected.trim_end(), "{}", sql);
+    }
+}
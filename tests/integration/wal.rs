@@ -0,0 +1,245 @@
+use crate::common::{execute, query, Sqlite3Session, TestDb};
+use sqlite_starter_rust::db::DB;
+use sqlite_starter_rust::sql::WalCheckpoint;
+
+fn checkpoint(db: &mut DB, mode: &str) -> Option<(bool, usize, usize)> {
+    let sql = format!("PRAGMA wal_checkpoint({})", mode);
+    db.checkpoint(WalCheckpoint::parse(&sql).unwrap()).unwrap()
+}
+
+#[test]
+fn reads_frames_sqlite3_has_not_checkpointed() {
+    let test_db = TestDb::new();
+    let copy = TestDb::new();
+    let mut session = match Sqlite3Session::connect(&test_db) {
+        Some(session) => session,
+        None => return,
+    };
+    session.run(
+        "PRAGMA journal_mode = WAL;
+        PRAGMA wal_autocheckpoint = 0;
+        CREATE TABLE t (a, b);
+        INSERT INTO t VALUES (1, 'x'), (2, 'y');
+        CREATE INDEX tb ON t (b);
+        UPDATE t SET b = 'z' WHERE a = 1;
+        INSERT INTO t SELECT a + 2, b || b FROM t;
+        DELETE FROM t WHERE a = 2;",
+    );
+    // The database file still has its first page only
+    assert_eq!(std::fs::metadata(&test_db.path).unwrap().len(), 4096);
+    let db = test_db.open();
+    let expected = ["1|z", "3|zz", "4|yy"];
+    assert_eq!(query(&db, "SELECT * FROM t").unwrap(), expected);
+    assert_eq!(query(&db, "SELECT a FROM t WHERE b = 'zz'").unwrap(), ["3"]);
+
+    // Without the wal-index of a connected sqlite3, the log is read again to rebuild it
+    session.run(&format!(
+        ".shell cp test.db {0} && cp test.db-wal {0}-wal",
+        copy.path
+    ));
+    let db = copy.open();
+    assert_eq!(query(&db, "SELECT * FROM t").unwrap(), expected);
+    session.run("SELECT count(*) FROM t;");
+    assert_eq!(session.finish(), "wal\n0\n3\n");
+    assert_eq!(copy.sqlite3("PRAGMA integrity_check").unwrap(), "ok");
+}
+
+#[test]
+fn read_transactions_keep_their_frames_while_sqlite3_writes() {
+    let test_db = TestDb::new();
+    let mut session = match Sqlite3Session::connect(&test_db) {
+        Some(session) => session,
+        None => return,
+    };
+    session.run(
+        "PRAGMA journal_mode = WAL;
+        PRAGMA wal_autocheckpoint = 0;
+        CREATE TABLE t (a);
+        INSERT INTO t VALUES (1);",
+    );
+    let mut db = test_db.open();
+    execute(&mut db, "BEGIN").unwrap();
+    assert_eq!(query(&db, "SELECT count(*) FROM t").unwrap(), ["1"]);
+    // The read mark of the transaction keeps sqlite3 from copying the frames written after it
+    // into the database file, or starting the log over
+    session.run(
+        "INSERT INTO t VALUES (2);
+        PRAGMA wal_checkpoint(PASSIVE);
+        PRAGMA wal_checkpoint(TRUNCATE);",
+    );
+    assert_eq!(query(&db, "SELECT count(*) FROM t").unwrap(), ["1"]);
+    execute(&mut db, "COMMIT").unwrap();
+    assert_eq!(query(&db, "SELECT count(*) FROM t").unwrap(), ["2"]);
+
+    // Each statement out of a transaction reads the log as sqlite3 last left it
+    session.run(
+        "PRAGMA wal_checkpoint(TRUNCATE);
+        INSERT INTO t VALUES (3);",
+    );
+    assert_eq!(query(&db, "SELECT sum(a) FROM t").unwrap(), ["6"]);
+    session.run("INSERT INTO t VALUES (4);");
+    assert_eq!(query(&db, "SELECT sum(a) FROM t").unwrap(), ["10"]);
+    assert_eq!(session.finish(), "wal\n0\n0|4|3\n1|4|3\n0|0|0\n");
+}
+
+#[test]
+fn connected_sqlite3_reads_the_wal_index_written_here() {
+    let test_db = TestDb::new();
+    let created = test_db.sqlite3(
+        "PRAGMA journal_mode = WAL;
+        CREATE TABLE t (a);
+        INSERT INTO t VALUES (1);",
+    );
+    let mut session = match (created, Sqlite3Session::connect(&test_db)) {
+        (Some(_), Some(session)) => session,
+        _ => return,
+    };
+    session.run("SELECT count(*) FROM t;");
+
+    let mut db = test_db.open();
+    execute(&mut db, "INSERT INTO t VALUES (2)").unwrap();
+    execute(&mut db, "INSERT INTO t SELECT a + 10 FROM t").unwrap();
+    session.run(
+        "SELECT count(*), sum(a) FROM t;
+        PRAGMA integrity_check;
+        INSERT INTO t VALUES (100);",
+    );
+    assert_eq!(query(&db, "SELECT sum(a) FROM t").unwrap(), ["126"]);
+    assert_eq!(checkpoint(&mut db, "TRUNCATE"), Some((false, 0, 0)));
+    session.run("SELECT sum(a) FROM t; INSERT INTO t VALUES (1000);");
+
+    // A transaction that read the log before sqlite3 changed it cannot write to it
+    execute(&mut db, "BEGIN").unwrap();
+    assert_eq!(query(&db, "SELECT sum(a) FROM t").unwrap(), ["1126"]);
+    session.run("INSERT INTO t VALUES (10000);");
+    let err = execute(&mut db, "INSERT INTO t VALUES (100000)").unwrap_err();
+    assert_eq!(err.to_string(), "database is locked");
+    execute(&mut db, "ROLLBACK").unwrap();
+    execute(&mut db, "INSERT INTO t VALUES (100000)").unwrap();
+    session.run("SELECT sum(a) FROM t; PRAGMA integrity_check;");
+
+    // The write lock of the transaction of sqlite3 keeps other connections from writing
+    session.run("BEGIN IMMEDIATE;");
+    let err = execute(&mut db, "INSERT INTO t VALUES (5)").unwrap_err();
+    assert_eq!(err.to_string(), "database is locked");
+    session.run("COMMIT;");
+    execute(&mut db, "INSERT INTO t VALUES (5)").unwrap();
+    assert_eq!(session.finish(), "1\n4|26\nok\n126\n111126\nok\n");
+    test_db.check_integrity();
+}
+
+#[test]
+fn sqlite3_reads_the_log_written_here() {
+    let test_db = TestDb::new();
+    let copy = TestDb::new();
+    if test_db
+        .sqlite3("PRAGMA journal_mode = WAL; CREATE TABLE t (a, b UNIQUE);")
+        .is_none()
+    {
+        return;
+    }
+    let mut db = test_db.open();
+    execute(&mut db, "INSERT INTO t VALUES (1, 'x'), (2, 'y')").unwrap();
+    execute(&mut db, "CREATE INDEX ta ON t (a)").unwrap();
+    execute(&mut db, "UPDATE t SET a = a * 10").unwrap();
+    drop(db);
+    let wal_path = format!("{}-wal", test_db.path);
+    assert!(std::fs::metadata(&wal_path).unwrap().len() > 0);
+    // The new index is in the log only
+    assert_eq!(std::fs::metadata(&test_db.path).unwrap().len(), 3 * 4096);
+
+    // Without the wal-index, sqlite3 rebuilds it from the log
+    std::fs::copy(&test_db.path, &copy.path).unwrap();
+    std::fs::copy(&wal_path, format!("{}-wal", copy.path)).unwrap();
+    let sql = "SELECT * FROM t WHERE a > 10; PRAGMA integrity_check;";
+    assert_eq!(copy.sqlite3(sql).unwrap(), "20|y\nok");
+    assert_eq!(test_db.sqlite3(sql).unwrap(), "20|y\nok");
+}
+
+#[test]
+fn checkpoints_copy_the_log_into_the_database_file() {
+    let test_db = TestDb::new();
+    if test_db
+        .sqlite3("PRAGMA journal_mode = WAL; CREATE TABLE t (a);")
+        .is_none()
+    {
+        return;
+    }
+    let wal_path = format!("{}-wal", test_db.path);
+    let wal_size = || std::fs::metadata(&wal_path).unwrap().len();
+    let mut db = test_db.open();
+    execute(&mut db, "INSERT INTO t VALUES (1)").unwrap();
+    execute(&mut db, "INSERT INTO t VALUES (2)").unwrap();
+    let size = wal_size();
+    // Each transaction writes the first page, for its header, and the page of the table
+    assert_eq!(checkpoint(&mut db, "PASSIVE"), Some((false, 4, 4)));
+    assert_eq!(wal_size(), size);
+    assert_eq!(test_db.sqlite3("SELECT sum(a) FROM t").unwrap(), "3");
+
+    // The next transaction starts the log over
+    execute(&mut db, "INSERT INTO t VALUES (3)").unwrap();
+    assert_eq!(wal_size(), size);
+    assert_eq!(query(&db, "SELECT sum(a) FROM t").unwrap(), ["6"]);
+    assert_eq!(checkpoint(&mut db, "FULL"), Some((false, 2, 2)));
+    execute(&mut db, "INSERT INTO t VALUES (4)").unwrap();
+    assert_eq!(checkpoint(&mut db, "TRUNCATE"), Some((false, 0, 0)));
+    assert_eq!(wal_size(), 0);
+    assert_eq!(checkpoint(&mut db, "RESTART"), Some((false, 0, 0)));
+
+    execute(&mut db, "BEGIN").unwrap();
+    let err = db
+        .checkpoint(WalCheckpoint::parse("PRAGMA wal_checkpoint").unwrap())
+        .unwrap_err();
+    assert_eq!(err.to_string(), "cannot checkpoint within a transaction");
+    execute(&mut db, "ROLLBACK").unwrap();
+    assert_eq!(
+        test_db.sqlite3("SELECT sum(a) FROM t; PRAGMA integrity_check;"),
+        Some("10\nok".to_string())
+    );
+
+    // A database in rollback journal mode has no log to checkpoint
+    let other = TestDb::new();
+    let mut db = other.open();
+    assert_eq!(checkpoint(&mut db, "TRUNCATE"), None);
+}
+
+#[test]
+fn checkpoints_leave_the_frames_readers_of_sqlite3_need() {
+    let test_db = TestDb::new();
+    let mut session = match Sqlite3Session::connect(&test_db) {
+        Some(session) => session,
+        None => return,
+    };
+    session.run(
+        "PRAGMA journal_mode = WAL;
+        PRAGMA wal_autocheckpoint = 0;
+        CREATE TABLE t (a);",
+    );
+    let mut db = test_db.open();
+    execute(&mut db, "INSERT INTO t VALUES (1)").unwrap();
+    session.run("BEGIN; SELECT sum(a) FROM t;");
+    execute(&mut db, "INSERT INTO t VALUES (2)").unwrap();
+
+    // The frames added after the snapshot of sqlite3 are left in the log, without blocking
+    // `PASSIVE`, while the other modes report that they could not copy them
+    assert_eq!(checkpoint(&mut db, "PASSIVE"), Some((false, 6, 4)));
+    assert_eq!(checkpoint(&mut db, "FULL"), Some((true, 6, 4)));
+    assert_eq!(checkpoint(&mut db, "TRUNCATE"), Some((true, 6, 4)));
+    session.run("SELECT sum(a) FROM t; COMMIT;");
+    assert_eq!(checkpoint(&mut db, "PASSIVE"), Some((false, 6, 6)));
+
+    // A reader of the database file alone lets the log start over, but keeps any frame from
+    // being copied
+    session.run("BEGIN; SELECT sum(a) FROM t;");
+    execute(&mut db, "INSERT INTO t VALUES (3)").unwrap();
+    assert_eq!(checkpoint(&mut db, "PASSIVE"), Some((false, 2, 0)));
+    session.run("COMMIT;");
+    assert_eq!(checkpoint(&mut db, "RESTART"), Some((false, 0, 0)));
+
+    // The write lock of sqlite3 keeps the modes other than `PASSIVE` from waiting for writers
+    execute(&mut db, "INSERT INTO t VALUES (4)").unwrap();
+    session.run("BEGIN IMMEDIATE;");
+    assert_eq!(checkpoint(&mut db, "FULL"), Some((true, 2, 2)));
+    session.run("COMMIT; SELECT sum(a) FROM t; PRAGMA integrity_check;");
+    assert_eq!(session.finish(), "wal\n0\n1\n1\n3\n10\nok\n");
+}
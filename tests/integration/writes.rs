@@ -0,0 +1,610 @@
+use crate::common::{execute, query, TestDb};
+
+#[test]
+fn inserts_split_pages_sqlite3_can_read() {
+    let test_db = TestDb::new();
+    let created = test_db.sqlite3(
+        "CREATE TABLE t (id INTEGER PRIMARY KEY, a TEXT, b REAL);
+        CREATE TABLE u (a, b);",
+    );
+    if created.is_none() {
+        return;
+    }
+    let mut db = test_db.open();
+    for i in 0..20 {
+        let values = (0..50)
+            .map(|j| format!("('{}', {})", "x".repeat(i * 10 + j), j))
+            .collect::<Vec<_>>()
+            .join(", ");
+        execute(&mut db, &format!("INSERT INTO t (a, b) VALUES {}", values)).unwrap();
+    }
+    execute(
+        &mut db,
+        "INSERT INTO u SELECT id, length(a) FROM t WHERE b = 7",
+    )
+    .unwrap();
+    execute(&mut db, "INSERT INTO t VALUES (5000, zeroblob(10000), 1)").unwrap();
+    execute(&mut db, "INSERT INTO t (b) VALUES (2)").unwrap();
+    execute(&mut db, "INSERT INTO t (rowid, a) VALUES ('20', 'x')").unwrap_err();
+
+    assert_eq!(db.count("t").unwrap(), 1002);
+    assert_eq!(db.count("u").unwrap(), 20);
+    let queries = [
+        (
+            "SELECT count(*), sum(length(a)), max(id), typeof(max(b)) FROM t",
+            "1002|129500|5001|real",
+        ),
+        (
+            "SELECT id, length(a), b FROM t WHERE id = 777",
+            "777|176|26.0",
+        ),
+        ("SELECT count(*), sum(a), sum(b) FROM u", "20|9660|2040"),
+        ("SELECT * FROM t WHERE id = 5001", "5001||2.0"),
+    ];
+    for (sql, expected) in queries {
+        assert_eq!(
+            query(&db, sql).unwrap().join("\n"),
+            expected.replace("||", "|NULL|")
+        );
+        if let Some(rows) = test_db.sqlite3(sql) {
+            assert_eq!(rows, expected);
+        }
+    }
+    test_db.check_integrity();
+}
+
+#[test]
+fn insert_errors() {
+    let test_db = TestDb::new();
+    let created = test_db.sqlite3(
+        "CREATE TABLE t (id INTEGER PRIMARY KEY, a NOT NULL, b);
+        CREATE TABLE w (k PRIMARY KEY) WITHOUT ROWID;
+        CREATE VIEW v AS SELECT * FROM t;
+        INSERT INTO t VALUES (1, 'a', 'b');",
+    );
+    if created.is_none() {
+        return;
+    }
+    let mut db = test_db.open();
+    let failures = [
+        (
+            "INSERT INTO t VALUES (1, 'x', 'y')",
+            "UNIQUE constraint failed: t.id",
+        ),
+        (
+            "INSERT INTO t (rowid, a) VALUES (1, 'x')",
+            "UNIQUE constraint failed: t.id",
+        ),
+        (
+            "INSERT INTO t VALUES ('one', 'x', 'y')",
+            "datatype mismatch",
+        ),
+        (
+            "INSERT INTO t (b) VALUES (1)",
+            "NOT NULL constraint failed: t.a",
+        ),
+        (
+            "INSERT INTO t (c) VALUES (1)",
+            "table t has no column named c",
+        ),
+        (
+            "INSERT INTO t VALUES (1, 2)",
+            "table t has 3 columns but 2 values were supplied",
+        ),
+        ("INSERT INTO t (a, b) VALUES (1)", "1 values for 2 columns"),
+        ("INSERT INTO x VALUES (1)", "no such table: x"),
+        (
+            "INSERT INTO v VALUES (1)",
+            "cannot modify v because it is a view",
+        ),
+        (
+            "INSERT INTO w VALUES (1)",
+            "Cannot write to WITHOUT ROWID table w",
+        ),
+    ];
+    for (statement, message) in failures {
+        let err = execute(&mut db, statement).unwrap_err();
+        assert_eq!(err.to_string(), message, "{}", statement);
+    }
+    assert_eq!(query(&db, "SELECT * FROM t").unwrap(), ["1|a|b"]);
+    test_db.check_integrity();
+}
+
+#[test]
+fn virtual_generated_columns_are_not_stored() {
+    let test_db = TestDb::new();
+    let created = test_db.sqlite3(
+        "CREATE TABLE g (a, b AS (a * 2) VIRTUAL, c, d AS (b + a) STORED, e AS (upper(c)));
+        CREATE TABLE r (id INTEGER PRIMARY KEY, twice AS (id * 2) STORED);",
+    );
+    if created.is_none() {
+        return;
+    }
+    let mut db = test_db.open();
+    execute(&mut db, "INSERT INTO g VALUES (1, 'x')").unwrap();
+    execute(&mut db, "INSERT INTO g (c, a) VALUES ('y', 2)").unwrap();
+    execute(&mut db, "UPDATE g SET a = 3 WHERE c = 'y'").unwrap();
+    execute(&mut db, "INSERT INTO r (id) VALUES (NULL), (5)").unwrap();
+    assert_eq!(
+        query(&db, "SELECT * FROM g").unwrap(),
+        ["1|2|x|3|X", "3|6|y|9|Y"]
+    );
+    assert_eq!(query(&db, "SELECT * FROM r").unwrap(), ["1|2", "5|10"]);
+
+    let err = execute(&mut db, "INSERT INTO g (a, b) VALUES (1, 2)").unwrap_err();
+    assert_eq!(err.to_string(), "cannot INSERT into generated column \"b\"");
+    let err = execute(&mut db, "UPDATE g SET d = 1").unwrap_err();
+    assert_eq!(err.to_string(), "cannot UPDATE generated column \"d\"");
+    let err = execute(&mut db, "INSERT INTO g VALUES (1, 2, 3)").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "table g has 2 columns but 3 values were supplied"
+    );
+
+    test_db.check_integrity();
+    if let Some(rows) = test_db.sqlite3("SELECT * FROM g; SELECT * FROM r") {
+        assert_eq!(rows, "1|2|x|3|X\n3|6|y|9|Y\n1|2\n5|10");
+    }
+}
+
+#[test]
+fn check_constraints() {
+    let test_db = TestDb::new();
+    let created = test_db.sqlite3(
+        "CREATE TABLE c (id INTEGER PRIMARY KEY CHECK (id < 10), x CHECK(x >= 0), y TEXT,
+            CONSTRAINT short CHECK (length(y) < 3), CHECK (x  <  100))",
+    );
+    if created.is_none() {
+        return;
+    }
+    let mut db = test_db.open();
+    execute(
+        &mut db,
+        "INSERT INTO c (x, y) VALUES (1, 'a'), (NULL, NULL)",
+    )
+    .unwrap();
+
+    let failures = [
+        ("INSERT INTO c (x, y) VALUES (-1, 'a')", "x >= 0"),
+        ("INSERT INTO c (x, y) VALUES (1, 'long')", "short"),
+        ("INSERT INTO c (x, y) VALUES (100, 'a')", "x  <  100"),
+        ("INSERT INTO c VALUES (10, 1, 'a')", "id < 10"),
+        ("UPDATE c SET x = x - 5", "x >= 0"),
+        ("UPDATE c SET id = id + 8 WHERE id = 2", "id < 10"),
+    ];
+    for (statement, name) in failures {
+        let err = execute(&mut db, statement).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("CHECK constraint failed: {}", name)
+        );
+    }
+    assert_eq!(
+        query(&db, "SELECT * FROM c").unwrap(),
+        ["1|1|a", "2|NULL|NULL"]
+    );
+    test_db.check_integrity();
+}
+
+#[test]
+fn rowids_are_picked_at_random_after_the_largest_one() {
+    let test_db = TestDb::new();
+    let created = test_db.sqlite3("CREATE TABLE m (id INTEGER PRIMARY KEY, x)");
+    if created.is_none() {
+        return;
+    }
+    let mut db = test_db.open();
+    execute(&mut db, "INSERT INTO m VALUES (9223372036854775807, 0)").unwrap();
+    execute(&mut db, "INSERT INTO m (x) VALUES (1), (2), (3)").unwrap();
+    assert_eq!(
+        query(&db, "SELECT count(*), min(id) > 0 FROM m").unwrap(),
+        ["4|1"]
+    );
+    test_db.check_integrity();
+}
+
+#[test]
+fn updates_and_deletes_rebalance_pages_sqlite3_can_read() {
+    let test_db = TestDb::new();
+    let created = test_db.sqlite3(
+        "CREATE TABLE t (id INTEGER PRIMARY KEY, a TEXT, b);
+        WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 3000)
+        INSERT INTO t SELECT i, printf('%.*c', i % 300, 'x'), i % 7 FROM n;",
+    );
+    if created.is_none() {
+        return;
+    }
+    let mut db = test_db.open();
+    execute(&mut db, "DELETE FROM t WHERE b = 3 OR id > 2500").unwrap();
+    execute(
+        &mut db,
+        "UPDATE t SET a = upper(a) || 'y', b = b * 10 WHERE b < 2",
+    )
+    .unwrap();
+    execute(&mut db, "UPDATE t SET id = id + 10000 WHERE id % 100 = 0").unwrap();
+    execute(&mut db, "UPDATE t SET a = zeroblob(5000) WHERE id = 10").unwrap();
+    let err = execute(&mut db, "UPDATE t SET id = 1 WHERE id = 2").unwrap_err();
+    assert_eq!(err.to_string(), "UNIQUE constraint failed: t.id");
+    test_db.check_integrity();
+
+    let queries = [
+        "SELECT count(*), sum(length(a)), sum(b), max(id) FROM t",
+        "SELECT id, length(a), b FROM t WHERE id = 15 OR id = 10 OR id = 10200",
+        "SELECT count(*) FROM t WHERE a LIKE '%xy'",
+    ];
+    for sql in queries {
+        if let Some(rows) = test_db.sqlite3(sql) {
+            assert_eq!(query(&db, sql).unwrap().join("\n"), rows, "{}", sql);
+        }
+    }
+    assert_eq!(
+        query(&db, "SELECT count(*) FROM t WHERE b = 3").unwrap(),
+        ["0"]
+    );
+
+    execute(&mut db, "DELETE FROM t").unwrap();
+    assert_eq!(db.count("t").unwrap(), 0);
+    test_db.check_integrity();
+    execute(&mut db, "INSERT INTO t (a) VALUES ('again')").unwrap();
+    assert_eq!(query(&db, "SELECT * FROM t").unwrap(), ["1|again|NULL"]);
+    test_db.check_integrity();
+}
+
+#[test]
+fn writes_keep_indexes_sqlite3_can_use() {
+    let test_db = TestDb::new();
+    let created = test_db.sqlite3(
+        "CREATE TABLE p (id INTEGER PRIMARY KEY, email TEXT UNIQUE COLLATE NOCASE, a, b,
+            UNIQUE (a, b));
+        CREATE INDEX p_b ON p (b DESC);
+        CREATE INDEX p_upper ON p (upper(email));
+        CREATE UNIQUE INDEX p_big ON p (a) WHERE a > 1000;
+        CREATE TABLE k (name TEXT PRIMARY KEY, n);",
+    );
+    if created.is_none() {
+        return;
+    }
+    let mut db = test_db.open();
+    for i in 0..10 {
+        let values = (0..100)
+            .map(|j| i * 100 + j)
+            .map(|n| format!("('user{}@Example.com', {}, {})", n, n % 500, n % 3))
+            .collect::<Vec<_>>()
+            .join(", ");
+        execute(
+            &mut db,
+            &format!("INSERT INTO p (email, a, b) VALUES {}", values),
+        )
+        .unwrap();
+    }
+    execute(
+        &mut db,
+        "INSERT INTO p (email, a) VALUES ('big@example.com', 2000)",
+    )
+    .unwrap();
+    execute(&mut db, "INSERT INTO k VALUES ('x', 1), ('y', 2)").unwrap();
+    test_db.check_integrity();
+
+    let failures = [
+        (
+            "INSERT INTO p (email) VALUES ('USER7@example.COM')",
+            "UNIQUE constraint failed: p.email",
+        ),
+        (
+            "INSERT INTO p (a, b) VALUES (7, 1)",
+            "UNIQUE constraint failed: p.a, p.b",
+        ),
+        (
+            "INSERT INTO p (a, b) VALUES (2000, 5)",
+            "UNIQUE constraint failed: p.a",
+        ),
+        (
+            "UPDATE p SET email = 'user8@example.com' WHERE id = 1",
+            "UNIQUE constraint failed: p.email",
+        ),
+        (
+            "INSERT INTO k VALUES ('x', 3)",
+            "UNIQUE constraint failed: k.name",
+        ),
+    ];
+    for (statement, message) in failures {
+        let err = execute(&mut db, statement).unwrap_err();
+        assert_eq!(err.to_string(), message, "{}", statement);
+    }
+    // NULLs are distinct from each other, and rows left out of a partial index never conflict
+    execute(
+        &mut db,
+        "INSERT INTO p (a, b) VALUES (7, NULL), (7, NULL), (5, 9)",
+    )
+    .unwrap();
+
+    execute(&mut db, "DELETE FROM p WHERE b = 2 OR id > 900").unwrap();
+    execute(
+        &mut db,
+        "UPDATE p SET email = lower(email), a = id + 1000 WHERE id % 10 = 0",
+    )
+    .unwrap();
+    execute(&mut db, "UPDATE p SET id = id + 5000 WHERE id % 7 = 0").unwrap();
+    execute(&mut db, "UPDATE k SET n = n * 10").unwrap();
+    execute(&mut db, "DELETE FROM k WHERE name = 'x'").unwrap();
+    test_db.check_integrity();
+
+    let queries = [
+        "SELECT count(*), sum(a), sum(id) FROM p",
+        "SELECT id, email, a, b FROM p WHERE email = 'USER301@EXAMPLE.COM'",
+        "SELECT count(*) FROM p WHERE b = 1",
+        "SELECT id FROM p WHERE upper(email) = 'USER55@EXAMPLE.COM'",
+        "SELECT id, a FROM p WHERE a > 1000 AND a = 1360",
+        "SELECT * FROM k",
+    ];
+    for sql in queries {
+        if let Some(rows) = test_db.sqlite3(sql) {
+            assert_eq!(query(&db, sql).unwrap().join("\n"), rows, "{}", sql);
+        }
+    }
+    if let Some(rows) = test_db.sqlite3("SELECT count(*) FROM p INDEXED BY p_b WHERE b = 0") {
+        assert_eq!(
+            query(&db, "SELECT count(*) FROM p WHERE b = 0").unwrap(),
+            [rows]
+        );
+    }
+}
+
+#[test]
+fn creates_and_drops_objects_sqlite3_can_use() {
+    let test_db = TestDb::new();
+    let mut db = test_db.open();
+    let statements = [
+        "CREATE TABLE t (id INTEGER PRIMARY KEY, a TEXT UNIQUE, b)",
+        "CREATE TABLE IF NOT EXISTS t (x)",
+        "CREATE TABLE w (k, v, PRIMARY KEY (k), UNIQUE (v)) WITHOUT ROWID",
+        "INSERT INTO t (a, b) VALUES ('x', 1), ('y', 2), ('z', 2)",
+        "CREATE INDEX t_b ON t (b)",
+        "CREATE VIEW v AS SELECT a FROM t WHERE b = 2",
+        "CREATE TRIGGER tr AFTER INSERT ON t BEGIN SELECT 1; END",
+    ];
+    for sql in statements {
+        execute(&mut db, sql).unwrap();
+    }
+    assert_eq!(db.tables().unwrap(), ["t", "w"]);
+    assert_eq!(query(&db, "SELECT * FROM v").unwrap(), ["y", "z"]);
+
+    let failures = [
+        ("CREATE TABLE T (x)", "table T already exists"),
+        (
+            "CREATE VIEW T_B AS SELECT 1",
+            "there is already an index named T_B",
+        ),
+        (
+            "CREATE INDEX T ON t (a)",
+            "there is already a table named T",
+        ),
+        (
+            "CREATE TABLE sqlite_x (a)",
+            "object name reserved for internal use: sqlite_x",
+        ),
+        ("CREATE TABLE u (a, A)", "duplicate column name: A"),
+        (
+            "CREATE TABLE u (a PRIMARY KEY, b PRIMARY KEY)",
+            "table \"u\" has more than one primary key",
+        ),
+        (
+            "CREATE TABLE u (a) WITHOUT ROWID",
+            "PRIMARY KEY missing on table u",
+        ),
+        ("CREATE INDEX i ON v (a)", "views may not be indexed"),
+        ("CREATE INDEX i ON x (a)", "no such table: main.x"),
+        (
+            "CREATE UNIQUE INDEX i ON t (b)",
+            "UNIQUE constraint failed: t.b",
+        ),
+        ("DROP VIEW t", "use DROP TABLE to delete table t"),
+        ("DROP INDEX i", "no such index: i"),
+        (
+            "DROP INDEX sqlite_autoindex_t_1",
+            "index associated with UNIQUE or PRIMARY KEY constraint cannot be dropped",
+        ),
+    ];
+    for (statement, message) in failures {
+        let err = execute(&mut db, statement).unwrap_err();
+        assert_eq!(err.to_string(), message, "{}", statement);
+    }
+    test_db.check_integrity();
+    let sql = "SELECT type, name, tbl_name, sql FROM sqlite_schema;
+        SELECT id FROM t INDEXED BY t_b WHERE b = 2;";
+    if let Some(rows) = test_db.sqlite3(sql) {
+        assert_eq!(
+            rows,
+            "table|t|t|CREATE TABLE t (id INTEGER PRIMARY KEY, a TEXT UNIQUE, b)
+index|sqlite_autoindex_t_1|t|
+table|w|w|CREATE TABLE w (k, v, PRIMARY KEY (k), UNIQUE (v)) WITHOUT ROWID
+index|sqlite_autoindex_w_2|w|
+index|t_b|t|CREATE INDEX t_b ON t (b)
+view|v|v|CREATE VIEW v AS SELECT a FROM t WHERE b = 2
+trigger|tr|t|CREATE TRIGGER tr AFTER INSERT ON t BEGIN SELECT 1; END
+2
+3"
+        );
+    }
+
+    // Dropping a table drops its indexes and triggers, and frees their pages. The unique index
+    // that could not be created left nothing behind
+    let mut db = test_db.open();
+    execute(&mut db, "DROP TABLE t").unwrap();
+    execute(&mut db, "DROP TABLE IF EXISTS t").unwrap();
+    execute(&mut db, "DROP VIEW v").unwrap();
+    assert_eq!(db.tables().unwrap(), ["w"]);
+    test_db.check_integrity();
+    if let Some(rows) = test_db.sqlite3("SELECT name FROM sqlite_schema; PRAGMA freelist_count") {
+        assert_eq!(rows, "w\nsqlite_autoindex_w_2\n3");
+    }
+}
+
+#[test]
+fn autoincrement_keeps_rowids_in_sqlite_sequence() {
+    let test_db = TestDb::new();
+    let mut db = test_db.open();
+    execute(
+        &mut db,
+        "CREATE TABLE t (id INTEGER PRIMARY KEY AUTOINCREMENT, x UNIQUE)",
+    )
+    .unwrap();
+    execute(
+        &mut db,
+        "CREATE TABLE u (id INTEGER PRIMARY KEY AUTOINCREMENT)",
+    )
+    .unwrap();
+    execute(&mut db, "INSERT INTO t (x) VALUES (1)").unwrap();
+    execute(&mut db, "INSERT INTO t VALUES (10, 2)").unwrap();
+    execute(&mut db, "DELETE FROM t").unwrap();
+    execute(&mut db, "INSERT INTO t (x) VALUES (3)").unwrap();
+    execute(&mut db, "INSERT INTO u VALUES (-5)").unwrap();
+    assert_eq!(query(&db, "SELECT * FROM t").unwrap(), ["11|3"]);
+    assert_eq!(
+        query(&db, "SELECT rowid, * FROM sqlite_sequence").unwrap(),
+        ["1|t|11", "2|u|0"]
+    );
+    assert_eq!(db.tables().unwrap(), ["t", "u"]);
+
+    let failures = [
+        (
+            "CREATE TABLE v (id INT PRIMARY KEY AUTOINCREMENT)",
+            "AUTOINCREMENT is only allowed on an INTEGER PRIMARY KEY",
+        ),
+        (
+            "CREATE TABLE v (id INTEGER PRIMARY KEY AUTOINCREMENT) WITHOUT ROWID",
+            "AUTOINCREMENT not allowed on WITHOUT ROWID tables",
+        ),
+    ];
+    for (statement, message) in failures {
+        let err = execute(&mut db, statement).unwrap_err();
+        assert_eq!(err.to_string(), message, "{}", statement);
+    }
+    test_db.check_integrity();
+    if let Some(rows) = test_db.sqlite3(
+        "SELECT name, rootpage > 0 FROM sqlite_schema;
+        INSERT INTO u VALUES (NULL);
+        SELECT * FROM sqlite_sequence;",
+    ) {
+        assert_eq!(
+            rows,
+            "t|1\nsqlite_autoindex_t_1|1\nsqlite_sequence|1\nu|1\nt|11\nu|1"
+        );
+    }
+
+    let mut db = test_db.open();
+    execute(&mut db, "INSERT INTO u VALUES (NULL)").unwrap();
+    execute(&mut db, "DROP TABLE t").unwrap();
+    assert_eq!(
+        query(&db, "SELECT * FROM sqlite_sequence").unwrap(),
+        ["u|2"]
+    );
+    execute(&mut db, "INSERT INTO u VALUES (9223372036854775807)").unwrap();
+    let err = execute(&mut db, "INSERT INTO u VALUES (NULL)").unwrap_err();
+    assert_eq!(err.to_string(), "database or disk is full");
+    test_db.check_integrity();
+}
+
+#[test]
+fn savepoints_nest_within_transactions() {
+    let test_db = TestDb::new();
+    let mut db = test_db.open();
+    let statements = [
+        "CREATE TABLE t (a UNIQUE)",
+        "SAVEPOINT one",
+        "INSERT INTO t VALUES (1)",
+        "SAVEPOINT two",
+        "INSERT INTO t VALUES (2)",
+        "SAVEPOINT One",
+        "INSERT INTO t VALUES (3)",
+        // The innermost savepoint of a name is rolled back to, and stays open
+        "ROLLBACK TO one",
+        "INSERT INTO t VALUES (4)",
+        "RELEASE SAVEPOINT two",
+        "SAVEPOINT three",
+        "INSERT INTO t VALUES (5)",
+        // Releasing a savepoint keeps its changes until the enclosing one is rolled back to
+        "RELEASE three",
+        "ROLLBACK TO SAVEPOINT one",
+        "INSERT INTO t VALUES (6)",
+        // Releasing the outermost savepoint commits
+        "RELEASE one",
+        "BEGIN",
+        "SAVEPOINT four",
+        "INSERT INTO t VALUES (7)",
+        "RELEASE four",
+        "SAVEPOINT five",
+        "INSERT INTO t VALUES (8)",
+        "ROLLBACK TO five",
+        "COMMIT",
+    ];
+    for statement in statements {
+        execute(&mut db, statement).unwrap();
+    }
+    assert_eq!(query(&db, "SELECT a FROM t").unwrap(), ["6", "7"]);
+    test_db.check_integrity();
+    if let Some(rows) = test_db.sqlite3(&format!(
+        "DROP TABLE t; {}; SELECT a FROM t;",
+        statements.join("; ")
+    )) {
+        assert_eq!(rows, "6\n7");
+    }
+
+    // A failing statement only undoes its own changes
+    execute(&mut db, "SAVEPOINT one").unwrap();
+    let err = execute(&mut db, "INSERT INTO t VALUES (9), (6)").unwrap_err();
+    assert_eq!(err.to_string(), "UNIQUE constraint failed: t.a");
+    execute(&mut db, "INSERT INTO t VALUES (10)").unwrap();
+    let err = execute(&mut db, "RELEASE two").unwrap_err();
+    assert_eq!(err.to_string(), "no such savepoint: two");
+    execute(&mut db, "ROLLBACK").unwrap();
+    let err = execute(&mut db, "ROLLBACK TO one").unwrap_err();
+    assert_eq!(err.to_string(), "no such savepoint: one");
+    assert_eq!(query(&db, "SELECT a FROM t").unwrap(), ["6", "7"]);
+
+    let mut db = test_db.open();
+    execute(&mut db, "SAVEPOINT one").unwrap();
+    execute(&mut db, "INSERT INTO t VALUES (10)").unwrap();
+    execute(&mut db, "RELEASE one").unwrap();
+    assert_eq!(query(&db, "SELECT a FROM t").unwrap(), ["6", "7", "10"]);
+    test_db.check_integrity();
+}
+
+#[test]
+fn free_pages_are_reused_before_the_file_grows() {
+    let test_db = TestDb::new();
+    let created = test_db.sqlite3(
+        "CREATE TABLE t (a, b);
+        INSERT INTO t VALUES (1, zeroblob(20000)), (2, zeroblob(20000));
+        DELETE FROM t WHERE a = 1;
+        PRAGMA freelist_count;",
+    );
+    let free_count = match created {
+        Some(count) => count.parse::<usize>().unwrap(),
+        None => return,
+    };
+    let size = std::fs::metadata(&test_db.path).unwrap().len();
+    let mut db = test_db.open();
+    let free_pages = db.free_pages().unwrap();
+    assert_eq!(free_pages.len(), free_count);
+    assert!(free_count > 1);
+
+    // The pages of the freelist sqlite3 left are taken, leaves before their trunk
+    execute(&mut db, "INSERT INTO t VALUES (3, zeroblob(16000))").unwrap();
+    let remaining = db.free_pages().unwrap();
+    assert_eq!(remaining, free_pages[..remaining.len()]);
+    assert!(!remaining.is_empty() && remaining.len() < free_count);
+    execute(&mut db, "INSERT INTO t VALUES (4, zeroblob(20000))").unwrap();
+    assert!(db.free_pages().unwrap().is_empty());
+    assert!(std::fs::metadata(&test_db.path).unwrap().len() > size);
+    test_db.check_integrity();
+
+    // Pages freed here make a freelist sqlite3 reads
+    execute(&mut db, "DELETE FROM t WHERE a > 2").unwrap();
+    let free_count = db.free_pages().unwrap().len();
+    assert!(free_count > 8);
+    test_db.check_integrity();
+    if let Some(count) = test_db.sqlite3("PRAGMA freelist_count") {
+        assert_eq!(count, free_count.to_string());
+    }
+}